@@ -1,5 +1,6 @@
 // cd .. && cargo build --release && json='{"min_count":10,"min_total_count":15,"case":"SJMB030827,SJMB030838,SJMB032893,SJMB031131,SJMB031227","control":"SJMB030488,SJMB030825,SJMB031110","input_file":"/Users/rpaul1/pp_data/files/hg38/sjmb12/rnaseq/geneCounts.txt"}' && time echo $json | target/release/DEanalysis
 // cd .. && cargo build --release && time cat ~/sjpp/test.txt | target/release/DEanalysis
+// Optional "mtc" field selects the multiple testing correction (fdr/BH, BY, holm, hochberg, hommel, bon/bonferroni, qvalue; default fdr), an unknown method is reported as { error: { kind: "invalid_input", message } }
 #![allow(non_snake_case)]
 use json;
 use nalgebra::base::dimension::Const;
@@ -23,6 +24,7 @@ use std::time::Instant;
 //use std::cmp::Ordering;
 //use std::env;
 use std::io;
+mod multiple_testing; // Importing multiple testing correction from multiple_testing.rs
 mod stats_functions; // Importing Wilcoxon function from stats_functions.rs
 const PAR_CUTOFF: usize = 100000; // Cutoff for triggering multithreading processing of data
 
@@ -336,6 +338,32 @@ fn input_data(
     (dm, case_indexes, control_indexes, gene_names, gene_symbols)
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+struct DEError {
+    kind: String, // "invalid_input"
+    message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ErrorResponse {
+    error: DEError,
+}
+
+fn invalid_input(message: &str) -> DEError {
+    DEError {
+        kind: "invalid_input".to_string(),
+        message: message.to_string(),
+    }
+}
+
+// Prints the error as { error: { kind, message } } instead of the results
+fn print_error(error: DEError) {
+    println!(
+        "{}",
+        serde_json::to_string(&ErrorResponse { error }).unwrap()
+    );
+}
+
 #[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize)]
 struct AdjustedPValueIndexes {
@@ -384,6 +412,13 @@ fn main() {
                             panic!("min_total_count is missing a value")
                         }
                     }
+                    // Multiple testing correction, Benjamini-Hochberg (fdr) by default
+                    let mtc = match multiple_testing::Method::from_mtc(
+                        json_string["mtc"].as_str().unwrap_or("fdr"),
+                    ) {
+                        Ok(method) => method,
+                        Err(error) => return print_error(invalid_input(&error)),
+                    };
                     let case_string = &json_string["case"].to_owned().as_str().unwrap().to_string();
                     let control_string = &json_string["control"]
                         .to_owned()
@@ -599,7 +634,7 @@ fn main() {
                         num_normalized_rows,
                         now2.elapsed()
                     );
-                    let adjusted_p_values = adjust_p_values(p_values);
+                    let adjusted_p_values = adjust_p_values(p_values, mtc);
                     println!("adjusted_p_values:{}", adjusted_p_values);
                     //let fold_changes =
                     //    calculate_fold_change(normalized_matrix, case_indexes, control_indexes);
@@ -611,46 +646,23 @@ fn main() {
     }
 }
 
-fn adjust_p_values(mut original_p_values: Vec<PValueIndexes>) -> String {
-    // Sorting p-values in ascending order
-    original_p_values.as_mut_slice().sort_by(|a, b| {
-        (a.p_value)
-            .partial_cmp(&b.p_value)
-            .unwrap_or(Ordering::Equal)
-    });
+fn adjust_p_values(
+    original_p_values: Vec<PValueIndexes>,
+    method: multiple_testing::Method,
+) -> String {
+    let p_values: Vec<f64> = original_p_values.iter().map(|x| x.p_value).collect();
+    let adjusted = multiple_testing::adjust_p_values(&p_values, method);
 
     let mut adjusted_p_values: Vec<AdjustedPValueIndexes> =
         Vec::with_capacity(original_p_values.len());
-    let mut old_p_value: f64 = 0.0;
-    let mut rank: f64 = original_p_values.len() as f64;
-    for j in 0..original_p_values.len() {
-        let i = original_p_values.len() - j - 1;
-
-        //println!("p_val:{}", p_val);
-        let mut adjusted_p_val: f64 =
-            original_p_values[i].p_value * (original_p_values.len() as f64 / rank); // adjusted p-value = original_p_value * (N/rank)
-        if adjusted_p_val > 1.0 {
-            // p_value should NEVER be greater than 1
-            adjusted_p_val = 1.0;
-        }
-        //println!("Original p_value:{}", original_p_values[i].p_value);
-        //println!("Raw adjusted p_value:{}", adjusted_p_value);
-        if i != original_p_values.len() - 1 {
-            if adjusted_p_val > old_p_value {
-                adjusted_p_val = old_p_value;
-            }
-        }
-        old_p_value = adjusted_p_val;
-        //println!("adjusted_p_value:{}", adjusted_p_val);
-        rank -= 1.0;
-
+    for (i, original) in original_p_values.iter().enumerate() {
         adjusted_p_values.push(AdjustedPValueIndexes {
-            index: original_p_values[i].index,
-            fold_change: original_p_values[i].fold_change,
-            gene_name: original_p_values[i].gene_name.to_owned(),
-            gene_symbol: original_p_values[i].gene_symbol.to_owned(),
-            original_p_value: (-1.0) * original_p_values[i].p_value.log10(),
-            adjusted_p_value: (-1.0) * adjusted_p_val.log10(),
+            index: original.index,
+            fold_change: original.fold_change,
+            gene_name: original.gene_name.to_owned(),
+            gene_symbol: original.gene_symbol.to_owned(),
+            original_p_value: -original.p_value.log10(),
+            adjusted_p_value: -adjusted[i].log10(),
         });
     }
     adjusted_p_values.sort_by(|a, b| a.index.cmp(&b.index));
@@ -666,29 +678,6 @@ fn adjust_p_values(mut original_p_values: Vec<PValueIndexes>) -> String {
     output_string
 }
 
-#[allow(dead_code)]
-fn adjust_p_values_bonferroni(original_p_values: Vec<PValueIndexes>) -> Vec<AdjustedPValueIndexes> {
-    let mut adjusted_p_values: Vec<AdjustedPValueIndexes> =
-        Vec::with_capacity(original_p_values.len());
-    for i in 0..original_p_values.len() {
-        let mut adjusted_p_value: f64 =
-            original_p_values[i].p_value * original_p_values.len() as f64; // In bonferroni correction, multiplying p_value by number of tests (excluding those with low sample sizes)
-        if adjusted_p_value > 1.0 {
-            // p_value should NEVER be greater than 1
-            adjusted_p_value = 1.0;
-        }
-        adjusted_p_values.push(AdjustedPValueIndexes {
-            index: original_p_values[i].index,
-            gene_name: original_p_values[i].gene_name.to_owned(),
-            gene_symbol: original_p_values[i].gene_symbol.to_owned(),
-            fold_change: original_p_values[i].fold_change,
-            original_p_value: (-1.0) * original_p_values[i].p_value.log10(),
-            adjusted_p_value: (-1.0) * adjusted_p_value.log10(),
-        });
-    }
-    adjusted_p_values
-}
-
 // Original TMM normalization source code in edgeR: https://rdrr.io/bioc/edgeR/src/R/calcNormFactors.R
 fn tmm_normalization(
     input_matrix: Matrix<f64, Dyn, Dyn, VecStorage<f64, Dyn, Dyn>>,
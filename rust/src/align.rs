@@ -5,6 +5,7 @@ use serde::{Deserialize, Serialize};
 use std::io;
 
 mod realign; // Importing functions from realign.rs
+mod vcf; // Importing functions from vcf.rs
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct ReadAlignmentInfo {
@@ -49,6 +50,25 @@ fn main() {
                     }
 
                     let read_start = json_string["start_position"].as_i64().unwrap(); // Contains start position of read
+                    let alignment_parameters =
+                        match realign::parse_alignment_parameters(&json_string) {
+                            Ok(alignment_parameters) => alignment_parameters,
+                            Err(error) => {
+                                println!("Invalid alignment parameters: {}", error);
+                                return;
+                            }
+                        };
+                    let vcf_file: Option<&str> = json_string["vcf_file"].as_str(); // When given, the allele supported by the read is also written in VCF format to this file
+                    let chrom: Option<&str> = json_string["chr"].as_str(); // Chromosome name, required for VCF output
+                    if vcf_file.is_some() && chrom.is_none() {
+                        println!("chr is required for VCF output");
+                        return;
+                    }
+                    let mut read_strand_reverse: bool = false; // Strand of the read, determined from the SAM flag (optional)
+                    if let Some(flag) = json_string["flag"].as_u64() {
+                        read_strand_reverse = flag & 16 == 16
+                    }
+                    let mut matched_nucleotides_ratios = Vec::<(f64, f64)>::new(); // Identity of the read with the ref and alt sequence of each allele
 
                     let mut indel_lengths_list = Vec::<usize>::new();
                     for indel_idx in 0..variant_positions_vec.len() {
@@ -59,7 +79,7 @@ fn main() {
                         indel_lengths_list.push(indel_length);
                     }
                     let (
-                        _within_indel,
+                        within_indel,
                         correct_start_positions,
                         correct_end_positions,
                         alignment_sides,
@@ -90,10 +110,21 @@ fn main() {
                         let variant_ref = &refalleles_vec[indel_idx];
                         let variant_alt = &altalleles_vec[indel_idx];
                         let alignment_side = &alignment_sides[indel_idx].to_owned();
-                        let (q_seq_ref, align_ref, r_seq_ref, _matched_nucleotides_ratio) =
-                            realign::align_single_reads(&final_sequence, ref_seq.to_string()); // Aligning against reference
-                        let (q_seq_alt, align_alt, r_seq_alt, _matched_nucleotides_ratio) =
-                            realign::align_single_reads(&final_sequence, alt_seq.to_string()); // Aligning against alternate
+                        let (q_seq_ref, align_ref, r_seq_ref, ref_matched_nucleotides_ratio) =
+                            realign::align_single_reads(
+                                final_sequence,
+                                ref_seq.to_string(),
+                                &alignment_parameters,
+                            ); // Aligning against reference
+                        let (q_seq_alt, align_alt, r_seq_alt, alt_matched_nucleotides_ratio) =
+                            realign::align_single_reads(
+                                final_sequence,
+                                alt_seq.to_string(),
+                                &alignment_parameters,
+                            ); // Aligning against alternate
+
+                        matched_nucleotides_ratios
+                            .push((ref_matched_nucleotides_ratio, alt_matched_nucleotides_ratio));
 
                         let (
                             red_region_start_alt,
@@ -143,6 +174,35 @@ fn main() {
                     output_string.pop();
                     output_string += &"]".to_string();
                     println!("Final_output:{}", output_string);
+
+                    if let (Some(vcf_file), Some(chrom)) = (vcf_file, chrom) {
+                        let vcf_records = match create_vcf_records(
+                            chrom,
+                            &variant_positions_vec,
+                            &refalleles_vec,
+                            &altalleles_vec,
+                            &matched_nucleotides_ratios,
+                            within_indel == 1,
+                            read_strand_reverse,
+                        ) {
+                            Ok(vcf_records) => vcf_records,
+                            Err(error) => {
+                                println!("{}", error);
+                                return;
+                            }
+                        };
+                        match vcf::write_vcf(
+                            vcf_file,
+                            "proteinpaint_align",
+                            chrom,
+                            json_string["sample_name"].as_str().unwrap_or("SAMPLE"),
+                            &vcf_records,
+                            &[],
+                        ) {
+                            Ok(_) => {}
+                            Err(error) => println!("{}", error),
+                        }
+                    }
                 }
                 Err(error) => println!("Incorrect json: {}", error),
             }
@@ -150,3 +210,74 @@ fn main() {
         Err(error) => println!("Piping error: {}", error),
     }
 }
+
+// Creates one VCF record for each variant site. Alleles given at the same position with the same ref allele are merged into a single multi-allelic record, so that AD contains the ref and every alt allele of the site. The read supports the allele (ref or one of the alts) with the highest identity and none of them if the highest identity is shared. DP is the sum of AD
+fn create_vcf_records(
+    chrom: &str,                               // Chromosome name
+    variant_positions: &[i64],                 // Start position of each variant (0-based)
+    refalleles: &[&str],                       // Ref allele of each variant
+    altalleles: &[&str],                       // Alt allele of each variant
+    matched_nucleotides_ratios: &[(f64, f64)], // Identity of the read with the ref and alt sequence of each variant
+    within_indel: bool,                        // Whether the read contains the variant region
+    read_strand_reverse: bool,                 // Strand of the read
+) -> Result<Vec<vcf::VcfRecord>, String> {
+    // Grouping alleles by site, in the order in which they are given
+    let mut sites = Vec::<(i64, &str, Vec<usize>)>::new();
+    for (allele_idx, (variant_pos, ref_allele)) in
+        variant_positions.iter().zip(refalleles).enumerate()
+    {
+        match sites
+            .iter_mut()
+            .find(|site| site.0 == *variant_pos && site.1 == *ref_allele)
+        {
+            Some(site) => site.2.push(allele_idx),
+            None => sites.push((*variant_pos, ref_allele, vec![allele_idx])),
+        }
+    }
+
+    let mut vcf_records = Vec::<vcf::VcfRecord>::new();
+    for (variant_pos, ref_allele, allele_indices) in sites {
+        // Identity of the read with each allele of the site (ref first). The ref sequence may differ slightly between alleles because of the flanking sequences, the best ref identity is used
+        let mut identities = vec![allele_indices
+            .iter()
+            .map(|allele_idx| matched_nucleotides_ratios[*allele_idx].0)
+            .fold(f64::MIN, f64::max)];
+        let mut alt_alleles = Vec::<String>::new();
+        for allele_idx in &allele_indices {
+            let (_pos, _ref_allele, alt_allele) =
+                vcf::anchor_alleles(variant_pos, ref_allele, altalleles[*allele_idx], None)?;
+            alt_alleles.push(alt_allele);
+            identities.push(matched_nucleotides_ratios[*allele_idx].1);
+        }
+        let max_identity = identities.iter().cloned().fold(f64::MIN, f64::max);
+        let mut allele_depths = vec![0u32; identities.len()];
+        if within_indel && identities.iter().filter(|x| **x == max_identity).count() == 1 {
+            let supported_allele = identities.iter().position(|x| *x == max_identity).unwrap();
+            allele_depths[supported_allele] = 1;
+        }
+        let depth: u32 = allele_depths.iter().sum();
+        let alt_depth: u32 = allele_depths[1..].iter().sum();
+        let strand_bias: [u32; 4] = if read_strand_reverse {
+            [0, allele_depths[0], 0, alt_depth]
+        } else {
+            [allele_depths[0], 0, alt_depth, 0]
+        }; // ref forward, ref reverse, alt forward, alt reverse
+        let allele_depths: Vec<String> = allele_depths.iter().map(|x| x.to_string()).collect();
+        let strand_bias: Vec<String> = strand_bias.iter().map(|x| x.to_string()).collect();
+        vcf_records.push(vcf::VcfRecord {
+            chrom: chrom.to_string(),
+            pos: variant_pos + 1, // VCF positions are 1-based
+            id: ".".to_string(),
+            ref_allele: ref_allele.to_string(),
+            alt_allele: alt_alleles.join(","),
+            info: Vec::<String>::new(),
+            format: vec!["AD".to_string(), "DP".to_string(), "SB".to_string()],
+            sample: vec![
+                allele_depths.join(","),
+                depth.to_string(),
+                strand_bias.join(","),
+            ],
+        });
+    }
+    Ok(vcf_records)
+}
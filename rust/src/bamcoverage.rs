@@ -0,0 +1,446 @@
+/*
+ Syntax:
+ local: cd .. && cargo build --release && time echo '{"bam_file":"/Users/rpaul1/proteinpaint/hg19/bam/file.bam","chr":"chr17","start":7568451,"stop":7591984,"datapoints":940}' | target/release/bamcoverage
+
+ Strand-specific coverage of reads with mapping quality of at least 20 from a CRAM file:
+ local: cd .. && cargo build --release && time echo '{"bam_file":"/Users/rpaul1/proteinpaint/hg38/cram/file.cram","fasta_file":"/Users/rpaul1/proteinpaint/hg38/genome/hg38.gz","chr":"chr17","start":7668402,"stop":7687550,"datapoints":500,"min_mapq":20,"strand_specific":true}' | target/release/bamcoverage
+*/
+
+/*
+Notes:
+   The script accepts a JSON request { bam_file, fasta_file, chr, start, stop, datapoints, min_mapq, exclude_flags, include_flags, exclude_duplicates, strand_specific, count_deletions }:
+      bam_file: Path to the indexed BAM/CRAM file (the index is expected next to the file)
+      fasta_file: Path to the FASTA file (indexed with samtools faidx), required for CRAM files
+      chr, start, stop: Region (0-based, stop not inclusive)
+      datapoints: Number of bins, the region is divided into bins in the same way as in bigwig.rs (bin i starts at start + i * (stop - start) / datapoints)
+      min_mapq: Minimum mapping quality of a read (default 0)
+      exclude_flags: Reads with any of these SAM flags set are skipped (default 1796, i.e. unmapped, secondary, QC failed and duplicate, same as samtools depth)
+      include_flags: Only reads with all of these SAM flags set are counted (default 0)
+      exclude_duplicates: When true, duplicates are skipped even if exclude_flags does not contain the duplicate flag (1024). When false, duplicates are counted even if exclude_flags contains it (default true)
+      strand_specific: Also compute the coverage of reads on the forward and reverse strand separately (default false)
+      count_deletions: Count deleted bases as covered (default false, same as samtools depth)
+
+   The response is { chr, start, stop, datapoints, reads, mean, forward, reverse } where reads is the number of reads passing the filters and mean is the mean depth of each bin (bases not covered by any read count as depth 0). forward/reverse are the mean depth of each bin from reads on each strand (only when strand_specific is true)
+   Errors are returned as { error: { kind, message } } where kind is "invalid_input", "cannot_read_file" or "query_failed"
+
+   The coverage is added to the bins directly from the aligned blocks of each read (cigar M/=/X, and D when count_deletions is true), so that memory does not depend on the size of the region
+
+   Function cascade:
+
+   main() - Parses the JSON request.
+     compute_coverage() - Fetches the reads in the region and filters them.
+       aligned_blocks() - Reference intervals covered by a read.
+       CoverageBins::add_block() - Adds the bases of an aligned block to the bins it overlaps.
+*/
+
+use json::JsonValue;
+use rust_htslib::bam;
+use rust_htslib::bam::record::Cigar;
+use rust_htslib::bam::Read;
+use serde::{Deserialize, Serialize};
+use std::io;
+
+const DUPLICATE_FLAG: u16 = 0x400;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CoverageError {
+    kind: String, // "invalid_input", "cannot_read_file" or "query_failed"
+    message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ErrorResponse {
+    error: CoverageError,
+}
+
+// Parsed JSON request
+#[derive(Debug)]
+struct CoverageRequest {
+    bam_file: String,
+    fasta_file: Option<String>,
+    chrom: String,
+    start_pos: u64,
+    stop_pos: u64,
+    datapoints: u32,
+    min_mapq: u8,
+    exclude_flags: u16,
+    include_flags: u16,
+    strand_specific: bool,
+    count_deletions: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CoverageSummary {
+    chr: String,
+    start: u64,
+    stop: u64,
+    datapoints: u32,
+    reads: u64,     // Number of reads passing the filters
+    mean: Vec<f64>, // Mean depth of each bin
+    #[serde(skip_serializing_if = "Option::is_none")]
+    forward: Option<Vec<f64>>, // Mean depth of each bin from forward strand reads
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reverse: Option<Vec<f64>>, // Mean depth of each bin from reverse strand reads
+}
+
+// Number of aligned bases in each bin
+struct CoverageBins {
+    bin_limits: Vec<f64>, // Start of each bin followed by the stop of the last bin
+    bin_width: f64,
+    bases: Vec<f64>,
+    forward_bases: Vec<f64>,
+    reverse_bases: Vec<f64>,
+}
+
+impl CoverageBins {
+    fn new(start_pos: f64, stop_pos: f64, datapoints: u32) -> CoverageBins {
+        let exact_offset: f64 = (stop_pos - start_pos) / datapoints as f64;
+        let mut bin_limits = Vec::<f64>::new();
+        let mut current_pos: f64 = start_pos;
+        for _i in 0..datapoints {
+            bin_limits.push(current_pos);
+            current_pos += exact_offset;
+        }
+        bin_limits.push(stop_pos);
+        CoverageBins {
+            bin_limits,
+            bin_width: exact_offset,
+            bases: vec![0.0; datapoints as usize],
+            forward_bases: vec![0.0; datapoints as usize],
+            reverse_bases: vec![0.0; datapoints as usize],
+        }
+    }
+
+    // Adds the aligned bases in block_start-block_stop to each bin it overlaps
+    fn add_block(&mut self, block_start: f64, block_stop: f64, is_reverse: bool) {
+        let num_bins = self.bases.len();
+        // First bin that can overlap the block, computed from the bin width instead of searching through the bins
+        let mut j: usize = if block_start <= self.bin_limits[0] {
+            0
+        } else {
+            std::cmp::min(
+                ((block_start - self.bin_limits[0]) / self.bin_width) as usize,
+                num_bins - 1,
+            )
+        };
+        while j > 0 && self.bin_limits[j] > block_start {
+            // Guard against rounding of the bin limits
+            j -= 1;
+        }
+        while j < num_bins && self.bin_limits[j] < block_stop {
+            let overlap =
+                block_stop.min(self.bin_limits[j + 1]) - block_start.max(self.bin_limits[j]);
+            if overlap > 0.0 {
+                self.bases[j] += overlap;
+                if is_reverse {
+                    self.reverse_bases[j] += overlap;
+                } else {
+                    self.forward_bases[j] += overlap;
+                }
+            }
+            j += 1;
+        }
+    }
+
+    // Mean depth of each bin
+    fn mean_depth(&self, bases: &[f64]) -> Vec<f64> {
+        bases
+            .iter()
+            .enumerate()
+            .map(|(i, bases)| bases / (self.bin_limits[i + 1] - self.bin_limits[i]))
+            .collect()
+    }
+}
+
+fn main() {
+    let mut input = String::new();
+    match io::stdin().read_line(&mut input) {
+        // Accepting the piped input from nodejs (or command line from testing)
+        Ok(_) => {}
+        Err(error) => println!("Piping error: {}", error),
+    }
+    let response = match parse_request(&input) {
+        Ok(request) => compute_coverage(&request),
+        Err(error) => Err(error),
+    };
+    match response {
+        Ok(summary) => println!("{}", serde_json::to_string(&summary).unwrap()),
+        Err(error) => println!(
+            "{}",
+            serde_json::to_string(&ErrorResponse { error }).unwrap()
+        ),
+    }
+}
+
+fn invalid_input(message: &str) -> CoverageError {
+    CoverageError {
+        kind: "invalid_input".to_string(),
+        message: message.to_string(),
+    }
+}
+
+// Parses and validates the JSON request
+fn parse_request(input: &str) -> Result<CoverageRequest, CoverageError> {
+    let json_string: JsonValue = match json::parse(input) {
+        Ok(json_string) => json_string,
+        Err(error) => return Err(invalid_input(&format!("Incorrect json: {}", error))),
+    };
+    let bam_file: String = match json_string["bam_file"].as_str() {
+        Some(bam_file) => bam_file.to_string(),
+        None => return Err(invalid_input("bam_file is missing")),
+    };
+    let chrom: String = match json_string["chr"].as_str() {
+        Some(chrom) => chrom.to_string(),
+        None => return Err(invalid_input("chr is missing")),
+    };
+    let (start_pos, stop_pos): (u64, u64) =
+        match (json_string["start"].as_u64(), json_string["stop"].as_u64()) {
+            (Some(start_pos), Some(stop_pos)) if start_pos < stop_pos => (start_pos, stop_pos),
+            _ => {
+                return Err(invalid_input(
+                    "start and stop must be integers with 0 <= start < stop",
+                ))
+            }
+        };
+    let datapoints: u32 = match json_string["datapoints"].as_u32() {
+        Some(datapoints) if datapoints > 0 => datapoints,
+        _ => return Err(invalid_input("datapoints must be a positive integer")),
+    };
+    let min_mapq: u8 = match &json_string["min_mapq"] {
+        JsonValue::Null => 0,
+        min_mapq => match min_mapq.as_u8() {
+            Some(min_mapq) => min_mapq,
+            None => {
+                return Err(invalid_input(
+                    "min_mapq must be an integer between 0 and 255",
+                ))
+            }
+        },
+    };
+    let exclude_flags: u16 = match &json_string["exclude_flags"] {
+        JsonValue::Null => 0x4 | 0x100 | 0x200 | DUPLICATE_FLAG, // Unmapped, secondary, QC failed and duplicate
+        exclude_flags => match exclude_flags.as_u16() {
+            Some(exclude_flags) => exclude_flags,
+            None => return Err(invalid_input("exclude_flags must be a SAM flag")),
+        },
+    };
+    let exclude_flags: u16 = if json_string["exclude_duplicates"].as_bool() == Some(false) {
+        exclude_flags & !DUPLICATE_FLAG
+    } else {
+        exclude_flags | DUPLICATE_FLAG
+    };
+    let include_flags: u16 = match &json_string["include_flags"] {
+        JsonValue::Null => 0,
+        include_flags => match include_flags.as_u16() {
+            Some(include_flags) => include_flags,
+            None => return Err(invalid_input("include_flags must be a SAM flag")),
+        },
+    };
+    Ok(CoverageRequest {
+        bam_file,
+        fasta_file: json_string["fasta_file"].as_str().map(|x| x.to_string()),
+        chrom,
+        start_pos,
+        stop_pos,
+        datapoints,
+        min_mapq,
+        exclude_flags,
+        include_flags,
+        strand_specific: json_string["strand_specific"].as_bool().unwrap_or(false),
+        count_deletions: json_string["count_deletions"].as_bool().unwrap_or(false),
+    })
+}
+
+// Fetches the reads overlapping the region and adds the aligned blocks of the reads passing the filters to the bins
+fn compute_coverage(request: &CoverageRequest) -> Result<CoverageSummary, CoverageError> {
+    let cannot_read = |message: String| CoverageError {
+        kind: "cannot_read_file".to_string(),
+        message,
+    };
+    let query_failed = |message: String| CoverageError {
+        kind: "query_failed".to_string(),
+        message,
+    };
+    let mut reader = match bam::IndexedReader::from_path(&request.bam_file) {
+        Ok(reader) => reader,
+        Err(error) => {
+            return Err(cannot_read(format!(
+                "Cannot open {}: {}",
+                request.bam_file, error
+            )))
+        }
+    };
+    if let Some(fasta) = &request.fasta_file {
+        // Setting the reference is needed for CRAM files, it is harmless for BAM files
+        if let Err(error) = reader.set_reference(fasta) {
+            return Err(cannot_read(format!(
+                "Cannot set reference {}: {}",
+                fasta, error
+            )));
+        }
+    }
+    if let Err(error) = reader.fetch((
+        request.chrom.as_str(),
+        request.start_pos as i64,
+        request.stop_pos as i64,
+    )) {
+        return Err(query_failed(format!(
+            "Cannot fetch {}:{}-{}: {}",
+            request.chrom, request.start_pos, request.stop_pos, error
+        )));
+    }
+
+    let mut bins = CoverageBins::new(
+        request.start_pos as f64,
+        request.stop_pos as f64,
+        request.datapoints,
+    );
+    let mut reads: u64 = 0;
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(error) => return Err(query_failed(format!("Cannot parse record: {}", error))),
+        };
+        let flag = record.flags();
+        if flag & request.exclude_flags != 0
+            || flag & request.include_flags != request.include_flags
+            || record.mapq() < request.min_mapq
+        {
+            continue;
+        }
+        let mut overlaps_region = false;
+        for (block_start, block_stop) in aligned_blocks(&record, request.count_deletions) {
+            let block_start = block_start.max(request.start_pos as i64);
+            let block_stop = block_stop.min(request.stop_pos as i64);
+            if block_start < block_stop {
+                overlaps_region = true;
+                bins.add_block(block_start as f64, block_stop as f64, record.is_reverse());
+            }
+        }
+        if overlaps_region {
+            reads += 1;
+        }
+    }
+
+    Ok(CoverageSummary {
+        chr: request.chrom.to_string(),
+        start: request.start_pos,
+        stop: request.stop_pos,
+        datapoints: request.datapoints,
+        reads,
+        mean: bins.mean_depth(&bins.bases),
+        forward: if request.strand_specific {
+            Some(bins.mean_depth(&bins.forward_bases))
+        } else {
+            None
+        },
+        reverse: if request.strand_specific {
+            Some(bins.mean_depth(&bins.reverse_bases))
+        } else {
+            None
+        },
+    })
+}
+
+// Reference intervals (0-based, stop not inclusive) covered by the aligned bases of a read. Adjacent intervals are merged
+fn aligned_blocks(record: &bam::Record, count_deletions: bool) -> Vec<(i64, i64)> {
+    let mut blocks = Vec::<(i64, i64)>::new();
+    let mut ref_pos: i64 = record.pos();
+    for op in record.cigar().iter() {
+        let (length, is_covered) = match op {
+            Cigar::Match(length) | Cigar::Equal(length) | Cigar::Diff(length) => (*length, true),
+            Cigar::Del(length) => (*length, count_deletions),
+            Cigar::RefSkip(length) => (*length, false),
+            _ => continue, // Insertions, clips and padding do not consume the reference
+        };
+        let next_pos = ref_pos + length as i64;
+        if is_covered {
+            match blocks.last_mut() {
+                Some(block) if block.1 == ref_pos => block.1 = next_pos,
+                _ => blocks.push((ref_pos, next_pos)),
+            }
+        }
+        ref_pos = next_pos;
+    }
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_block() {
+        // Same bin limits as BinAccumulator::new() in bigwig.rs, so that the bins of a BAM track line up with those of a bigWig track of the same region
+        let expected_bin_limits: [(f64, f64, u32, Vec<f64>); 3] = [
+            (0.0, 100.0, 4, vec![0.0, 25.0, 50.0, 75.0, 100.0]),
+            (
+                1000.0,
+                1100.0,
+                3,
+                vec![1000.0, 1033.333333, 1066.666667, 1100.0],
+            ),
+            (
+                5.0,
+                12.0,
+                7,
+                vec![5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 11.0, 12.0],
+            ),
+        ];
+        for (start_pos, stop_pos, datapoints, bin_limits) in expected_bin_limits.iter() {
+            let bins = CoverageBins::new(*start_pos, *stop_pos, *datapoints);
+            assert_eq!(bins.bin_limits.len(), bin_limits.len());
+            for (bin_limit, expected) in bins.bin_limits.iter().zip(bin_limits) {
+                assert!((bin_limit - expected).abs() < 1e-6);
+            }
+            assert_eq!(bins.bin_limits.last(), Some(stop_pos)); // The last limit is exactly stop_pos
+        }
+
+        // Bins 1000-1025, 1025-1050, 1050-1075, 1075-1100
+        let mut bins = CoverageBins::new(1000.0, 1100.0, 4);
+        bins.add_block(990.0, 1010.0, false); // Starts before the first bin
+        bins.add_block(1020.0, 1060.0, true); // Overlaps three bins
+        bins.add_block(1075.0, 1100.0, false); // Exactly the last bin
+        bins.add_block(1100.0, 1150.0, true); // After the last bin
+        assert_eq!(bins.bases, vec![15.0, 25.0, 10.0, 25.0]);
+        assert_eq!(bins.forward_bases, vec![10.0, 0.0, 0.0, 25.0]);
+        assert_eq!(bins.reverse_bases, vec![5.0, 25.0, 10.0, 0.0]);
+        assert_eq!(bins.mean_depth(&bins.bases), vec![0.6, 1.0, 0.4, 1.0]);
+
+        // Bin width that is not a whole number of bases
+        let mut bins = CoverageBins::new(1000.0, 1100.0, 3);
+        bins.add_block(1000.0, 1100.0, false);
+        let total: f64 = bins.bases.iter().sum();
+        assert!((total - 100.0).abs() < 1e-9);
+        for depth in bins.mean_depth(&bins.bases) {
+            assert!((depth - 1.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_parse_request() {
+        let request = |flags: &str| {
+            parse_request(&format!(
+                "{{\"bam_file\":\"file.bam\",\"chr\":\"chr1\",\"start\":0,\"stop\":100,\"datapoints\":10{}}}",
+                flags
+            ))
+            .unwrap()
+        };
+        // Unmapped, secondary, QC failed and duplicate by default
+        assert_eq!(request("").exclude_flags, 1796);
+        // The duplicate flag is added to custom exclude_flags unless exclude_duplicates is false
+        assert_eq!(request(",\"exclude_flags\":4").exclude_flags, 4 | 1024);
+        assert_eq!(
+            request(",\"exclude_flags\":4,\"exclude_duplicates\":true").exclude_flags,
+            4 | 1024
+        );
+        assert_eq!(
+            request(",\"exclude_flags\":1028,\"exclude_duplicates\":false").exclude_flags,
+            4
+        );
+        assert_eq!(
+            request(",\"exclude_duplicates\":false").exclude_flags,
+            1796 & !1024
+        );
+    }
+}
@@ -0,0 +1,1170 @@
+/*
+ Syntax:
+ Region query (same output as bigBedToBed -chrom=chr17 -start=7568451 -end=7591984 file.bb stdout):
+ local: cd .. && cargo build --release && time echo '{"file":"/Users/rpaul1/proteinpaint/hg19/anno/file.bb","query":"region","chr":"chr17","start":7568451,"stop":7591984}' | target/release/bigbed
+
+ Region query with the fields of each item parsed using the autoSql definition in the file:
+ local: cd .. && cargo build --release && time echo '{"file":"/Users/rpaul1/proteinpaint/hg19/anno/file.bb","query":"region","chr":"chr17","start":7568451,"stop":7591984,"parse_fields":true}' | target/release/bigbed
+
+ Header and zoom info (same information as bigBedInfo -chroms -zooms -as file.bb):
+ url: cd .. && cargo build --release && time echo '{"file":"https://proteinpaint.stjude.org/ppdemo/hg19/anno/file.bb","query":"info"}' | target/release/bigbed
+
+ Name index lookup (same output as bigBedNamedItems file.bb TP53 stdout):
+ local: cd .. && cargo build --release && time echo '{"file":"/Users/rpaul1/proteinpaint/hg19/anno/file.bb","query":"name","name":"TP53"}' | target/release/bigbed
+*/
+
+/*
+Notes:
+   The script accepts a JSON request { file, query, ... } where file is a local path or a web URL (read through RemoteFile in the same way as in bigwig.rs) and query is one of:
+
+   1) "region" { chr, start, stop, parse_fields }: Items overlapping chr:start-stop (0-based, stop not inclusive). The response is { items } where each item is a bed line "chr\tstart\tend\trest" (same as bigBedToBed). When parse_fields is true, each item is instead a JSON object with one key per field of the autoSql definition in the file (the standard bed field names are used when the file has no autoSql). Numeric fields are converted to numbers and comma-separated list fields (e.g. int[blockCount]) to arrays
+
+   2) "info": Header information { version, item_count, field_count, defined_field_count, uncompress_buf_size, zoom_levels, chroms, total_summary, autosql, fields, extra_indices } (same information as bigBedInfo), where extra_indices are the fields with a name index
+
+   3) "name" { name, field, parse_fields }: Items whose field (default "name") is equal to name, looked up through the extra (B+ tree) index of the field created by bedToBigBed -extraIndex (same as bigBedNamedItems). The response is { items } in the same format as the region query
+
+   Errors are returned as { error: { kind, message } } where kind is "invalid_input", "cannot_read_file" or "query_failed"
+
+   Region queries, the chromosome list and the zoom levels are read through bigtools. The header, autoSql, total summary and extra indices are not exposed by bigtools and are read directly from the file following the bigBed specification (Kent et al. 2010, https://genome.ucsc.edu/goldenPath/help/bigBed.html)
+
+   Function cascade:
+
+   main() - Parses the JSON request.
+     run_request() - Opens the web url/local file, returns a "cannot_read_file" error if it cannot be read.
+       query_bigbed() - Runs the query.
+         read_header() - Reads the fixed size header at the start of the file.
+         read_autosql() - Reads and parses (parse_autosql()) the autoSql definition.
+         query_region() / query_info() / query_name()
+           query_name():
+             read_extra_indices() - Finds the B+ tree index of the field.
+             search_bpt_node() - Recursively searches the B+ tree for the data blocks containing the name.
+             read_block_items() - Decompresses each data block and keeps the items matching the name.
+*/
+
+use bigtools::bigbedread::BigBedRead;
+use bigtools::utils::file::remote_file::RemoteFile;
+use bigtools::utils::file::seekableread::{Reopen, SeekableRead};
+use flate2::read::ZlibDecoder;
+use json::JsonValue;
+use serde::{Deserialize, Serialize};
+use std::convert::TryInto;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+
+const BIGBED_MAGIC: u32 = 0x8789F2EB; // Magic number at the start of a bigBed file
+const BPT_MAGIC: u32 = 0x78CA8C91; // Magic number at the start of a B+ tree index
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BigbedError {
+    kind: String, // "invalid_input", "cannot_read_file" or "query_failed"
+    message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ErrorResponse {
+    error: BigbedError,
+}
+
+// Parsed JSON request
+#[derive(Debug)]
+struct BigbedRequest {
+    file: String,
+    query: String,      // "region", "info" or "name"
+    chrom: String,      // Chromosome name (region query)
+    start_pos: u32,     // Start position (region query)
+    stop_pos: u32,      // Stop position (region query)
+    name: String,       // Name to be looked up (name query)
+    field: String,      // Field of the name index (name query)
+    parse_fields: bool, // Return items as JSON objects instead of bed lines
+}
+
+// Fixed size header at the start of the bigBed file
+#[derive(Debug)]
+struct BigbedHeader {
+    big_endian: bool,
+    version: u16,
+    full_data_offset: u64,
+    field_count: u16,
+    defined_field_count: u16,
+    auto_sql_offset: u64,
+    total_summary_offset: u64,
+    uncompress_buf_size: u32, // Size of the buffer needed to uncompress a data block, 0 when the data is not compressed
+    extension_offset: u64,
+}
+
+// Field of the autoSql definition
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AutoSqlField {
+    name: String,
+    #[serde(rename = "type")]
+    field_type: String, // autoSql type e.g uint, string, int[blockCount]
+    comment: String,
+}
+
+// Extra index created by bedToBigBed -extraIndex
+#[derive(Debug)]
+struct ExtraIndex {
+    field_id: usize,  // Index of the field in the bed line
+    file_offset: u64, // Offset of the B+ tree
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ItemsResponse {
+    items: Vec<serde_json::Value>, // Bed lines or JSON objects (parse_fields)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ZoomLevel {
+    reduction_level: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Chrom {
+    name: String,
+    length: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TotalSummary {
+    bases_covered: u64,
+    min: f64,
+    max: f64,
+    mean: f64,
+    std: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct InfoResponse {
+    version: u16,
+    item_count: u64,
+    field_count: u16,
+    defined_field_count: u16,
+    uncompress_buf_size: u32,
+    zoom_levels: Vec<ZoomLevel>,
+    chroms: Vec<Chrom>,
+    total_summary: Option<TotalSummary>,
+    autosql: Option<String>,
+    fields: Vec<AutoSqlField>,
+    extra_indices: Vec<String>, // Names of the fields with a name index
+}
+
+fn main() {
+    let mut input = String::new();
+    match io::stdin().read_line(&mut input) {
+        // Accepting the piped input from nodejs (or command line from testing)
+        Ok(_) => {}
+        Err(error) => println!("Piping error: {}", error),
+    }
+    let response = match parse_request(&input) {
+        Ok(request) => run_request(&request),
+        Err(error) => Err(error),
+    };
+    match response {
+        Ok(response) => println!("{}", response),
+        Err(error) => println!(
+            "{}",
+            serde_json::to_string(&ErrorResponse { error }).unwrap()
+        ),
+    }
+}
+
+fn invalid_input(message: &str) -> BigbedError {
+    BigbedError {
+        kind: "invalid_input".to_string(),
+        message: message.to_string(),
+    }
+}
+
+fn cannot_read(message: String) -> BigbedError {
+    BigbedError {
+        kind: "cannot_read_file".to_string(),
+        message: format!("Cannot read bigBed file: {}", message),
+    }
+}
+
+fn query_failed(message: String) -> BigbedError {
+    BigbedError {
+        kind: "query_failed".to_string(),
+        message,
+    }
+}
+
+// Parses and validates the JSON request
+fn parse_request(input: &str) -> Result<BigbedRequest, BigbedError> {
+    let json_string: JsonValue = match json::parse(input) {
+        Ok(json_string) => json_string,
+        Err(error) => return Err(invalid_input(&format!("Incorrect json: {}", error))),
+    };
+    let file: String = match json_string["file"].as_str() {
+        Some(file) => file.to_string(),
+        None => return Err(invalid_input("file is missing")),
+    };
+    let query: String = match json_string["query"].as_str() {
+        Some(query @ ("region" | "info" | "name")) => query.to_string(),
+        _ => return Err(invalid_input("query must be region, info or name")),
+    };
+    let mut request = BigbedRequest {
+        file,
+        query,
+        chrom: String::new(),
+        start_pos: 0,
+        stop_pos: 0,
+        name: String::new(),
+        field: "name".to_string(),
+        parse_fields: json_string["parse_fields"].as_bool().unwrap_or(false),
+    };
+    if request.query == "region" {
+        request.chrom = match json_string["chr"].as_str() {
+            Some(chrom) => chrom.to_string(),
+            None => return Err(invalid_input("chr is missing")),
+        };
+        match (json_string["start"].as_u32(), json_string["stop"].as_u32()) {
+            (Some(start_pos), Some(stop_pos)) if start_pos < stop_pos => {
+                request.start_pos = start_pos;
+                request.stop_pos = stop_pos;
+            }
+            _ => {
+                return Err(invalid_input(
+                    "start and stop must be integers with 0 <= start < stop",
+                ))
+            }
+        }
+    } else if request.query == "name" {
+        request.name = match json_string["name"].as_str() {
+            Some(name) if !name.is_empty() => name.to_string(),
+            _ => return Err(invalid_input("name is missing")),
+        };
+        if let Some(field) = json_string["field"].as_str() {
+            request.field = field.to_string();
+        }
+    }
+    Ok(request)
+}
+
+// Opens the bigBed file from a web URL or a local path and runs the query. The file is opened twice, once through bigtools and once for reading the parts of the file not exposed by bigtools
+fn run_request(request: &BigbedRequest) -> Result<String, BigbedError> {
+    if request.file.starts_with("http://")
+        || request.file.starts_with("https://")
+        || request.file.starts_with("www.")
+    {
+        // Its a web URL
+        let reader = match BigBedRead::from(RemoteFile::new(&request.file)) {
+            Ok(reader) => reader,
+            Err(error) => return Err(cannot_read(error.to_string())),
+        };
+        let raw_file = match RemoteFile::new(&request.file).reopen() {
+            Ok(raw_file) => raw_file,
+            Err(error) => return Err(cannot_read(error.to_string())),
+        };
+        query_bigbed(reader, raw_file, request)
+    } else {
+        // Its a local file
+        let reader = match BigBedRead::from_file_and_attach(&request.file) {
+            Ok(reader) => reader,
+            Err(error) => return Err(cannot_read(error.to_string())),
+        };
+        let raw_file = match File::open(&request.file) {
+            Ok(raw_file) => raw_file,
+            Err(error) => return Err(cannot_read(error.to_string())),
+        };
+        query_bigbed(reader, raw_file, request)
+    }
+}
+
+fn query_bigbed<R: Reopen<S>, S: SeekableRead, T: Read + Seek>(
+    mut reader: BigBedRead<R, S>,
+    mut raw_file: T,
+    request: &BigbedRequest,
+) -> Result<String, BigbedError> {
+    let header = read_header(&mut raw_file)?;
+    let autosql = read_autosql(&mut raw_file, &header)?;
+    let fields = match &autosql {
+        Some(autosql) => parse_autosql(autosql),
+        None => default_fields(header.field_count as usize),
+    };
+    let response = if request.query == "region" {
+        serde_json::to_string(&query_region(&mut reader, &fields, request)?)
+    } else if request.query == "info" {
+        serde_json::to_string(&query_info(
+            &mut reader,
+            &mut raw_file,
+            &header,
+            autosql,
+            fields,
+        )?)
+    } else {
+        serde_json::to_string(&query_name(
+            &mut reader,
+            &mut raw_file,
+            &header,
+            &fields,
+            request,
+        )?)
+    };
+    Ok(response.unwrap())
+}
+
+// Items overlapping chr:start-stop
+fn query_region<R: Reopen<S>, S: SeekableRead>(
+    reader: &mut BigBedRead<R, S>,
+    fields: &[AutoSqlField],
+    request: &BigbedRequest,
+) -> Result<ItemsResponse, BigbedError> {
+    let mut items = Vec::<serde_json::Value>::new();
+    let entries = match reader.get_interval(&request.chrom, request.start_pos, request.stop_pos) {
+        Ok(entries) => entries,
+        Err(error) => return Err(query_failed(error.to_string())),
+    };
+    for entry in entries {
+        match entry {
+            Ok(entry) => items.push(format_item(
+                fields,
+                &request.chrom,
+                entry.start,
+                entry.end,
+                &entry.rest,
+                request.parse_fields,
+            )),
+            Err(error) => return Err(query_failed(error.to_string())),
+        }
+    }
+    Ok(ItemsResponse { items })
+}
+
+// Header, zoom levels, chromosomes, total summary and autoSql of the file
+fn query_info<R: Reopen<S>, S: SeekableRead, T: Read + Seek>(
+    reader: &mut BigBedRead<R, S>,
+    raw_file: &mut T,
+    header: &BigbedHeader,
+    autosql: Option<String>,
+    fields: Vec<AutoSqlField>,
+) -> Result<InfoResponse, BigbedError> {
+    let item_count = to_u64(
+        &read_bytes(raw_file, header.full_data_offset, 8)?,
+        header.big_endian,
+    ); // The data section starts with the number of items
+    let total_summary = if header.total_summary_offset == 0 {
+        None
+    } else {
+        // validCount (u64), minVal, maxVal, sumData, sumSquares (f64)
+        let bytes = read_bytes(raw_file, header.total_summary_offset, 40)?;
+        let bases_covered = to_u64(&bytes[0..8], header.big_endian);
+        let sum = to_f64(&bytes[24..32], header.big_endian);
+        let sum_squares = to_f64(&bytes[32..40], header.big_endian);
+        let n = bases_covered as f64;
+        Some(TotalSummary {
+            bases_covered,
+            min: to_f64(&bytes[8..16], header.big_endian),
+            max: to_f64(&bytes[16..24], header.big_endian),
+            mean: if bases_covered > 0 { sum / n } else { 0.0 },
+            std: if bases_covered > 1 {
+                ((sum_squares - sum * sum / n) / (n - 1.0)).max(0.0).sqrt()
+            } else {
+                0.0
+            },
+        })
+    };
+    let extra_indices: Vec<String> = read_extra_indices(raw_file, header)?
+        .iter()
+        .map(|index| field_name(&fields, index.field_id))
+        .collect();
+    Ok(InfoResponse {
+        version: header.version,
+        item_count,
+        field_count: header.field_count,
+        defined_field_count: header.defined_field_count,
+        uncompress_buf_size: header.uncompress_buf_size,
+        zoom_levels: reader
+            .info
+            .zoom_headers
+            .iter()
+            .map(|zoom_header| ZoomLevel {
+                reduction_level: zoom_header.reduction_level,
+            })
+            .collect(),
+        chroms: reader
+            .get_chroms()
+            .into_iter()
+            .map(|chrom| Chrom {
+                name: chrom.name,
+                length: chrom.length,
+            })
+            .collect(),
+        total_summary,
+        autosql,
+        fields,
+        extra_indices,
+    })
+}
+
+// Items whose field is equal to name, using the extra index of the field
+fn query_name<R: Reopen<S>, S: SeekableRead, T: Read + Seek>(
+    reader: &mut BigBedRead<R, S>,
+    raw_file: &mut T,
+    header: &BigbedHeader,
+    fields: &[AutoSqlField],
+    request: &BigbedRequest,
+) -> Result<ItemsResponse, BigbedError> {
+    let index = match read_extra_indices(raw_file, header)?
+        .into_iter()
+        .find(|index| field_name(fields, index.field_id) == request.field)
+    {
+        Some(index) => index,
+        None => {
+            return Err(query_failed(format!(
+                "File has no name index on field {}",
+                request.field
+            )))
+        }
+    };
+
+    // B+ tree header: magic (u32), blockSize (u32), keySize (u32), valSize (u32), itemCount (u64), reserved (8 bytes)
+    let bytes = read_bytes(raw_file, index.file_offset, 32)?;
+    let big_endian = header.big_endian;
+    if to_u32(&bytes[0..4], big_endian) != BPT_MAGIC {
+        return Err(query_failed("Name index is corrupted".to_string()));
+    }
+    let key_size = to_u32(&bytes[8..12], big_endian) as usize;
+    let val_size = to_u32(&bytes[12..16], big_endian) as usize;
+    let mut blocks = Vec::<(u64, u64)>::new(); // Offset and size of the data blocks containing the name
+    if request.name.len() <= key_size {
+        // Keys are padded with zeros to key_size, longer names cannot be in the index
+        let mut key = request.name.as_bytes().to_vec();
+        key.resize(key_size, 0);
+        search_bpt_node(
+            raw_file,
+            big_endian,
+            index.file_offset + 32,
+            key_size,
+            val_size,
+            &key,
+            &mut blocks,
+        )?;
+    }
+    blocks.sort();
+    blocks.dedup(); // Several items with the same name can be in the same block
+
+    let chroms = reader.get_chroms();
+    let mut items = Vec::<serde_json::Value>::new();
+    for (block_offset, block_size) in blocks {
+        for (chrom_id, start, end, rest) in read_block_items(
+            raw_file,
+            header,
+            block_offset,
+            block_size,
+            index.field_id,
+            &request.name,
+        )? {
+            let chrom = match chroms.iter().find(|chrom| chrom.id == chrom_id) {
+                Some(chrom) => chrom.name.to_string(),
+                None => return Err(query_failed(format!("Unknown chromosome id {}", chrom_id))),
+            };
+            items.push(format_item(
+                fields,
+                &chrom,
+                start,
+                end,
+                &rest,
+                request.parse_fields,
+            ));
+        }
+    }
+    Ok(ItemsResponse { items })
+}
+
+// Collects the data blocks of all leaf items whose key is equal to key. Since several items can have the same key, every child whose key range can contain the key is searched
+fn search_bpt_node<T: Read + Seek>(
+    raw_file: &mut T,
+    big_endian: bool,
+    node_offset: u64,
+    key_size: usize,
+    val_size: usize,
+    key: &[u8],
+    blocks: &mut Vec<(u64, u64)>,
+) -> Result<(), BigbedError> {
+    // Node header: isLeaf (u8), reserved (u8), childCount (u16)
+    let node_header = read_bytes(raw_file, node_offset, 4)?;
+    let is_leaf = node_header[0] == 1;
+    let child_count = to_u16(&node_header[2..4], big_endian) as usize;
+    let item_size = key_size + if is_leaf { val_size } else { 8 };
+    let node = read_bytes(raw_file, node_offset + 4, child_count * item_size)?;
+    let items: Vec<&[u8]> = node.chunks(item_size).collect();
+    if is_leaf {
+        for item in items {
+            if &item[0..key_size] == key {
+                // Value is the offset (u64) and size (u64) of the data block
+                blocks.push((
+                    to_u64(&item[key_size..key_size + 8], big_endian),
+                    to_u64(&item[key_size + 8..key_size + 16], big_endian),
+                ));
+            }
+        }
+    } else {
+        for i in 0..items.len() {
+            // Child i contains the keys from its own key up to the key of the next child
+            let child_key = &items[i][0..key_size];
+            let next_key_below = i + 1 == items.len() || &items[i + 1][0..key_size] >= key;
+            if child_key <= key && next_key_below {
+                let child_offset = to_u64(&items[i][key_size..key_size + 8], big_endian);
+                search_bpt_node(
+                    raw_file,
+                    big_endian,
+                    child_offset,
+                    key_size,
+                    val_size,
+                    key,
+                    blocks,
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Decompresses a data block and returns the items (chrom id, start, end, rest) whose field field_id is equal to name
+fn read_block_items<T: Read + Seek>(
+    raw_file: &mut T,
+    header: &BigbedHeader,
+    block_offset: u64,
+    block_size: u64,
+    field_id: usize,
+    name: &String,
+) -> Result<Vec<(u32, u32, u32, String)>, BigbedError> {
+    let mut block = read_bytes(raw_file, block_offset, block_size as usize)?;
+    if header.uncompress_buf_size > 0 {
+        let mut uncompressed = Vec::<u8>::new();
+        if let Err(error) = ZlibDecoder::new(block.as_slice()).read_to_end(&mut uncompressed) {
+            return Err(query_failed(format!(
+                "Cannot uncompress data block: {}",
+                error
+            )));
+        }
+        block = uncompressed;
+    }
+    let mut items = Vec::<(u32, u32, u32, String)>::new();
+    let mut pos: usize = 0;
+    while pos + 12 <= block.len() {
+        // Each item is chromId (u32), start (u32), end (u32) and the rest of the bed line as a null-terminated string
+        let chrom_id = to_u32(&block[pos..pos + 4], header.big_endian);
+        let start = to_u32(&block[pos + 4..pos + 8], header.big_endian);
+        let end = to_u32(&block[pos + 8..pos + 12], header.big_endian);
+        let rest_length = block[pos + 12..]
+            .iter()
+            .position(|byte| *byte == 0)
+            .unwrap_or(block.len() - pos - 12);
+        let rest = String::from_utf8_lossy(&block[pos + 12..pos + 12 + rest_length]).to_string();
+        pos += 12 + rest_length + 1;
+        let field_value = if field_id >= 3 {
+            rest.split('\t').nth(field_id - 3).unwrap_or("").to_string()
+        } else {
+            String::new()
+        };
+        if &field_value == name {
+            items.push((chrom_id, start, end, rest));
+        }
+    }
+    Ok(items)
+}
+
+// Reads the fixed size (64 bytes) header at the start of the file
+fn read_header<T: Read + Seek>(raw_file: &mut T) -> Result<BigbedHeader, BigbedError> {
+    let bytes = match read_bytes(raw_file, 0, 64) {
+        Ok(bytes) => bytes,
+        Err(error) => return Err(cannot_read(error.message)),
+    };
+    let big_endian = if to_u32(&bytes[0..4], false) == BIGBED_MAGIC {
+        false
+    } else if to_u32(&bytes[0..4], true) == BIGBED_MAGIC {
+        true
+    } else {
+        return Err(cannot_read("File is not a bigBed file".to_string()));
+    };
+    // magic (u32), version (u16), zoomLevels (u16), chromTreeOffset (u64), fullDataOffset (u64), fullIndexOffset (u64), fieldCount (u16), definedFieldCount (u16), autoSqlOffset (u64), totalSummaryOffset (u64), uncompressBufSize (u32), extensionOffset (u64)
+    Ok(BigbedHeader {
+        big_endian,
+        version: to_u16(&bytes[4..6], big_endian),
+        full_data_offset: to_u64(&bytes[16..24], big_endian),
+        field_count: to_u16(&bytes[32..34], big_endian),
+        defined_field_count: to_u16(&bytes[34..36], big_endian),
+        auto_sql_offset: to_u64(&bytes[36..44], big_endian),
+        total_summary_offset: to_u64(&bytes[44..52], big_endian),
+        uncompress_buf_size: to_u32(&bytes[52..56], big_endian),
+        extension_offset: to_u64(&bytes[56..64], big_endian),
+    })
+}
+
+// Reads the null-terminated autoSql definition, None when the file has no autoSql
+fn read_autosql<T: Read + Seek>(
+    raw_file: &mut T,
+    header: &BigbedHeader,
+) -> Result<Option<String>, BigbedError> {
+    if header.auto_sql_offset == 0 {
+        return Ok(None);
+    }
+    let mut autosql = Vec::<u8>::new();
+    if let Err(error) = raw_file.seek(SeekFrom::Start(header.auto_sql_offset)) {
+        return Err(cannot_read(error.to_string()));
+    }
+    let mut buffer = [0u8; 1024];
+    loop {
+        let n = match raw_file.read(&mut buffer) {
+            Ok(n) => n,
+            Err(error) => return Err(cannot_read(error.to_string())),
+        };
+        match buffer[0..n].iter().position(|byte| *byte == 0) {
+            Some(end) => {
+                autosql.extend_from_slice(&buffer[0..end]);
+                break;
+            }
+            None if n == 0 => break,
+            None => autosql.extend_from_slice(&buffer[0..n]),
+        }
+    }
+    Ok(Some(String::from_utf8_lossy(&autosql).to_string()))
+}
+
+// Reads the list of extra indices from the extension header, empty when the file has no extra index
+fn read_extra_indices<T: Read + Seek>(
+    raw_file: &mut T,
+    header: &BigbedHeader,
+) -> Result<Vec<ExtraIndex>, BigbedError> {
+    let mut indices = Vec::<ExtraIndex>::new();
+    if header.extension_offset == 0 {
+        return Ok(indices);
+    }
+    // Extension header: extensionSize (u16), extraIndexCount (u16), extraIndexListOffset (u64)
+    let bytes = read_bytes(raw_file, header.extension_offset, 12)?;
+    let index_count = to_u16(&bytes[2..4], header.big_endian) as u64;
+    let list_offset = to_u64(&bytes[4..12], header.big_endian);
+    for i in 0..index_count {
+        // Each entry: type (u16), fieldCount (u16), fileOffset (u64), reserved (4 bytes), fieldId (u16), reserved (u16)
+        let bytes = read_bytes(raw_file, list_offset + i * 20, 20)?;
+        indices.push(ExtraIndex {
+            field_id: to_u16(&bytes[16..18], header.big_endian) as usize,
+            file_offset: to_u64(&bytes[4..12], header.big_endian),
+        });
+    }
+    Ok(indices)
+}
+
+// Parses the fields of an autoSql definition e.g
+// table bed6 "Browser extensible data"
+//    (
+//    string chrom;      "Reference sequence chromosome or scaffold"
+//    uint   chromStart; "Start position in chromosome"
+//    ...
+//    )
+fn parse_autosql(autosql: &str) -> Vec<AutoSqlField> {
+    let mut fields = Vec::<AutoSqlField>::new();
+    let body = match (autosql.find('('), autosql.rfind(')')) {
+        (Some(start), Some(end)) if start < end => &autosql[start + 1..end],
+        _ => return fields,
+    };
+    for line in body.lines() {
+        let (declaration, comment) = match line.find(';') {
+            Some(end) => (&line[0..end], line[end + 1..].trim()),
+            None => continue,
+        };
+        let words: Vec<&str> = declaration.split_whitespace().collect();
+        if words.len() < 2 {
+            continue;
+        }
+        fields.push(AutoSqlField {
+            name: words[words.len() - 1].to_string(),
+            field_type: words[0..words.len() - 1].join(" "),
+            comment: comment.trim_matches('"').to_string(),
+        });
+    }
+    fields
+}
+
+// Standard bed fields, used when the file has no autoSql definition
+fn default_fields(field_count: usize) -> Vec<AutoSqlField> {
+    let bed_fields = vec![
+        ("string", "chrom"),
+        ("uint", "chromStart"),
+        ("uint", "chromEnd"),
+        ("string", "name"),
+        ("uint", "score"),
+        ("char[1]", "strand"),
+        ("uint", "thickStart"),
+        ("uint", "thickEnd"),
+        ("uint", "reserved"),
+        ("int", "blockCount"),
+        ("int[blockCount]", "blockSizes"),
+        ("int[blockCount]", "chromStarts"),
+    ];
+    bed_fields
+        .into_iter()
+        .take(field_count)
+        .map(|(field_type, name)| AutoSqlField {
+            name: name.to_string(),
+            field_type: field_type.to_string(),
+            comment: String::new(),
+        })
+        .collect()
+}
+
+// Name of field field_id, extra fields not in the autoSql definition are named field{field_id}
+fn field_name(fields: &[AutoSqlField], field_id: usize) -> String {
+    match fields.get(field_id) {
+        Some(field) => field.name.to_string(),
+        None => format!("field{}", field_id),
+    }
+}
+
+// Formats an item either as a bed line or, when parse_fields is true, as a JSON object with one key per field
+fn format_item(
+    fields: &[AutoSqlField],
+    chrom: &str,
+    start: u32,
+    end: u32,
+    rest: &str,
+    parse_fields: bool,
+) -> serde_json::Value {
+    if !parse_fields {
+        let mut line = format!("{}\t{}\t{}", chrom, start, end);
+        if !rest.is_empty() {
+            line += &("\t".to_string() + rest);
+        }
+        return serde_json::Value::String(line);
+    }
+    let mut item = serde_json::Map::new();
+    let mut values: Vec<String> = vec![chrom.to_string(), start.to_string(), end.to_string()];
+    if !rest.is_empty() {
+        values.extend(rest.split('\t').map(|value| value.to_string()));
+    }
+    for (field_id, value) in values.iter().enumerate() {
+        let field_value = match fields.get(field_id) {
+            Some(field) => parse_field_value(&field.field_type, value),
+            None => serde_json::Value::String(value.to_string()),
+        };
+        item.insert(field_name(fields, field_id), field_value);
+    }
+    serde_json::Value::Object(item)
+}
+
+// Converts the value of a field into a JSON value according to its autoSql type. Numeric lists (e.g int[blockCount]) are comma-separated with an optional trailing comma
+fn parse_field_value(field_type: &str, value: &str) -> serde_json::Value {
+    let base_type: &str = field_type.split('[').next().unwrap().trim();
+    let is_list = field_type.contains('[') && base_type != "char";
+    let parse_number = |value: &str| -> serde_json::Value {
+        match base_type {
+            "int" | "uint" | "short" | "ushort" | "byte" | "ubyte" | "bigint" => {
+                match value.parse::<i64>() {
+                    Ok(number) => serde_json::Value::from(number),
+                    Err(_) => serde_json::Value::String(value.to_string()),
+                }
+            }
+            "float" | "double" => match value.parse::<f64>() {
+                Ok(number) if number.is_finite() => serde_json::Value::from(number),
+                _ => serde_json::Value::String(value.to_string()),
+            },
+            _ => serde_json::Value::String(value.to_string()),
+        }
+    };
+    if is_list {
+        serde_json::Value::Array(
+            value
+                .split(',')
+                .filter(|element| !element.is_empty())
+                .map(parse_number)
+                .collect(),
+        )
+    } else {
+        parse_number(value)
+    }
+}
+
+fn read_bytes<T: Read + Seek>(
+    raw_file: &mut T,
+    offset: u64,
+    length: usize,
+) -> Result<Vec<u8>, BigbedError> {
+    let mut bytes = vec![0u8; length];
+    if let Err(error) = raw_file.seek(SeekFrom::Start(offset)) {
+        return Err(query_failed(format!(
+            "Cannot seek to {}: {}",
+            offset, error
+        )));
+    }
+    if let Err(error) = raw_file.read_exact(&mut bytes) {
+        return Err(query_failed(format!(
+            "Cannot read {} bytes at {}: {}",
+            length, offset, error
+        )));
+    }
+    Ok(bytes)
+}
+
+fn to_u16(bytes: &[u8], big_endian: bool) -> u16 {
+    let bytes: [u8; 2] = bytes[0..2].try_into().unwrap();
+    if big_endian {
+        u16::from_be_bytes(bytes)
+    } else {
+        u16::from_le_bytes(bytes)
+    }
+}
+
+fn to_u32(bytes: &[u8], big_endian: bool) -> u32 {
+    let bytes: [u8; 4] = bytes[0..4].try_into().unwrap();
+    if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    }
+}
+
+fn to_u64(bytes: &[u8], big_endian: bool) -> u64 {
+    let bytes: [u8; 8] = bytes[0..8].try_into().unwrap();
+    if big_endian {
+        u64::from_be_bytes(bytes)
+    } else {
+        u64::from_le_bytes(bytes)
+    }
+}
+
+fn to_f64(bytes: &[u8], big_endian: bool) -> f64 {
+    f64::from_bits(to_u64(bytes, big_endian))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // B+ tree node with the given items (key followed by the child offset or the data block offset and size)
+    fn bpt_node(is_leaf: bool, items: &[(&str, Vec<u64>)], key_size: usize) -> Vec<u8> {
+        let mut node = vec![is_leaf as u8, 0];
+        node.extend_from_slice(&(items.len() as u16).to_le_bytes());
+        for (key, values) in items {
+            let mut key = key.as_bytes().to_vec();
+            key.resize(key_size, 0);
+            node.extend_from_slice(&key);
+            for value in values {
+                node.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        node
+    }
+
+    const AUTOSQL: &str = "table bed6plus \"Bed 6 with gene id\"\n(\nstring chrom;       \"Reference sequence chromosome or scaffold\"\nuint   chromStart;  \"Start position in chromosome\"\nuint   chromEnd;    \"End position in chromosome\"\nstring name;        \"Name of item\"\nuint   score;       \"Score from 0-1000\"\nchar[1] strand;     \"+ or -\"\nint[blockCount] geneIds; \"Gene ids\"\n)\n";
+
+    // Appends the size lowest bytes of value in the byte order of the file
+    fn push_number(bytes: &mut Vec<u8>, value: u64, size: usize, big_endian: bool) {
+        if big_endian {
+            bytes.extend_from_slice(&value.to_be_bytes()[8 - size..]);
+        } else {
+            bytes.extend_from_slice(&value.to_le_bytes()[0..size]);
+        }
+    }
+
+    // bigBed file with a header, autoSql, total summary and an extension header listing extra indices on the fields 3 (name) and 6 (geneIds). Only the parts read directly from the file are present, the data section is the item count
+    fn bigbed_fixture(big_endian: bool) -> Vec<u8> {
+        let auto_sql_offset: u64 = 64;
+        let total_summary_offset: u64 = auto_sql_offset + AUTOSQL.len() as u64 + 1;
+        let extension_offset: u64 = total_summary_offset + 40;
+        let index_list_offset: u64 = extension_offset + 64;
+        let full_data_offset: u64 = index_list_offset + 2 * 20;
+
+        let mut file = Vec::<u8>::new();
+        push_number(&mut file, BIGBED_MAGIC as u64, 4, big_endian);
+        push_number(&mut file, 4, 2, big_endian); // version
+        push_number(&mut file, 0, 2, big_endian); // zoomLevels
+        push_number(&mut file, 0, 8, big_endian); // chromTreeOffset
+        push_number(&mut file, full_data_offset, 8, big_endian);
+        push_number(&mut file, 0, 8, big_endian); // fullIndexOffset
+        push_number(&mut file, 7, 2, big_endian); // fieldCount
+        push_number(&mut file, 6, 2, big_endian); // definedFieldCount
+        push_number(&mut file, auto_sql_offset, 8, big_endian);
+        push_number(&mut file, total_summary_offset, 8, big_endian);
+        push_number(&mut file, 16384, 4, big_endian); // uncompressBufSize
+        push_number(&mut file, extension_offset, 8, big_endian);
+        assert_eq!(file.len(), 64);
+
+        file.extend_from_slice(AUTOSQL.as_bytes());
+        file.push(0);
+
+        push_number(&mut file, 4, 8, big_endian); // validCount
+        for value in [1.0f64, 5.0, 12.0, 42.0].iter() {
+            // minVal, maxVal, sumData, sumSquares
+            push_number(&mut file, value.to_bits(), 8, big_endian);
+        }
+
+        push_number(&mut file, 64, 2, big_endian); // extensionSize
+        push_number(&mut file, 2, 2, big_endian); // extraIndexCount
+        push_number(&mut file, index_list_offset, 8, big_endian);
+        file.extend_from_slice(&[0u8; 52]); // reserved
+
+        for (field_id, index_offset) in [(3u64, 1000u64), (6, 2000)].iter() {
+            push_number(&mut file, 0, 2, big_endian); // type
+            push_number(&mut file, 1, 2, big_endian); // fieldCount
+            push_number(&mut file, *index_offset, 8, big_endian);
+            push_number(&mut file, 0, 4, big_endian); // reserved
+            push_number(&mut file, *field_id, 2, big_endian);
+            push_number(&mut file, 0, 2, big_endian); // reserved
+        }
+
+        push_number(&mut file, 3, 8, big_endian); // itemCount
+        file
+    }
+
+    // Data block with the items (chrom id, start, end, rest), compressed with zlib when compress is true
+    fn data_block(items: &[(u32, u32, u32, &str)], big_endian: bool, compress: bool) -> Vec<u8> {
+        let mut block = Vec::<u8>::new();
+        for (chrom_id, start, end, rest) in items {
+            push_number(&mut block, *chrom_id as u64, 4, big_endian);
+            push_number(&mut block, *start as u64, 4, big_endian);
+            push_number(&mut block, *end as u64, 4, big_endian);
+            block.extend_from_slice(rest.as_bytes());
+            block.push(0);
+        }
+        if !compress {
+            return block;
+        }
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::<u8>::new(), flate2::Compression::default());
+        std::io::Write::write_all(&mut encoder, &block).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_read_header() {
+        for big_endian in [false, true].iter() {
+            let fixture = bigbed_fixture(*big_endian);
+            let mut raw_file = Cursor::new(fixture.to_vec());
+            let header = read_header(&mut raw_file).unwrap();
+            assert_eq!(header.big_endian, *big_endian);
+            assert_eq!(header.version, 4);
+            assert_eq!(header.field_count, 7);
+            assert_eq!(header.defined_field_count, 6);
+            assert_eq!(header.auto_sql_offset, 64);
+            assert_eq!(header.total_summary_offset, 64 + AUTOSQL.len() as u64 + 1);
+            assert_eq!(header.uncompress_buf_size, 16384);
+            assert_eq!(header.extension_offset, header.total_summary_offset + 40);
+            assert_eq!(header.full_data_offset, fixture.len() as u64 - 8);
+
+            // Extra indices listed in the extension header
+            let indices = read_extra_indices(&mut raw_file, &header).unwrap();
+            assert_eq!(indices.len(), 2);
+            assert_eq!((indices[0].field_id, indices[0].file_offset), (3, 1000));
+            assert_eq!((indices[1].field_id, indices[1].file_offset), (6, 2000));
+
+            // No extension header (files created before extra indices were introduced)
+            let no_extension = BigbedHeader {
+                extension_offset: 0,
+                ..header
+            };
+            assert!(read_extra_indices(&mut raw_file, &no_extension)
+                .unwrap()
+                .is_empty());
+        }
+
+        // Not a bigBed file (bigWig magic number)
+        let mut fixture = bigbed_fixture(false);
+        fixture[0..4].copy_from_slice(&0x888FFC26u32.to_le_bytes());
+        let error = read_header(&mut Cursor::new(fixture)).unwrap_err();
+        assert_eq!(error.kind, "cannot_read_file");
+        // Truncated header
+        let fixture = bigbed_fixture(false)[0..40].to_vec();
+        assert!(read_header(&mut Cursor::new(fixture)).is_err());
+    }
+
+    #[test]
+    fn test_parse_autosql() {
+        let mut raw_file = Cursor::new(bigbed_fixture(true));
+        let header = read_header(&mut raw_file).unwrap();
+        let autosql = read_autosql(&mut raw_file, &header).unwrap();
+        assert_eq!(autosql.as_deref(), Some(AUTOSQL));
+        let no_autosql = BigbedHeader {
+            auto_sql_offset: 0,
+            ..header
+        };
+        assert_eq!(read_autosql(&mut raw_file, &no_autosql).unwrap(), None);
+
+        let fields = parse_autosql(AUTOSQL);
+        let fields: Vec<(&str, &str, &str)> = fields
+            .iter()
+            .map(|field| {
+                (
+                    field.name.as_str(),
+                    field.field_type.as_str(),
+                    field.comment.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                (
+                    "chrom",
+                    "string",
+                    "Reference sequence chromosome or scaffold"
+                ),
+                ("chromStart", "uint", "Start position in chromosome"),
+                ("chromEnd", "uint", "End position in chromosome"),
+                ("name", "string", "Name of item"),
+                ("score", "uint", "Score from 0-1000"),
+                ("strand", "char[1]", "+ or -"),
+                ("geneIds", "int[blockCount]", "Gene ids"),
+            ]
+        );
+        // Missing parentheses
+        assert!(parse_autosql("table bed3 \"Bed 3\"").is_empty());
+    }
+
+    #[test]
+    fn test_parse_field_value() {
+        use serde_json::json;
+        assert_eq!(parse_field_value("uint", "42"), json!(42));
+        assert_eq!(parse_field_value("int", "-7"), json!(-7));
+        assert_eq!(parse_field_value("float", "0.5"), json!(0.5));
+        assert_eq!(parse_field_value("double", "nan"), json!("nan")); // Not a valid JSON number
+        assert_eq!(parse_field_value("uint", "abc"), json!("abc"));
+        assert_eq!(parse_field_value("string", "TP53"), json!("TP53"));
+        assert_eq!(parse_field_value("char[1]", "+"), json!("+"));
+        assert_eq!(
+            parse_field_value("int[blockCount]", "10,20,30,"),
+            json!([10, 20, 30])
+        );
+        assert_eq!(
+            parse_field_value("float[count]", "1.5,2"),
+            json!([1.5, 2.0])
+        );
+        assert_eq!(parse_field_value("string[count]", "a,b"), json!(["a", "b"]));
+        assert_eq!(parse_field_value("int[blockCount]", ""), json!([]));
+    }
+
+    #[test]
+    fn test_read_block_items() {
+        // The last item has an extra field not in the autoSql definition
+        let items = [
+            (0, 100, 200, "geneA\t500\t+\t1,2,"),
+            (0, 300, 400, "geneB\t0\t-\t3,"),
+            (1, 50, 60, "geneA\t1000\t+\t4,5,6,\textra"),
+        ];
+        for big_endian in [false, true].iter() {
+            let mut raw_file = Cursor::new(bigbed_fixture(*big_endian));
+            let header = read_header(&mut raw_file).unwrap();
+            for compress in [true, false].iter() {
+                let block = data_block(&items, *big_endian, *compress);
+                let block_offset = raw_file.get_ref().len() as u64;
+                let mut file = raw_file.get_ref().to_vec();
+                file.extend_from_slice(&block);
+                let mut file = Cursor::new(file);
+                let header = BigbedHeader {
+                    uncompress_buf_size: if *compress { 16384 } else { 0 },
+                    ..read_header(&mut file).unwrap()
+                };
+                let read = |file: &mut Cursor<Vec<u8>>, field_id: usize, name: &str| {
+                    read_block_items(
+                        file,
+                        &header,
+                        block_offset,
+                        block.len() as u64,
+                        field_id,
+                        &name.to_string(),
+                    )
+                    .unwrap()
+                };
+                assert_eq!(
+                    read(&mut file, 3, "geneA"),
+                    vec![
+                        (0, 100, 200, items[0].3.to_string()),
+                        (1, 50, 60, items[2].3.to_string())
+                    ]
+                );
+                assert_eq!(
+                    read(&mut file, 6, "3,"),
+                    vec![(0, 300, 400, items[1].3.to_string())]
+                );
+                assert_eq!(read(&mut file, 3, "geneC"), vec![]);
+            }
+            assert_eq!(header.big_endian, *big_endian);
+        }
+
+        // Corrupted compressed block
+        let mut raw_file = Cursor::new(bigbed_fixture(false));
+        let header = read_header(&mut raw_file).unwrap();
+        let error =
+            read_block_items(&mut raw_file, &header, 64, 20, 3, &"geneA".to_string()).unwrap_err();
+        assert_eq!(error.kind, "query_failed");
+
+        // Item with the extra field, which is named after its index
+        let fields = parse_autosql(AUTOSQL);
+        let item = format_item(&fields, "chr2", 50, 60, items[2].3, true);
+        assert_eq!(
+            item,
+            serde_json::json!({
+                "chrom": "chr2",
+                "chromStart": 50,
+                "chromEnd": 60,
+                "name": "geneA",
+                "score": 1000,
+                "strand": "+",
+                "geneIds": [4, 5, 6],
+                "field7": "extra"
+            })
+        );
+        assert_eq!(
+            format_item(&fields, "chr2", 50, 60, items[2].3, false),
+            serde_json::json!("chr2\t50\t60\tgeneA\t1000\t+\t4,5,6,\textra")
+        );
+    }
+
+    #[test]
+    fn test_search_bpt_node() {
+        // Root node (offset 0) with two leaves, the key "bb" is present in both leaves
+        let key_size = 4;
+        let root_size = 4 + 2 * (key_size + 8);
+        let leaf_size = 4 + 2 * (key_size + 16);
+        let mut index = bpt_node(
+            false,
+            &[
+                ("aa", vec![root_size as u64]),
+                ("bb", vec![(root_size + leaf_size) as u64]),
+            ],
+            key_size,
+        );
+        index.extend(bpt_node(
+            true,
+            &[("aa", vec![100, 10]), ("bb", vec![200, 20])],
+            key_size,
+        ));
+        index.extend(bpt_node(
+            true,
+            &[("bb", vec![300, 30]), ("cc", vec![400, 40])],
+            key_size,
+        ));
+        let mut raw_file = Cursor::new(index);
+
+        let search = |raw_file: &mut Cursor<Vec<u8>>, name: &str| {
+            let mut key = name.as_bytes().to_vec();
+            key.resize(key_size, 0);
+            let mut blocks = Vec::<(u64, u64)>::new();
+            search_bpt_node(raw_file, false, 0, key_size, 16, &key, &mut blocks).unwrap();
+            blocks
+        };
+        assert_eq!(search(&mut raw_file, "aa"), vec![(100, 10)]);
+        assert_eq!(search(&mut raw_file, "bb"), vec![(200, 20), (300, 30)]);
+        assert_eq!(search(&mut raw_file, "cc"), vec![(400, 40)]);
+        assert_eq!(search(&mut raw_file, "ab"), vec![]);
+        assert_eq!(search(&mut raw_file, "a"), vec![]);
+        assert_eq!(search(&mut raw_file, "zz"), vec![]);
+
+        // Truncated index
+        let mut truncated_file = Cursor::new(raw_file.into_inner()[0..root_size + 10].to_vec());
+        let mut blocks = Vec::<(u64, u64)>::new();
+        assert!(search_bpt_node(
+            &mut truncated_file,
+            false,
+            0,
+            key_size,
+            16,
+            b"aa\0\0",
+            &mut blocks
+        )
+        .is_err());
+    }
+}
@@ -23,34 +23,203 @@
  cd .. && cargo build --release && time echo http://hgdownload.soe.ucsc.edu/goldenPath/hg19/encodeDCC/wgEncodeMapability/wgEncodeCrgMapabilityAlign100mer.bigWig,chr17,0,81195210,1140 | target/release/bigwig (not working)
  ~/proteinpaint/server/utils/bigWigSummary http://hgdownload.soe.ucsc.edu/goldenPath/hg19/encodeDCC/wgEncodeMapability/wgEncodeCrgMapabilityAlign100mer.bigWig chr17 6074169 9086266 1140
 
+ JSON: cd .. && cargo build --release && time echo '{"file":"/Users/rpaul1/proteinpaint/proteinpaint_demo/hg19/bigwig/file.bw","chr":"chr17","start":7568451,"stop":7591984,"datapoints":940,"stats":["mean","max","min"]}' | target/release/bigwig
+
+ JSON with block cache: cd .. && cargo build --release && time echo '{"file":"https://proteinpaint.stjude.org/ppdemo/hg19/bigwig/file.bw","chr":"chr17","start":7568451,"stop":7591984,"datapoints":940,"cache_dir":"/tmp/bigwig_cache","cache_max_size":1000000000}' | target/release/bigwig
+
 */
 
 /*
 Notes:
-   The script accepts piped input in this format: {Bigwig_file_path/URL},{chr},{start_region},{stop_region}. See syntax above.
+   The script accepts piped input in one of two formats:
+
+   1) {Bigwig_file_path/URL},{chr},{start_region},{stop_region},{datapoints}. See syntax above. The mean of each of the datapoints bins is printed, separated by tab character. In case the file cannot be read, the script gives the message "Cannot read bigWig file".
 
-   In case path to file is not correct, the script gives a message "File not found". If the file is found, the aggregated data points are separated by tab character.
+   2) JSON request { file, chr, start, stop, datapoints, stats } where stats is an array of summary statistics to be computed for each bin (default ["mean"]):
+        mean: Mean value of the bases with data in the bin (null when there is no data)
+        max/min: Maximum/minimum value in the bin (null when there is no data)
+        coverage: Fraction of bases in the bin with data
+        std: Standard deviation of the values of the bases with data in the bin (null when there is no data)
+        sum: Sum of the values of the bases with data in the bin
+      The response is a single JSON object { file, chr, start, stop, datapoints, zoom_level, mean, max, min, coverage, std, sum } containing only the requested stats, where zoom_level is the reduction level chosen by calculate_appropriate_zoom_level_ucsc() (null when the raw data is used at base-pair resolution)
+      Errors are returned as { error: { kind, message } } where kind is "invalid_input", "cannot_read_file" or "query_failed". The message never contains the path of the file
+      Optional cache_dir and cache_max_size (bytes, default 1000000000): When file is a web URL, the byte ranges read from the file are cached in cache_dir (see remote_cache.rs), so that viewing the same region again does not fetch the header, index and data blocks over HTTP. The cache is validated against the ETag/Last-Modified of the file and the least recently used blocks are removed when the cache is larger than cache_max_size
+
+   3) JSON array of requests [{ file, chr, start, stop, datapoints, stats }, ...] (batch mode). The response is a JSON array with the response (or error) of each request in the same order. Each file is opened only once and shared by all its requests, and different files are read in parallel
+
+   4) JSON request { files, chr, start, stop, datapoints, scale_factors, zscore } (cross-sample aggregation) where files is a group of bigwig files (e.g. all samples of a cohort):
+        scale_factors: Optional normalization factor of each file, the mean of each bin is multiplied by the scale factor of the file (default 1)
+        zscore: Optional boolean, when true the z-score of each file against the group is also computed for each bin
+      The mean of each bin is computed for every file (bins with no data are taken as 0, the same value printed in format 1) and aggregated across files. The response is { files, chr, start, stop, datapoints, mean, median, p25, p75, zscore } where mean/median/p25/p75 are the cross-sample mean, median and 25th/75th percentiles of each bin, and zscore (only when requested) has one array per file with (value - group mean) / group standard deviation of each bin (null when the standard deviation is 0). If any file cannot be read, the error of that file is returned with the index of the file in files
 
    Function cascade:
 
-   main() - Checks if the web url/local file path is correct. If not, it will display "File not found" message and exit. if true, it will collect zoom headers.
-     calculate_datapoints() - Calculates range for each region for the number of points required by the user.
-         calculate_appropriate_zoom_level() - Calculates approporiate zoom level (or query raw data) depending upon the range being viewed.
+   main() - Parses the input in any of the formats.
+     aggregate_json_request() - Aggregates the per-file bin means across files (format 4).
+     read_bigwig_files() - Reads the files in parallel threads.
+     read_bigwig() - Opens the web url (through CachedRemoteFile when cache_dir is given)/local file once for all the queries of the file, returns a "cannot_read_file" error for each query if it cannot be read.
+       summarize_bins() - Calculates range for each bin for the number of points required by the user.
+         calculate_appropriate_zoom_level_ucsc() - Calculates approporiate zoom level (or query raw data) depending upon the range being viewed.
 
-         if web_url {
-            Some(zoom_level) => use get_zoom_interval()
-            None => use get_interval()
-         } else local file {
+         match zoom_level {
             Some(zoom_level) => use get_zoom_interval()
             None => use get_interval()
          }
+         add_entry() - Adds each entry of the bigwig file to the bins it overlaps.
 */
 
 use bigtools::bigwig::ZoomHeader;
 use bigtools::bigwigread::BigWigRead;
 use bigtools::utils::file::remote_file::RemoteFile;
+use json::JsonValue;
+use serde::{Deserialize, Serialize};
 use std::env;
 use std::io;
+use std::sync::{Arc, Mutex}; // Multithreading library
+use std::thread;
+mod remote_cache; // Imports the on-disk cache of remote files from remote_cache.rs
+use remote_cache::{CacheConfig, CachedRemoteFile};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BigwigError {
+    kind: String,    // "invalid_input", "cannot_read_file" or "query_failed"
+    message: String, // Description of the error
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ErrorResponse {
+    error: BigwigError,
+}
+
+// Response to a JSON request, only the requested stats are present
+#[derive(Debug, Serialize, Deserialize)]
+struct BinSummary {
+    file: String,
+    chr: String,
+    start: u64,
+    stop: u64,
+    datapoints: u32,
+    zoom_level: Option<u32>, // Reduction level used for the query, None at base-pair resolution
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mean: Option<Vec<Option<f64>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max: Option<Vec<Option<f64>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min: Option<Vec<Option<f64>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    coverage: Option<Vec<f64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    std: Option<Vec<Option<f64>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sum: Option<Vec<f64>>,
+}
+
+// Region to be summarized into datapoints bins
+#[derive(Debug, Clone)]
+struct BinQuery {
+    chrom: String,
+    start_pos: f64,
+    stop_pos: f64,
+    datapoints: u32,
+}
+
+// Parsed JSON request
+#[derive(Debug, Clone)]
+struct JsonRequest {
+    file: String,
+    query: BinQuery,
+    stats: Vec<String>,         // Requested summary statistics
+    cache: Option<CacheConfig>, // Cache for remote files
+}
+
+// Response to a cross-sample aggregation request
+#[derive(Debug, Serialize, Deserialize)]
+struct AggregateSummary {
+    files: Vec<String>,
+    chr: String,
+    start: u64,
+    stop: u64,
+    datapoints: u32,
+    mean: Vec<f64>,   // Cross-sample mean of each bin
+    median: Vec<f64>, // Cross-sample median of each bin
+    p25: Vec<f64>,    // Cross-sample 25th percentile of each bin
+    p75: Vec<f64>,    // Cross-sample 75th percentile of each bin
+    #[serde(skip_serializing_if = "Option::is_none")]
+    zscore: Option<Vec<Vec<Option<f64>>>>, // z-score of each bin for each file
+}
+
+// Entry read from the bigwig file, either a raw value or a zoom record summarizing several raw values
+struct BigwigEntry {
+    start: u32,
+    end: u32,
+    mean: f64,        // Mean value per base
+    mean_square: f64, // Mean of squared values per base
+    min: f64,
+    max: f64,
+    coverage: f64, // Fraction of the bases between start and end with data (always 1 for raw values)
+}
+
+// Zoom level used (None for raw values) and bins of a query
+type QueryResult = Result<(Option<u32>, BinAccumulator), BigwigError>;
+
+// Running totals of each bin
+struct BinAccumulator {
+    bin_limits: Vec<f64>, // Start of each bin followed by the stop of the last bin
+    bases: Vec<f64>, // Number of bases of the entries overlapping each bin, used as the denominator of mean
+    sum: Vec<f64>,
+    sum_squares: Vec<f64>,
+    covered: Vec<f64>, // Number of bases with data in each bin
+    min: Vec<Option<f64>>,
+    max: Vec<Option<f64>>,
+}
+
+impl BinAccumulator {
+    // Empty bins dividing start_pos-stop_pos into datapoints bins of equal width
+    fn new(start_pos: f64, stop_pos: f64, datapoints: u32) -> BinAccumulator {
+        let exact_offset: f64 = (stop_pos - start_pos) / datapoints as f64;
+        let mut datapoints_list = Vec::<f64>::new(); // Vector for storing datapoints
+        let mut current_pos: f64 = start_pos; // Initializing current_pos to start position
+        for _i in 0..datapoints {
+            datapoints_list.push(current_pos);
+            current_pos += exact_offset;
+        }
+        datapoints_list.push(stop_pos);
+        //println!("datapoints_list:{:?}", datapoints_list);
+        BinAccumulator {
+            bin_limits: datapoints_list,
+            bases: vec![0.0; datapoints as usize],
+            sum: vec![0.0; datapoints as usize],
+            sum_squares: vec![0.0; datapoints as usize],
+            covered: vec![0.0; datapoints as usize],
+            min: vec![None; datapoints as usize],
+            max: vec![None; datapoints as usize],
+        }
+    }
+
+    fn mean(&self, i: usize) -> Option<f64> {
+        if self.bases[i] == 0.0 {
+            None
+        } else {
+            Some(self.sum[i] / self.bases[i])
+        }
+    }
+
+    fn std(&self, i: usize) -> Option<f64> {
+        self.mean(i).map(|mean| {
+            let variance = self.sum_squares[i] / self.bases[i] - mean * mean;
+            determine_max(variance, 0.0).sqrt() // Rounding errors may give a slightly negative variance
+        })
+    }
+
+    fn coverage(&self, i: usize) -> f64 {
+        let bin_width = self.bin_limits[i + 1] - self.bin_limits[i];
+        if bin_width <= 0.0 {
+            0.0
+        } else {
+            determine_min(self.covered[i] / bin_width, 1.0)
+        }
+    }
+}
 
 fn main() {
     let mut input = String::new();
@@ -64,7 +233,37 @@ fn main() {
         }
         Err(error) => println!("Piping error: {}", error),
     }
-    let args: Vec<&str> = input.split(",").collect(); // Various input from nodejs is separated by "_" character
+    if input.trim_start().starts_with("{") || input.trim_start().starts_with("[") {
+        let json_string: JsonValue = match json::parse(&input) {
+            Ok(json_string) => json_string,
+            Err(error) => {
+                println!(
+                    "{}",
+                    error_response(&BigwigError {
+                        kind: "invalid_input".to_string(),
+                        message: format!("Incorrect json: {}", error),
+                    })
+                );
+                return;
+            }
+        };
+        if json_string.is_object() && json_string.has_key("files") {
+            match aggregate_json_request(&json_string) {
+                Ok(summary) => println!("{}", serde_json::to_string(&summary).unwrap()),
+                Err(error) => println!("{}", error_response(&error)),
+            }
+        } else if json_string.is_array() {
+            let requests: Vec<Result<JsonRequest, BigwigError>> =
+                json_string.members().map(parse_json_request).collect();
+            println!("[{}]", summarize_json_requests(requests).join(","));
+        } else {
+            let responses = summarize_json_requests(vec![parse_json_request(&json_string)]);
+            println!("{}", responses[0]);
+        }
+        return;
+    }
+
+    let args: Vec<&str> = input.split(",").collect(); // Various input from nodejs is separated by "," character
 
     //println!("args:{:?}", args);
 
@@ -72,89 +271,444 @@ fn main() {
     let chrom: String = args[1].parse::<String>().unwrap(); // Chromosome name
     let start_pos: f64 = args[2].parse::<f64>().unwrap(); // Start position
     let stop_pos: f64 = args[3].parse::<f64>().unwrap(); // Stop position
-
-    let difference = stop_pos - start_pos;
     let datapoints: u32 = args[4].replace("\n", "").parse::<u32>().unwrap(); // Number of intervals
 
     //println!("start_pos:{}", start_pos);
     //println!("stop_pos:{}", stop_pos);
-    //println!("difference:{}", difference);
     //println!("datapoints:{}", datapoints);
 
-    //println!("datapoints_list length:{:?}", datapoints_list);
+    let query = BinQuery {
+        chrom,
+        start_pos,
+        stop_pos,
+        datapoints,
+    };
+    match read_bigwig(&bigwig_file_url, &None, &[query]).remove(0) {
+        Ok((_zoom_level, bins)) => {
+            let mut output_vec: String = "".to_string();
+            for i in 0..datapoints as usize {
+                output_vec.push_str(&bins.mean(i).unwrap_or(0.0).to_string());
+                output_vec.push('\t');
+            }
+            output_vec.pop();
+            println!("{}", output_vec);
+        }
+        Err(_error) => println!("Cannot read bigWig file"), // Same message as before the JSON mode, the server checks for this message
+    }
+}
 
-    let mut is_weburl: bool = false;
-    if bigwig_file_url.starts_with("http://") == true
-        || bigwig_file_url.starts_with("https://") == true
-        || bigwig_file_url.starts_with("www.") == true
-    {
-        // Its a web URL
-        let remote_file = RemoteFile::new(&bigwig_file_url);
-        let reader = BigWigRead::from(remote_file);
-        match reader {
-            Ok(reader) => {
-                is_weburl = true;
-                let zoom_headers = reader.info.zoom_headers.clone();
-                calculate_datapoints(
-                    zoom_headers,
-                    reader,
-                    &bigwig_file_url,
-                    is_weburl,
-                    datapoints,
-                    difference,
-                    chrom,
-                    start_pos as f64,
-                    stop_pos as f64,
-                );
+// Serializes an error as { error: { kind, message } }
+fn error_response(error: &BigwigError) -> String {
+    serde_json::to_string(&ErrorResponse {
+        error: error.clone(),
+    })
+    .unwrap()
+}
+
+// Computes the requested stats of each JSON request and returns the serialized response of each request in the same order. Requests are grouped by file so that each file is opened once
+fn summarize_json_requests(requests: Vec<Result<JsonRequest, BigwigError>>) -> Vec<String> {
+    let mut responses = Vec::<String>::new();
+    let mut file_groups = Vec::<(String, Vec<usize>)>::new(); // Indices of the valid requests of each file, in the order the files first appear
+    for (request_idx, request) in requests.iter().enumerate() {
+        match request {
+            Ok(request) => {
+                responses.push(String::new()); // Filled in after the file is read
+                match file_groups
+                    .iter_mut()
+                    .find(|(file, _request_indices)| file == &request.file)
+                {
+                    Some((_file, request_indices)) => request_indices.push(request_idx),
+                    None => file_groups.push((request.file.to_string(), vec![request_idx])),
+                }
             }
-            Err(_) => {
-                println!("Cannot read bigWig file");
+            Err(error) => responses.push(error_response(error)),
+        }
+    }
+
+    let group_queries: Vec<(String, Option<CacheConfig>, Vec<BinQuery>)> = file_groups
+        .iter()
+        .map(|(file, request_indices)| {
+            let queries: Vec<BinQuery> = request_indices
+                .iter()
+                .map(|request_idx| requests[*request_idx].as_ref().unwrap().query.clone())
+                .collect();
+            let cache = requests[request_indices[0]].as_ref().unwrap().cache.clone(); // The cache of the first request of the file is used for all its requests
+            (file.to_string(), cache, queries)
+        })
+        .collect();
+    let group_results = read_bigwig_files(group_queries);
+    for ((_file, request_indices), results) in file_groups.iter().zip(group_results) {
+        for (request_idx, result) in request_indices.iter().zip(results) {
+            let request = requests[*request_idx].as_ref().unwrap();
+            responses[*request_idx] = match result {
+                Ok((zoom_level, bins)) => {
+                    serde_json::to_string(&bin_summary(request, zoom_level, &bins)).unwrap()
+                }
+                Err(error) => error_response(&error),
+            };
+        }
+    }
+    responses
+}
+
+// Reads the queries of each file, the files are divided among threads. The results of each file are returned in the same order as the input
+fn read_bigwig_files(
+    file_queries: Vec<(String, Option<CacheConfig>, Vec<BinQuery>)>,
+) -> Vec<Vec<QueryResult>> {
+    let max_threads: usize = 6; // Max number of files read in parallel
+    let num_files = file_queries.len();
+    let file_queries = Arc::new(file_queries);
+    let file_results = Arc::new(Mutex::new(
+        (0..num_files)
+            .map(|_file_idx| Vec::new())
+            .collect::<Vec<Vec<QueryResult>>>(),
+    ));
+    let mut handles = vec![]; // Vector to store handle which is used to prevent one thread going ahead of another
+    for thread_num in 0..std::cmp::min(max_threads, num_files) {
+        let file_queries = Arc::clone(&file_queries);
+        let file_results = Arc::clone(&file_results);
+        let handle = thread::spawn(move || {
+            for file_idx in 0..file_queries.len() {
+                let remainder: usize = file_idx % max_threads; // Calculate remainder of file number divided by max_threads to decide which thread reads this file
+                if remainder != thread_num {
+                    continue;
+                }
+                let (file, cache, queries) = &file_queries[file_idx];
+                let results = read_bigwig(file, cache, queries);
+                file_results.lock().unwrap()[file_idx] = results;
             }
+        });
+        handles.push(handle); // The handle (which contains the thread) is stored in the handles vector
+    }
+    for handle in handles {
+        // Wait for all threads to finish before proceeding further
+        handle.join().unwrap();
+    }
+    let file_results = std::mem::take(&mut *file_results.lock().unwrap());
+    file_results
+}
+
+// Parses a cross-sample aggregation request { files, chr, start, stop, datapoints, scale_factors, zscore } and aggregates the bin means of all files
+fn aggregate_json_request(json_string: &JsonValue) -> Result<AggregateSummary, BigwigError> {
+    let invalid_input = |message: String| BigwigError {
+        kind: "invalid_input".to_string(),
+        message,
+    };
+    let mut files = Vec::<String>::new();
+    for file in json_string["files"].members() {
+        match file.as_str() {
+            Some(file) => files.push(file.to_string()),
+            None => return Err(invalid_input("files must be strings".to_string())),
         }
+    }
+    if files.is_empty() {
+        return Err(invalid_input("files must be a non-empty array".to_string()));
+    }
+    // The region is validated in the same way as a single file request
+    let mut region = json_string.clone();
+    region["file"] = files[0].as_str().into();
+    let region = parse_json_request(&region)?;
+    let query = region.query;
+    let cache = region.cache;
+
+    let scale_factors: Vec<f64> = if json_string["scale_factors"].is_null() {
+        vec![1.0; files.len()]
     } else {
-        // Its a local file
-        let reader = BigWigRead::from_file_and_attach(&bigwig_file_url);
-        //let a = &reader.info.zoom_headers;
-        match reader {
-            Ok(_) => {
-                let reader = BigWigRead::from_file_and_attach(&bigwig_file_url).unwrap();
-                let zoom_headers = reader.info.zoom_headers.clone();
-                calculate_datapoints(
-                    zoom_headers,
-                    reader,
-                    &bigwig_file_url,
-                    is_weburl,
-                    datapoints,
-                    difference,
-                    chrom,
-                    start_pos as f64,
-                    stop_pos as f64,
-                );
-            }
-            Err(_) => {
-                println!("Cannot read bigWig file");
+        let scale_factors: Vec<Option<f64>> = json_string["scale_factors"]
+            .members()
+            .map(|scale_factor| scale_factor.as_f64())
+            .collect();
+        if scale_factors.len() != files.len() || scale_factors.iter().any(|x| x.is_none()) {
+            return Err(invalid_input(
+                "scale_factors must be an array of numbers with one scale factor per file"
+                    .to_string(),
+            ));
+        }
+        scale_factors.into_iter().map(|x| x.unwrap()).collect()
+    };
+    let zscore: bool = match json_string["zscore"].as_bool() {
+        Some(zscore) => zscore,
+        None if json_string["zscore"].is_null() => false,
+        None => return Err(invalid_input("zscore must be a boolean".to_string())),
+    };
+
+    let file_queries: Vec<(String, Option<CacheConfig>, Vec<BinQuery>)> = files
+        .iter()
+        .map(|file| (file.to_string(), cache.clone(), vec![query.clone()]))
+        .collect();
+    let num_bins = query.datapoints as usize;
+    let mut values = Vec::<Vec<f64>>::new(); // Scaled bin means of each file
+    for (file_idx, (mut results, scale_factor)) in read_bigwig_files(file_queries)
+        .into_iter()
+        .zip(scale_factors.iter())
+        .enumerate()
+    {
+        match results.remove(0) {
+            Ok((_zoom_level, bins)) => values.push(scaled_means(&bins, *scale_factor)),
+            Err(error) => {
+                return Err(BigwigError {
+                    kind: error.kind,
+                    message: format!("{} (files[{}])", error.message, file_idx), // The index rather than the path of the file, which must not be given away to the client
+                });
             }
         }
     }
-    /*
-    //println!("zoom_headers:{:?}", zoom_headers);
 
-    if file_found == true {
-        //println!("datapoints_sum:{:?}", datapoints_sum);
-        //println!("datapoints_num:{:?}", datapoints_num);
+    let mut summary = AggregateSummary {
+        files,
+        chr: query.chrom.to_string(),
+        start: query.start_pos as u64,
+        stop: query.stop_pos as u64,
+        datapoints: query.datapoints,
+        mean: Vec::with_capacity(num_bins),
+        median: Vec::with_capacity(num_bins),
+        p25: Vec::with_capacity(num_bins),
+        p75: Vec::with_capacity(num_bins),
+        zscore: None,
+    };
+    aggregate_values(&values, &mut summary, zscore);
+    Ok(summary)
+}
 
-        calculate_datapoints(
-            zoom_headers,
-            &bigwig_file_url,
-            is_weburl,
-            datapoints,
-            difference,
+// Bin means of a file multiplied by its scale factor. Bins without data (or with a non-finite mean) are 0
+fn scaled_means(bins: &BinAccumulator, scale_factor: f64) -> Vec<f64> {
+    (0..bins.bases.len())
+        .map(|i| bins.mean(i).filter(|mean| mean.is_finite()).unwrap_or(0.0) * scale_factor)
+        .collect()
+}
+
+// Cross-sample mean, median and quartiles of each bin (and the z-score of each file when zscore is true) from the scaled bin means of each file
+fn aggregate_values(values: &[Vec<f64>], summary: &mut AggregateSummary, zscore: bool) {
+    let num_bins = summary.datapoints as usize;
+    let mut zscores = vec![Vec::<Option<f64>>::with_capacity(num_bins); values.len()];
+    for i in 0..num_bins {
+        let mut bin_values: Vec<f64> = values.iter().map(|file_values| file_values[i]).collect();
+        let n = bin_values.len() as f64;
+        let mean: f64 = bin_values.iter().sum::<f64>() / n;
+        let sd: f64 = if bin_values.len() > 1 {
+            (bin_values
+                .iter()
+                .map(|x| (x - mean) * (x - mean))
+                .sum::<f64>()
+                / (n - 1.0))
+                .sqrt()
+        } else {
+            0.0
+        };
+        for (file_idx, value) in bin_values.iter().enumerate() {
+            zscores[file_idx].push(if sd > 0.0 {
+                Some((value - mean) / sd)
+            } else {
+                None
+            });
+        }
+        bin_values.sort_by(|a, b| a.total_cmp(b));
+        summary.mean.push(mean);
+        summary.median.push(calc_quantile(&bin_values, 0.5));
+        summary.p25.push(calc_quantile(&bin_values, 0.25));
+        summary.p75.push(calc_quantile(&bin_values, 0.75));
+    }
+    if zscore {
+        summary.zscore = Some(zscores);
+    }
+}
+
+// Quantile of sorted values with linear interpolation between the closest ranks (default type 7 quantile in R)
+fn calc_quantile(sorted_values: &[f64], p: f64) -> f64 {
+    let index: f64 = ((sorted_values.len() - 1) as f64) * p; // 0-based index
+    let lo: usize = index.floor() as usize;
+    let hi: usize = index.ceil() as usize;
+    let h: f64 = index - lo as f64;
+    (1.0 - h) * sorted_values[lo] + h * sorted_values[hi]
+}
+
+// Parses a single JSON request { file, chr, start, stop, datapoints, stats }
+fn parse_json_request(json_string: &JsonValue) -> Result<JsonRequest, BigwigError> {
+    let invalid_input = |message: String| BigwigError {
+        kind: "invalid_input".to_string(),
+        message,
+    };
+    let file: String = match json_string["file"].as_str() {
+        Some(file) => file.to_string(),
+        None => return Err(invalid_input("file is missing".to_string())),
+    };
+    let chrom: String = match json_string["chr"].as_str() {
+        Some(chrom) => chrom.to_string(),
+        None => return Err(invalid_input("chr is missing".to_string())),
+    };
+    let (start_pos, stop_pos): (u64, u64) =
+        match (json_string["start"].as_u64(), json_string["stop"].as_u64()) {
+            (Some(start_pos), Some(stop_pos)) if start_pos < stop_pos => (start_pos, stop_pos),
+            _ => {
+                return Err(invalid_input(
+                    "start and stop must be integers with 0 <= start < stop".to_string(),
+                ))
+            }
+        };
+    let datapoints: u32 = match json_string["datapoints"].as_u32() {
+        Some(datapoints) if datapoints > 0 => datapoints,
+        _ => {
+            return Err(invalid_input(
+                "datapoints must be a positive integer".to_string(),
+            ))
+        }
+    };
+    let mut stats = Vec::<String>::new();
+    if json_string["stats"].is_null() {
+        stats.push("mean".to_string());
+    } else if json_string["stats"].is_array() {
+        for stat in json_string["stats"].members() {
+            match stat.as_str() {
+                Some(stat @ ("mean" | "max" | "min" | "coverage" | "std" | "sum")) => {
+                    stats.push(stat.to_string())
+                }
+                _ => {
+                    return Err(invalid_input(format!(
+                        "Unknown stat {}, allowed stats are mean, max, min, coverage, std and sum",
+                        stat
+                    )))
+                }
+            }
+        }
+    } else {
+        return Err(invalid_input("stats must be an array".to_string()));
+    }
+    let cache: Option<CacheConfig> = match json_string["cache_dir"].as_str() {
+        Some(cache_dir) => {
+            let max_bytes: u64 = if json_string["cache_max_size"].is_null() {
+                1000000000
+            } else {
+                match json_string["cache_max_size"].as_u64() {
+                    Some(max_bytes) => max_bytes,
+                    None => {
+                        return Err(invalid_input(
+                            "cache_max_size must be a number of bytes".to_string(),
+                        ))
+                    }
+                }
+            };
+            Some(CacheConfig {
+                dir: cache_dir.to_string(),
+                max_bytes,
+            })
+        }
+        None => None,
+    };
+    Ok(JsonRequest {
+        file,
+        query: BinQuery {
             chrom,
-            start_pos as f64,
-            stop_pos as f64,
-        );
+            start_pos: start_pos as f64,
+            stop_pos: stop_pos as f64,
+            datapoints,
+        },
+        stats,
+        cache,
+    })
+}
+
+// Response to a JSON request containing the requested stats of each bin
+fn bin_summary(
+    request: &JsonRequest,
+    zoom_level: Option<u32>,
+    bins: &BinAccumulator,
+) -> BinSummary {
+    let num_bins = request.query.datapoints as usize;
+    let requested = |stat: &str| request.stats.iter().any(|x| x == stat);
+    BinSummary {
+        file: request.file.to_string(),
+        chr: request.query.chrom.to_string(),
+        start: request.query.start_pos as u64,
+        stop: request.query.stop_pos as u64,
+        datapoints: request.query.datapoints,
+        zoom_level,
+        mean: if requested("mean") {
+            Some((0..num_bins).map(|i| bins.mean(i)).collect())
+        } else {
+            None
+        },
+        max: if requested("max") {
+            Some(bins.max.to_vec())
+        } else {
+            None
+        },
+        min: if requested("min") {
+            Some(bins.min.to_vec())
+        } else {
+            None
+        },
+        coverage: if requested("coverage") {
+            Some((0..num_bins).map(|i| bins.coverage(i)).collect())
+        } else {
+            None
+        },
+        std: if requested("std") {
+            Some((0..num_bins).map(|i| bins.std(i)).collect())
+        } else {
+            None
+        },
+        sum: if requested("sum") {
+            Some(bins.sum.to_vec())
+        } else {
+            None
+        },
+    }
+}
+
+// Opens a bigwig file from a web URL (through the cache when given) or a local path once and summarizes each query into its datapoints bins
+fn read_bigwig(
+    bigwig_file_url: &String,
+    cache: &Option<CacheConfig>,
+    queries: &[BinQuery],
+) -> Vec<QueryResult> {
+    // The underlying error is not included in the message since it may contain the path of the file, which must not be given away to the client. Stderr is not used either, as it is passed on by the server when the binary writes to it
+    let cannot_read = || {
+        queries
+            .iter()
+            .map(|_query| {
+                Err(BigwigError {
+                    kind: "cannot_read_file".to_string(),
+                    message: "Cannot read bigWig file".to_string(),
+                })
+            })
+            .collect()
+    };
+    if bigwig_file_url.starts_with("http://") == true
+        || bigwig_file_url.starts_with("https://") == true
+        || bigwig_file_url.starts_with("www.") == true
+    {
+        // Its a web URL
+        if let Some(cache) = cache {
+            let cached_file = match CachedRemoteFile::open(bigwig_file_url, cache) {
+                Ok(cached_file) => cached_file,
+                Err(_error) => return cannot_read(),
+            };
+            return match BigWigRead::from(cached_file) {
+                Ok(mut reader) => queries
+                    .iter()
+                    .map(|query| summarize_bins(&mut reader, query))
+                    .collect(),
+                Err(_error) => cannot_read(),
+            };
+        }
+        let remote_file = RemoteFile::new(&bigwig_file_url);
+        match BigWigRead::from(remote_file) {
+            Ok(mut reader) => queries
+                .iter()
+                .map(|query| summarize_bins(&mut reader, query))
+                .collect(),
+            Err(_error) => cannot_read(),
+        }
+    } else {
+        // Its a local file
+        match BigWigRead::from_file_and_attach(bigwig_file_url) {
+            Ok(mut reader) => queries
+                .iter()
+                .map(|query| summarize_bins(&mut reader, query))
+                .collect(),
+            Err(_error) => cannot_read(),
+        }
     }
-    */
 }
 
 fn determine_max(n1: f64, n2: f64) -> f64 {
@@ -248,518 +802,230 @@ fn calculate_appropriate_zoom_level_ucsc(
     closest_level
 }
 
-fn calculate_datapoints<
+fn summarize_bins<
     R: bigtools::utils::file::seekableread::Reopen<S>,
     S: bigtools::utils::file::seekableread::SeekableRead,
 >(
-    zoom_headers: Vec<ZoomHeader>,
-    mut reader: BigWigRead<R, S>,
-    bigwig_file_url: &String,
-    is_weburl: bool,
-    datapoints: u32,
-    difference: f64,
-    chrom: String,
-    start_pos: f64,
-    stop_pos: f64,
-) {
+    reader: &mut BigWigRead<R, S>,
+    query: &BinQuery,
+) -> QueryResult {
+    let chrom = &query.chrom;
+    let start_pos = query.start_pos;
+    let stop_pos = query.stop_pos;
+    let datapoints = query.datapoints;
+    // The underlying error is left out of the message since it may contain the path of the file (see read_bigwig())
+    let query_failed = || BigwigError {
+        kind: "query_failed".to_string(),
+        message: format!("Cannot query {}:{}-{}", chrom, start_pos, stop_pos),
+    };
+    let difference = stop_pos - start_pos;
     let exact_offset: f64 = difference as f64 / datapoints as f64;
     //println!("exact_offset:{}", exact_offset);
-    let mut datapoints_list = Vec::<f64>::new(); // Vector for storing datapoints
-    let mut datapoints_sum = vec![0.0 as f64; datapoints as usize]; // Sum of all values within a region
-    let mut datapoints_num = vec![0 as f64; datapoints as usize]; // Number of all values within a region
-
-    //let zoom_level = calculate_appropriate_zoom_level(zoom_headers, difference);
-    let zoom_level = calculate_appropriate_zoom_level_ucsc(zoom_headers, exact_offset);
-
-    let mut current_pos: f64 = start_pos as f64; // Initializing current_pos to start position
-    for _i in 0..datapoints {
-        datapoints_list.push(current_pos);
-        current_pos = current_pos as f64 + exact_offset as f64;
-    }
-    datapoints_list.push(stop_pos as f64);
-    //println!("datapoints_list:{:?}", datapoints_list);
-
-    if is_weburl == true {
-        // Read from a web URL
-        match zoom_level {
-            Some(level) => {
-                // Using some zoom level
-                //let pre = std::time::Instant::now();
-                let bigwig_output = reader
-                    .get_zoom_interval(&chrom, start_pos as u32, stop_pos as u32, level)
-                    .unwrap();
-                //dbg!(pre.elapsed());
-                let mut i = 0;
-                let mut start_region = datapoints_list[i];
-                let mut end_region = datapoints_list[i + 1];
-                for entry in bigwig_output {
-                    match entry {
-                        Ok(v) => {
-                            //println!(
-                            //    "start,end,sum,bases_covered:{},{},{},{}",
-                            //    v.start, v.end, v.summary.sum, v.summary.bases_covered
-                            //);
-
-                            if v.start == v.end {
-                                continue;
-                            } else {
-                                if (v.start as f64 <= start_region && end_region < v.end as f64)
-                                    || (v.start as f64 >= start_region
-                                        && (v.start as f64) < end_region)
-                                    || (v.end as f64 >= start_region && (v.end as f64) < end_region)
-                                    || (start_region >= v.start as f64
-                                        && (v.end as f64) < end_region)
-                                {
-                                    // Calculate sum and number for this region
-                                    //println!("i:{}", i);
-                                    //println!("v.start:{}", v.start);
-                                    //println!("v.end:{}", v.end);
-                                    //println!("start_region:{}", start_region);
-                                    //println!("end_region:{}", end_region);
-                                    let start_entry_within_region =
-                                        determine_max(v.start as f64, start_region);
-                                    let stop_entry_within_region =
-                                        determine_min(v.end as f64, end_region);
-                                    datapoints_num[i] += (stop_entry_within_region
-                                        - start_entry_within_region)
-                                        as f64;
-                                    datapoints_sum[i] += (stop_entry_within_region
-                                        - start_entry_within_region)
-                                        as f64
-                                        * ((v.summary.sum as f64) / v.summary.bases_covered as f64);
-                                    //println!(
-                                    //    "start_entry_within_region:{}",
-                                    //    start_entry_within_region
-                                    //);
-                                    //println!(
-                                    //    "stop_entry_within_region:{}",
-                                    //    stop_entry_within_region
-                                    //);
-                                    //println!("datapoints_num[i]:{}", datapoints_num[i]);
-                                    //println!("datapoints_sum[i]:{}", datapoints_sum[i]);
-                                }
-                                let mut iter = 1;
-                                while end_region < v.end as f64 && end_region != stop_pos {
-                                    //println!("iter:{}", iter);
-                                    //println!("i:{}", i);
-                                    //println!("v.start:{}", v.start);
-                                    //println!("v.end:{}", v.end);
-                                    //println!("start_region:{}", start_region);
-                                    //println!("end_region:{}", end_region);
-                                    if ((v.start as f64 <= start_region
-                                        && end_region < v.end as f64)
-                                        || (v.start as f64 >= start_region
-                                            && (v.start as f64) < end_region)
-                                        || (v.end as f64 >= start_region
-                                            && (v.end as f64) < end_region)
-                                        || (start_region >= v.start as f64
-                                            && (v.end as f64) < end_region))
-                                        && iter > 1
-                                    {
-                                        // Calculate sum and number for this region
-                                        //println!("Hello");
-                                        let start_entry_within_region =
-                                            determine_max(v.start as f64, start_region);
-                                        let stop_entry_within_region =
-                                            determine_min(v.end as f64, end_region);
-                                        datapoints_num[i] += (stop_entry_within_region
-                                            - start_entry_within_region)
-                                            as f64;
-                                        datapoints_sum[i] += (stop_entry_within_region
-                                            - start_entry_within_region)
-                                            as f64
-                                            * ((v.summary.sum as f64)
-                                                / v.summary.bases_covered as f64);
-                                        //println!(
-                                        //    "start_entry_within_region inside:{}",
-                                        //    start_entry_within_region
-                                        //);
-                                        //println!(
-                                        //    "stop_entry_within_region inside:{}",
-                                        //    stop_entry_within_region
-                                        //);
-                                        //println!("datapoints_num inside[i]:{}", datapoints_num[i]);
-                                        //println!("datapoints_sum inside[i]:{}", datapoints_sum[i]);
-                                    }
-                                    if end_region <= v.end as f64 {
-                                        // Entry spans into next region, need to increment iterator
-                                        if i + 2 < datapoints_list.len() {
-                                            i += 1;
-                                            start_region = datapoints_list[i];
-                                            end_region = datapoints_list[i + 1];
-                                        }
-                                    }
-                                    iter += 1;
-                                }
-                            }
-                        }
-                        Err(_) => {
-                            println!("Possible error:{:?}", entry);
-                        }
-                    }
-                }
-            }
-            None => {
-                // To be used in nucleotide resolution
-                let bigwig_output = reader
-                    .get_interval(&chrom, start_pos as u32, stop_pos as u32)
-                    .unwrap();
-                let mut i = 0;
-                let mut start_region = datapoints_list[i];
-                let mut end_region = datapoints_list[i + 1];
-                for entry in bigwig_output {
-                    match entry {
-                        Ok(v) => {
-                            //println!("start,end,value:{},{},{}", v.start, v.end, v.value);
-                            if v.start == v.end {
-                                continue;
-                            } else {
-                                if (v.start as f64 <= start_region && end_region < v.end as f64)
-                                    || (v.start as f64 >= start_region
-                                        && (v.start as f64) < end_region)
-                                    || (v.end as f64 >= start_region && (v.end as f64) < end_region)
-                                    || (start_region >= v.start as f64
-                                        && (v.end as f64) < end_region)
-                                {
-                                    // Calculate sum and number for this region
-                                    //println!("i:{}", i);
-                                    //println!("v.start:{}", v.start);
-                                    //println!("v.end:{}", v.end);
-                                    //println!("start_region:{}", start_region);
-                                    //println!("end_region:{}", end_region);
-                                    let start_entry_within_region =
-                                        determine_max(v.start as f64, start_region);
-                                    let stop_entry_within_region =
-                                        determine_min(v.end as f64, end_region);
-                                    datapoints_num[i] += (stop_entry_within_region
-                                        - start_entry_within_region)
-                                        as f64;
-                                    datapoints_sum[i] += (stop_entry_within_region
-                                        - start_entry_within_region)
-                                        as f64
-                                        * v.value as f64;
-                                    //println!(
-                                    //    "start_entry_within_region:{}",
-                                    //    start_entry_within_region
-                                    //);
-                                    //println!(
-                                    //    "stop_entry_within_region:{}",
-                                    //    stop_entry_within_region
-                                    //);
-                                    //println!("datapoints_num[i]:{}", datapoints_num[i]);
-                                    //println!("datapoints_sum[i]:{}", datapoints_sum[i]);
-                                }
-                                let mut iter = 1;
-                                while end_region < v.end as f64 && end_region != stop_pos {
-                                    //println!("iter:{}", iter);
-                                    //println!("i:{}", i);
-                                    //println!("v.start:{}", v.start);
-                                    //println!("v.end:{}", v.end);
-                                    //println!("start_region:{}", start_region);
-                                    //println!("end_region:{}", end_region);
-                                    if ((v.start as f64 <= start_region
-                                        && end_region < v.end as f64)
-                                        || (v.start as f64 >= start_region
-                                            && (v.start as f64) < end_region)
-                                        || (v.end as f64 >= start_region
-                                            && (v.end as f64) < end_region)
-                                        || (start_region >= v.start as f64
-                                            && (v.end as f64) < end_region))
-                                        && iter > 1
-                                    {
-                                        // Calculate sum and number for this region
-                                        //println!("Hello");
-                                        let start_entry_within_region =
-                                            determine_max(v.start as f64, start_region);
-                                        let stop_entry_within_region =
-                                            determine_min(v.end as f64, end_region);
-                                        datapoints_num[i] += (stop_entry_within_region
-                                            - start_entry_within_region)
-                                            as f64;
-                                        datapoints_sum[i] += (stop_entry_within_region
-                                            - start_entry_within_region)
-                                            as f64
-                                            * v.value as f64;
-                                        //println!(
-                                        //    "start_entry_within_region inside:{}",
-                                        //    start_entry_within_region
-                                        //);
-                                        //println!(
-                                        //    "stop_entry_within_region inside:{}",
-                                        //    stop_entry_within_region
-                                        //);
-                                        //println!("datapoints_num inside[i]:{}", datapoints_num[i]);
-                                        //println!("datapoints_sum inside[i]:{}", datapoints_sum[i]);
-                                    }
-                                    if end_region <= v.end as f64 {
-                                        // Entry spans into next region, need to increment iterator
-                                        if i + 2 < datapoints_list.len() {
-                                            i += 1;
-                                            start_region = datapoints_list[i];
-                                            end_region = datapoints_list[i + 1];
-                                        } else {
-                                            break;
-                                        }
-                                    }
-                                    iter += 1;
-                                }
-                            }
-                        }
-                        Err(_) => {
-                            println!("Possible error:{:?}", entry);
+    let mut bins = BinAccumulator::new(start_pos, stop_pos, datapoints);
+
+    //let zoom_level = calculate_appropriate_zoom_level(reader.info.zoom_headers.clone(), difference);
+    let zoom_level =
+        calculate_appropriate_zoom_level_ucsc(reader.info.zoom_headers.clone(), exact_offset);
+    let mut i: usize = 0; // Bin containing the start of the current entry
+    match zoom_level {
+        Some(level) => {
+            // Using some zoom level
+            let bigwig_output =
+                match reader.get_zoom_interval(chrom, start_pos as u32, stop_pos as u32, level) {
+                    Ok(bigwig_output) => bigwig_output,
+                    Err(_error) => return Err(query_failed()),
+                };
+            for entry in bigwig_output {
+                match entry {
+                    Ok(v) => {
+                        //println!(
+                        //    "start,end,sum,bases_covered:{},{},{},{}",
+                        //    v.start, v.end, v.summary.sum, v.summary.bases_covered
+                        //);
+                        if v.summary.bases_covered == 0 {
+                            // Zoom record without any data, its mean would be NaN
+                            continue;
                         }
+                        add_entry(
+                            &mut bins,
+                            &mut i,
+                            &BigwigEntry {
+                                start: v.start,
+                                end: v.end,
+                                mean: v.summary.sum / v.summary.bases_covered as f64,
+                                mean_square: v.summary.sum_squares / v.summary.bases_covered as f64,
+                                min: v.summary.min_val,
+                                max: v.summary.max_val,
+                                coverage: v.summary.bases_covered as f64
+                                    / determine_max((v.end - v.start) as f64, 1.0),
+                            },
+                        );
                     }
+                    Err(_error) => return Err(query_failed()),
                 }
             }
         }
-    } else {
-        // Read from a local file
-        let mut reader = BigWigRead::from_file_and_attach(&bigwig_file_url).unwrap();
-        match zoom_level {
-            Some(level) => {
-                // Using some zoom level
-                let bigwig_output = reader
-                    .get_zoom_interval(&chrom, start_pos as u32, stop_pos as u32, level)
-                    .unwrap();
-                let mut i = 0;
-                let mut start_region = datapoints_list[i];
-                let mut end_region = datapoints_list[i + 1];
-                for entry in bigwig_output {
-                    match entry {
-                        Ok(v) => {
-                            //println!(
-                            //    "start,end,sum,bases_covered:{},{},{},{}",
-                            //    v.start, v.end, v.summary.sum, v.summary.bases_covered
-                            //);
-
-                            if v.start == v.end {
-                                continue;
-                            } else {
-                                if (v.start as f64 <= start_region && end_region < v.end as f64)
-                                    || (v.start as f64 >= start_region
-                                        && (v.start as f64) < end_region)
-                                    || (v.end as f64 >= start_region && (v.end as f64) < end_region)
-                                    || (start_region >= v.start as f64
-                                        && (v.end as f64) < end_region)
-                                {
-                                    // Calculate sum and number for this region
-                                    //println!("i:{}", i);
-                                    //println!("v.start:{}", v.start);
-                                    //println!("v.end:{}", v.end);
-                                    //println!("start_region:{}", start_region);
-                                    //println!("end_region:{}", end_region);
-                                    let start_entry_within_region =
-                                        determine_max(v.start as f64, start_region);
-                                    let stop_entry_within_region =
-                                        determine_min(v.end as f64, end_region);
-                                    datapoints_num[i] += (stop_entry_within_region
-                                        - start_entry_within_region)
-                                        as f64;
-                                    datapoints_sum[i] += (stop_entry_within_region
-                                        - start_entry_within_region)
-                                        as f64
-                                        * ((v.summary.sum as f64) / v.summary.bases_covered as f64);
-                                    //println!(
-                                    //    "start_entry_within_region:{}",
-                                    //    start_entry_within_region
-                                    //);
-                                    //println!(
-                                    //    "stop_entry_within_region:{}",
-                                    //    stop_entry_within_region
-                                    //);
-                                    //println!("datapoints_num[i]:{}", datapoints_num[i]);
-                                    //println!("datapoints_sum[i]:{}", datapoints_sum[i]);
-                                }
-                                let mut iter = 1;
-                                while end_region < v.end as f64 && end_region != stop_pos {
-                                    //println!("iter:{}", iter);
-                                    //println!("i:{}", i);
-                                    //println!("v.start:{}", v.start);
-                                    //println!("v.end:{}", v.end);
-                                    //println!("start_region:{}", start_region);
-                                    //println!("end_region:{}", end_region);
-                                    if ((v.start as f64 <= start_region
-                                        && end_region < v.end as f64)
-                                        || (v.start as f64 >= start_region
-                                            && (v.start as f64) < end_region)
-                                        || (v.end as f64 >= start_region
-                                            && (v.end as f64) < end_region)
-                                        || (start_region >= v.start as f64
-                                            && (v.end as f64) < end_region))
-                                        && iter > 1
-                                    {
-                                        // Calculate sum and number for this region
-                                        let start_entry_within_region =
-                                            determine_max(v.start as f64, start_region);
-                                        let stop_entry_within_region =
-                                            determine_min(v.end as f64, end_region);
-                                        datapoints_num[i] += (stop_entry_within_region
-                                            - start_entry_within_region)
-                                            as f64;
-                                        datapoints_sum[i] += (stop_entry_within_region
-                                            - start_entry_within_region)
-                                            as f64
-                                            * ((v.summary.sum as f64)
-                                                / v.summary.bases_covered as f64);
-                                        //println!(
-                                        //    "start_entry_within_region inside:{}",
-                                        //    start_entry_within_region
-                                        //);
-                                        //println!(
-                                        //    "stop_entry_within_region inside:{}",
-                                        //    stop_entry_within_region
-                                        //);
-                                        //println!("datapoints_num inside[i]:{}", datapoints_num[i]);
-                                        //println!("datapoints_sum inside[i]:{}", datapoints_sum[i]);
-                                    }
-                                    if end_region <= v.end as f64 {
-                                        // Entry spans into next region, need to increment iterator
-                                        if i + 2 < datapoints_list.len() {
-                                            i += 1;
-                                            start_region = datapoints_list[i];
-                                            end_region = datapoints_list[i + 1];
-                                        } else {
-                                            break;
-                                        }
-                                    }
-                                    iter += 1;
-                                }
-                            }
-                        }
-                        Err(_) => {
-                            println!("Possible error:{:?}", entry);
-                        }
-                    }
-                }
-            }
-            None => {
-                // To be used in nucleotide resolution
-                let bigwig_output = reader
-                    .get_interval(&chrom, start_pos as u32, stop_pos as u32)
-                    .unwrap();
-                let mut i = 0;
-                let mut start_region = datapoints_list[i];
-                let mut end_region = datapoints_list[i + 1];
-                for entry in bigwig_output {
-                    match entry {
-                        Ok(v) => {
-                            //println!("start,end,value:{},{},{}", v.start, v.end, v.value);
-                            if v.start == v.end {
-                                continue;
-                            } else {
-                                if (v.start as f64 <= start_region && end_region < v.end as f64)
-                                    || (v.start as f64 >= start_region
-                                        && (v.start as f64) < end_region)
-                                    || (v.end as f64 >= start_region && (v.end as f64) < end_region)
-                                    || (start_region >= v.start as f64
-                                        && (v.end as f64) < end_region)
-                                {
-                                    // Calculate sum and number for this region
-                                    //println!("i:{}", i);
-                                    //println!("v.start:{}", v.start);
-                                    //println!("v.end:{}", v.end);
-                                    //println!("start_region:{}", start_region);
-                                    //println!("end_region:{}", end_region);
-                                    let start_entry_within_region =
-                                        determine_max(v.start as f64, start_region);
-                                    let stop_entry_within_region =
-                                        determine_min(v.end as f64, end_region);
-                                    datapoints_num[i] += (stop_entry_within_region
-                                        - start_entry_within_region)
-                                        as f64;
-                                    datapoints_sum[i] += (stop_entry_within_region
-                                        - start_entry_within_region)
-                                        as f64
-                                        * v.value as f64;
-                                    //println!(
-                                    //    "start_entry_within_region:{}",
-                                    //    start_entry_within_region
-                                    //);
-                                    //println!(
-                                    //    "stop_entry_within_region:{}",
-                                    //    stop_entry_within_region
-                                    //);
-                                    //println!("datapoints_num[i]:{}", datapoints_num[i]);
-                                    //println!("datapoints_sum[i]:{}", datapoints_sum[i]);
-                                }
-                                let mut iter = 1;
-                                while end_region < v.end as f64 && end_region != stop_pos {
-                                    //println!("iter:{}", iter);
-                                    //println!("i:{}", i);
-                                    //println!("v.start:{}", v.start);
-                                    //println!("v.end:{}", v.end);
-                                    //println!("start_region:{}", start_region);
-                                    //println!("end_region:{}", end_region);
-                                    if ((v.start as f64 <= start_region
-                                        && end_region < v.end as f64)
-                                        || (v.start as f64 >= start_region
-                                            && (v.start as f64) < end_region)
-                                        || (v.end as f64 >= start_region
-                                            && (v.end as f64) < end_region)
-                                        || (start_region >= v.start as f64
-                                            && (v.end as f64) < end_region))
-                                        && iter > 1
-                                    {
-                                        // Calculate sum and number for this region
-                                        //println!("Hello");
-                                        let start_entry_within_region =
-                                            determine_max(v.start as f64, start_region);
-                                        let stop_entry_within_region =
-                                            determine_min(v.end as f64, end_region);
-                                        datapoints_num[i] += (stop_entry_within_region
-                                            - start_entry_within_region)
-                                            as f64;
-                                        datapoints_sum[i] += (stop_entry_within_region
-                                            - start_entry_within_region)
-                                            as f64
-                                            * v.value as f64;
-                                        //println!(
-                                        //    "start_entry_within_region inside:{}",
-                                        //    start_entry_within_region
-                                        //);
-                                        //println!(
-                                        //    "stop_entry_within_region inside:{}",
-                                        //    stop_entry_within_region
-                                        //);
-                                        //println!("datapoints_num inside[i]:{}", datapoints_num[i]);
-                                        //println!("datapoints_sum inside[i]:{}", datapoints_sum[i]);
-                                    }
-                                    if end_region <= v.end as f64 {
-                                        // Entry spans into next region, need to increment iterator
-                                        if i + 2 < datapoints_list.len() {
-                                            i += 1;
-                                            start_region = datapoints_list[i];
-                                            end_region = datapoints_list[i + 1];
-                                        } else {
-                                            break;
-                                        }
-                                    }
-                                    iter += 1;
-                                }
-                            }
-                        }
-                        Err(_) => {
-                            println!("Possible error:{:?}", entry);
-                        }
+        None => {
+            // To be used in nucleotide resolution
+            let bigwig_output = match reader.get_interval(chrom, start_pos as u32, stop_pos as u32)
+            {
+                Ok(bigwig_output) => bigwig_output,
+                Err(_error) => return Err(query_failed()),
+            };
+            for entry in bigwig_output {
+                match entry {
+                    Ok(v) => {
+                        //println!("start,end,value:{},{},{}", v.start, v.end, v.value);
+                        add_entry(
+                            &mut bins,
+                            &mut i,
+                            &BigwigEntry {
+                                start: v.start,
+                                end: v.end,
+                                mean: v.value as f64,
+                                mean_square: v.value as f64 * v.value as f64,
+                                min: v.value as f64,
+                                max: v.value as f64,
+                                coverage: 1.0,
+                            },
+                        );
                     }
+                    Err(_error) => return Err(query_failed()),
                 }
             }
         }
     }
+    Ok((zoom_level, bins))
+}
 
-    //calculate_datapoints(bigwig_output, chrom, start_pos, stop_pos, data_points);
+// Adds an entry to all the bins it overlaps, weighted by the number of bases of the entry within each bin. Entries are sorted by start position, so the search for the first overlapping bin starts from the bin of the previous entry (i)
+fn add_entry(bins: &mut BinAccumulator, i: &mut usize, entry: &BigwigEntry) {
+    if entry.start == entry.end {
+        return;
+    }
+    let num_bins = bins.bin_limits.len() - 1;
+    while *i + 1 < num_bins && bins.bin_limits[*i + 1] <= entry.start as f64 {
+        *i += 1;
+    }
+    let mut j = *i;
+    while j < num_bins && bins.bin_limits[j] < entry.end as f64 {
+        let start_entry_within_region = determine_max(entry.start as f64, bins.bin_limits[j]);
+        let stop_entry_within_region = determine_min(entry.end as f64, bins.bin_limits[j + 1]);
+        let overlap = stop_entry_within_region - start_entry_within_region;
+        if overlap > 0.0 {
+            bins.bases[j] += overlap;
+            bins.sum[j] += overlap * entry.mean;
+            bins.sum_squares[j] += overlap * entry.mean_square;
+            bins.covered[j] += overlap * entry.coverage;
+            bins.min[j] = Some(bins.min[j].map_or(entry.min, |x| determine_min(x, entry.min)));
+            bins.max[j] = Some(bins.max[j].map_or(entry.max, |x| determine_max(x, entry.max)));
+        }
+        j += 1;
+    }
+}
 
-    let mut output_vec: String = "".to_string();
-    for i in 0..datapoints_num.len() {
-        let mean;
-        if datapoints_num[i] == 0.0 {
-            mean = 0.0;
-        } else {
-            mean = datapoints_sum[i] / datapoints_num[i] as f64;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw_entry(start: u32, end: u32, value: f64) -> BigwigEntry {
+        BigwigEntry {
+            start,
+            end,
+            mean: value,
+            mean_square: value * value,
+            min: value,
+            max: value,
+            coverage: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_add_entry() {
+        // Bins 0-25, 25-50, 50-75, 75-100
+        let mut bins = BinAccumulator::new(0.0, 100.0, 4);
+        assert_eq!(bins.bin_limits, vec![0.0, 25.0, 50.0, 75.0, 100.0]);
+        let mut i: usize = 0;
+        add_entry(&mut bins, &mut i, &raw_entry(10, 30, 2.0));
+        add_entry(&mut bins, &mut i, &raw_entry(30, 60, 4.0));
+        add_entry(&mut bins, &mut i, &raw_entry(60, 60, 8.0)); // Empty entry is ignored
+
+        assert_eq!(bins.mean(0), Some(2.0));
+        assert_eq!(bins.coverage(0), 0.6);
+        // 5 bases of 2 and 20 bases of 4
+        assert_eq!(bins.mean(1), Some(3.6));
+        assert!((bins.std(1).unwrap() - 0.8).abs() < 1e-12);
+        assert_eq!(bins.coverage(1), 1.0);
+        assert_eq!(bins.min[1], Some(2.0));
+        assert_eq!(bins.max[1], Some(4.0));
+        assert_eq!(bins.sum[1], 90.0);
+        assert_eq!(bins.mean(2), Some(4.0));
+        assert_eq!(bins.coverage(2), 0.4);
+        assert_eq!(bins.mean(3), None);
+        assert_eq!(bins.std(3), None);
+        assert_eq!(bins.coverage(3), 0.0);
+
+        // Zoom record spanning all bins with data for half of its bases
+        let mut bins = BinAccumulator::new(0.0, 100.0, 4);
+        let mut i: usize = 0;
+        add_entry(
+            &mut bins,
+            &mut i,
+            &BigwigEntry {
+                start: 0,
+                end: 100,
+                mean: 3.0,
+                mean_square: 10.0,
+                min: 1.0,
+                max: 5.0,
+                coverage: 0.5,
+            },
+        );
+        for bin in 0..4 {
+            assert_eq!(bins.mean(bin), Some(3.0));
+            assert_eq!(bins.std(bin), Some(1.0));
+            assert_eq!(bins.coverage(bin), 0.5);
         }
-        output_vec.push_str(&mean.to_string());
-        output_vec.push_str(&"\t".to_string());
     }
-    output_vec.pop();
-    println!("{}", output_vec);
-}
\ No newline at end of file
+
+    #[test]
+    fn test_aggregate_values() {
+        let mut bins1 = BinAccumulator::new(0.0, 30.0, 3);
+        let mut bins2 = BinAccumulator::new(0.0, 30.0, 3);
+        let mut i: usize = 0;
+        add_entry(&mut bins1, &mut i, &raw_entry(0, 10, 1.0));
+        add_entry(&mut bins1, &mut i, &raw_entry(10, 20, 4.0));
+        let mut i: usize = 0;
+        add_entry(&mut bins2, &mut i, &raw_entry(0, 10, 1.5));
+        add_entry(&mut bins2, &mut i, &raw_entry(10, 20, 2.0));
+        bins2.sum[2] = f64::NAN; // Non-finite mean is treated as a bin without data
+        bins2.bases[2] = 10.0;
+
+        let values = vec![scaled_means(&bins1, 1.0), scaled_means(&bins2, 2.0)];
+        assert_eq!(values, vec![vec![1.0, 4.0, 0.0], vec![3.0, 4.0, 0.0]]);
+
+        let mut summary = AggregateSummary {
+            files: vec!["a.bw".to_string(), "b.bw".to_string()],
+            chr: "chr1".to_string(),
+            start: 0,
+            stop: 30,
+            datapoints: 3,
+            mean: Vec::new(),
+            median: Vec::new(),
+            p25: Vec::new(),
+            p75: Vec::new(),
+            zscore: None,
+        };
+        aggregate_values(&values, &mut summary, true);
+        assert_eq!(summary.mean, vec![2.0, 4.0, 0.0]);
+        assert_eq!(summary.median, vec![2.0, 4.0, 0.0]);
+        assert_eq!(summary.p25, vec![1.5, 4.0, 0.0]);
+        assert_eq!(summary.p75, vec![2.5, 4.0, 0.0]);
+        // Bins with the same value in all files have no z-score
+        let z = 1.0 / 2.0_f64.sqrt();
+        assert_eq!(
+            summary.zscore,
+            Some(vec![vec![Some(-z), None, None], vec![Some(z), None, None]])
+        );
+    }
+}
@@ -0,0 +1,633 @@
+/*
+ Syntax:
+ bedGraph: cd .. && cargo build --release && time echo '{"input":"/Users/rpaul1/proteinpaint/hg19/bigwig/file.bedgraph","format":"bedgraph","chrom_sizes":"/Users/rpaul1/proteinpaint/hg19/genome/hg19.chrom.sizes","output":"/Users/rpaul1/proteinpaint/hg19/bigwig/file.bw"}' | target/release/bigwigwrite
+
+ WIG: cd .. && cargo build --release && time echo '{"input":"/Users/rpaul1/proteinpaint/hg19/bigwig/file.wig","format":"wig","chrom_sizes":"/Users/rpaul1/proteinpaint/hg19/genome/hg19.chrom.sizes","output":"/Users/rpaul1/proteinpaint/hg19/bigwig/file.bw","max_zooms":8}' | target/release/bigwigwrite
+
+ Per-base coverage piped after the JSON line (e.g. from samtools depth):
+ cd .. && cargo build --release && time (echo '{"format":"coverage","chrom_sizes":"/Users/rpaul1/proteinpaint/hg19/genome/hg19.chrom.sizes","output":"/Users/rpaul1/proteinpaint/hg19/bigwig/file.bw"}' && samtools depth /Users/rpaul1/proteinpaint/hg19/bam/file.bam) | target/release/bigwigwrite
+*/
+
+/*
+Notes:
+   The script accepts a JSON request { input, format, chrom_sizes, output, max_zooms, compress, threads } on the first line of stdin:
+      input: Path to the input file. When missing, the input is read from the remaining lines of stdin
+      format: "bedgraph" (chr start end value, 0-based), "wig" (variableStep/fixedStep) or "coverage" (chr pos depth, 1-based per-base coverage as printed by samtools depth)
+      chrom_sizes: Path to the chrom.sizes file (chr and length separated by whitespace)
+      output: Path to the output bigWig file
+      max_zooms: Maximum number of zoom levels (default 10)
+      compress: Compress the data blocks (default true)
+      threads: Number of threads used to compress the data blocks (default 6)
+
+   The input must be sorted by position within each chromosome and all lines of a chromosome must be contiguous, same as required by bedGraphToBigWig. The chromosomes can be in any order (e.g. chr1, chr2, ..., chr10 as printed by samtools depth). In coverage format consecutive bases with the same depth are merged into a single value and bases with depth 0 are skipped. Lines starting with "track", "browser" or "#" and empty lines are skipped
+
+   The values are parsed lazily while the bigWig is written, so that the whole input is never held in memory. When a line cannot be parsed the parsing stops, the incomplete output file is removed and the error is returned.
+
+   The response is { output, values } where values is the number of values written, or { error: { kind, message } } where kind is "invalid_input", "cannot_read_file" or "write_failed"
+
+   Function cascade:
+
+   main() - Parses the JSON request.
+     read_chrom_sizes() - Reads the chrom.sizes file.
+     write_bigwig() - Writes the values returned by ValueParser with the bigtools writer.
+       ValueParser::next() - Parses the next value from the input and checks that it is sorted and within the chromosome.
+*/
+
+use bigtools::bed::bedparser::BedParser;
+use bigtools::bedchromdata::BedParserStreamingIterator;
+use bigtools::bigwig::Value;
+use bigtools::bigwigwrite::BigWigWrite;
+use json::JsonValue;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::io::{BufRead, BufReader};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct WriteError {
+    kind: String, // "invalid_input", "cannot_read_file" or "write_failed"
+    message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ErrorResponse {
+    error: WriteError,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct WriteResponse {
+    output: String,
+    values: u64, // Number of values written
+}
+
+// Step settings of the current WIG section
+#[derive(Debug, Clone)]
+struct WigSection {
+    chrom: String,
+    fixed_step: bool,        // fixedStep when true, variableStep when false
+    next_start: Option<u32>, // 0-based start of the next value (fixedStep), None when it is beyond the u32 range
+    step: u32,               // Distance between the start of consecutive values (fixedStep)
+    span: u32,               // Number of bases covered by each value
+}
+
+// Iterator parsing the values from the input lines. Errors are stored in error and end the iteration, since the bigtools writer consumes the values as an infallible iterator
+struct ValueParser {
+    lines: io::Lines<Box<dyn BufRead + Send>>,
+    format: String,
+    line_number: usize,
+    chrom_sizes: HashMap<String, u32>,
+    previous: Option<(String, u32)>, // Chromosome and end of the last value, used for checking that the input is sorted
+    finished_chroms: HashSet<String>, // Chromosomes before the current one
+    wig_section: Option<WigSection>,
+    coverage_run: Option<(String, Value, usize)>, // Run of consecutive bases with the same depth and line number of its first base (coverage format)
+    count: Arc<Mutex<u64>>,                       // Number of values returned
+    error: Arc<Mutex<Option<String>>>,
+}
+
+impl Iterator for ValueParser {
+    type Item = (String, Value);
+
+    fn next(&mut self) -> Option<(String, Value)> {
+        loop {
+            let line = match self.lines.next() {
+                Some(Ok(line)) => line,
+                Some(Err(error)) => {
+                    return self.fail(format!("Cannot read input: {}", error));
+                }
+                None => {
+                    // End of input, the last coverage run is returned
+                    return match self.coverage_run.take() {
+                        Some((chrom, value, line_number)) => {
+                            self.checked(chrom, value, line_number)
+                        }
+                        None => None,
+                    };
+                }
+            };
+            self.line_number += 1;
+            let line = line.trim();
+            if line.is_empty()
+                || line.starts_with("#")
+                || line.starts_with("track")
+                || line.starts_with("browser")
+            {
+                continue;
+            }
+            let parsed = if self.format == "bedgraph" {
+                self.parse_bedgraph_line(line)
+            } else if self.format == "wig" {
+                self.parse_wig_line(line)
+            } else {
+                self.parse_coverage_line(line)
+            };
+            match parsed {
+                Ok(Some((chrom, value, line_number))) => {
+                    return self.checked(chrom, value, line_number)
+                }
+                Ok(None) => continue,
+                Err(message) => {
+                    return self.fail(format!("Line {}: {}", self.line_number, message))
+                }
+            }
+        }
+    }
+}
+
+impl ValueParser {
+    fn fail(&mut self, message: String) -> Option<(String, Value)> {
+        *self.error.lock().unwrap() = Some(message);
+        None
+    }
+
+    // Checks that the value is within the chromosome and sorted with respect to the previous value. line_number is the line where the value starts, reported in the error
+    fn checked(
+        &mut self,
+        chrom: String,
+        value: Value,
+        line_number: usize,
+    ) -> Option<(String, Value)> {
+        let chrom_size = match self.chrom_sizes.get(&chrom) {
+            Some(chrom_size) => *chrom_size,
+            None => {
+                return self.fail(format!(
+                    "Line {}: chromosome {} is not in chrom_sizes",
+                    line_number, chrom
+                ))
+            }
+        };
+        if value.start >= value.end || value.end > chrom_size {
+            return self.fail(format!(
+                "Line {}: {}:{}-{} is not within the chromosome (length {})",
+                line_number, chrom, value.start, value.end, chrom_size
+            ));
+        }
+        match self.previous.take() {
+            Some((previous_chrom, previous_end))
+                if previous_chrom == chrom && value.start < previous_end =>
+            {
+                return self.fail(format!(
+                    "Line {}: input is not sorted or has overlapping values at {}:{}",
+                    line_number, chrom, value.start
+                ));
+            }
+            Some((previous_chrom, _previous_end)) if previous_chrom == chrom => {}
+            Some((previous_chrom, _previous_end)) => {
+                self.finished_chroms.insert(previous_chrom);
+                if self.finished_chroms.contains(&chrom) {
+                    return self.fail(format!(
+                        "Line {}: lines of chromosome {} are not contiguous",
+                        line_number, chrom
+                    ));
+                }
+            }
+            None => {}
+        }
+        self.previous = Some((chrom.to_string(), value.end));
+        *self.count.lock().unwrap() += 1;
+        Some((chrom, value))
+    }
+
+    // chr start end value
+    fn parse_bedgraph_line(
+        &mut self,
+        line: &str,
+    ) -> Result<Option<(String, Value, usize)>, String> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 4 {
+            return Err("bedGraph line must have 4 fields".to_string());
+        }
+        Ok(Some((
+            fields[0].to_string(),
+            Value {
+                start: parse_number(fields[1], "start")?,
+                end: parse_number(fields[2], "end")?,
+                value: parse_number(fields[3], "value")?,
+            },
+            self.line_number,
+        )))
+    }
+
+    // Declaration lines (variableStep chrom=chr1 span=10, fixedStep chrom=chr1 start=1 step=10 span=5) or data lines
+    fn parse_wig_line(&mut self, line: &str) -> Result<Option<(String, Value, usize)>, String> {
+        if line.starts_with("variableStep") || line.starts_with("fixedStep") {
+            let mut section = WigSection {
+                chrom: String::new(),
+                fixed_step: line.starts_with("fixedStep"),
+                next_start: Some(0),
+                step: 1,
+                span: 1,
+            };
+            for setting in line.split_whitespace().skip(1) {
+                let (key, value) = match setting.split_once('=') {
+                    Some(key_value) => key_value,
+                    None => return Err(format!("Invalid setting {}", setting)),
+                };
+                match key {
+                    "chrom" => section.chrom = value.to_string(),
+                    "start" => {
+                        let start: u32 = parse_number(value, "start")?;
+                        if start == 0 {
+                            return Err("start is 1-based".to_string());
+                        }
+                        section.next_start = Some(start - 1);
+                    }
+                    "step" => section.step = parse_number(value, "step")?,
+                    "span" => section.span = parse_number(value, "span")?,
+                    _ => return Err(format!("Unknown setting {}", key)),
+                }
+            }
+            if section.chrom.is_empty() {
+                return Err("chrom is missing".to_string());
+            }
+            self.wig_section = Some(section);
+            return Ok(None);
+        }
+        let section = match self.wig_section.as_mut() {
+            Some(section) => section,
+            None => return Err("Data line before variableStep/fixedStep".to_string()),
+        };
+        let start: u32 = if section.fixed_step {
+            let start = match section.next_start {
+                Some(start) => start,
+                None => return Err("position is out of range".to_string()),
+            };
+            section.next_start = start.checked_add(section.step);
+            start
+        } else {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 2 {
+                return Err("variableStep line must have 2 fields".to_string());
+            }
+            let position: u32 = parse_number(fields[0], "position")?;
+            if position == 0 {
+                return Err("position is 1-based".to_string());
+            }
+            position - 1
+        };
+        let end = match start.checked_add(section.span) {
+            Some(end) => end,
+            None => return Err("position is out of range".to_string()),
+        };
+        let value: f32 = parse_number(line.split_whitespace().last().unwrap(), "value")?;
+        Ok(Some((
+            section.chrom.to_string(),
+            Value { start, end, value },
+            self.line_number,
+        )))
+    }
+
+    // chr pos depth. The run of the previous bases is returned when the chromosome or depth changes or a base is skipped
+    fn parse_coverage_line(
+        &mut self,
+        line: &str,
+    ) -> Result<Option<(String, Value, usize)>, String> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 3 {
+            return Err("coverage line must have 3 fields".to_string());
+        }
+        let position: u32 = parse_number(fields[1], "position")?;
+        if position == 0 {
+            return Err("position is 1-based".to_string());
+        }
+        let depth: f32 = parse_number(fields[2], "depth")?;
+        let start = position - 1;
+        if let Some((chrom, run, _line_number)) = self.coverage_run.as_mut() {
+            if chrom == fields[0] && run.end == start && run.value == depth {
+                run.end += 1;
+                return Ok(None);
+            }
+        }
+        let new_run = if depth == 0.0 {
+            None
+        } else {
+            Some((
+                fields[0].to_string(),
+                Value {
+                    start,
+                    end: start + 1,
+                    value: depth,
+                },
+                self.line_number,
+            ))
+        };
+        Ok(std::mem::replace(&mut self.coverage_run, new_run))
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str, name: &str) -> Result<T, String> {
+    match value.parse::<T>() {
+        Ok(number) => Ok(number),
+        Err(_) => Err(format!("Invalid {}: {}", name, value)),
+    }
+}
+
+fn main() {
+    let mut input = String::new();
+    match io::stdin().read_line(&mut input) {
+        // Accepting the piped input from nodejs (or command line from testing)
+        Ok(_) => {}
+        Err(error) => println!("Piping error: {}", error),
+    }
+    match write_bigwig(&input) {
+        Ok(response) => println!("{}", serde_json::to_string(&response).unwrap()),
+        Err(error) => println!(
+            "{}",
+            serde_json::to_string(&ErrorResponse { error }).unwrap()
+        ),
+    }
+}
+
+fn invalid_input(message: String) -> WriteError {
+    WriteError {
+        kind: "invalid_input".to_string(),
+        message,
+    }
+}
+
+fn cannot_read(message: String) -> WriteError {
+    WriteError {
+        kind: "cannot_read_file".to_string(),
+        message,
+    }
+}
+
+// Parses the request and writes the bigWig file
+fn write_bigwig(input: &str) -> Result<WriteResponse, WriteError> {
+    let json_string: JsonValue = match json::parse(input) {
+        Ok(json_string) => json_string,
+        Err(error) => return Err(invalid_input(format!("Incorrect json: {}", error))),
+    };
+    let format: String = match json_string["format"].as_str() {
+        Some(format @ ("bedgraph" | "wig" | "coverage")) => format.to_string(),
+        _ => {
+            return Err(invalid_input(
+                "format must be bedgraph, wig or coverage".to_string(),
+            ))
+        }
+    };
+    let chrom_sizes_file: String = match json_string["chrom_sizes"].as_str() {
+        Some(chrom_sizes_file) => chrom_sizes_file.to_string(),
+        None => return Err(invalid_input("chrom_sizes is missing".to_string())),
+    };
+    let output: String = match json_string["output"].as_str() {
+        Some(output) => output.to_string(),
+        None => return Err(invalid_input("output is missing".to_string())),
+    };
+    let max_zooms: u32 = json_string["max_zooms"].as_u32().unwrap_or(10);
+    let compress: bool = json_string["compress"].as_bool().unwrap_or(true);
+    let threads: usize = match json_string["threads"].as_usize() {
+        Some(threads) if threads > 0 => threads,
+        _ => 6,
+    };
+
+    let chrom_sizes = read_chrom_sizes(&chrom_sizes_file)?;
+    let reader: Box<dyn BufRead + Send> = match json_string["input"].as_str() {
+        Some(input_file) => match fs::File::open(input_file) {
+            Ok(file) => Box::new(BufReader::new(file)),
+            Err(error) => {
+                return Err(cannot_read(format!(
+                    "Cannot read {}: {}",
+                    input_file, error
+                )))
+            }
+        },
+        None => Box::new(BufReader::new(io::stdin())), // Input lines follow the JSON line
+    };
+
+    let count = Arc::new(Mutex::new(0u64));
+    let error = Arc::new(Mutex::new(Option::<String>::None));
+    let parser = ValueParser {
+        lines: reader.lines(),
+        format,
+        line_number: if json_string["input"].is_null() { 1 } else { 0 }, // The JSON request is line 1 when the input is read from stdin
+        chrom_sizes: chrom_sizes.clone(),
+        previous: None,
+        finished_chroms: HashSet::new(),
+        wig_section: None,
+        coverage_run: None,
+        count: Arc::clone(&count),
+        error: Arc::clone(&error),
+    };
+
+    let mut writer = BigWigWrite::create_file(output.to_string());
+    writer.options.max_zooms = max_zooms;
+    writer.options.compress = compress;
+    let pool = match futures::executor::ThreadPoolBuilder::new()
+        .pool_size(threads)
+        .create()
+    {
+        Ok(pool) => pool,
+        Err(error) => {
+            return Err(WriteError {
+                kind: "write_failed".to_string(),
+                message: format!("Cannot create thread pool: {}", error),
+            })
+        }
+    };
+    let values = BedParserStreamingIterator::new(BedParser::wrap_infallible_iter(parser), true); // The chromosomes are not required to be in lexicographic order, ValueParser checks that they are contiguous
+    let write_result = writer.write(chrom_sizes, values, pool);
+
+    if let Some(message) = error.lock().unwrap().take() {
+        // The bigWig file only contains the values before the error
+        fs::remove_file(&output).ok();
+        return Err(invalid_input(message));
+    }
+    if let Err(error) = write_result {
+        fs::remove_file(&output).ok();
+        return Err(WriteError {
+            kind: "write_failed".to_string(),
+            message: format!("Cannot write {}: {}", output, error),
+        });
+    }
+    let values = *count.lock().unwrap();
+    Ok(WriteResponse { output, values })
+}
+
+// Reads the chrom.sizes file (chromosome name and length separated by whitespace on each line)
+fn read_chrom_sizes(chrom_sizes_file: &String) -> Result<HashMap<String, u32>, WriteError> {
+    let text = match fs::read_to_string(chrom_sizes_file) {
+        Ok(text) => text,
+        Err(error) => {
+            return Err(cannot_read(format!(
+                "Cannot read {}: {}",
+                chrom_sizes_file, error
+            )))
+        }
+    };
+    let mut chrom_sizes = HashMap::<String, u32>::new();
+    for line in text.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.is_empty() {
+            continue;
+        }
+        if fields.len() < 2 {
+            return Err(invalid_input(format!(
+                "chrom_sizes line must have 2 fields: {}",
+                line
+            )));
+        }
+        match fields[1].parse::<u32>() {
+            Ok(size) => chrom_sizes.insert(fields[0].to_string(), size),
+            Err(_) => {
+                return Err(invalid_input(format!(
+                    "Invalid chromosome length in chrom_sizes: {}",
+                    line
+                )))
+            }
+        };
+    }
+    Ok(chrom_sizes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // Values (chrom, start, end, value) returned by the parser
+    type ParsedValues = Vec<(String, u32, u32, f32)>;
+
+    // Parses input in the given format and returns the values and the error, if any
+    fn parse_values(format: &str, input: &str) -> (ParsedValues, Option<String>) {
+        let mut chrom_sizes = HashMap::<String, u32>::new();
+        chrom_sizes.insert("chr1".to_string(), 1000);
+        chrom_sizes.insert("chr2".to_string(), 1000);
+        let reader: Box<dyn BufRead + Send> = Box::new(Cursor::new(input.as_bytes().to_vec()));
+        let error = Arc::new(Mutex::new(Option::<String>::None));
+        let parser = ValueParser {
+            lines: reader.lines(),
+            format: format.to_string(),
+            line_number: 0,
+            chrom_sizes,
+            previous: None,
+            finished_chroms: HashSet::new(),
+            wig_section: None,
+            coverage_run: None,
+            count: Arc::new(Mutex::new(0)),
+            error: Arc::clone(&error),
+        };
+        let values = parser
+            .map(|(chrom, value)| (chrom, value.start, value.end, value.value))
+            .collect();
+        let error = error.lock().unwrap().take();
+        (values, error)
+    }
+
+    #[test]
+    fn test_parse_wig_line() {
+        let input = "track type=wiggle_0\n\
+                     variableStep chrom=chr1 span=5\n\
+                     10 1.5\n\
+                     20 2\n\
+                     fixedStep chrom=chr2 start=101 step=10 span=5\n\
+                     3\n\
+                     4\n";
+        let (values, error) = parse_values("wig", input);
+        assert_eq!(error, None);
+        assert_eq!(
+            values,
+            vec![
+                ("chr1".to_string(), 9, 14, 1.5),
+                ("chr1".to_string(), 19, 24, 2.0),
+                ("chr2".to_string(), 100, 105, 3.0),
+                ("chr2".to_string(), 110, 115, 4.0),
+            ]
+        );
+
+        // Data line before the declaration line
+        let (values, error) = parse_values("wig", "10 1.5\n");
+        assert_eq!(values, vec![]);
+        assert_eq!(
+            error,
+            Some("Line 1: Data line before variableStep/fixedStep".to_string())
+        );
+        // Overlapping values
+        let (values, error) = parse_values("wig", "variableStep chrom=chr1 span=10\n10 1\n15 2\n");
+        assert_eq!(values, vec![("chr1".to_string(), 9, 19, 1.0)]);
+        assert_eq!(
+            error,
+            Some("Line 3: input is not sorted or has overlapping values at chr1:14".to_string())
+        );
+        // End of the value beyond the u32 range
+        let (values, error) = parse_values(
+            "wig",
+            "fixedStep chrom=chr1 start=4294967295 step=1 span=5\n1\n",
+        );
+        assert_eq!(values, vec![]);
+        assert_eq!(error, Some("Line 2: position is out of range".to_string()));
+    }
+
+    #[test]
+    fn test_parse_coverage_line() {
+        // Consecutive bases with the same depth are merged, a skipped base, a depth change or a new chromosome ends the run and bases with depth 0 are skipped
+        let input = "chr1 1 2\n\
+                     chr1 2 2\n\
+                     chr1 3 2\n\
+                     chr1 4 5\n\
+                     chr1 6 5\n\
+                     chr1 7 0\n\
+                     chr1 8 0\n\
+                     chr1 9 5\n\
+                     chr2 1 5\n\
+                     chr2 2 5\n";
+        let (values, error) = parse_values("coverage", input);
+        assert_eq!(error, None);
+        assert_eq!(
+            values,
+            vec![
+                ("chr1".to_string(), 0, 3, 2.0),
+                ("chr1".to_string(), 3, 4, 5.0),
+                ("chr1".to_string(), 5, 6, 5.0),
+                ("chr1".to_string(), 8, 9, 5.0),
+                ("chr2".to_string(), 0, 2, 5.0),
+            ]
+        );
+
+        // Lines of a chromosome are not contiguous
+        let (values, error) = parse_values("coverage", "chr1 1 2\nchr2 1 2\nchr1 5 2\n");
+        assert_eq!(
+            values,
+            vec![
+                ("chr1".to_string(), 0, 1, 2.0),
+                ("chr2".to_string(), 0, 1, 2.0)
+            ]
+        );
+        assert_eq!(
+            error,
+            Some("Line 3: lines of chromosome chr1 are not contiguous".to_string())
+        );
+        // The error of a run is reported at the line of its first base
+        let (values, error) = parse_values("coverage", "chr3 1 2\nchr3 2 2\nchr3 3 2\nchr3 4 5\n");
+        assert_eq!(values, vec![]);
+        assert_eq!(
+            error,
+            Some("Line 1: chromosome chr3 is not in chrom_sizes".to_string())
+        );
+    }
+
+    #[test]
+    fn test_write_bigwig() {
+        // Chromosomes in the order printed by samtools depth, which is not lexicographic (chr10 before chr2)
+        let dir = std::env::temp_dir().join(format!("bigwigwrite_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let chrom_sizes_file = dir.join("chrom.sizes");
+        let input_file = dir.join("input.txt");
+        let output_file = dir.join("output.bw");
+        fs::write(&chrom_sizes_file, "chr1\t1000\nchr2\t1000\nchr10\t1000\n").unwrap();
+        fs::write(&input_file, "chr1 1 2\nchr2 1 3\nchr2 2 3\nchr10 5 1\n").unwrap();
+        let request = json::object! {
+            "input" => input_file.to_str().unwrap(),
+            "format" => "coverage",
+            "chrom_sizes" => chrom_sizes_file.to_str().unwrap(),
+            "output" => output_file.to_str().unwrap(),
+        };
+        let result = write_bigwig(&request.dump());
+        fs::remove_dir_all(&dir).ok();
+        match result {
+            Ok(response) => assert_eq!(response.values, 3),
+            Err(error) => panic!("{}", error.message),
+        }
+    }
+}
@@ -5,8 +5,11 @@
 
 // Input JSON specifications:
 // {
-//   mtc: fdr/bon (optional). If some other string is used in this field, the script panics.
-//   skipLowSampleSize: Flag to check if entries with low sample size need to be ignored (true/false) (optional). When entry follows this criteria total < 2000.0 && ((n1 + n2) / total < 0.03 || (n1 + n3)  / total < 0.03 || (n2 + n4)  / total < 0.03 || (n3 + n4)  / total < 0.03)
+//   mtc: Multiple testing correction (optional), fdr/BH, BY, holm, hochberg, hommel, bon/bonferroni or qvalue (see multiple_testing.rs). If some other string is used in this field, an error is returned.
+//   skipLowSampleSize: Flag to check if entries with low sample size need to be ignored (true/false) (optional). When entry follows this criteria total < 2000.0 && ((n1 + n2) / total < 0.03 || (n1 + n3)  / total < 0.03 || (n2 + n4)  / total < 0.03 || (n3 + n4)  / total < 0.03). For R x C tables the same criteria is applied to every row and column sum
+//   montecarlo: Number of random tables for a Monte Carlo p-value of R x C tables instead of the exact test (optional)
+//   seed: Seed of the random tables of the Monte Carlo p-value (optional, default 0)
+//   risk_difference_method: Confidence interval of the risk difference of 2x2 tables, newcombe (Newcombe hybrid score interval) or wald (optional, default newcombe)
 //   input:[{
 //     index: Index of the entry
 //        n1:
@@ -14,9 +17,22 @@
 //        n3:
 //        n4:
 //   }]
+//   An entry may give an R x C table of counts instead of n1..n4: { index: Index of the entry, table: [[row 1 counts], [row 2 counts], ...] }
+//   or a list of 2x2 strata (e.g. ancestry groups or sequencing batches) for the Cochran-Mantel-Haenszel test: { index: Index of the entry, strata: [{ n1, n2, n3, n4 }, ...] }
 // }
 
-// NOTE: For now harcoded fisher.rs to ALWAYS use fisher's test
+// Invalid options and malformed tables or strata are reported as { error: { kind: "invalid_input", message } } instead of the results
+
+// NOTE: For now harcoded fisher.rs to ALWAYS use fisher's test for 2x2 tables
+
+/*
+    R x C tables are tested with the Fisher-Freeman-Halton exact test (network algorithm, same p-value as fisher.test() in R), or with a
+    Monte Carlo p-value when montecarlo is given. When the exact test is too large to carry out, the chi-square test is used if every
+    expected value is at least 5, otherwise a Monte Carlo p-value with DEFAULT_MONTECARLO_REPLICATES random tables.
+
+    Stratified entries are tested with the Cochran-Mantel-Haenszel test (with continuity correction, same as mantelhaen.test() in R).
+    skipLowSampleSize is applied to the 2x2 table summed over the strata.
+*/
 
 /*
     Suggested by Qian & Yutaka:
@@ -31,7 +47,7 @@
 */
 
 // Output JSON specifications
-//    When fdr is not specified
+//    When mtc is not specified
 //
 //    [{ Array of json objects
 //      index:
@@ -39,20 +55,35 @@
 //      n2:
 //      n3:
 //      n4:
+//      table: (instead of n1..n4 for R x C tables)
+//      strata: (instead of n1..n4 for stratified entries)
 //      p_value: p-value from fisher/chisq test, "null" for cases with low sample sizes
-//      fisher_chisq: "NA" for cases with low sample size, "fisher" when fisher test is used, "chisq" when chisq test is used
+//      fisher_chisq: "NA" for cases with low sample size (or stratified entries where no stratum has both rows and columns), "fisher" when fisher test is used, "chisq" when chisq test is used, "fisher_montecarlo" when the Monte Carlo p-value is used, "cmh" for the Cochran-Mantel-Haenszel test
+//      breslow_day_p_value: p-value of the Breslow-Day test of homogeneity of the odds ratios of the strata (stratified entries only)
+//      Effect sizes (reported also for cases with low sample size, infinite values are null):
+//      odds_ratio: conditional maximum likelihood estimate of the odds ratio (2x2 only), same as fisher.test() in R. Mantel-Haenszel common odds ratio for stratified entries
+//      odds_ratio_ci: [lower, upper] exact 95% confidence interval of the odds ratio (2x2 only). Robins-Breslow-Greenland 95% confidence interval for stratified entries
+//      relative_risk: (n1 / (n1 + n2)) / (n3 / (n3 + n4)) (2x2 only, absent when n1 or n3 is 0)
+//      relative_risk_ci: [lower, upper] 95% Wald confidence interval of the log relative risk (2x2 only)
+//      risk_difference: n1 / (n1 + n2) - n3 / (n3 + n4) (2x2 only, absent when a row is empty)
+//      risk_difference_ci: [lower, upper] 95% confidence interval of the risk difference (2x2 only), computed by risk_difference_method
+//      risk_difference_method: "newcombe" or "wald", the method of risk_difference_ci (2x2 only, absent when a row is empty)
+//      cramers_v: Cramer's V (R x C tables only)
 //    }]
 
-//    When fdr is specified
+//    When mtc is specified
 //    [{ Array of json objects
 //      index:
 //      n1:
 //      n2:
 //      n3:
 //      n4:
+//      table: (instead of n1..n4 for R x C tables)
+//      strata: (instead of n1..n4 for stratified entries)
 //      p_value: p-value from fisher/chisq test, "null" for cases with low sample sizes
-//      adjusted_p_value: adjusted p-value using Benjamini-Hochberg/Bonferonni correction, "null" for cases with low sample sizes
-//      fisher_chisq: "NA" for cases with low sample size, "fisher" when fisher test is used, "chisq" when chisq test is used
+//      adjusted_p_value: adjusted p-value (or q-value) using the correction given by mtc, "null" for cases with low sample sizes
+//      fisher_chisq: "NA" for cases with low sample size, "fisher" when fisher test is used, "chisq" when chisq test is used, "fisher_montecarlo" when the Monte Carlo p-value is used
+//      breslow_day_p_value, effect sizes as above
 //    }]
 //
 // Example of json input containing the fdr flag
@@ -75,6 +106,16 @@
 
 // cd ~/proteinpaint/rust && cargo build --release && json='{"mtc":"fdr","input":[{"index":0,"n1":214,"n2":2057,"n3":134,"n4":1954},{"index":1,"n1":134,"n2":1954,"n3":214,"n4":2057},{"index":2,"n1":1863,"n2":225,"n3":1935,"n4":336},{"index":3,"n1":1935,"n2":336,"n3":1863,"n4":225},{"index":4,"n1":106,"n2":2165,"n3":74,"n4":2014},{"index":5,"n1":74,"n2":2014,"n3":106,"n4":2165}]}' && time echo "$json" | target/release/fisher
 
+// Example of json input with R x C tables
+
+// cd ~/proteinpaint/rust && cargo build --release && json='{"mtc":"fdr","input":[{"index":0,"table":[[3,1,6],[10,12,4]]},{"index":1,"table":[[12,30,41],[21,19,33],[4,9,2]]},{"index":2,"n1":10,"n2":22,"n3":9,"n4":15}]}' && time echo "$json" | target/release/fisher
+
+// cd ~/proteinpaint/rust && cargo build --release && json='{"montecarlo":10000,"seed":1,"input":[{"index":0,"table":[[3,1,6],[10,12,4]]},{"index":1,"table":[[12,30,41],[21,19,33],[4,9,2]]}]}' && time echo "$json" | target/release/fisher
+
+// Example of json input with stratified entries
+
+// cd ~/proteinpaint/rust && cargo build --release && json='{"mtc":"bon","input":[{"index":0,"strata":[{"n1":0,"n2":0,"n3":6,"n4":5},{"n1":3,"n2":0,"n3":3,"n4":6},{"n1":6,"n2":2,"n3":0,"n4":4},{"n1":5,"n2":6,"n3":1,"n4":0},{"n1":2,"n2":5,"n3":0,"n4":0}]},{"index":1,"n1":10,"n2":22,"n3":9,"n4":15}]}' && time echo "$json" | target/release/fisher
+
 // Example of json input missing the fdr/bon flag
 
 // cd ~/proteinpaint/rust && cargo build --release && json='{"input":[{"index":0,"n1":514,"n2":626,"n3":45,"n4":106},{"index":1,"n1":11,"n2":948,"n3":364,"n4":292},{"index":2,"n1":129,"n2":951,"n3":531,"n4":268},{"index":3,"n1":677,"n2":40,"n3":11,"n4":837},{"index":4,"n1":947,"n2":937,"n3":245,"n4":817},{"index":5,"n1":589,"n2":889,"n3":934,"n4":400},{"index":6,"n1":5,"n2":119,"n3":278,"n4":641},{"index":7,"n1":873,"n2":113,"n3":771,"n4":109},{"index":8,"n1":495,"n2":69,"n3":759,"n4":884},{"index":9,"n1":266,"n2":192,"n3":686,"n4":761},{"index":10,"n1":484,"n2":814,"n3":754,"n4":521},{"index":11,"n1":50,"n2":615,"n3":357,"n4":470},{"index":12,"n1":416,"n2":109,"n3":472,"n4":462},{"index":13,"n1":535,"n2":935,"n3":969,"n4":35},{"index":14,"n1":605,"n2":667,"n3":553,"n4":359},{"index":15,"n1":483,"n2":719,"n3":879,"n4":254},{"index":16,"n1":940,"n2":32,"n3":259,"n4":373},{"index":17,"n1":228,"n2":565,"n3":154,"n4":155},{"index":18,"n1":23,"n2":57,"n3":232,"n4":238},{"index":19,"n1":356,"n2":39,"n3":771,"n4":887},{"index":20,"n1":481,"n2":307,"n3":776,"n4":952},{"index":21,"n1":463,"n2":202,"n3":57,"n4":218},{"index":22,"n1":658,"n2":68,"n3":431,"n4":774},{"index":23,"n1":334,"n2":266,"n3":266,"n4":677},{"index":24,"n1":97,"n2":544,"n3":532,"n4":863},{"index":25,"n1":562,"n2":313,"n3":725,"n4":574}]}' && time echo "$json" | target/release/fisher
@@ -82,47 +123,100 @@
 use json::JsonValue;
 use serde::{Deserialize, Serialize};
 use serde_json;
-use std::cmp::Ordering;
 use std::io;
+mod multiple_testing; // Import functions from multiple_testing.rs
 mod stats_functions; // Import functions from stats_functions.rs
 
+// Confidence level of the confidence intervals of the effect sizes
+const CONFIDENCE_LEVEL: f64 = 0.95;
+
+// Number of random tables of the Monte Carlo p-value when the exact test of an R x C table is too large and the chi-square test is not applicable
+const DEFAULT_MONTECARLO_REPLICATES: usize = 10000;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FisherError {
+    kind: String, // "invalid_input"
+    message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ErrorResponse {
+    error: FisherError,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct PValueIndexes {
     index: usize,
-    n1: u32,
-    n2: u32,
-    n3: u32,
-    n4: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n1: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n2: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n3: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n4: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    table: Option<Vec<Vec<u32>>>, // R x C table, given instead of n1..n4
+    #[serde(skip_serializing_if = "Option::is_none")]
+    strata: Option<Vec<Stratum>>, // 2x2 strata, given instead of n1..n4
     p_value: Option<f64>, // The "option" keyword tells the compiler that this field can possibly be "None"
     fisher_chisq: String,
-}
-
-impl PValueIndexes {
-    fn abs_p_value(&self) -> f64 {
-        // This function is specific to this struct PValueIndexes
-        let final_p_val;
-        match self.p_value {
-            Some(p_val) => {
-                final_p_val = p_val;
-            }
-            None => {
-                final_p_val = 0.0;
-            }
-        }
-        final_p_val
-    }
+    #[serde(skip_serializing_if = "Option::is_none")]
+    breslow_day_p_value: Option<f64>,
+    #[serde(flatten)]
+    effect_sizes: EffectSizes,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct AdjustedPValueIndexes {
     index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n1: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n2: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n3: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n4: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    table: Option<Vec<Vec<u32>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    strata: Option<Vec<Stratum>>,
+    p_value: Option<f64>, // The "option" keyword tells the compiler that this field can possibly be "None"
+    adjusted_p_value: Option<f64>, // The "option" keyword tells the compiler that this field can possibly be "None"
+    fisher_chisq: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    breslow_day_p_value: Option<f64>,
+    #[serde(flatten)]
+    effect_sizes: EffectSizes,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Stratum {
     n1: u32,
     n2: u32,
     n3: u32,
     n4: u32,
-    p_value: Option<f64>, // The "option" keyword tells the compiler that this field can possibly be "None"
-    adjusted_p_value: Option<f64>, // The "option" keyword tells the compiler that this field can possibly be "None"
-    fisher_chisq: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct EffectSizes {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    odds_ratio: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    odds_ratio_ci: Option<[f64; 2]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    relative_risk: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    relative_risk_ci: Option<[f64; 2]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    risk_difference: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    risk_difference_ci: Option<[f64; 2]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    risk_difference_method: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cramers_v: Option<f64>,
 }
 
 fn main() {
@@ -142,40 +236,37 @@ fn main() {
 
                     //let fisher_limit: u32 = json_string["fisher_limit"].as_u32().unwrap(); // Cutoff for sum of all four numbers to decide whether to use fisher or chisq test
                     let individual_fisher_limit: f64 = 5.0;
-                    let mtc_string = &json_string["mtc"].to_owned();
                     let low_sample_size_string = &json_string["skipLowSampleSize"].to_owned();
-                    //println!("fdr_string:{}", fdr_string);
-
-                    let mut fdr_bool = false;
-                    let mut bon_bool = false;
-
-                    match mtc_string.as_str() {
-                        Some(mtc) => {
-                            if mtc == "fdr" {
-                                fdr_bool = true;
-                            } else if mtc == "bon" {
-                                bon_bool = true;
-                            } else {
-                                panic!("Unrecognized option, mtc field should be fdr/bon");
+                    let mtc: Option<multiple_testing::Method> = match json_string["mtc"].as_str() {
+                        Some(mtc) => match multiple_testing::Method::from_mtc(mtc) {
+                            Ok(method) => Some(method),
+                            Err(error) => return print_error(invalid_input(&error)),
+                        },
+                        None => None,
+                    };
+
+                    let montecarlo: Option<usize> = match &json_string["montecarlo"] {
+                        JsonValue::Null => None,
+                        montecarlo => match montecarlo.as_usize() {
+                            Some(replicates) if replicates > 0 => Some(replicates),
+                            _ => {
+                                return print_error(invalid_input(
+                                    "montecarlo should be a positive number of random tables",
+                                ))
                             }
-                        }
-                        None => {}
-                    }
-                    //match fdr_string.as_bool() {
-                    //    Some(fdr) => match fdr {
-                    //        true => fdr_bool = true,
-                    //        false => {}
-                    //    },
-                    //    None => {}
-                    //}
-                    //
-                    //match bon_string.as_bool() {
-                    //    Some(bon) => match bon {
-                    //        true => bon_bool = true,
-                    //        false => {}
-                    //    },
-                    //    None => {}
-                    //}
+                        },
+                    };
+                    let seed: u64 = json_string["seed"].as_u64().unwrap_or(0);
+                    let risk_difference_method: stats_functions::RiskDifferenceMethod =
+                        match json_string["risk_difference_method"].as_str() {
+                            Some(method) => {
+                                match stats_functions::RiskDifferenceMethod::from_option(method) {
+                                    Ok(method) => method,
+                                    Err(error) => return print_error(invalid_input(&error)),
+                                }
+                            }
+                            None => stats_functions::RiskDifferenceMethod::Newcombe,
+                        };
 
                     let mut low_sample_size_bool = false;
                     match low_sample_size_string.as_bool() {
@@ -186,43 +277,22 @@ fn main() {
                         None => {}
                     }
 
-                    if fdr_bool == false && bon_bool == false && low_sample_size_bool == true {
-                        panic!("skipLowSampleSize = true but fdr and bon is set to false.");
+                    if mtc.is_none() && low_sample_size_bool {
+                        return print_error(invalid_input(
+                            "skipLowSampleSize = true but mtc is not set.",
+                        ));
                     }
 
-                    if fdr_bool == false && bon_bool == false {
-                        // Check if FDR calculation is required or not
-                        calculate_fisher_chisq_test(
-                            variants,
-                            individual_fisher_limit,
-                            false,
-                            false,
-                            low_sample_size_bool,
-                        );
-                    } else if fdr_bool == true && bon_bool == false {
-                        calculate_fisher_chisq_test(
-                            variants,
-                            individual_fisher_limit,
-                            true,
-                            false,
-                            low_sample_size_bool,
-                        );
-                    } else if fdr_bool == false && bon_bool == true {
-                        calculate_fisher_chisq_test(
-                            variants,
-                            individual_fisher_limit,
-                            false,
-                            true,
-                            low_sample_size_bool,
-                        );
-                    } else if fdr_bool == true && bon_bool == true {
-                        // This scenario is probably no longer possible ?
-                        println!(
-                            "Choose either Bonferronni correction or Benjamini-Hochberg correction"
-                        );
-                    } else {
-                        // Should not happen
-                        println!("Unidentified case");
+                    if let Err(error) = calculate_fisher_chisq_test(
+                        variants,
+                        individual_fisher_limit,
+                        mtc,
+                        low_sample_size_bool,
+                        montecarlo,
+                        seed,
+                        risk_difference_method,
+                    ) {
+                        print_error(error);
                     }
                 }
                 Err(error) => println!("Incorrect json: {}", error),
@@ -232,19 +302,198 @@ fn main() {
     }
 }
 
+fn invalid_input(message: &str) -> FisherError {
+    FisherError {
+        kind: "invalid_input".to_string(),
+        message: message.to_string(),
+    }
+}
+
+// Prints the error as { error: { kind, message } } instead of the results
+fn print_error(error: FisherError) {
+    println!(
+        "{}",
+        serde_json::to_string(&ErrorResponse { error }).unwrap()
+    );
+}
+
+// Index of the entry, which must be a non-negative integer
+fn parse_index(variant: &JsonValue, i: usize) -> Result<usize, FisherError> {
+    match variant["index"].as_usize() {
+        Some(index) => Ok(index),
+        None => Err(invalid_input(&format!(
+            "input[{}]: index should be a non-negative integer",
+            i
+        ))),
+    }
+}
+
+// R x C table of counts, which must be non-empty rows of non-negative integers of equal length
+fn parse_table(variant: &JsonValue, i: usize) -> Result<Vec<Vec<u32>>, FisherError> {
+    let table: Option<Vec<Vec<u32>>> = variant["table"]
+        .members()
+        .map(|row| {
+            if row.is_array() {
+                row.members().map(|x| x.as_u32()).collect()
+            } else {
+                None
+            }
+        })
+        .collect();
+    match table {
+        Some(table)
+            if !table.is_empty()
+                && !table[0].is_empty()
+                && table.iter().all(|row| row.len() == table[0].len()) =>
+        {
+            Ok(table)
+        }
+        Some(_) => Err(invalid_input(&format!(
+            "input[{}]: table should be an array of rows of equal length",
+            i
+        ))),
+        None => Err(invalid_input(&format!(
+            "input[{}]: table counts should be non-negative integers",
+            i
+        ))),
+    }
+}
+
+// 2x2 strata of a stratified entry, n1..n4 of each stratum must be non-negative integers
+fn parse_strata(variant: &JsonValue, i: usize) -> Result<Vec<Stratum>, FisherError> {
+    let mut strata = Vec::<Stratum>::new();
+    for (j, stratum) in variant["strata"].members().enumerate() {
+        match (
+            stratum["n1"].as_u32(),
+            stratum["n2"].as_u32(),
+            stratum["n3"].as_u32(),
+            stratum["n4"].as_u32(),
+        ) {
+            (Some(n1), Some(n2), Some(n3), Some(n4)) => strata.push(Stratum { n1, n2, n3, n4 }),
+            _ => {
+                return Err(invalid_input(&format!(
+                    "input[{}]: n1, n2, n3 and n4 of strata[{}] should be non-negative integers",
+                    i, j
+                )))
+            }
+        }
+    }
+    if strata.is_empty() {
+        return Err(invalid_input(&format!(
+            "input[{}]: strata should not be empty",
+            i
+        )));
+    }
+    Ok(strata)
+}
+
 fn calculate_fisher_chisq_test(
     variants: &JsonValue,
-    _individual_fisher_limit: f64,
-    fdr: bool,
-    bon: bool,
+    individual_fisher_limit: f64,
+    mtc: Option<multiple_testing::Method>,
     low_sample_size: bool,
-) {
+    montecarlo: Option<usize>,
+    seed: u64,
+    risk_difference_method: stats_functions::RiskDifferenceMethod,
+) -> Result<(), FisherError> {
     let mut p_values_list = Vec::<PValueIndexes>::new();
-    let mut num_of_tests: f64 = 0.0; // Number of test actually carried out ignoring those which have low sample-size
     for i in 0..variants.len() {
         let variant = &variants[i];
         //println!("variant:{:?}", variant);
-        if variant.len() > 1 {
+        if variant["table"].is_array() {
+            let table: Vec<Vec<u32>> = parse_table(variant, i)?;
+            let row_sums: Vec<u32> = table.iter().map(|row| row.iter().sum()).collect();
+            let column_sums: Vec<u32> = (0..table[0].len())
+                .map(|j| table.iter().map(|row| row[j]).sum())
+                .collect();
+            let total: f64 = row_sums.iter().sum::<u32>() as f64;
+
+            let mut p_value_original = Option::<f64>::None;
+            let fisher_chisq_test_string;
+            if total < 2000.0
+                && row_sums
+                    .iter()
+                    .chain(column_sums.iter())
+                    .any(|x| *x as f64 / total < 0.03)
+                && low_sample_size
+            {
+                // Sample size too low for doing association test
+                fisher_chisq_test_string = "NA".to_string();
+            } else {
+                let (p_value_final, test_used) = rxc_test(
+                    &table,
+                    individual_fisher_limit,
+                    montecarlo,
+                    seed.wrapping_add(i as u64),
+                );
+                fisher_chisq_test_string = test_used;
+                p_value_original = Some(p_value_final);
+            }
+
+            p_values_list.push(PValueIndexes {
+                index: parse_index(variant, i)?,
+                n1: None,
+                n2: None,
+                n3: None,
+                n4: None,
+                effect_sizes: EffectSizes {
+                    cramers_v: cramers_v(&table),
+                    ..Default::default()
+                },
+                table: Some(table),
+                strata: None,
+                p_value: p_value_original,
+                fisher_chisq: fisher_chisq_test_string,
+                breslow_day_p_value: None,
+            });
+        } else if variant["strata"].is_array() {
+            let strata: Vec<Stratum> = parse_strata(variant, i)?;
+            let counts: Vec<[u32; 4]> = strata
+                .iter()
+                .map(|stratum| [stratum.n1, stratum.n2, stratum.n3, stratum.n4])
+                .collect();
+            // Low sample size is checked on the 2x2 table summed over the strata
+            let n1: u32 = strata.iter().map(|stratum| stratum.n1).sum();
+            let n2: u32 = strata.iter().map(|stratum| stratum.n2).sum();
+            let n3: u32 = strata.iter().map(|stratum| stratum.n3).sum();
+            let n4: u32 = strata.iter().map(|stratum| stratum.n4).sum();
+            let total: f64 = (n1 + n2 + n3 + n4) as f64;
+
+            let mut p_value_original = Option::<f64>::None;
+            let mut fisher_chisq_test_string = "NA".to_string();
+            let mut breslow_day_p_value = Option::<f64>::None;
+            let mut effect_sizes = EffectSizes::default();
+            let cmh = stats_functions::cochran_mantel_haenszel_test(&counts, CONFIDENCE_LEVEL);
+            if total < 2000.0
+                && ((n1 + n2) as f64 / total < 0.03
+                    || (n1 + n3) as f64 / total < 0.03
+                    || (n2 + n4) as f64 / total < 0.03
+                    || (n3 + n4) as f64 / total < 0.03)
+                && low_sample_size
+            {
+                // Sample size too low for doing association test
+            } else if let Some((p_value_final, odds_ratio, lower, upper)) = cmh {
+                fisher_chisq_test_string = "cmh".to_string();
+                p_value_original = Some(p_value_final);
+                breslow_day_p_value = stats_functions::breslow_day_test(&counts, odds_ratio);
+                effect_sizes.odds_ratio = Some(odds_ratio);
+                effect_sizes.odds_ratio_ci = Some([lower, upper]);
+            }
+
+            p_values_list.push(PValueIndexes {
+                index: parse_index(variant, i)?,
+                n1: None,
+                n2: None,
+                n3: None,
+                n4: None,
+                table: None,
+                strata: Some(strata),
+                p_value: p_value_original,
+                fisher_chisq: fisher_chisq_test_string,
+                breslow_day_p_value,
+                effect_sizes,
+            });
+        } else if variant.len() > 1 {
             // Check if total greater than fisher limit, if yes then use chisq test
             let fisher_chisq_test: u64 = 1; // Initializing to fisher-test
             let n1 = variant["n1"].as_u32().unwrap();
@@ -284,7 +533,6 @@ fn calculate_fisher_chisq_test(
             //        );
             //    fisher_chisq_test_string = "chisq".to_string();
             //    p_value_original = Some(p_value_final);
-            //    num_of_tests += 1.0;
             //}
             else {
                 let _fisher_chisq_test_final;
@@ -298,145 +546,62 @@ fn calculate_fisher_chisq_test(
                     );
                 fisher_chisq_test_string = "fisher".to_string();
                 p_value_original = Some(p_value_final);
-                num_of_tests += 1.0;
             }
 
             p_values_list.push(PValueIndexes {
                 index: variant["index"].as_usize().unwrap(),
-                n1: n1,
-                n2: n2,
-                n3: n3,
-                n4: n4,
+                n1: Some(n1),
+                n2: Some(n2),
+                n3: Some(n3),
+                n4: Some(n4),
+                table: None,
+                strata: None,
                 p_value: p_value_original,
                 fisher_chisq: fisher_chisq_test_string.to_string(),
+                breslow_day_p_value: None,
+                effect_sizes: effect_sizes_2x2(n1, n2, n3, n4, risk_difference_method),
             });
         }
     }
 
-    // Calculate Benjamini Hochberg/Bonferroni correction
-    match fdr {
-        true => benjamini_hochberg_correction(p_values_list, num_of_tests),
-        false => {
-            match bon {
-                true => bonferroni_correction(p_values_list, num_of_tests),
-
-                false => {
-                    let mut output_string = "[".to_string();
-                    for i in 0..p_values_list.len() {
-                        output_string += &serde_json::to_string(&p_values_list[i]).unwrap();
-                        if i != p_values_list.len() - 1 {
-                            output_string += &",".to_string();
-                        }
-                    }
-                    output_string += &"]".to_string();
-                    //println!("output:{}", json::stringify(output_string));
-                    println!("{}", output_string);
-                }
-            }
-        }
-    }
-}
-
-fn benjamini_hochberg_correction(mut p_values_list: Vec<PValueIndexes>, num_of_tests: f64) {
-    // Sorting p-values in ascending order
-    //println!("MTC using FDR correction");
-    p_values_list.as_mut_slice().sort_by(|a, b| {
-        (a.abs_p_value())
-            .partial_cmp(&b.abs_p_value())
-            .unwrap_or(Ordering::Equal)
-    });
-
-    //println!("p_values_list:{:?}", p_values_list);
-
-    //println!("num_of_tests:{}", num_of_tests);
-    let mut adjusted_p_values = Vec::<AdjustedPValueIndexes>::new();
-    let mut old_p_value: f64 = 0.0;
-    let mut rank: f64 = num_of_tests;
-    for j in 0..p_values_list.len() {
-        let i = p_values_list.len() - j - 1;
-
-        let mut adjusted_p_value: Option<f64> = None;
-        match p_values_list[i].p_value {
-            Some(p_val) => {
-                //println!("p_val:{}", p_val);
-                let mut adjusted_p_val: f64 = p_val * (num_of_tests / rank); // adjusted p-value = original_p_value * (N/rank)
-                if adjusted_p_val > 1.0 {
-                    // p_value should NEVER be greater than 1
-                    adjusted_p_val = 1.0;
-                }
-                //println!("Original p_value:{}", p_values_list[i].p_value);
-                //println!("Raw adjusted p_value:{}", adjusted_p_value);
+    // Multiple testing correction
+    match mtc {
+        Some(method) => adjust_p_values(p_values_list, method),
+        None => {
+            let mut output_string = "[".to_string();
+            for i in 0..p_values_list.len() {
+                output_string += &serde_json::to_string(&p_values_list[i]).unwrap();
                 if i != p_values_list.len() - 1 {
-                    if adjusted_p_val > old_p_value {
-                        adjusted_p_val = old_p_value;
-                    }
+                    output_string += ",";
                 }
-                old_p_value = adjusted_p_val;
-                //println!("adjusted_p_value:{}", adjusted_p_val);
-                adjusted_p_value = Some(adjusted_p_val);
-                rank -= 1.0;
             }
-            None => {}
-        }
-
-        adjusted_p_values.push(AdjustedPValueIndexes {
-            index: p_values_list[i].index,
-            n1: p_values_list[i].n1,
-            n2: p_values_list[i].n2,
-            n3: p_values_list[i].n3,
-            n4: p_values_list[i].n4,
-            p_value: p_values_list[i].p_value,
-            adjusted_p_value: adjusted_p_value,
-            fisher_chisq: p_values_list[i].fisher_chisq.clone(),
-        });
-    }
-
-    // Sorting original p-values by their original indexes in ascending order
-    adjusted_p_values
-        .as_mut_slice()
-        .sort_by(|a, b| (a.index).partial_cmp(&b.index).unwrap_or(Ordering::Equal));
-
-    // Printing original and adjusted p-values
-
-    let mut output_string = "[".to_string();
-    for i in 0..adjusted_p_values.len() {
-        output_string += &serde_json::to_string(&adjusted_p_values[i]).unwrap();
-        if i != adjusted_p_values.len() - 1 {
-            output_string += &",".to_string();
+            output_string += "]";
+            //println!("output:{}", json::stringify(output_string));
+            println!("{}", output_string);
         }
     }
-    output_string += &"]".to_string();
-    println!("{}", output_string);
+    Ok(())
 }
 
-fn bonferroni_correction(p_values_list: Vec<PValueIndexes>, num_of_tests: f64) {
-    //println!("num_of_tests:{}", num_of_tests);
-    //println!("MTC using Bonferroni correction");
+// Adjusts the p-values of the tests that were carried out (ignoring those with low sample sizes) with the method chosen by mtc
+fn adjust_p_values(p_values_list: Vec<PValueIndexes>, method: multiple_testing::Method) {
+    let p_values: Vec<f64> = p_values_list.iter().filter_map(|x| x.p_value).collect();
+    let mut adjusted = multiple_testing::adjust_p_values(&p_values, method).into_iter();
     let mut adjusted_p_values = Vec::<AdjustedPValueIndexes>::new();
-    for i in 0..p_values_list.len() {
-        let mut adjusted_p_value: Option<f64> = None;
-        match p_values_list[i].p_value {
-            Some(p_val) => {
-                //println!("p_val:{}", p_val);
-                let mut adjusted_p_val: f64 = p_val * num_of_tests; // In bonferroni correction, multiplying p_value by number of tests (excluding those with low sample sizes)
-                if adjusted_p_val > 1.0 {
-                    // p_value should NEVER be greater than 1
-                    adjusted_p_val = 1.0;
-                }
-                adjusted_p_value = Some(adjusted_p_val);
-            }
-            None => {}
-        }
-
+    for p_value_indexes in p_values_list {
         adjusted_p_values.push(AdjustedPValueIndexes {
-            index: p_values_list[i].index,
-            n1: p_values_list[i].n1,
-            n2: p_values_list[i].n2,
-            n3: p_values_list[i].n3,
-            n4: p_values_list[i].n4,
-            p_value: p_values_list[i].p_value,
-            adjusted_p_value: adjusted_p_value,
-            fisher_chisq: p_values_list[i].fisher_chisq.clone(),
+            index: p_value_indexes.index,
+            n1: p_value_indexes.n1,
+            n2: p_value_indexes.n2,
+            n3: p_value_indexes.n3,
+            n4: p_value_indexes.n4,
+            table: p_value_indexes.table,
+            strata: p_value_indexes.strata,
+            p_value: p_value_indexes.p_value,
+            adjusted_p_value: p_value_indexes.p_value.map(|_| adjusted.next().unwrap()),
+            fisher_chisq: p_value_indexes.fisher_chisq,
+            breslow_day_p_value: p_value_indexes.breslow_day_p_value,
+            effect_sizes: p_value_indexes.effect_sizes,
         });
     }
 
@@ -475,3 +640,95 @@ fn calculate_expected_value(
     }
     expected_value
 }
+
+// Expected value of every cell of an R x C table, generalizing calculate_expected_value(): (row sum) * (column sum) / total
+fn calculate_expected_values(table: &[Vec<u32>], total: f64) -> Vec<Vec<f64>> {
+    let column_sums: Vec<u32> = (0..table[0].len())
+        .map(|j| table.iter().map(|row| row[j]).sum())
+        .collect();
+    table
+        .iter()
+        .map(|row| {
+            let row_sum: u32 = row.iter().sum();
+            column_sums
+                .iter()
+                .map(|column_sum| row_sum as f64 * *column_sum as f64 / total)
+                .collect()
+        })
+        .collect()
+}
+
+// Odds ratio, relative risk and risk difference of the 2x2 table [[n1, n2], [n3, n4]] with their confidence intervals
+fn effect_sizes_2x2(
+    n1: u32,
+    n2: u32,
+    n3: u32,
+    n4: u32,
+    risk_difference_method: stats_functions::RiskDifferenceMethod,
+) -> EffectSizes {
+    let (odds_ratio, odds_ratio_lower, odds_ratio_upper) =
+        stats_functions::conditional_odds_ratio(n1, n2, n3, n4, CONFIDENCE_LEVEL);
+    let relative_risk = stats_functions::relative_risk(n1, n2, n3, n4, CONFIDENCE_LEVEL);
+    let risk_difference =
+        stats_functions::risk_difference(n1, n2, n3, n4, CONFIDENCE_LEVEL, risk_difference_method);
+    EffectSizes {
+        odds_ratio: Some(odds_ratio),
+        odds_ratio_ci: Some([odds_ratio_lower, odds_ratio_upper]),
+        relative_risk: relative_risk.map(|x| x.0),
+        relative_risk_ci: relative_risk.map(|x| [x.1, x.2]),
+        risk_difference: risk_difference.map(|x| x.0),
+        risk_difference_ci: risk_difference.map(|x| [x.1, x.2]),
+        risk_difference_method: risk_difference.map(|_x| risk_difference_method.name().to_string()),
+        cramers_v: None,
+    }
+}
+
+// Cramer's V of an R x C table: sqrt(chi-square / (total * (min(R, C) - 1))). None when the table has less than 2 non-empty rows or columns
+fn cramers_v(table: &[Vec<u32>]) -> Option<f64> {
+    let table = stats_functions::remove_empty_rows_columns(table);
+    if table.len() < 2 || table[0].len() < 2 {
+        return None;
+    }
+    let total: f64 = table.iter().flatten().sum::<u32>() as f64;
+    let expected_values = calculate_expected_values(&table, total);
+    let chi_sq = stats_functions::chi_square_statistic_rxc(&table, &expected_values);
+    let min_dimension = std::cmp::min(table.len(), table[0].len()) as f64;
+    Some((chi_sq / (total * (min_dimension - 1.0))).sqrt())
+}
+
+// Runs the Fisher-Freeman-Halton exact test of an R x C table, or the Monte Carlo p-value when montecarlo is given. Falls back to the chi-square test (when all expected values are at least individual_fisher_limit) or to the Monte Carlo p-value when the exact test is too large. Returns the p-value and the test that was used
+fn rxc_test(
+    table: &[Vec<u32>],
+    individual_fisher_limit: f64,
+    montecarlo: Option<usize>,
+    seed: u64,
+) -> (f64, String) {
+    if let Some(replicates) = montecarlo {
+        return (
+            stats_functions::fisher_montecarlo_rxc(table, replicates, seed),
+            "fisher_montecarlo".to_string(),
+        );
+    }
+    if let Some(p_value) = stats_functions::fisher_exact_rxc(table) {
+        return (p_value, "fisher".to_string());
+    }
+    // Empty rows and columns would have expected values of zero
+    let table = stats_functions::remove_empty_rows_columns(table);
+    let total: f64 = table.iter().flatten().sum::<u32>() as f64;
+    let expected_values = calculate_expected_values(&table, total);
+    if expected_values
+        .iter()
+        .flatten()
+        .all(|expected| *expected >= individual_fisher_limit)
+    {
+        (
+            stats_functions::chi_square_test_rxc(&table, &expected_values),
+            "chisq".to_string(),
+        )
+    } else {
+        (
+            stats_functions::fisher_montecarlo_rxc(&table, DEFAULT_MONTECARLO_REPLICATES, seed),
+            "fisher_montecarlo".to_string(),
+        )
+    }
+}
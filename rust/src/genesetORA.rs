@@ -1,5 +1,6 @@
 // The hypergeometric distribution is computed based on the implementation in https://rdrr.io/github/GuangchuangYu/DOSE/src/R/enricher_internal.R
 // Syntax: cd .. && cargo build --release && cat ~/sjpp/test.txt | target/release/genesetORA
+// Optional "mtc" field selects the multiple testing correction (fdr/BH, BY, holm, hochberg, hommel, bon/bonferroni, qvalue; default fdr), an unknown method is reported as { error: { kind: "invalid_input", message } }
 #![allow(non_snake_case)]
 use json::JsonValue;
 use r_mathlib;
@@ -9,6 +10,7 @@ use serde_json;
 use std::cmp::Ordering;
 use std::io;
 use std::time::Instant;
+mod multiple_testing; // Import functions from multiple_testing.rs
 
 #[allow(non_camel_case_types)]
 #[allow(non_snake_case)]
@@ -38,6 +40,32 @@ struct pathway_p_value {
     gene_set_size: usize,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+struct ORAError {
+    kind: String, // "invalid_input"
+    message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ErrorResponse {
+    error: ORAError,
+}
+
+fn invalid_input(message: &str) -> ORAError {
+    ORAError {
+        kind: "invalid_input".to_string(),
+        message: message.to_string(),
+    }
+}
+
+// Prints the error as { error: { kind, message } } instead of the results
+fn print_error(error: ORAError) {
+    println!(
+        "{}",
+        serde_json::to_string(&ErrorResponse { error }).unwrap()
+    );
+}
+
 fn calculate_hypergeometric_p_value(
     sample_genes: &Vec<&str>,
     num_background_genes: usize,
@@ -94,6 +122,16 @@ fn main() -> Result<()> {
                         Some(db_string) => msigdb = db_string.to_string(),
                         None => panic!("msigdb file path is missing"),
                     }
+                    // Multiple testing correction, Benjamini-Hochberg (fdr) by default
+                    let mtc = match multiple_testing::Method::from_mtc(
+                        json_string["mtc"].as_str().unwrap_or("fdr"),
+                    ) {
+                        Ok(method) => method,
+                        Err(error) => {
+                            print_error(invalid_input(&error));
+                            return Ok(());
+                        }
+                    };
                     let genesetgroup;
                     let genesetgroup_input: &JsonValue = &json_string["gene_set_group"];
                     match genesetgroup_input.as_str() {
@@ -224,7 +262,7 @@ fn main() -> Result<()> {
                     let output_string = "{\"num_pathways\":".to_string()
                         + &pathway_p_values.len().to_string()
                         + &",\"pathways\":"
-                        + &adjust_p_values(pathway_p_values, num_items_output)
+                        + &adjust_p_values(pathway_p_values, num_items_output, mtc)
                         + &"}";
                     println!("pathway_p_values:{}", output_string);
                     println!(
@@ -243,6 +281,7 @@ fn main() -> Result<()> {
 fn adjust_p_values(
     mut original_p_values: Vec<pathway_p_value>,
     mut num_items_output: usize,
+    method: multiple_testing::Method,
 ) -> String {
     // Sorting p-values in ascending order
     original_p_values.as_mut_slice().sort_by(|a, b| {
@@ -251,36 +290,17 @@ fn adjust_p_values(
             .unwrap_or(Ordering::Equal)
     });
 
-    let mut adjusted_p_values: Vec<pathway_p_value> = Vec::with_capacity(original_p_values.len());
-    let mut old_p_value: f64 = 0.0;
-    let mut rank: f64 = original_p_values.len() as f64;
-    for j in 0..original_p_values.len() {
-        let i = original_p_values.len() - j - 1;
-
-        //println!("p_val:{}", p_val);
-        let mut adjusted_p_val: f64 =
-            original_p_values[i].p_value_original * (original_p_values.len() as f64 / rank); // adjusted p-value = original_p_value * (N/rank)
-        if adjusted_p_val > 1.0 {
-            // p_value should NEVER be greater than 1
-            adjusted_p_val = 1.0;
-        }
-        //println!("Original p_value:{}", original_p_values[i].p_value);
-        //println!("Raw adjusted p_value:{}", adjusted_p_value);
-        if i != original_p_values.len() - 1 {
-            if adjusted_p_val > old_p_value {
-                adjusted_p_val = old_p_value;
-            }
-        }
-        old_p_value = adjusted_p_val;
-        //println!("adjusted_p_value:{}", adjusted_p_val);
-        rank -= 1.0;
+    let p_values: Vec<f64> = original_p_values
+        .iter()
+        .map(|x| x.p_value_original)
+        .collect();
+    let adjusted = multiple_testing::adjust_p_values(&p_values, method);
 
+    let mut adjusted_p_values: Vec<pathway_p_value> = Vec::with_capacity(original_p_values.len());
+    for (original, adjusted_p_val) in original_p_values.into_iter().zip(adjusted) {
         adjusted_p_values.push(pathway_p_value {
-            pathway_name: original_p_values[i].pathway_name.clone(),
-            p_value_original: original_p_values[i].p_value_original,
             p_value_adjusted: Some(adjusted_p_val),
-            gene_set_hits: original_p_values[i].gene_set_hits.clone(),
-            gene_set_size: original_p_values[i].gene_set_size,
+            ..original
         });
     }
     adjusted_p_values.as_mut_slice().sort_by(|a, b| {
@@ -7,17 +7,43 @@
 //   0: No postprocessing, pure indel typing results
 //   1: Postprocessing will be carried out (Checks if read is polyclonal, reads with insertion near indel but classified as reference by identity ratio algorithm is put into none category)
 
+// Input JSON:
+//   alleles: [{ ref_position, refallele, altallele, refseq, altseq, leftflankseq, rightflankseq }] (refseq, altseq, leftflankseq and rightflankseq may be omitted when fasta is given, they will then be retrieved from the FASTA file)
+//   strictness: 0/1 (see above)
+//   reads: [{ read_sequence, start_position, cigar, flag, quality, mapq, qname }] Reads already parsed out of the BAM file by the server (quality (QUAL field, phred33), mapq (MAPQ field) and qname (read name) are optional)
+//   min_base_quality: Reads having a nucleotide with lower base quality in the variant region are classified as none (optional, default 0 i.e no filtering)
+//   min_mapping_quality: Reads with lower mapping quality are not classified (optional, default 0 i.e no filtering)
+//   Alternatively, instead of reads the alignments can be read directly from an indexed BAM/CRAM file:
+//     bam_file: Path to BAM/CRAM file (index must be present next to the file)
+//     chr, start, stop: Region from which reads will be fetched (0-based, stop not inclusive)
+//     fasta: Path to indexed FASTA file (optional, required for CRAM files)
+//     flank_length: Length of flanking sequences retrieved from FASTA file (optional, defaults to maximum read length)
+//   When reads are fetched from bam_file, the read names are printed in the "read_names:" line and read_number in the final output refers to the index in that list
+//   alignment_mode: global (default), semiglobal, local or banded. Alignment mode used for aligning each read against the ref and alt sequences (optional). Banded mode only computes the alignment around the expected diagonal and is recommended for high read depth
+//   match_score, mismatch_score, gap_open, gap_extend: Scoring scheme (optional, defaults 1, -1, -5, -1), kmer_length and band_width (optional, defaults 8 and 20) are used only in banded mode
+//   realign: true/false Multiple alignment of reads with insertions/deletions near each allele (optional, default false)
+//   Each entry in alleles may be an insertion, deletion, SNV or MNV (refallele and altallele of equal length). Several alleles at the same position (multi-allelic sites) are classified together, each read being assigned to the allele with the highest identity
+
+// Output:
+//   allele_counts: [{ allele, count, forward, reverse }] Number of reads assigned to ref, each alt allele (alt0, alt1 ...), none and amb. When read names are available (qname or bam_file), both mates of a paired-end template containing the variant region are merged by merge_mates() and counted once
+//   genotype: { genotype, genotype_quality, pl, depth, allele_fractions: [{ allele, vaf, ci_lower, ci_upper }] } Diploid genotype call and allele fraction (with 95% confidence interval) of each alt allele, computed from the allele_counts of ref and alt alleles
+//   fisher_strand: Strand bias of ref/alt reads (only for single allele)
+//   Final_output: [{ read_number, categories, mate_read_number }] Classification of each read (mate_read_number only when the mate also contains the variant region)
+//   allele_counts, genotype, fisher_strand and Final_output are also printed when no reads contain the variant region (zero counts, genotype ./. and empty Final_output)
+//   realignment: [{ read_indices, aligned_sequences, consensus_sequence }] Only when realign is true. Multiple alignment (first entry of aligned_sequences is refseq) of reads having insertions/deletions near each allele and the consensus sequence of those reads
+//   When vcf_file is given (chr is then required, sample_name is optional), a VCF 4.3 file is also written with one record per site (alleles at the same position with the same ref allele are merged into a multi-allelic record) containing GT, GQ, AD, DP, PL and SB in FORMAT and the strand bias (FS) in INFO
+
 // Function cascade:
 //
 // Optimize ref/alt allele given by user
-// preprocess_input() (Optimizing ref/alt allele entered by user to account for flanking repeat regions. This is accomplished by modifying variant_pos (optimized_variant_pos) and indel_length (optimized_indel_length) to account for repeats present on either or both sides of the predicted variant region. For e.g A-AT in the region CACA{T}TTTTGCGA will become ATTTT-ATTTTT)
+// preprocess_input() (Not carried out for SNV/MNV. Optimizing ref/alt allele entered by user to account for flanking repeat regions. This is accomplished by modifying variant_pos (optimized_variant_pos) and indel_length (optimized_indel_length) to account for repeats present on either or both sides of the predicted variant region. For e.g A-AT in the region CACA{T}TTTTGCGA will become ATTTT-ATTTTT)
 //       check_if_repeat_inside_indel() (This functions helps to determine if the indel is part of a repeat such as a tandem repeat within the indel sequence)
 //       check_flanking_sequence_for_repeats() (Checks if the monomer or indel is repeated on either or both sides of the predicted indel region)
 //
 // if number of reads > single_thread_limit (multithreading option is turned on)
 //
 // Analyze each read
-//   for each read {
+//   for each read classify_read() {
 //      check_read_within_indel_region() (Checks if the read contains indel region)
 //      check_polyclonal_with_read_alignment() (checking if read is polyclonal)
 //      percentage identity w.r.t ref allele = align_single_reads(sequence, reference sequence)
@@ -31,6 +57,8 @@
 //     if polyclonal, classify as none
 //     if ref classified read, but contains inserted/deleted nucleotides in indel region, classify as none
 //
+// merge_mates() (Both mates of a paired-end template are given the same classification and counted once)
+//
 // Strand analysis:
 // strand_analysis() (Inputs alternate_forward, alternate_reverse, reference_forward, reference_reverse and outputs final phred scale p-value)
 //       if number of reads < fisher_limit
@@ -46,15 +74,18 @@ use serde::{Deserialize, Serialize};
 //use serde_json::json as other_json;
 //use serde_json::Value;
 use std::cmp;
-use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::str::FromStr;
 //use std::env;
 use std::sync::{Arc, Mutex}; // Multithreading library
 use std::thread;
 //use std::time::{SystemTime};
 use std::io;
 
+mod read_bam; // Imports functions from read_bam.rs
 mod realign; // Imports functions from realign.rs
 mod stats_functions; // Imports functions from stats_functions.rs
+mod vcf; // Imports functions from vcf.rs
 
 #[derive(Debug, Clone)]
 struct ReadComparison {
@@ -66,6 +97,44 @@ struct ReadComparison {
 struct ReadClassification {
     read_number: usize,
     categories: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mate_read_number: Option<usize>, // read_number of the mate, when both mates of a paired-end template contain the variant region. The template is then counted only once in allele_counts
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AlleleCount {
+    allele: String, // ref, alt0, alt1 ... none, amb
+    count: u32,     // Total number of reads assigned to this group
+    forward: u32,   // Number of forward strand reads assigned to this group
+    reverse: u32,   // Number of reverse strand reads assigned to this group
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AlleleFraction {
+    allele: String, // alt0, alt1 ...
+    vaf: f64, // Fraction of reads supporting this allele among reads assigned to ref or any alt allele
+    ci_lower: f64, // Lower limit of 95% confidence interval (Clopper-Pearson) of vaf
+    ci_upper: f64, // Upper limit of 95% confidence interval (Clopper-Pearson) of vaf
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct GenotypeCall {
+    genotype: String, // Diploid genotype e.g 0/0, 0/1, 1/1 (./. when there are no informative reads)
+    genotype_quality: u32, // Phred scaled confidence of genotype call (capped at 99)
+    pl: Vec<u32>,     // Phred scaled genotype likelihoods in VCF order
+    depth: u32,       // Number of reads assigned to ref or any alt allele
+    allele_fractions: Vec<AlleleFraction>,
+}
+
+impl AlleleCount {
+    fn new(allele: &str) -> AlleleCount {
+        AlleleCount {
+            allele: allele.to_string(),
+            count: 0,
+            forward: 0,
+            reverse: 0,
+        }
+    }
 }
 
 //impl ReadClassification {
@@ -96,39 +165,158 @@ fn main() {
                     let reads_list: &JsonValue = &json_string["reads"];
                     let strictness: usize = json_string["strictness"].as_usize().unwrap();
 
+                    let fasta_file: Option<String> =
+                        json_string["fasta"].as_str().map(|fasta| fasta.to_string()); // Path to FASTA file (optional), needed for CRAM files and when flanking sequences are not given in alleles
+
+                    let min_base_quality: u8 =
+                        json_string["min_base_quality"].as_u8().unwrap_or_default(); // 0 (no filtering on base quality) by default
+                    let min_mapping_quality: u8 = json_string["min_mapping_quality"]
+                        .as_u8()
+                        .unwrap_or_default(); // 0 (no filtering on mapping quality) by default
+                    let alignment_parameters =
+                        match realign::parse_alignment_parameters(&json_string) {
+                            Ok(alignment_parameters) => alignment_parameters,
+                            Err(error) => {
+                                println!("Invalid alignment parameters: {}", error);
+                                return;
+                            }
+                        };
+
                     let mut sequences: String = "".to_string();
                     let mut cigar_sequences: String = "".to_string();
                     let mut start_positions: String = "".to_string();
                     let mut sequence_flags: String = "".to_string();
-                    for i in 0..reads_list.len() {
-                        let item = &reads_list[i];
-                        sequences.push_str(
-                            &(item["read_sequence"].as_str().unwrap().to_string()
-                                + &"-".to_string())
-                                .to_string(),
-                        );
-                        start_positions.push_str(
-                            &(item["start_position"].as_usize().unwrap().to_string()
-                                + &"-".to_string())
-                                .to_string(),
-                        );
-                        cigar_sequences.push_str(
-                            &(item["cigar"].as_str().unwrap().to_string() + &"-".to_string())
-                                .to_string(),
-                        );
-                        sequence_flags.push_str(
-                            &(item["flag"].as_usize().unwrap().to_string() + &"-".to_string())
-                                .to_string(),
-                        );
+                    let mut quality_scores: String = "".to_string(); // Quality scores are separated by " " since "-" is a valid phred33 character
+                    let mut mapping_qualities: String = "".to_string();
+                    let mut max_read_length: usize = 0; // Used as the default flanking sequence length (same as the server)
+                    let mut read_names = Vec::<String>::new(); // Read name of each read, used to identify mates of the same template ("" when not available)
+                    match json_string["bam_file"].as_str() {
+                        Some(bam_file) => {
+                            // Reading alignments directly from the BAM/CRAM file instead of the "reads" array
+                            let (chrom, region_start, region_stop) = match (
+                                json_string["chr"].as_str(),
+                                json_string["start"].as_i64(),
+                                json_string["stop"].as_i64(),
+                            ) {
+                                (Some(chrom), Some(region_start), Some(region_stop)) => {
+                                    (chrom.to_string(), region_start, region_stop)
+                                }
+                                _ => {
+                                    println!("chr, start and stop are required with bam_file");
+                                    return;
+                                }
+                            };
+                            let bam_reads = match read_bam::fetch_reads_from_bam(
+                                &bam_file.to_string(),
+                                &fasta_file,
+                                &chrom,
+                                region_start,
+                                region_stop,
+                            ) {
+                                Ok(bam_reads) => bam_reads,
+                                Err(error) => {
+                                    println!("Cannot read BAM file: {}", error);
+                                    return;
+                                }
+                            };
+                            for read in bam_reads {
+                                if read.read_sequence.len() > max_read_length {
+                                    max_read_length = read.read_sequence.len();
+                                }
+                                sequences.push_str(&(read.read_sequence + "-"));
+                                start_positions.push_str(&(read.start_position.to_string() + "-"));
+                                cigar_sequences.push_str(&(read.cigar + "-"));
+                                sequence_flags.push_str(&(read.flag.to_string() + "-"));
+                                quality_scores.push_str(&(read.quality + " "));
+                                mapping_qualities.push_str(&(read.mapq.to_string() + "-"));
+                                read_names.push(read.qname);
+                            }
+                            // read_number in the final output is the index of the read in this list
+                            println!("read_names:{}", serde_json::to_string(&read_names).unwrap());
+                        }
+                        None => {
+                            for i in 0..reads_list.len() {
+                                let item = &reads_list[i];
+                                if item["read_sequence"].as_str().unwrap().len() > max_read_length {
+                                    max_read_length = item["read_sequence"].as_str().unwrap().len();
+                                }
+                                sequences.push_str(
+                                    &(item["read_sequence"].as_str().unwrap().to_string() + "-")
+                                        .to_string(),
+                                );
+                                start_positions.push_str(
+                                    &(item["start_position"].as_usize().unwrap().to_string() + "-")
+                                        .to_string(),
+                                );
+                                cigar_sequences.push_str(
+                                    &(item["cigar"].as_str().unwrap().to_string() + "-")
+                                        .to_string(),
+                                );
+                                sequence_flags.push_str(
+                                    &(item["flag"].as_usize().unwrap().to_string() + "-")
+                                        .to_string(),
+                                );
+                                match item["quality"].as_str() {
+                                    Some(quality) => {
+                                        quality_scores.push_str(&(quality.to_string() + " "))
+                                    }
+                                    None => quality_scores.push_str("* "), // Quality scores not available
+                                }
+                                read_names.push(item["qname"].as_str().unwrap_or("").to_string());
+                                match item["mapq"].as_u8() {
+                                    Some(mapq) => {
+                                        mapping_qualities.push_str(&(mapq.to_string() + "-"))
+                                    }
+                                    None => mapping_qualities.push_str("255-"), // Mapping quality not available
+                                }
+                            }
+                        }
                     }
                     sequences.pop();
                     start_positions.pop();
                     cigar_sequences.pop();
                     sequence_flags.pop();
-                    let sequences_list: Vec<&str> = sequences.split("-").collect(); // Vector containing list of sequences, the first two containing ref and alt.
-                    let start_positions_list: Vec<&str> = start_positions.split("-").collect(); // Vector containing start positions
-                    let cigar_sequences_list: Vec<&str> = cigar_sequences.split("-").collect(); // Vector containing cigar sequences
-                    let sequence_flags_list: Vec<&str> = sequence_flags.split("-").collect(); // Vector containing sam flag of read sequences
+                    quality_scores.pop();
+                    mapping_qualities.pop();
+                    // When no reads overlap the variant region (e.g. empty region in bam_file) the lists are empty, so that all output lines are still printed with zero counts
+                    let num_reads: usize = read_names.len();
+                    let sequences_list: Vec<&str> = split_reads(&sequences, "-", num_reads); // Vector containing list of sequences, the first two containing ref and alt.
+                    let start_positions_list: Vec<&str> =
+                        split_reads(&start_positions, "-", num_reads); // Vector containing start positions
+                    let cigar_sequences_list: Vec<&str> =
+                        split_reads(&cigar_sequences, "-", num_reads); // Vector containing cigar sequences
+                    let sequence_flags_list: Vec<&str> =
+                        split_reads(&sequence_flags, "-", num_reads); // Vector containing sam flag of read sequences
+                    let quality_scores_list: Vec<&str> =
+                        split_reads(&quality_scores, " ", num_reads); // Vector containing base pair quality scores of read sequences
+                    let mapping_qualities_list: Vec<&str> =
+                        split_reads(&mapping_qualities, "-", num_reads); // Vector containing mapping quality of read sequences
+
+                    // Numeric fields of each read are parsed once here so that malformed values are reported instead of panicking inside the classification threads
+                    let read_start_positions: Vec<i64> =
+                        match parse_read_field(&start_positions_list, "start_position") {
+                            Ok(read_start_positions) => read_start_positions,
+                            Err(error) => {
+                                println!("{}", error);
+                                return;
+                            }
+                        };
+                    let read_flags: Vec<i64> = match parse_read_field(&sequence_flags_list, "flag")
+                    {
+                        Ok(read_flags) => read_flags,
+                        Err(error) => {
+                            println!("{}", error);
+                            return;
+                        }
+                    };
+                    let read_mapping_qualities: Vec<u8> =
+                        match parse_read_field(&mapping_qualities_list, "mapq") {
+                            Ok(read_mapping_qualities) => read_mapping_qualities,
+                            Err(error) => {
+                                println!("{}", error);
+                                return;
+                            }
+                        };
 
                     //println!("sequences_list:{:?}", sequences_list);
 
@@ -139,6 +327,8 @@ fn main() {
                     let mut leftflankseqs: String = "".to_string();
                     let mut rightflankseqs: String = "".to_string();
                     let mut variant_positions: String = "".to_string();
+                    let mut fasta_flanking_sequences =
+                        HashMap::<(i64, usize), (String, String)>::new(); // Flanking sequences retrieved from the FASTA file for each site (position and ref allele length), so that they are fetched once for all alleles of the site
                     for i in 0..alleles_list.len() {
                         let item = &alleles_list[i];
                         //println!("item:{:?}", item);
@@ -150,24 +340,64 @@ fn main() {
                             &(item["altallele"].as_str().unwrap().to_string() + &"-".to_string())
                                 .to_string(),
                         );
-                        refseqs.push_str(
-                            &(item["refseq"].as_str().unwrap().to_string() + &"-".to_string())
-                                .to_string(),
-                        );
-                        altseqs.push_str(
-                            &(item["altseq"].as_str().unwrap().to_string() + &"-".to_string())
-                                .to_string(),
-                        );
-                        leftflankseqs.push_str(
-                            &(item["leftflankseq"].as_str().unwrap().to_string()
-                                + &"-".to_string())
-                                .to_string(),
-                        );
-                        rightflankseqs.push_str(
-                            &(item["rightflankseq"].as_str().unwrap().to_string()
-                                + &"-".to_string())
-                                .to_string(),
-                        );
+                        let (leftflankseq, rightflankseq, refseq, altseq);
+                        if let (true, Some(fasta)) =
+                            (item["leftflankseq"].is_null(), fasta_file.as_ref())
+                        {
+                            // Flanking sequences not given by the user, retrieving them from the FASTA file
+                            let flank_length: usize = json_string["flank_length"]
+                                .as_usize()
+                                .unwrap_or(max_read_length);
+                            let chrom: &str = match json_string["chr"].as_str() {
+                                Some(chrom) => chrom,
+                                None => {
+                                    println!("chr is required for retrieving flanking sequences from fasta");
+                                    return;
+                                }
+                            };
+                            let site: (i64, usize) = (
+                                item["ref_position"].as_i64().unwrap(),
+                                item["refallele"].as_str().unwrap().len(),
+                            );
+                            let (leftflankseq_fasta, rightflankseq_fasta) =
+                                match fasta_flanking_sequences.get(&site) {
+                                    Some(flanking_sequences) => flanking_sequences.clone(),
+                                    None => match read_bam::fetch_flanking_sequences(
+                                        fasta,
+                                        &chrom.to_string(),
+                                        site.0,
+                                        site.1,
+                                        flank_length,
+                                    ) {
+                                        Ok(flanking_sequences) => {
+                                            fasta_flanking_sequences
+                                                .insert(site, flanking_sequences.clone());
+                                            flanking_sequences
+                                        }
+                                        Err(error) => {
+                                            println!("Cannot read FASTA file: {}", error);
+                                            return;
+                                        }
+                                    },
+                                };
+                            refseq = leftflankseq_fasta.to_owned()
+                                + item["refallele"].as_str().unwrap()
+                                + &rightflankseq_fasta;
+                            altseq = leftflankseq_fasta.to_owned()
+                                + item["altallele"].as_str().unwrap()
+                                + &rightflankseq_fasta;
+                            leftflankseq = leftflankseq_fasta;
+                            rightflankseq = rightflankseq_fasta;
+                        } else {
+                            leftflankseq = item["leftflankseq"].as_str().unwrap().to_string();
+                            rightflankseq = item["rightflankseq"].as_str().unwrap().to_string();
+                            refseq = item["refseq"].as_str().unwrap().to_string();
+                            altseq = item["altseq"].as_str().unwrap().to_string();
+                        }
+                        refseqs.push_str(&(refseq + "-"));
+                        altseqs.push_str(&(altseq + "-"));
+                        leftflankseqs.push_str(&(leftflankseq + "-"));
+                        rightflankseqs.push_str(&(rightflankseq + "-"));
                         variant_positions.push_str(
                             &(item["ref_position"].as_usize().unwrap().to_string()
                                 + &"-".to_string())
@@ -252,15 +482,25 @@ fn main() {
                         }
 
                         // Preprocessing of input
-                        let (optimized_variant_pos, optimized_indel_length) = preprocess_input(
-                            &ref_nucleotides,
-                            &alt_nucleotides,
-                            variant_pos,
-                            indel_length,
-                            leftflankseq,
-                            rightflankseq,
-                            surrounding_region_length,
-                        );
+                        let (optimized_variant_pos, optimized_indel_length);
+                        if ref_length == alt_length {
+                            // SNV/MNV, there are no inserted/deleted nucleotides that can be shifted within flanking repeats so the variant region is simply the ref allele
+                            optimized_variant_pos = variant_pos;
+                            optimized_indel_length = ref_length;
+                        } else {
+                            let (optimized_variant_pos_temp, optimized_indel_length_temp) =
+                                preprocess_input(
+                                    &ref_nucleotides,
+                                    &alt_nucleotides,
+                                    variant_pos,
+                                    indel_length,
+                                    leftflankseq,
+                                    rightflankseq,
+                                    surrounding_region_length,
+                                );
+                            optimized_variant_pos = optimized_variant_pos_temp;
+                            optimized_indel_length = optimized_indel_length_temp;
+                        }
 
                         if indel_length != optimized_indel_length {
                             indel_length = optimized_indel_length;
@@ -284,514 +524,74 @@ fn main() {
 
                     //println!("indel_length:{}", indel_length);
 
-                    //if args.len() > 14 {
-                    //    // This is true when realigning reads to determine correct indel sequence. Currently in development (not functional)
-                    //    let clustalo_path: String = args[14].parse::<String>().unwrap(); // Removing "\n" from the end of the string
-                    //    let quality_scores: String = args[15].parse::<String>().unwrap(); // Variable contains quality scores of reads separated by "-" character
-                    //    realign::realign_reads(
-                    //        &sequences,
-                    //        &start_positions,
-                    //        &cigar_sequences,
-                    //        &quality_scores,
-                    //        &clustalo_path,
-                    //        &lines[0].to_string(),
-                    //        &lines[1].to_string(),
-                    //        variant_pos,
-                    //        indel_length,
-                    //    );
-                    //}
-
-                    //let mut all_alleles = Vec::<ReadClassification>::new();
-                    let mut alternate_forward_count: u32 = 0; // Alternate forward read counter
-                    let mut alternate_reverse_count: u32 = 0; // Alternate reverse read counter
-                    let mut reference_forward_count: u32 = 0; // Reference forward read counter
-                    let mut reference_reverse_count: u32 = 0; // Reference reverse read counter
-                    let mut output_string = "[".to_string();
+                    if json_string["realign"].as_bool() == Some(true) {
+                        // Multiple alignment of reads containing insertions/deletions near each variant, used to determine the correct indel sequence
+                        let realign_start_positions: Vec<i64> =
+                            read_start_positions.iter().map(|item| item - 1).collect();
+                        let mut realignments = Vec::<realign::RealignmentResult>::new();
+                        for indel_idx in 0..variant_positions_list.len() {
+                            realignments.push(realign::realign_reads(
+                                &sequences_list,
+                                &realign_start_positions,
+                                &cigar_sequences_list,
+                                &quality_scores_list,
+                                &refseqs_list[indel_idx].to_string(),
+                                variant_positions_list[indel_idx],
+                                indel_lengths_list[indel_idx] as i64,
+                            ));
+                        }
+                        println!(
+                            "realignment:{}",
+                            serde_json::to_string(&realignments).unwrap()
+                        );
+                    }
+
+                    let mut classified_reads = Vec::<(ReadClassification, String)>::new(); // Classification of each read containing the variant region along with the strand ("F"/"R") of the read
                     if sequences_list.len() <= single_thread_limit {
                         // Start of sequential single-thread implementation for classifying reads
-                        let mut i: i64 = 0;
-                        //let num_of_reads: f64 = sequences_list.len() as f64;
-                        for read in sequences_list {
-                            // The first two sequences are reference and alternate allele and therefore skipped. Also checking there are no blank lines in the input file
-                            let (
-                                within_indel,
-                                correct_start_positions,
-                                correct_end_positions,
-                                alignment_sides,
-                                spliced_sequences,
-                            ) = realign::check_read_within_indel_region(
-                                // Checks if the read contains the indel region (or a part of it)
-                                start_positions_list[i as usize].parse::<i64>().unwrap() - 1,
-                                cigar_sequences_list[i as usize].to_string(),
+                        for i in 0..sequences_list.len() {
+                            if let Some(classified_read) = classify_read(
+                                i,
+                                sequences_list[i],
+                                read_start_positions[i] - 1,
+                                cigar_sequences_list[i],
+                                read_flags[i],
+                                quality_scores_list[i],
+                                read_mapping_qualities[i],
                                 &variant_positions_list,
                                 &indel_lengths_list,
                                 &refalleles_list,
                                 &altalleles_list,
+                                &refseqs_list,
+                                &altseqs_list,
+                                &alt_allele_names_list,
                                 strictness,
-                                read.to_string(),
-                            );
-                            //println!("correct_start_positions:{:?}", correct_start_positions);
-                            //println!("correct_end_positions:{:?}", correct_end_positions);
-                            //println!(
-                            //    "cigar_sequence:{}",
-                            //    &cigar_sequences_list[i as usize].to_string()
-                            //);
-                            //println!("within_indel:{}", within_indel);
-                            if within_indel == 1 {
-                                // Checking if the read is in forward or reverse strand
-                                let mut sequence_strand: String = "F".to_string(); // Initializing sequence strand to forward
-                                if sequence_flags_list[i as usize].parse::<i64>().unwrap() & 16
-                                    == 16
-                                {
-                                    sequence_strand = "R".to_string();
-                                }
-                                //let mut read_ambiguous = check_if_read_ambiguous(
-                                //    // Function that checks if the start/end of a read is in a region such that it cannot be distinguished as supporting ref or alt allele
-                                //    correct_start_position,
-                                //    correct_end_position,
-                                //    left_offset,
-                                //    right_offset,
-                                //    variant_pos,
-                                //    refallele.len(),
-                                //    &ref_nucleotides,
-                                //    &alt_nucleotides,
-                                //    optimized_allele,
-                                //);
-
-                                //println!("read_ambiguous:{}", read_ambiguous);
-                                //println!("ref_insertion:{}", ref_insertion);
-
-                                let mut alt_comparisons = Vec::<ReadComparison>::new();
-                                let mut ref_comparisons = Vec::<ReadComparison>::new();
-                                let mut indel_nones = Vec::<bool>::new();
-                                let mut ref_polyclonal_read_status_global: i64 = 0;
-                                let mut alt_polyclonal_read_status_global: i64 = 1;
-                                let mut ref_polyclonal_read_statuses = Vec::<i64>::new();
-                                let mut alt_polyclonal_read_statuses = Vec::<i64>::new();
-                                for indel_idx in 0..variant_positions_list.len() {
-                                    let spliced_sequence = &spliced_sequences[indel_idx];
-                                    let reference_sequence = refseqs_list[indel_idx];
-                                    let alternate_sequence = altseqs_list[indel_idx];
-                                    let (q_seq_ref, align_ref, r_seq_ref, ref_comparison) =
-                                        realign::align_single_reads(
-                                            &spliced_sequence,
-                                            reference_sequence.to_string(),
-                                        );
-
-                                    let (q_seq_alt, align_alt, r_seq_alt, alt_comparison) =
-                                        realign::align_single_reads(
-                                            &spliced_sequence,
-                                            alternate_sequence.to_string(),
-                                        );
-                                    alt_comparisons.push(ReadComparison {
-                                        comparison: alt_comparison,
-                                        index: indel_idx,
-                                    });
-                                    ref_comparisons.push(ReadComparison {
-                                        comparison: ref_comparison,
-                                        index: indel_idx,
-                                    });
-
-                                    //println!("indel_idx:{}", indel_idx);
-                                    //println!("alt allele:{}", altalleles_list[indel_idx]);
-                                    //println!("ref_comparison:{}", ref_comparison);
-                                    //println!("alt_comparison:{}", alt_comparison);
-
-                                    let (ref_polyclonal_read_status, alt_polyclonal_read_status);
-                                    if strictness == 0 {
-                                        ref_polyclonal_read_status = 0;
-                                        alt_polyclonal_read_status = 0;
-                                    } else {
-                                        let (
-                                            ref_polyclonal_read_status_temp,
-                                            alt_polyclonal_read_status_temp,
-                                        ) = check_polyclonal_with_read_alignment(
-                                            &alignment_sides[indel_idx],
-                                            &q_seq_alt,
-                                            &q_seq_ref,
-                                            &r_seq_alt,
-                                            &r_seq_ref,
-                                            &align_alt,
-                                            &align_ref,
-                                            correct_start_positions[indel_idx],
-                                            correct_end_positions[indel_idx],
-                                            variant_positions_list[indel_idx],
-                                            refalleles_list[indel_idx].len(),
-                                            altalleles_list[indel_idx].len(),
-                                            indel_lengths_list[indel_idx],
-                                            variant_positions_list.len(),
-                                        );
-                                        ref_polyclonal_read_status =
-                                            ref_polyclonal_read_status_temp;
-                                        alt_polyclonal_read_status =
-                                            alt_polyclonal_read_status_temp;
-                                    }
-                                    if ref_polyclonal_read_status == 1 {
-                                        ref_polyclonal_read_status_global = 1;
-                                    }
-                                    if alt_polyclonal_read_status == 0 {
-                                        alt_polyclonal_read_status_global = 0;
-                                    }
-                                    let mut indel_none: bool = false;
-                                    if ref_polyclonal_read_status == 1
-                                        && alt_polyclonal_read_status == 1
-                                    {
-                                        indel_none = true;
-                                    }
-                                    //println!("indel_none:{}", indel_none);
-                                    indel_nones.push(indel_none);
-                                    //println!(
-                                    //    "ref_polyclonal_read_status:{}",
-                                    //    ref_polyclonal_read_status
-                                    //);
-                                    //println!(
-                                    //    "alt_polyclonal_read_status:{}",
-                                    //    alt_polyclonal_read_status
-                                    //);
-                                    ref_polyclonal_read_statuses.push(ref_polyclonal_read_status);
-                                    alt_polyclonal_read_statuses.push(alt_polyclonal_read_status);
-                                }
-
-                                // Sorting in ascending order so as to check if the read is ambiguous or not
-                                alt_comparisons.as_mut_slice().sort_by(|a, b| {
-                                    (a.comparison)
-                                        .partial_cmp(&b.comparison)
-                                        .unwrap_or(Ordering::Equal)
-                                });
-                                ref_comparisons.as_mut_slice().sort_by(|a, b| {
-                                    (a.comparison)
-                                        .partial_cmp(&b.comparison)
-                                        .unwrap_or(Ordering::Equal)
-                                });
-
-                                let mut max_index = 0;
-                                let mut refalt = "ref".to_string();
-                                let mut max_alignment_ref: f64 = 0.0;
-                                let mut max_index_ref: usize = 0;
-                                for alignment_idx in 0..ref_comparisons.len() {
-                                    if ref_comparisons[alignment_idx].comparison > max_alignment_ref
-                                    {
-                                        max_alignment_ref =
-                                            ref_comparisons[alignment_idx].comparison;
-                                        max_index_ref = ref_comparisons[alignment_idx].index;
-                                    }
-                                }
-
-                                let mut read_ambiguous = 0;
-                                let mut equal_alignment_indices = Vec::<usize>::new();
-                                let mut max_alignment_alt: f64 = 0.0;
-                                let mut max_index_alt: usize = 0;
-                                for alignment_idx in 0..alt_comparisons.len() {
-                                    //println!("max_index_alt:{}", max_index_alt);
-                                    //println!(
-                                    //    "indel_nones[alignment_idx]:{}",
-                                    //    indel_nones[alignment_idx]
-                                    //);
-                                    //println!("alignment_idx:{}", alignment_idx);
-                                    //println!(
-                                    //    "ref_polyclonal_read_status_global:{}",
-                                    //    ref_polyclonal_read_status_global
-                                    //);
-                                    //println!(
-                                    //    "alt_polyclonal_read_status_global:{}",
-                                    //    alt_polyclonal_read_status_global
-                                    //);
-                                    if alt_comparisons[alignment_idx].comparison > max_alignment_alt
-                                        && indel_nones[alt_comparisons[alignment_idx].index]
-                                            == false
-                                        && alt_polyclonal_read_statuses
-                                            [alt_comparisons[alignment_idx].index]
-                                            != 1
-                                    {
-                                        max_alignment_alt =
-                                            alt_comparisons[alignment_idx].comparison;
-                                        max_index_alt = alt_comparisons[alignment_idx].index;
-                                    } else if ref_polyclonal_read_status_global != 1
-                                        || alt_polyclonal_read_status_global != 1
-                                    // If the read is going to be classified as none, no need to check if it is ambiguous
-                                    {
-                                        if alignment_idx == 0 {
-                                        } else {
-                                            //println!("alt_comparisons:{:?}", alt_comparisons);
-                                            for sub_alignment_idx in 0..alignment_idx {
-                                                let alt_comparison1 = alt_comparisons
-                                                    .iter()
-                                                    .find(|x| x.index == sub_alignment_idx);
-                                                let alt_comparison2 = alt_comparisons
-                                                    .iter()
-                                                    .find(|x| x.index == alignment_idx);
-                                                if alt_comparison1.unwrap().comparison
-                                                    == alt_comparison2.unwrap().comparison
-                                                {
-                                                    equal_alignment_indices.push(sub_alignment_idx);
-                                                    equal_alignment_indices.push(alignment_idx);
-                                                    //println!(
-                                                    //    "sub_alignment_idx:{}",
-                                                    //    sub_alignment_idx
-                                                    //);
-                                                    //println!("alignment_idx:{}", alignment_idx);
-                                                    read_ambiguous = 1;
-                                                    max_alignment_alt =
-                                                        alt_comparison1.unwrap().comparison;
-                                                    max_index_alt =
-                                                        alt_comparisons[alignment_idx].index;
-                                                }
-                                            }
-                                        }
-                                    } else if alt_polyclonal_read_statuses
-                                        [alt_comparisons[alignment_idx].index]
-                                        == 1
-                                        && ref_polyclonal_read_statuses
-                                            [ref_comparisons[alignment_idx].index]
-                                            == 0
-                                    // This logic is kind of side stepping the smith-watermann alignment and classifying on the basis of nucleotide-by-nucleotide comparison (not sure if this will work in all cases or any other possible side effects)
-                                    {
-                                        max_alignment_ref =
-                                            ref_comparisons[alignment_idx].comparison;
-                                        max_index_ref = ref_comparisons[alignment_idx].index;
-                                    } else if alt_comparisons[alignment_idx].comparison
-                                        > max_alignment_alt
-                                    // Should this logic be placed at the top ?
-                                    {
-                                        max_alignment_alt =
-                                            alt_comparisons[alignment_idx].comparison;
-                                        max_index_alt = alt_comparisons[alignment_idx].index;
-                                    }
-                                }
-
-                                //println!("max_alignment_alt:{}", max_alignment_alt);
-                                //println!("max_alignment_ref:{}", max_alignment_ref);
-                                let mut ref_is_ambiguous = false; // Flag which is true/false when ref allele is one of the reads that is ambiguous for a particular read
-                                if max_alignment_alt > max_alignment_ref {
-                                    //max_alignment = max_alignment_alt;
-                                    max_index = max_index_alt;
-                                    refalt = "alt".to_string();
-                                } else if max_alignment_alt < max_alignment_ref {
-                                    //max_alignment = max_alignment_ref;
-                                    max_index = max_index_ref;
-                                    refalt = "ref".to_string();
-                                } else if max_alignment_alt == max_alignment_ref {
-                                    //max_alignment = max_alignment_ref;
-                                    //println!(
-                                    //    "Ambiguous case between one of the alt alleles and the ref"
-                                    //);
-                                    read_ambiguous = 1;
-                                    //if equal_alignment_indices.iter().any(|&i| i == max_index_alt) {
-                                    //} else {
-                                    //    equal_alignment_indices.push(max_index_alt);
-                                    //    ref_is_ambiguous = true;
-                                    //}
-                                    equal_alignment_indices.push(max_index_alt);
-                                    ref_is_ambiguous = true;
-                                }
-                                equal_alignment_indices.sort();
-                                equal_alignment_indices.dedup();
-                                //println!("equal_alignment_indices:{:?}", equal_alignment_indices);
-                                // Check if the read does not support any of the reference or alternate alleles
-                                if ((ref_polyclonal_read_status_global == 1
-                                    && alt_polyclonal_read_status_global == 1)
-                                    || (ref_polyclonal_read_status_global == 1
-                                        && alt_polyclonal_read_status_global == 0
-                                        && refalt == "ref".to_string())) // The 2nd condition is experimental and was observed in case of deletion in repeat region. Somehow alt_polyclonal_read_status_global was 0 so it got classifed into ref allele although it does not support ref allele. 
-                                    && strictness == 1
-                                {
-                                    // Setting read to none
-                                    //if !all_alleles.contains_key(&"none".to_string()) {
-                                    //    all_alleles.insert("none".to_string(), Vec::new());
-                                    //}
-                                    //all_alleles
-                                    //    .get_mut(&"none".to_string())
-                                    //    .unwrap()
-                                    //    .push(i as usize);
-                                    //if all_alleles["none"] == other_json!(null) {
-                                    //    all_alleles["none"] = other_json!(vec![i]);
-                                    //} else {
-                                    //    all_alleles["none"]
-                                    //        .as_array_mut()
-                                    //        .unwrap()
-                                    //        .push(other_json!(i));
-                                    //}
-
-                                    let mut none_groups_for_each_read = Vec::<String>::new(); // When a read is assigned to none group, the first entry in the vector is "none", followed by the group with which it has maximum sequence similarity
-                                    none_groups_for_each_read.push("none".to_string());
-
-                                    if refalt == "ref".to_string() {
-                                        none_groups_for_each_read.push("ref".to_string())
-                                    } else if refalt == "alt".to_string() {
-                                        let alt_allele_name =
-                                            alt_allele_names_list[max_index].to_string();
-                                        none_groups_for_each_read.push(alt_allele_name);
-                                    }
-                                    //all_alleles[i.to_string()] =
-                                    //    other_json!(none_groups_for_each_read);
-                                    //all_alleles.push(ReadClassification {
-                                    //    read_number: i as usize,
-                                    //    categories: none_groups_for_each_read,
-                                    //});
-                                    //output_string += &json::stringify(object! {
-                                    //    read_number: i as usize,
-                                    //    categories: none_groups_for_each_read,
-                                    //});
-                                    output_string += &serde_json::to_string(&ReadClassification {
-                                        read_number: i as usize,
-                                        categories: none_groups_for_each_read,
-                                    })
-                                    .unwrap();
-                                    output_string += &",".to_string();
-                                } else if read_ambiguous == 1 {
-                                    // Setting read to ambiguous
-
-                                    //if !all_alleles.contains_key(&"amb".to_string()) {
-                                    //    all_alleles.insert("amb".to_string(), Vec::new());
-                                    //}
-                                    //all_alleles
-                                    //    .get_mut(&"amb".to_string())
-                                    //    .unwrap()
-                                    //    .push(i as usize);
-
-                                    //if all_alleles["amb"] == other_json!(null) {
-                                    //    all_alleles["amb"] = other_json!(vec![i]);
-                                    //} else {
-                                    //    all_alleles["amb"]
-                                    //        .as_array_mut()
-                                    //        .unwrap()
-                                    //        .push(other_json!(i));
-                                    //}
-                                    let mut ambiguous_groups_for_each_read = Vec::<String>::new(); // When a read is asigned to ambiguous group, the first entry in the vector is "amb". Subsequent entries are for each group with which the read has equal sequence similarity with
-                                    ambiguous_groups_for_each_read.push("amb".to_string());
-                                    for eq_alignment_idx in 0..equal_alignment_indices.len() {
-                                        //println!(
-                                        //    "eq_alignment_idx:{}",
-                                        //    equal_alignment_indices[eq_alignment_idx]
-                                        //);
-                                        ambiguous_groups_for_each_read.push(
-                                            alt_allele_names_list
-                                                [equal_alignment_indices[eq_alignment_idx]]
-                                                .to_string(),
-                                        );
-                                    }
-                                    if ref_is_ambiguous == true {
-                                        ambiguous_groups_for_each_read.push("ref".to_string());
-                                    }
-                                    //all_alleles.push(ReadClassification {
-                                    //    read_number: i as usize,
-                                    //    categories: ambiguous_groups_for_each_read,
-                                    //});
-                                    //output_string += &json::stringify(object! {
-                                    //    read_number: i as usize,
-                                    //    categories: ambiguous_groups_for_each_read,
-                                    //});
-                                    //println!(
-                                    //    "ambiguous_groups_for_each_read:{:?}",
-                                    //    ambiguous_groups_for_each_read
-                                    //);
-                                    output_string += &serde_json::to_string(&ReadClassification {
-                                        read_number: i as usize,
-                                        categories: ambiguous_groups_for_each_read,
-                                    })
-                                    .unwrap();
-                                    output_string += &",".to_string();
-                                } else if refalt == "ref".to_string() {
-                                    //all_alleles.push(ReadClassification {
-                                    //    read_number: i as usize,
-                                    //    categories: vec!["ref".to_string()],
-                                    //});
-                                    //output_string += &json::stringify(object! {
-                                    //    read_number: i as usize,
-                                    //    categories: vec!["ref".to_string()],
-                                    //});
-                                    output_string += &serde_json::to_string(&ReadClassification {
-                                        read_number: i as usize,
-                                        categories: vec!["ref".to_string()],
-                                    })
-                                    .unwrap();
-                                    output_string += &",".to_string();
-                                    if variant_positions_list.len() == 1 // Forward/reverse for reference/alternate allele is only counted for single allele variants
-                                && sequence_strand == "R".to_string()
-                                    {
-                                        reference_reverse_count += 1;
-                                    } else if variant_positions_list.len() == 1 // Forward/reverse for reference/alternate allele is only counted for single allele variants
-                                && sequence_strand == "F".to_string()
-                                    {
-                                        reference_forward_count += 1;
-                                    }
-                                } else if refalt == "alt".to_string() {
-                                    // Setting read to alt
-                                    // Determine which alt allele read needs to be classified into
-                                    let alt_allele_name =
-                                        alt_allele_names_list[max_index].to_string();
-                                    //if !all_alleles.contains_key(alt_allele_name) {
-                                    //    all_alleles.insert(alt_allele_name.to_string(), Vec::new());
-                                    //}
-                                    //all_alleles
-                                    //    .get_mut(alt_allele_name)
-                                    //    .unwrap()
-                                    //    .push(i as usize);
-
-                                    //if all_alleles[alt_allele_name] == other_json!(null) {
-                                    //    all_alleles[alt_allele_name] = other_json!(vec![i]);
-                                    //} else {
-                                    //    all_alleles[alt_allele_name]
-                                    //        .as_array_mut()
-                                    //        .unwrap()
-                                    //        .push(other_json!(i));
-                                    //}
-
-                                    //all_alleles[i.to_string()] =
-                                    //    other_json!(vec![alt_allele_name.to_string()]);
-                                    //all_alleles.push(ReadClassification {
-                                    //    read_number: i as usize,
-                                    //    categories: vec![alt_allele_name],
-                                    //});
-                                    output_string += &serde_json::to_string(&ReadClassification {
-                                        read_number: i as usize,
-                                        categories: vec![alt_allele_name],
-                                    })
-                                    .unwrap();
-                                    //output_string += &json::stringify(object! {
-                                    //    read_number: i as usize,
-                                    //    categories: vec![alt_allele_name],
-                                    //});
-                                    output_string += &",".to_string();
-                                    if variant_positions_list.len() == 1 // Forward/reverse for reference/alternate allele is only counted for single allele variants
-                                && sequence_strand == "R".to_string()
-                                    {
-                                        alternate_reverse_count += 1;
-                                    } else if variant_positions_list.len() == 1 // Forward/reverse for reference/alternate allele is only counted for single allele variants
-                                && sequence_strand == "F".to_string()
-                                    {
-                                        alternate_forward_count += 1;
-                                    }
-                                } else {
-                                    // Should not happen
-                                    println!("Unaccounted scenario, please check!");
-                                }
-                            }
-                            i += 1;
+                                min_base_quality,
+                                min_mapping_quality,
+                                &alignment_parameters,
+                            ) {
+                                classified_reads.push(classified_read)
+                            } // Otherwise the read does not contain the variant region
                         }
                     } else {
                         // Multithreaded implementation for parsing reads in parallel starts from here
                         // Generally in rust one variable only own a data at a time, but `Arc` keyword is special and allows for multiple threads to access the same data.
 
                         let sequences = Arc::new(sequences);
-                        let start_positions = Arc::new(start_positions);
+                        let read_start_positions = Arc::new(read_start_positions);
                         let cigar_sequences = Arc::new(cigar_sequences);
+                        let read_flags_shared = Arc::new(read_flags.to_owned()); // Copied since read_flags is used again by merge_mates()
+                        let quality_scores = Arc::new(quality_scores);
+                        let read_mapping_qualities = Arc::new(read_mapping_qualities);
                         let variant_positions = Arc::new(variant_positions);
                         let indel_lengths = Arc::new(indel_lengths);
-                        let refalleles = Arc::new(refalleles);
-                        let altalleles = Arc::new(altalleles);
+                        let refalleles = Arc::new(refalleles.to_string()); // Copied since refalleles_list is used again after classification
+                        let altalleles = Arc::new(altalleles.to_string()); // Copied since altalleles_list is used again after classification
                         let refseqs = Arc::new(refseqs);
                         let altseqs = Arc::new(altseqs);
-                        let indel_lengths = Arc::new(indel_lengths);
                         let alt_allele_names = Arc::new(alt_allele_names);
-                        let sequence_flags = Arc::new(sequence_flags);
-                        //let all_alleles_temp =
-                        //    Arc::new(Mutex::new(Vec::<ReadClassification>::new()));
-                        let output_string_temp = Arc::new(Mutex::new(String::new()));
-                        let alternate_forward_count_temp = Arc::new(Mutex::<u32>::new(0));
-                        let alternate_reverse_count_temp = Arc::new(Mutex::<u32>::new(0));
-                        let reference_forward_count_temp = Arc::new(Mutex::<u32>::new(0));
-                        let reference_reverse_count_temp = Arc::new(Mutex::<u32>::new(0));
+                        let classified_reads_temp =
+                            Arc::new(Mutex::new(Vec::<(ReadClassification, String)>::new()));
 
                         let mut handles = vec![]; // Vector to store handle which is used to prevent one thread going ahead of another
 
@@ -799,523 +599,81 @@ fn main() {
                             // Assigning thread number thread_num to each thread
                             // In the next few lines each variable gets cloned, so that each thread has its own copy of the variable
                             let sequences = Arc::clone(&sequences);
-                            let start_positions = Arc::clone(&start_positions);
+                            let read_start_positions = Arc::clone(&read_start_positions);
                             let cigar_sequences = Arc::clone(&cigar_sequences);
-                            let sequence_flags = Arc::clone(&sequence_flags);
-                            //let all_alleles_temp = Arc::clone(&all_alleles_temp);
-                            let alt_allele_names = Arc::clone(&alt_allele_names);
-                            let output_string_temp = Arc::clone(&output_string_temp);
-                            let alternate_forward_count_temp =
-                                Arc::clone(&alternate_forward_count_temp);
-                            let alternate_reverse_count_temp =
-                                Arc::clone(&alternate_reverse_count_temp);
-                            let reference_forward_count_temp =
-                                Arc::clone(&reference_forward_count_temp);
-                            let reference_reverse_count_temp =
-                                Arc::clone(&reference_reverse_count_temp);
+                            let read_flags = Arc::clone(&read_flags_shared);
+                            let quality_scores = Arc::clone(&quality_scores);
+                            let read_mapping_qualities = Arc::clone(&read_mapping_qualities);
+                            let variant_positions = Arc::clone(&variant_positions);
                             let indel_lengths = Arc::clone(&indel_lengths);
-                            let alt_allele_names = Arc::clone(&alt_allele_names);
                             let refalleles = Arc::clone(&refalleles);
                             let altalleles = Arc::clone(&altalleles);
                             let refseqs = Arc::clone(&refseqs);
                             let altseqs = Arc::clone(&altseqs);
-                            let variant_positions = Arc::clone(&variant_positions);
-                            let indel_lengths = Arc::clone(&indel_lengths);
+                            let alt_allele_names = Arc::clone(&alt_allele_names);
+                            let classified_reads_temp = Arc::clone(&classified_reads_temp);
+                            let alignment_parameters = alignment_parameters.clone();
 
                             let handle = thread::spawn(move || {
                                 // Thread is initiallized here
                                 //println!("thread:{}", thread_num);
                                 let sequences_list: Vec<&str> = sequences.split("-").collect();
-                                let start_positions_list: Vec<&str> =
-                                    start_positions.split("-").collect();
                                 let cigar_sequences_list: Vec<&str> =
                                     cigar_sequences.split("-").collect();
-                                let sequence_flags_list: Vec<&str> =
-                                    sequence_flags.split("-").collect();
-                                //let mut all_alleles_thread = Vec::<ReadClassification>::new();
-                                let mut alt_allele_names_list: Vec<String> = Vec::<String>::new();
-                                let alt_allele_names_str: Vec<&str> =
-                                    alt_allele_names.split("-").collect();
-                                for item in alt_allele_names_str {
-                                    alt_allele_names_list.push(item.to_string());
-                                }
-                                let mut output_string_thread = "".to_string();
-                                let mut alternate_forward_count_thread: u32 = 0; // Alternate forward read counter (for each thread)
-                                let mut alternate_reverse_count_thread: u32 = 0; // Alternate reverse read counter (for each thread)
-                                let mut reference_forward_count_thread: u32 = 0; // Reference forward read counter (for each thread)
-                                let mut reference_reverse_count_thread: u32 = 0; // Reference reverse read counter (for each thread)
+                                let quality_scores_list: Vec<&str> =
+                                    quality_scores.split(" ").collect();
+                                let refalleles_list: Vec<&str> = refalleles.split("-").collect();
+                                let altalleles_list: Vec<&str> = altalleles.split("-").collect();
+                                let refseqs_list: Vec<&str> = refseqs.split("-").collect();
+                                let altseqs_list: Vec<&str> = altseqs.split("-").collect();
+                                let variant_positions_list: Vec<i64> = variant_positions
+                                    .split("-")
+                                    .map(|item| item.parse::<i64>().unwrap())
+                                    .collect();
+                                let indel_lengths_list: Vec<usize> = indel_lengths
+                                    .split("-")
+                                    .map(|item| item.parse::<usize>().unwrap())
+                                    .collect();
+                                let alt_allele_names_list: Vec<String> = alt_allele_names
+                                    .split("-")
+                                    .map(|item| item.to_string())
+                                    .collect();
+
+                                let mut classified_reads_thread =
+                                    Vec::<(ReadClassification, String)>::new();
                                 for iter in 0..sequences_list.len() {
                                     let remainder: usize = iter % max_threads; // Calculate remainder of read number divided by max_threads to decide which thread parses this read
-                                                                               //println!("iter:{}", iter);
                                     if remainder == thread_num {
-                                        // Thread analyzing a particular read must have the same remainder as the thread_num, this avoids multiple threads from parsing the same read. Also checking if the read length > 0
-
-                                        //println!(
-                                        //    "start_positions_list:{}",
-                                        //    start_positions_list[iter].parse::<i64>().unwrap() - 1
-                                        //);
-                                        //println!("cigar_sequences_list[iter]:{}", cigar_sequences_list[iter]);
-                                        let refalleles_list: Vec<&str> =
-                                            refalleles.split("-").collect();
-                                        let altalleles_list: Vec<&str> =
-                                            altalleles.split("-").collect();
-                                        let refseqs_list: Vec<&str> = refseqs.split("-").collect();
-                                        let altseqs_list: Vec<&str> = altseqs.split("-").collect();
-
-                                        let mut variant_positions_list = Vec::<i64>::new();
-                                        let variant_positions_str: Vec<&str> =
-                                            variant_positions.split("-").collect();
-                                        for item in variant_positions_str {
-                                            variant_positions_list
-                                                .push(item.parse::<i64>().unwrap());
-                                        }
-                                        let mut indel_lengths_list = Vec::<usize>::new();
-                                        let indel_lengths_str: Vec<&str> =
-                                            indel_lengths.split("-").collect();
-                                        for item in indel_lengths_str {
-                                            indel_lengths_list.push(item.parse::<usize>().unwrap());
-                                        }
-                                        let (
-                                            within_indel,
-                                            correct_start_positions,
-                                            correct_end_positions,
-                                            alignment_sides,
-                                            spliced_sequences,
-                                        ) = realign::check_read_within_indel_region(
-                                            // Checks if the read contains the indel region (or a part of it)
-                                            start_positions_list[iter].parse::<i64>().unwrap() - 1,
-                                            cigar_sequences_list[iter].to_string(),
+                                        // Thread analyzing a particular read must have the same remainder as the thread_num, this avoids multiple threads from parsing the same read
+                                        if let Some(classified_read) = classify_read(
+                                            iter,
+                                            sequences_list[iter],
+                                            read_start_positions[iter] - 1,
+                                            cigar_sequences_list[iter],
+                                            read_flags[iter],
+                                            quality_scores_list[iter],
+                                            read_mapping_qualities[iter],
                                             &variant_positions_list,
                                             &indel_lengths_list,
                                             &refalleles_list,
                                             &altalleles_list,
+                                            &refseqs_list,
+                                            &altseqs_list,
+                                            &alt_allele_names_list,
                                             strictness,
-                                            sequences_list[iter].to_string(),
-                                        );
-                                        if within_indel == 1 {
-                                            // Checking if the read is in forward or reverse strand
-                                            let mut sequence_strand: String = "F".to_string(); // Initializing sequence strand to forward
-                                            if sequence_flags_list[iter].parse::<i64>().unwrap()
-                                                & 16
-                                                == 16
-                                            {
-                                                sequence_strand = "R".to_string();
-                                            }
-
-                                            let mut alt_comparisons = Vec::<ReadComparison>::new();
-                                            let mut ref_comparisons = Vec::<ReadComparison>::new();
-                                            let mut indel_nones = Vec::<bool>::new();
-                                            let mut ref_polyclonal_read_status_global: i64 = 0;
-                                            let mut alt_polyclonal_read_status_global: i64 = 1;
-                                            let mut ref_polyclonal_read_statuses =
-                                                Vec::<i64>::new();
-                                            let mut alt_polyclonal_read_statuses =
-                                                Vec::<i64>::new();
-                                            for indel_idx in 0..variant_positions_list.len() {
-                                                let spliced_sequence =
-                                                    &spliced_sequences[indel_idx];
-                                                let reference_sequence = refseqs_list[indel_idx];
-                                                let alternate_sequence = altseqs_list[indel_idx];
-                                                let (
-                                                    q_seq_ref,
-                                                    align_ref,
-                                                    r_seq_ref,
-                                                    ref_comparison,
-                                                ) = realign::align_single_reads(
-                                                    &spliced_sequence,
-                                                    reference_sequence.to_string(),
-                                                );
-                                                let (
-                                                    q_seq_alt,
-                                                    align_alt,
-                                                    r_seq_alt,
-                                                    alt_comparison,
-                                                ) = realign::align_single_reads(
-                                                    &spliced_sequence,
-                                                    alternate_sequence.to_string(),
-                                                );
-                                                alt_comparisons.push(ReadComparison {
-                                                    comparison: alt_comparison,
-                                                    index: indel_idx,
-                                                });
-                                                ref_comparisons.push(ReadComparison {
-                                                    comparison: ref_comparison,
-                                                    index: indel_idx,
-                                                });
-
-                                                let (
-                                                    ref_polyclonal_read_status,
-                                                    alt_polyclonal_read_status,
-                                                );
-                                                if strictness == 0 {
-                                                    ref_polyclonal_read_status = 0;
-                                                    alt_polyclonal_read_status = 0;
-                                                } else {
-                                                    let (
-                                                        ref_polyclonal_read_status_temp,
-                                                        alt_polyclonal_read_status_temp,
-                                                    ) = check_polyclonal_with_read_alignment(
-                                                        &alignment_sides[indel_idx],
-                                                        &q_seq_alt,
-                                                        &q_seq_ref,
-                                                        &r_seq_alt,
-                                                        &r_seq_ref,
-                                                        &align_alt,
-                                                        &align_ref,
-                                                        correct_start_positions[indel_idx],
-                                                        correct_end_positions[indel_idx],
-                                                        variant_positions_list[indel_idx],
-                                                        refalleles_list[indel_idx].len(),
-                                                        altalleles_list[indel_idx].len(),
-                                                        indel_lengths_list[indel_idx],
-                                                        variant_positions_list.len(),
-                                                    );
-                                                    ref_polyclonal_read_status =
-                                                        ref_polyclonal_read_status_temp;
-                                                    alt_polyclonal_read_status =
-                                                        alt_polyclonal_read_status_temp;
-                                                }
-                                                if ref_polyclonal_read_status == 1 {
-                                                    ref_polyclonal_read_status_global = 1;
-                                                }
-                                                if alt_polyclonal_read_status == 0 {
-                                                    alt_polyclonal_read_status_global = 0;
-                                                }
-                                                let mut indel_none: bool = false;
-                                                if ref_polyclonal_read_status == 1
-                                                    && alt_polyclonal_read_status == 1
-                                                {
-                                                    indel_none = true;
-                                                }
-                                                indel_nones.push(indel_none);
-                                                ref_polyclonal_read_statuses
-                                                    .push(ref_polyclonal_read_status);
-                                                alt_polyclonal_read_statuses
-                                                    .push(alt_polyclonal_read_status);
-                                            }
-                                            // Sorting in ascending order so as to check if the read is ambiguous or not
-                                            alt_comparisons.as_mut_slice().sort_by(|a, b| {
-                                                (a.comparison)
-                                                    .partial_cmp(&b.comparison)
-                                                    .unwrap_or(Ordering::Equal)
-                                            });
-                                            ref_comparisons.as_mut_slice().sort_by(|a, b| {
-                                                (a.comparison)
-                                                    .partial_cmp(&b.comparison)
-                                                    .unwrap_or(Ordering::Equal)
-                                            });
-
-                                            let mut max_index = 0;
-                                            let mut refalt = "ref".to_string();
-                                            let mut max_alignment_ref: f64 = 0.0;
-                                            let mut max_index_ref: usize = 0;
-                                            for alignment_idx in 0..ref_comparisons.len() {
-                                                if ref_comparisons[alignment_idx].comparison
-                                                    > max_alignment_ref
-                                                {
-                                                    max_alignment_ref =
-                                                        ref_comparisons[alignment_idx].comparison;
-                                                    max_index_ref =
-                                                        ref_comparisons[alignment_idx].index;
-                                                }
-                                            }
-
-                                            let mut read_ambiguous = 0;
-                                            let mut equal_alignment_indices = Vec::<usize>::new();
-                                            let mut max_alignment_alt: f64 = 0.0;
-                                            let mut max_index_alt: usize = 0;
-                                            for alignment_idx in 0..alt_comparisons.len() {
-                                                if alt_comparisons[alignment_idx].comparison
-                                                    > max_alignment_alt
-                                                    && indel_nones
-                                                        [alt_comparisons[alignment_idx].index]
-                                                        == false
-                                                    && alt_polyclonal_read_statuses
-                                                        [alt_comparisons[alignment_idx].index]
-                                                        != 1
-                                                {
-                                                    max_alignment_alt =
-                                                        alt_comparisons[alignment_idx].comparison;
-                                                    max_index_alt =
-                                                        alt_comparisons[alignment_idx].index;
-                                                } else if ref_polyclonal_read_status_global != 1
-                                                    || alt_polyclonal_read_status_global != 1
-                                                // If the read is going to be classified as none, no need to check if it is ambiguous
-                                                {
-                                                    if alignment_idx == 0 {
-                                                    } else {
-                                                        //println!("alt_comparisons:{:?}", alt_comparisons);
-                                                        for sub_alignment_idx in 0..alignment_idx {
-                                                            let alt_comparison1 =
-                                                                alt_comparisons.iter().find(|x| {
-                                                                    x.index == sub_alignment_idx
-                                                                });
-                                                            let alt_comparison2 = alt_comparisons
-                                                                .iter()
-                                                                .find(|x| x.index == alignment_idx);
-                                                            if alt_comparison1.unwrap().comparison
-                                                                == alt_comparison2
-                                                                    .unwrap()
-                                                                    .comparison
-                                                            {
-                                                                equal_alignment_indices
-                                                                    .push(sub_alignment_idx);
-                                                                equal_alignment_indices
-                                                                    .push(alignment_idx);
-                                                                //println!(
-                                                                //    "sub_alignment_idx:{}",
-                                                                //    sub_alignment_idx
-                                                                //);
-                                                                //println!("alignment_idx:{}", alignment_idx);
-                                                                read_ambiguous = 1;
-                                                                max_alignment_alt = alt_comparison1
-                                                                    .unwrap()
-                                                                    .comparison;
-                                                            }
-                                                        }
-                                                    }
-                                                } else if alt_polyclonal_read_statuses
-                                                    [alt_comparisons[alignment_idx].index]
-                                                    == 1
-                                                    && ref_polyclonal_read_statuses
-                                                        [ref_comparisons[alignment_idx].index]
-                                                        == 0
-                                                // This logic is kind of side stepping the smith-watermann alignment and classifying on the basis of nucleotide-by-nucleotide comparison (not sure if this will work in all cases or any other possible side effects)
-                                                {
-                                                    max_alignment_ref =
-                                                        ref_comparisons[alignment_idx].comparison;
-                                                    max_index_ref =
-                                                        ref_comparisons[alignment_idx].index;
-                                                } else if alt_comparisons[alignment_idx].comparison
-                                                    > max_alignment_alt
-                                                // Should this logic be placed at the top ?
-                                                {
-                                                    max_alignment_alt =
-                                                        alt_comparisons[alignment_idx].comparison;
-                                                    max_index_alt =
-                                                        alt_comparisons[alignment_idx].index;
-                                                }
-                                            }
-
-                                            let mut ref_is_ambiguous = false; // Flag which is true/false when ref allele is one of the reads that is ambiguous for a particular read
-                                            if max_alignment_alt > max_alignment_ref {
-                                                //max_alignment = max_alignment_alt;
-                                                max_index = max_index_alt;
-                                                refalt = "alt".to_string();
-                                            } else if max_alignment_alt < max_alignment_ref {
-                                                //max_alignment = max_alignment_ref;
-                                                max_index = max_index_ref;
-                                                refalt = "ref".to_string();
-                                            } else if max_alignment_alt == max_alignment_ref {
-                                                //max_alignment = max_alignment_ref;
-                                                //println!("Ambiguous case between one of the alt alleles and the ref");
-                                                read_ambiguous = 1;
-                                                //if equal_alignment_indices
-                                                //    .iter()
-                                                //    .any(|&i| i == max_index_alt)
-                                                //{
-                                                //} else {
-                                                //    equal_alignment_indices.push(max_index_alt);
-                                                //    ref_is_ambiguous = true;
-                                                //}
-                                                equal_alignment_indices.push(max_index_alt);
-                                                ref_is_ambiguous = true;
-                                            }
-                                            equal_alignment_indices.sort();
-                                            equal_alignment_indices.dedup();
-                                            // Check if the read does not support any of the reference or alternate alleles
-                                            if ((ref_polyclonal_read_status_global == 1
-                                                && alt_polyclonal_read_status_global == 1)
-                                                || (ref_polyclonal_read_status_global == 1
-                                                    && alt_polyclonal_read_status_global == 0
-                                                    && refalt == "ref".to_string()))
-                                                && strictness == 1
-                                            {
-                                                // Setting read to none
-                                                //if !all_alleles_thread
-                                                //    .contains_key(&"none".to_string())
-                                                //{
-                                                //    all_alleles_thread
-                                                //        .insert("none".to_string(), Vec::new());
-                                                //}
-                                                //all_alleles_thread
-                                                //    .get_mut(&"none".to_string())
-                                                //    .unwrap()
-                                                //    .push(iter);
-
-                                                let mut none_groups_for_each_read =
-                                                    Vec::<String>::new(); // When a read is assigned to none group, the first entry in the vector is "none", followed by the group with which it has maximum sequence similarity
-                                                none_groups_for_each_read.push("none".to_string());
-
-                                                if refalt == "ref".to_string() {
-                                                    none_groups_for_each_read
-                                                        .push("ref".to_string())
-                                                } else if refalt == "alt".to_string() {
-                                                    let alt_allele_name = alt_allele_names_list
-                                                        [max_index]
-                                                        .to_string();
-                                                    none_groups_for_each_read.push(alt_allele_name);
-                                                }
-                                                //all_alleles_thread.push(ReadClassification {
-                                                //    read_number: iter,
-                                                //    categories: none_groups_for_each_read,
-                                                //});
-                                                //all_alleles_thread[&iter.to_string()] =
-                                                //    other_json!(none_groups_for_each_read);
-                                                output_string_thread +=
-                                                    &serde_json::to_string(&ReadClassification {
-                                                        read_number: iter as usize,
-                                                        categories: none_groups_for_each_read,
-                                                    })
-                                                    .unwrap();
-                                                output_string_thread += &",".to_string();
-                                            } else if read_ambiguous == 1 {
-                                                // Setting read to ambiguous
-                                                //if !all_alleles_thread
-                                                //    .contains_key(&"amb".to_string())
-                                                //{
-                                                //    all_alleles_thread
-                                                //        .insert("amb".to_string(), Vec::new());
-                                                //} else {
-                                                //    all_alleles_thread
-                                                //        .get_mut(&"amb".to_string())
-                                                //        .unwrap()
-                                                //        .push(iter);
-                                                //}
-
-                                                let mut ambiguous_groups_for_each_read =
-                                                    Vec::<String>::new(); // When a read is asigned to ambiguous group, the first entry in the vector is "amb". Subsequent entries are for each group with which the read has equal sequence similarity with
-                                                ambiguous_groups_for_each_read
-                                                    .push("amb".to_string());
-                                                for eq_alignment_idx in
-                                                    0..equal_alignment_indices.len()
-                                                {
-                                                    let item2 = alt_allele_names_list
-                                                        [equal_alignment_indices[eq_alignment_idx]]
-                                                        .clone()
-                                                        .to_string();
-                                                    ambiguous_groups_for_each_read.push(item2);
-                                                }
-                                                if ref_is_ambiguous == true {
-                                                    ambiguous_groups_for_each_read
-                                                        .push("ref".to_string());
-                                                }
-                                                //all_alleles_thread[&iter.to_string()] =
-                                                //    other_json!(ambiguous_groups_for_each_read);
-                                                //all_alleles_thread.push(ReadClassification {
-                                                //    read_number: iter,
-                                                //    categories: ambiguous_groups_for_each_read,
-                                                //});
-                                                output_string_thread +=
-                                                    &serde_json::to_string(&ReadClassification {
-                                                        read_number: iter as usize,
-                                                        categories: ambiguous_groups_for_each_read,
-                                                    })
-                                                    .unwrap();
-                                                output_string_thread += &",".to_string();
-                                                //ambiguous_list_thread
-                                                //    .insert(iter, ambiguous_groups_for_each_read);
-                                            } else if refalt == "ref".to_string() {
-                                                // Setting read to ref
-                                                //if !all_alleles_thread
-                                                //    .contains_key(&"ref".to_string())
-                                                //{
-                                                //    all_alleles_thread
-                                                //        .insert("ref".to_string(), Vec::new());
-                                                //}
-                                                //all_alleles_thread
-                                                //    .get_mut(&"ref".to_string())
-                                                //    .unwrap()
-                                                //    .push(iter);
-                                                //all_alleles_thread[iter.to_string()] =
-                                                //    other_json!(vec!["ref".to_string()]);
-                                                //all_alleles_thread.push(ReadClassification {
-                                                //    read_number: iter,
-                                                //    categories: vec!["ref".to_string()],
-                                                //});
-                                                output_string_thread +=
-                                                    &serde_json::to_string(&ReadClassification {
-                                                        read_number: iter as usize,
-                                                        categories: vec!["ref".to_string()],
-                                                    })
-                                                    .unwrap();
-                                                output_string_thread += &",".to_string();
-                                                if variant_positions_list.len() == 1 // Forward/reverse for reference/alternate allele is only counted for single allele variants
-                                && sequence_strand == "R".to_string()
-                                                {
-                                                    reference_reverse_count_thread += 1;
-                                                } else if variant_positions_list.len() == 1 // Forward/reverse for reference/alternate allele is only counted for single allele variants
-                                && sequence_strand == "F".to_string()
-                                                {
-                                                    reference_forward_count_thread += 1;
-                                                }
-                                            } else if refalt == "alt".to_string() {
-                                                // Setting read to alt
-                                                // Determine which alt allele read needs to be classified into
-                                                let alt_allele_name =
-                                                    alt_allele_names_list[max_index].to_string();
-                                                //if !all_alleles_thread
-                                                //    .contains_key(&alt_allele_name.clone())
-                                                //{
-                                                //    all_alleles_thread.insert(
-                                                //        alt_allele_name.clone(),
-                                                //        Vec::new(),
-                                                //    );
-                                                //}
-                                                //all_alleles_thread
-                                                //    .get_mut(&alt_allele_name)
-                                                //    .unwrap()
-                                                //    .push(iter);
-
-                                                //all_alleles_thread[iter.to_string()] =
-                                                //    other_json!(vec![alt_allele_name.to_string()]);
-                                                //all_alleles_thread.push(ReadClassification {
-                                                //    read_number: iter,
-                                                //    categories: vec![alt_allele_name],
-                                                //});
-                                                output_string_thread +=
-                                                    &serde_json::to_string(&ReadClassification {
-                                                        read_number: iter as usize,
-                                                        categories: vec![alt_allele_name],
-                                                    })
-                                                    .unwrap();
-                                                output_string_thread += &",".to_string();
-                                                if variant_positions_list.len() == 1 // Forward/reverse for reference/alternate allele is only counted for single allele variants
-                                && sequence_strand == "R".to_string()
-                                                {
-                                                    alternate_reverse_count_thread += 1;
-                                                } else if variant_positions_list.len() == 1 // Forward/reverse for reference/alternate allele is only counted for single allele variants
-                                && sequence_strand == "F".to_string()
-                                                {
-                                                    alternate_forward_count_thread += 1;
-                                                }
-                                            } else {
-                                                // Should not happen
-                                                println!("Unaccounted scenario, please check!");
-                                            }
-                                        }
+                                            min_base_quality,
+                                            min_mapping_quality,
+                                            &alignment_parameters,
+                                        ) {
+                                            classified_reads_thread.push(classified_read)
+                                        } // Otherwise the read does not contain the variant region
                                     }
                                 }
-                                // Once all reads are analyzed by a thread it transfers all the read_diff_scores thread. I tried out an alternative implementation where all read_diff_scores struct was directly stored in alt_scores_thread and ref_scores_thread but that was slower because it was keeping other threads idle while one was writing into those variables
-                                //for item in all_alleles_thread {
-                                //    all_alleles_temp.lock().unwrap().push(item);
-                                //}
-                                //drop(all_alleles_temp); // This drops the vector so that other threads can now write into this variable while the current thread can do some other computation. This helps in better concurrency.
-
-                                *output_string_temp.lock().unwrap() += &output_string_thread;
-
-                                *alternate_forward_count_temp.lock().unwrap() +=
-                                    alternate_forward_count_thread;
-                                drop(alternate_forward_count_temp);
-                                *alternate_reverse_count_temp.lock().unwrap() +=
-                                    alternate_reverse_count_thread;
-                                drop(alternate_reverse_count_temp);
-                                *reference_forward_count_temp.lock().unwrap() +=
-                                    reference_forward_count_thread;
-                                drop(reference_forward_count_temp);
-                                *reference_reverse_count_temp.lock().unwrap() +=
-                                    reference_reverse_count_thread;
-                                drop(reference_reverse_count_temp);
+                                // Once all reads are analyzed by a thread it transfers all the classified reads in one go, so that other threads are not kept waiting on the lock for each read
+                                classified_reads_temp
+                                    .lock()
+                                    .unwrap()
+                                    .append(&mut classified_reads_thread);
                             });
                             handles.push(handle); // The handle (which contains the thread) is stored in the handles vector
                         }
@@ -1324,18 +682,105 @@ fn main() {
                             handle.join().unwrap();
                         }
                         // Combining data from all different threads
-                        //for item in &mut *all_alleles_temp.lock().unwrap() {
-                        //    all_alleles.push(ReadClassification::to_owned(item));
-                        //}
-                        output_string += &*output_string_temp.lock().unwrap();
-                        alternate_forward_count += *alternate_forward_count_temp.lock().unwrap();
-                        alternate_reverse_count += *alternate_reverse_count_temp.lock().unwrap();
-                        reference_forward_count += *reference_forward_count_temp.lock().unwrap();
-                        reference_reverse_count += *reference_reverse_count_temp.lock().unwrap();
+                        classified_reads.append(&mut *classified_reads_temp.lock().unwrap());
+                        classified_reads.sort_by_key(|a| a.0.read_number);
+                        // Threads finish in arbitrary order, sorting so that the output is in the same order as the single-thread implementation
+                    }
+
+                    // Per-allele read counts. The first entry is the ref allele, followed by each alt allele (in the same order as in alleles) and then the none and amb groups
+                    let mut allele_counts = Vec::<AlleleCount>::new();
+                    allele_counts.push(AlleleCount::new("ref"));
+                    for alt_allele_name in &alt_allele_names_list {
+                        allele_counts.push(AlleleCount::new(alt_allele_name));
                     }
+                    allele_counts.push(AlleleCount::new("none"));
+                    allele_counts.push(AlleleCount::new("amb"));
+
+                    merge_mates(&mut classified_reads, &read_names, &read_flags);
 
+                    let mut output_string = "[".to_string();
+                    for (read_classification, sequence_strand) in &classified_reads {
+                        output_string += &serde_json::to_string(read_classification).unwrap();
+                        output_string += ",";
+                        match read_classification.mate_read_number {
+                            Some(mate_read_number)
+                                if mate_read_number < read_classification.read_number =>
+                            {
+                                continue
+                            } // The template has already been counted with the mate
+                            _ => {}
+                        }
+                        // The first entry in categories is the group the read is assigned to
+                        match allele_counts
+                            .iter_mut()
+                            .find(|x| x.allele == read_classification.categories[0])
+                        {
+                            Some(allele_count) => {
+                                allele_count.count += 1;
+                                if sequence_strand == "R" {
+                                    allele_count.reverse += 1;
+                                } else {
+                                    allele_count.forward += 1;
+                                }
+                            }
+                            None => {
+                                // Should not happen
+                                println!("Unaccounted scenario, please check!");
+                            }
+                        }
+                    }
+
+                    if let Some(vcf_file) = json_string["vcf_file"].as_str() {
+                        let chrom: &str = match json_string["chr"].as_str() {
+                            Some(chrom) => chrom,
+                            None => {
+                                println!("chr is required for VCF output");
+                                return;
+                            }
+                        };
+                        let sample_name: &str =
+                            json_string["sample_name"].as_str().unwrap_or("SAMPLE");
+                        let vcf_records = match create_vcf_records(
+                            chrom,
+                            &variant_positions_list,
+                            &refalleles_list,
+                            &altalleles_list,
+                            &leftflankseqs_list,
+                            &allele_counts,
+                        ) {
+                            Ok(vcf_records) => vcf_records,
+                            Err(error) => {
+                                println!("{}", error);
+                                return;
+                            }
+                        };
+                        match vcf::write_vcf(
+                            vcf_file,
+                            "proteinpaint_indel",
+                            chrom,
+                            sample_name,
+                            &vcf_records,
+                            &[],
+                        ) {
+                            Ok(_) => {}
+                            Err(error) => println!("{}", error),
+                        }
+                    }
+
+                    println!(
+                        "allele_counts:{}",
+                        serde_json::to_string(&allele_counts).unwrap()
+                    );
+                    println!(
+                        "genotype:{}",
+                        serde_json::to_string(&call_genotype(&allele_counts)).unwrap()
+                    );
                     if variant_positions_list.len() == 1 {
                         // Forward/reverse for reference/alternate allele is only counted for single allele variants
+                        let alternate_forward_count: u32 = allele_counts[1].forward; // Alternate forward read counter
+                        let alternate_reverse_count: u32 = allele_counts[1].reverse; // Alternate reverse read counter
+                        let reference_forward_count: u32 = allele_counts[0].forward; // Reference forward read counter
+                        let reference_reverse_count: u32 = allele_counts[0].reverse; // Reference reverse read counter
                         let p_value = strand_analysis(
                             alternate_forward_count,
                             alternate_reverse_count,
@@ -1371,6 +816,505 @@ fn main() {
     }
 }
 
+// Splits a field joined over all reads by separator, returning an empty list when there are no reads (splitting an empty string would give one empty entry)
+fn split_reads<'a>(joined: &'a str, separator: &str, num_reads: usize) -> Vec<&'a str> {
+    if num_reads == 0 {
+        return Vec::new();
+    }
+    joined.split(separator).collect()
+}
+
+// Parses a numeric field (start_position, flag, mapq) of every read, returning an error naming the read and the field if any value is malformed
+fn parse_read_field<T: FromStr>(values: &[&str], field: &str) -> Result<Vec<T>, String> {
+    let mut parsed = Vec::<T>::with_capacity(values.len());
+    for (read_number, value) in values.iter().enumerate() {
+        match value.parse::<T>() {
+            Ok(value) => parsed.push(value),
+            Err(_) => {
+                return Err(format!(
+                    "Invalid {} \"{}\" for read {}",
+                    field, value, read_number
+                ))
+            }
+        }
+    }
+    Ok(parsed)
+}
+
+// Merges the classifications of both mates of a paired-end template (reads with the same read name and the paired flag set), so that a fragment is counted only once. Both mates are given the same categories:
+//   Both mates assigned to the same group: that group
+//   One mate is none/amb: the group of the other mate (the informative mate decides)
+//   Mates assigned to different alleles: amb (followed by the allele of each mate)
+// The strand of the template is the strand of the first mate in pair, since the second mate of a fragment is always sequenced from the opposite strand
+fn merge_mates(
+    classified_reads: &mut [(ReadClassification, String)], // Classified reads along with the strand of each read
+    read_names: &[String],                                 // Read name of each read in the input
+    read_flags: &[i64],                                    // SAM flag of each read in the input
+) {
+    let mut first_mates = HashMap::<&str, usize>::new(); // Index in classified_reads of the first classified mate of each template
+    for idx in 0..classified_reads.len() {
+        let read_number = classified_reads[idx].0.read_number;
+        if read_number >= read_names.len() || read_names[read_number].is_empty() {
+            // Read name not available, mates cannot be identified
+            continue;
+        }
+        let sequence_flag = read_flags[read_number];
+        if sequence_flag & 1 == 0 {
+            // Read is not paired
+            continue;
+        }
+        let mate_idx = match first_mates.get(read_names[read_number].as_str()) {
+            Some(mate_idx) => *mate_idx,
+            None => {
+                first_mates.insert(read_names[read_number].as_str(), idx);
+                continue;
+            }
+        };
+        let mate_read_number = classified_reads[mate_idx].0.read_number;
+        let group = classified_reads[idx].0.categories[0].to_owned();
+        let mate_group = classified_reads[mate_idx].0.categories[0].to_owned();
+        let uninformative = |group: &String| group == "none" || group == "amb";
+        let categories: Vec<String> = if group == mate_group {
+            classified_reads[idx].0.categories.to_owned()
+        } else if uninformative(&group) && !uninformative(&mate_group) {
+            classified_reads[mate_idx].0.categories.to_owned()
+        } else if uninformative(&mate_group) && !uninformative(&group) {
+            classified_reads[idx].0.categories.to_owned()
+        } else if uninformative(&group) && uninformative(&mate_group) {
+            vec!["amb".to_string()] // One mate is none and the other amb
+        } else {
+            vec!["amb".to_string(), mate_group, group] // Mates support different alleles
+        };
+        let template_strand: String = if read_flags[mate_read_number] & 64 == 64 {
+            classified_reads[mate_idx].1.to_owned()
+        } else if sequence_flag & 64 == 64 {
+            classified_reads[idx].1.to_owned()
+        } else {
+            classified_reads[mate_idx].1.to_owned() // First mate in pair flag not set, using the strand of the mate with the lower read_number
+        };
+        classified_reads[idx].0.categories = categories.to_owned();
+        classified_reads[idx].0.mate_read_number = Some(mate_read_number);
+        classified_reads[idx].1 = template_strand.to_owned();
+        classified_reads[mate_idx].0.categories = categories;
+        classified_reads[mate_idx].0.mate_read_number = Some(read_number);
+        classified_reads[mate_idx].1 = template_strand;
+    }
+}
+
+// Classifies a single read into ref, one of the alt alleles, none or amb. Returns None if the read does not contain the variant region or has a mapping quality lower than min_mapping_quality. The read is assigned to the allele (among ref and all alt alleles) with the highest identity from align_single_reads(). If two or more alleles share the highest identity the read is classified as amb. This function is used by both the single-thread and multithreaded implementations
+#[allow(clippy::too_many_arguments)] // Takes the per-read inputs of the classification loop it was extracted from
+fn classify_read(
+    read_number: usize,                                  // Index of read in the input
+    read_sequence: &str,                                 // Read sequence
+    left_most_pos: i64,                                  // 0-based start position of the read
+    cigar_sequence: &str,                                // Cigar sequence of the read
+    sequence_flag: i64,                                  // SAM flag of the read
+    quality_score_sequence: &str, // Base pair quality scores of the read (phred33, "*" when not available)
+    mapping_quality: u8,          // Mapping quality of the read (255 when not available)
+    variant_positions_list: &Vec<i64>, // Start position of each variant
+    indel_lengths_list: &Vec<usize>, // Length of each variant (after preprocess_input)
+    refalleles_list: &Vec<&str>,  // Ref allele of each variant
+    altalleles_list: &Vec<&str>,  // Alt allele of each variant
+    refseqs_list: &[&str], // Reference sequence (leftflankseq + refallele + rightflankseq) of each variant
+    altseqs_list: &[&str], // Alternate sequence (leftflankseq + altallele + rightflankseq) of each variant
+    alt_allele_names_list: &[String], // Names of alt alleles (alt0, alt1 ...)
+    strictness: usize,     // Strictness of the indel pipeline
+    min_base_quality: u8,  // Minimum base quality of nucleotides in the variant region
+    min_mapping_quality: u8, // Minimum mapping quality of the read
+    alignment_parameters: &realign::AlignmentParameters, // Scoring scheme and alignment mode used by align_single_reads()
+) -> Option<(ReadClassification, String)> {
+    if mapping_quality != 255 && mapping_quality < min_mapping_quality {
+        // Read may have been aligned to the wrong region, therefore not considered
+        return None;
+    }
+    if cigar_sequence == "*" {
+        // Unmapped read or CIGAR not available, the read cannot be placed w.r.t the variant region (nor its base qualities checked)
+        return None;
+    }
+    let (
+        within_indel,
+        correct_start_positions,
+        correct_end_positions,
+        alignment_sides,
+        spliced_sequences,
+    ) = realign::check_read_within_indel_region(
+        // Checks if the read contains the indel region (or a part of it)
+        left_most_pos,
+        cigar_sequence.to_string(),
+        variant_positions_list,
+        indel_lengths_list,
+        refalleles_list,
+        altalleles_list,
+        strictness,
+        read_sequence.to_string(),
+    );
+    //println!("correct_start_positions:{:?}", correct_start_positions);
+    //println!("correct_end_positions:{:?}", correct_end_positions);
+    //println!("within_indel:{}", within_indel);
+    if within_indel == 0 {
+        return None;
+    }
+
+    // Checking base pair quality scores of the read in the variant region (union of the regions of all variants)
+    let mut low_base_quality: bool = false;
+    if min_base_quality > 0 {
+        let flanking_region_length: i64 = 2; // Number of nucleotides on either side of the variant region whose base quality is also checked
+        let mut left_flanking_region_limit: i64 = variant_positions_list[0];
+        let mut right_flanking_region_limit: i64 =
+            variant_positions_list[0] + indel_lengths_list[0] as i64;
+        for indel_idx in 1..variant_positions_list.len() {
+            left_flanking_region_limit = cmp::min(
+                left_flanking_region_limit,
+                variant_positions_list[indel_idx],
+            );
+            right_flanking_region_limit = cmp::max(
+                right_flanking_region_limit,
+                variant_positions_list[indel_idx] + indel_lengths_list[indel_idx] as i64,
+            );
+        }
+        let (alphabets, numbers) = realign::parse_cigar(&cigar_sequence.to_string());
+        low_base_quality = !realign::check_base_pair_quality_scores(
+            quality_score_sequence,
+            &alphabets,
+            &numbers,
+            left_most_pos,
+            left_flanking_region_limit - flanking_region_length,
+            right_flanking_region_limit + flanking_region_length,
+            min_base_quality,
+        );
+    }
+
+    // Checking if the read is in forward or reverse strand
+    let mut sequence_strand: String = "F".to_string(); // Initializing sequence strand to forward
+    if sequence_flag & 16 == 16 {
+        sequence_strand = "R".to_string();
+    }
+
+    let mut alt_comparisons = Vec::<ReadComparison>::new();
+    let mut ref_comparisons = Vec::<ReadComparison>::new();
+    let mut ref_polyclonal_read_status_global: i64 = 0;
+    let mut alt_polyclonal_read_status_global: i64 = 1;
+    let mut alt_polyclonal_read_statuses = Vec::<i64>::new();
+    for indel_idx in 0..variant_positions_list.len() {
+        let spliced_sequence = &spliced_sequences[indel_idx];
+        let (q_seq_ref, align_ref, r_seq_ref, ref_comparison) = realign::align_single_reads(
+            spliced_sequence,
+            refseqs_list[indel_idx].to_string(),
+            alignment_parameters,
+        );
+        let (q_seq_alt, align_alt, r_seq_alt, alt_comparison) = realign::align_single_reads(
+            spliced_sequence,
+            altseqs_list[indel_idx].to_string(),
+            alignment_parameters,
+        );
+        alt_comparisons.push(ReadComparison {
+            comparison: alt_comparison,
+            index: indel_idx,
+        });
+        ref_comparisons.push(ReadComparison {
+            comparison: ref_comparison,
+            index: indel_idx,
+        });
+
+        //println!("indel_idx:{}", indel_idx);
+        //println!("alt allele:{}", altalleles_list[indel_idx]);
+        //println!("ref_comparison:{}", ref_comparison);
+        //println!("alt_comparison:{}", alt_comparison);
+
+        let (ref_polyclonal_read_status, alt_polyclonal_read_status);
+        if strictness == 0 {
+            ref_polyclonal_read_status = 0;
+            alt_polyclonal_read_status = 0;
+        } else {
+            let (ref_polyclonal_read_status_temp, alt_polyclonal_read_status_temp) =
+                check_polyclonal_with_read_alignment(
+                    &alignment_sides[indel_idx],
+                    &q_seq_alt,
+                    &q_seq_ref,
+                    &r_seq_alt,
+                    &r_seq_ref,
+                    &align_alt,
+                    &align_ref,
+                    correct_start_positions[indel_idx],
+                    correct_end_positions[indel_idx],
+                    variant_positions_list[indel_idx],
+                    refalleles_list[indel_idx].len(),
+                    altalleles_list[indel_idx].len(),
+                    indel_lengths_list[indel_idx],
+                    variant_positions_list.len(),
+                );
+            ref_polyclonal_read_status = ref_polyclonal_read_status_temp;
+            alt_polyclonal_read_status = alt_polyclonal_read_status_temp;
+        }
+        if ref_polyclonal_read_status == 1 {
+            ref_polyclonal_read_status_global = 1;
+        }
+        if alt_polyclonal_read_status == 0 {
+            alt_polyclonal_read_status_global = 0;
+        }
+        alt_polyclonal_read_statuses.push(alt_polyclonal_read_status);
+    }
+
+    // Check if the read does not support the reference or any of the alternate alleles
+    let read_supports_no_allele: bool =
+        ref_polyclonal_read_status_global == 1 && alt_polyclonal_read_status_global == 1;
+
+    // Highest identity w.r.t the reference sequence among all variants
+    let mut max_alignment_ref: f64 = 0.0;
+    for ref_comparison in &ref_comparisons {
+        if ref_comparison.comparison > max_alignment_ref {
+            max_alignment_ref = ref_comparison.comparison;
+        }
+    }
+
+    // Highest identity w.r.t the alternate sequences. Alt alleles for which the read contains inserted/deleted nucleotides not matching the alt allele (alt_polyclonal_read_status = 1) are skipped, unless the read does not support any allele (in that case the read will be put in none and the best alt allele is only used to report the closest group)
+    let alt_allele_considered = |alt_comparison: &ReadComparison| -> bool {
+        alt_polyclonal_read_statuses[alt_comparison.index] != 1 || read_supports_no_allele
+    };
+    let mut max_alignment_alt: f64 = 0.0;
+    for alt_comparison in &alt_comparisons {
+        if alt_allele_considered(alt_comparison) && alt_comparison.comparison > max_alignment_alt {
+            max_alignment_alt = alt_comparison.comparison;
+        }
+    }
+    // All alt alleles sharing the highest identity. More than one entry means the read cannot distinguish between these alt alleles (e.g. two different insertions at the same position)
+    let max_indices_alt: Vec<usize> = alt_comparisons
+        .iter()
+        .filter(|alt_comparison| {
+            alt_allele_considered(alt_comparison) && alt_comparison.comparison == max_alignment_alt
+        })
+        .map(|alt_comparison| alt_comparison.index)
+        .collect();
+    //println!("max_alignment_alt:{}", max_alignment_alt);
+    //println!("max_alignment_ref:{}", max_alignment_ref);
+
+    let mut categories: Vec<String>;
+    if strictness == 1
+        && (read_supports_no_allele
+            || (ref_polyclonal_read_status_global == 1 && max_alignment_alt <= max_alignment_ref))
+    // The 2nd condition is experimental and was observed in case of deletion in repeat region. Somehow alt_polyclonal_read_status_global was 0 so it got classifed into ref allele although it does not support ref allele.
+    {
+        // Setting read to none. When a read is assigned to none group, the first entry in the vector is "none", followed by the group with which it has maximum sequence similarity
+        let mut none_groups_for_each_read = Vec::<String>::new();
+        none_groups_for_each_read.push("none".to_string());
+        if max_alignment_alt > max_alignment_ref && !max_indices_alt.is_empty() {
+            none_groups_for_each_read.push(alt_allele_names_list[max_indices_alt[0]].to_string());
+        } else {
+            none_groups_for_each_read.push("ref".to_string());
+        }
+        categories = none_groups_for_each_read;
+    } else if max_alignment_alt == max_alignment_ref
+        || (max_alignment_alt > max_alignment_ref && max_indices_alt.len() > 1)
+    {
+        // Setting read to ambiguous. When a read is asigned to ambiguous group, the first entry in the vector is "amb". Subsequent entries are for each group with which the read has equal sequence similarity with
+        let mut ambiguous_groups_for_each_read = Vec::<String>::new();
+        ambiguous_groups_for_each_read.push("amb".to_string());
+        for max_index_alt in &max_indices_alt {
+            ambiguous_groups_for_each_read.push(alt_allele_names_list[*max_index_alt].to_string());
+        }
+        if max_alignment_alt == max_alignment_ref {
+            ambiguous_groups_for_each_read.push("ref".to_string());
+        }
+        //println!(
+        //    "ambiguous_groups_for_each_read:{:?}",
+        //    ambiguous_groups_for_each_read
+        //);
+        categories = ambiguous_groups_for_each_read;
+    } else if max_alignment_alt > max_alignment_ref {
+        // Setting read to the alt allele with highest identity
+        categories = vec![alt_allele_names_list[max_indices_alt[0]].to_string()];
+    } else {
+        categories = vec!["ref".to_string()];
+    }
+
+    if low_base_quality && categories[0] != "none" {
+        // Read has low quality nucleotides in the variant region and cannot be used as evidence for any allele. Setting read to none, followed by the group it would otherwise have been assigned to
+        let mut closest_group = categories[0].to_string();
+        if closest_group == "amb" {
+            closest_group = categories[1].to_string();
+        }
+        categories = vec!["none".to_string(), closest_group];
+    }
+
+    Some((
+        ReadClassification {
+            read_number,
+            categories,
+            mate_read_number: None, // Determined later by merge_mates()
+        },
+        sequence_strand,
+    ))
+}
+
+// Calculates allele fraction of each alt allele and calls the diploid genotype from the number of reads assigned to ref and each alt allele. Reads classified as none or amb are not informative and therefore not used
+fn call_genotype(allele_counts: &[AlleleCount]) -> GenotypeCall {
+    let sequencing_error_rate: f64 = 0.01; // Probability of a read being assigned to an allele other than the one it was sequenced from
+    let confidence: f64 = 0.95; // Confidence level of allele fraction confidence intervals
+    let informative_counts: Vec<u32> = allele_counts
+        .iter()
+        .filter(|x| x.allele != "none" && x.allele != "amb")
+        .map(|x| x.count)
+        .collect(); // First entry is ref followed by each alt allele
+    let depth: u32 = informative_counts.iter().sum();
+
+    let mut allele_fractions = Vec::<AlleleFraction>::new();
+    for allele_idx in 1..informative_counts.len() {
+        let mut vaf: f64 = 0.0;
+        if depth > 0 {
+            vaf = informative_counts[allele_idx] as f64 / depth as f64;
+        }
+        let (ci_lower, ci_upper) = stats_functions::clopper_pearson_interval(
+            informative_counts[allele_idx],
+            depth,
+            confidence,
+        );
+        allele_fractions.push(AlleleFraction {
+            allele: allele_counts[allele_idx].allele.to_string(),
+            vaf,
+            ci_lower,
+            ci_upper,
+        });
+    }
+
+    let pl = stats_functions::genotype_likelihoods(&informative_counts, sequencing_error_rate);
+    let mut genotype = "./.".to_string();
+    let mut genotype_quality: u32 = 0;
+    if depth > 0 {
+        // Genotype j/k (j <= k) is at index k*(k+1)/2 + j in pl
+        let mut genotype_idx: usize = 0;
+        for k in 0..informative_counts.len() {
+            for j in 0..=k {
+                if pl[genotype_idx] == 0 {
+                    genotype = j.to_string() + "/" + &k.to_string();
+                }
+                genotype_idx += 1;
+            }
+        }
+        // Genotype quality is the difference between the best and second best genotype
+        let mut sorted_pl = pl.to_vec();
+        sorted_pl.sort();
+        genotype_quality = cmp::min(sorted_pl[1], 99);
+    }
+
+    GenotypeCall {
+        genotype,
+        genotype_quality,
+        pl,
+        depth,
+        allele_fractions,
+    }
+}
+
+// Creates one VCF record for each variant site. Alleles given at the same position with the same ref allele are merged into a single multi-allelic record: the genotype is called from the reads assigned to the ref and every alt allele of the site, AD contains one entry per allele and DP is the sum of AD (reads classified as none or amb are not counted, same as in the genotype call). Strand bias is computed between the ref allele and all alt alleles of the site. Indel alleles without anchor base get the last nucleotide of the left flanking sequence prepended
+fn create_vcf_records(
+    chrom: &str,                    // Chromosome name
+    variant_positions_list: &[i64], // Start position of each variant (0-based)
+    refalleles_list: &[&str],       // Ref allele of each variant
+    altalleles_list: &[&str],       // Alt allele of each variant
+    leftflankseqs_list: &[&str],    // Left flanking sequence of each variant
+    allele_counts: &[AlleleCount],  // Per-allele read counts (ref, alt0, alt1 ... none, amb)
+) -> Result<Vec<vcf::VcfRecord>, String> {
+    // Grouping alleles by site, in the order in which they are given
+    let mut sites = Vec::<(i64, &str, Vec<usize>)>::new();
+    for (allele_idx, (variant_pos, ref_allele)) in variant_positions_list
+        .iter()
+        .zip(refalleles_list)
+        .enumerate()
+    {
+        match sites
+            .iter_mut()
+            .find(|site| site.0 == *variant_pos && site.1 == *ref_allele)
+        {
+            Some(site) => site.2.push(allele_idx),
+            None => sites.push((*variant_pos, ref_allele, vec![allele_idx])),
+        }
+    }
+
+    let mut vcf_records = Vec::<vcf::VcfRecord>::new();
+    let reference_count = &allele_counts[0];
+    for (variant_pos, ref_allele, allele_indices) in sites {
+        let mut anchored_alleles = Vec::<(i64, String, String)>::new();
+        for allele_idx in &allele_indices {
+            anchored_alleles.push(vcf::anchor_alleles(
+                variant_pos,
+                ref_allele,
+                altalleles_list[*allele_idx],
+                leftflankseqs_list[*allele_idx].chars().last(),
+            )?);
+        }
+        // All alleles of the record must share the same ref allele, so when one of them needs the anchor base it is prepended to all of them
+        let (pos, record_ref_allele, alt_alleles) = if anchored_alleles
+            .iter()
+            .any(|allele| allele.0 == variant_pos)
+        {
+            let anchor_base: String = leftflankseqs_list[allele_indices[0]]
+                .chars()
+                .last()
+                .unwrap()
+                .to_string();
+            let alt_alleles: Vec<String> = allele_indices
+                .iter()
+                .map(|allele_idx| anchor_base.to_owned() + altalleles_list[*allele_idx])
+                .collect();
+            (variant_pos, anchor_base + ref_allele, alt_alleles)
+        } else {
+            let alt_alleles: Vec<String> = anchored_alleles
+                .into_iter()
+                .map(|(_pos, _ref_allele, alt_allele)| alt_allele)
+                .collect();
+            (variant_pos + 1, ref_allele.to_string(), alt_alleles)
+        };
+
+        // Read counts of the ref allele followed by each alt allele of the site
+        let mut site_counts = vec![reference_count.clone()];
+        for allele_idx in &allele_indices {
+            site_counts.push(allele_counts[allele_idx + 1].clone());
+        }
+        let alternate_forward: u32 = site_counts[1..].iter().map(|x| x.forward).sum();
+        let alternate_reverse: u32 = site_counts[1..].iter().map(|x| x.reverse).sum();
+        let p_value = strand_analysis(
+            alternate_forward,
+            alternate_reverse,
+            reference_count.forward,
+            reference_count.reverse,
+        );
+        let genotype_call = call_genotype(&site_counts);
+        let allele_depths: Vec<String> = site_counts.iter().map(|x| x.count.to_string()).collect();
+        let pl: Vec<String> = genotype_call.pl.iter().map(|x| x.to_string()).collect();
+        vcf_records.push(vcf::VcfRecord {
+            chrom: chrom.to_string(),
+            pos,
+            id: ".".to_string(),
+            ref_allele: record_ref_allele,
+            alt_allele: alt_alleles.join(","),
+            info: vec![format!("FS={:.2}", p_value)],
+            format: vec![
+                "GT".to_string(),
+                "GQ".to_string(),
+                "AD".to_string(),
+                "DP".to_string(),
+                "PL".to_string(),
+                "SB".to_string(),
+            ],
+            sample: vec![
+                genotype_call.genotype,
+                genotype_call.genotype_quality.to_string(),
+                allele_depths.join(","),
+                genotype_call.depth.to_string(),
+                pl.join(","),
+                format!(
+                    "{},{},{},{}",
+                    reference_count.forward,
+                    reference_count.reverse,
+                    alternate_forward,
+                    alternate_reverse
+                ),
+            ],
+        });
+    }
+    Ok(vcf_records)
+}
+
 fn strand_analysis(
     alternate_forward_count: u32,
     alternate_reverse_count: u32,
@@ -2044,3 +1988,72 @@ fn check_flanking_sequence_for_repeats(
     }
     (repeat_flanking_sequence, num_repeats_in_flanking_sequence)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allele_count(allele: &str, forward: u32, reverse: u32) -> AlleleCount {
+        AlleleCount {
+            allele: allele.to_string(),
+            count: forward + reverse,
+            forward,
+            reverse,
+        }
+    }
+
+    #[test]
+    fn test_create_vcf_records() {
+        // Heterozygous site with two different insertions at the same position (alt0/alt1) and a deletion at another position (alt2) without anchor base
+        let allele_counts = vec![
+            allele_count("ref", 1, 0),
+            allele_count("alt0", 6, 5),
+            allele_count("alt1", 4, 6),
+            allele_count("alt2", 0, 0),
+            allele_count("none", 2, 1),
+            allele_count("amb", 1, 1),
+        ];
+        let vcf_records = create_vcf_records(
+            "chr1",
+            &[99, 99, 199],
+            &["A", "A", "GT"],
+            &["AT", "ATT", ""],
+            &["CCC", "CCC", "TTC"],
+            &allele_counts,
+        )
+        .unwrap();
+        assert_eq!(vcf_records.len(), 2);
+
+        let site = &vcf_records[0];
+        assert_eq!(site.pos, 100);
+        assert_eq!(site.ref_allele, "A");
+        assert_eq!(site.alt_allele, "AT,ATT");
+        assert_eq!(site.format, vec!["GT", "GQ", "AD", "DP", "PL", "SB"]);
+        assert_eq!(site.sample[0], "1/2");
+        assert_eq!(site.sample[2], "1,11,10");
+        assert_eq!(site.sample[3], "22");
+        assert_eq!(site.sample[4].split(',').count(), 6); // One likelihood per genotype of 3 alleles
+        assert_eq!(site.sample[5], "1,0,10,11");
+
+        let site = &vcf_records[1];
+        assert_eq!(site.pos, 199);
+        assert_eq!(site.ref_allele, "CGT");
+        assert_eq!(site.alt_allele, "C");
+        assert_eq!(site.sample[2], "1,0");
+        assert_eq!(site.sample[3], "1");
+
+        // Indel needing the anchor base merged with an allele that does not, the anchor base is prepended to all alleles of the record
+        let vcf_records = create_vcf_records(
+            "chr1",
+            &[99, 99],
+            &["AG", "AG"],
+            &["A", "G"],
+            &["CCG", "CCG"],
+            &allele_counts[..3],
+        )
+        .unwrap();
+        assert_eq!(vcf_records[0].pos, 99);
+        assert_eq!(vcf_records[0].ref_allele, "GAG");
+        assert_eq!(vcf_records[0].alt_allele, "GA,GG");
+    }
+}
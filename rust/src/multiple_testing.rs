@@ -0,0 +1,491 @@
+// Multiple testing correction shared by the binaries that run many tests. The corrections are the same as p.adjust() in R, and the
+// Storey q-values the same as qvalue() in the qvalue R package (default pi0.method = "smoother", smooth.df = 3, lambda = 0.05..0.95)
+
+// Each binary selects the correction with an "mtc" option in its input json, which can be one of:
+//   fdr or BH: Benjamini-Hochberg
+//   BY: Benjamini-Yekutieli
+//   holm: Holm
+//   hochberg: Hochberg
+//   hommel: Hommel
+//   bon or bonferroni: Bonferroni
+//   qvalue: Storey q-values
+
+use std::cmp::Ordering;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Method {
+    BenjaminiHochberg,
+    BenjaminiYekutieli,
+    Holm,
+    Hochberg,
+    Hommel,
+    Bonferroni,
+    StoreyQValue,
+}
+
+impl Method {
+    // Parses the value of the mtc option
+    #[allow(dead_code)]
+    pub fn from_mtc(mtc: &str) -> Result<Method, String> {
+        match mtc {
+            "fdr" | "BH" => Ok(Method::BenjaminiHochberg),
+            "BY" => Ok(Method::BenjaminiYekutieli),
+            "holm" => Ok(Method::Holm),
+            "hochberg" => Ok(Method::Hochberg),
+            "hommel" => Ok(Method::Hommel),
+            "bon" | "bonferroni" => Ok(Method::Bonferroni),
+            "qvalue" => Ok(Method::StoreyQValue),
+            _ => Err(format!(
+                "Unrecognized mtc option {}, it should be one of fdr/BH, BY, holm, hochberg, hommel, bon/bonferroni, qvalue",
+                mtc
+            )),
+        }
+    }
+}
+
+// Adjusts the p-values for the number of p-values. The adjusted p-values are in the same order as the p-values. NaN p-values are not counted
+// and their adjusted p-values are NaN (as NA in p.adjust() in R)
+#[allow(dead_code)]
+pub fn adjust_p_values(p_values: &[f64], method: Method) -> Vec<f64> {
+    if p_values.iter().any(|p| p.is_nan()) {
+        let tested: Vec<f64> = p_values.iter().cloned().filter(|p| !p.is_nan()).collect();
+        let mut adjusted_tested = adjust_p_values(&tested, method).into_iter();
+        return p_values
+            .iter()
+            .map(|p| {
+                if p.is_nan() {
+                    f64::NAN
+                } else {
+                    adjusted_tested.next().unwrap()
+                }
+            })
+            .collect();
+    }
+    let n = p_values.len();
+    if n <= 1 {
+        return p_values.to_vec();
+    }
+    let n_f64 = n as f64;
+    match method {
+        Method::Bonferroni => p_values.iter().map(|p| (p * n_f64).min(1.0)).collect(),
+        Method::Holm => {
+            // Step down from the smallest p-value: cummax((n + 1 - i) * p(i))
+            let order = sorted_order(p_values, false);
+            let mut adjusted = vec![0.0; n];
+            let mut running_max: f64 = 0.0;
+            for (i, index) in order.iter().enumerate() {
+                running_max = running_max.max((n - i) as f64 * p_values[*index]);
+                adjusted[*index] = running_max.min(1.0);
+            }
+            adjusted
+        }
+        Method::Hochberg => step_up(p_values, |i| (n - i + 1) as f64),
+        Method::BenjaminiHochberg => step_up(p_values, |i| n_f64 / i as f64),
+        Method::BenjaminiYekutieli => {
+            let harmonic_sum: f64 = (1..=n).map(|i| 1.0 / i as f64).sum();
+            step_up(p_values, |i| harmonic_sum * n_f64 / i as f64)
+        }
+        Method::Hommel => hommel(p_values),
+        Method::StoreyQValue => {
+            let pi0 = estimate_pi0(p_values);
+            step_up(p_values, |i| n_f64 / i as f64)
+                .iter()
+                .map(|q| pi0 * q)
+                .collect()
+        }
+    }
+}
+
+// Indexes of the p-values in ascending (or descending) order. Ties keep their original order, NaN p-values are placed at the end in both orders
+fn sorted_order(p_values: &[f64], descending: bool) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..p_values.len()).collect();
+    order.sort_by(|a, b| {
+        let (p_a, p_b) = (p_values[*a], p_values[*b]);
+        match (p_a.is_nan(), p_b.is_nan()) {
+            (true, true) => Ordering::Equal,
+            (true, false) => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            (false, false) => {
+                let ordering = p_a.partial_cmp(&p_b).unwrap();
+                if descending {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            }
+        }
+    });
+    order
+}
+
+// Step up from the largest p-value: cummin(factor(i) * p(i)) for i = n..1, where p(i) is the i-th smallest p-value
+fn step_up(p_values: &[f64], factor: impl Fn(usize) -> f64) -> Vec<f64> {
+    let n = p_values.len();
+    let order = sorted_order(p_values, true);
+    let mut adjusted = vec![0.0; n];
+    let mut running_min = f64::INFINITY;
+    for (j, index) in order.iter().enumerate() {
+        let i = n - j; // Rank of the p-value in ascending order
+        running_min = running_min.min(factor(i) * p_values[*index]);
+        adjusted[*index] = running_min.min(1.0);
+    }
+    adjusted
+}
+
+// Hommel's method, following the implementation of p.adjust() in R
+fn hommel(p_values: &[f64]) -> Vec<f64> {
+    let n = p_values.len();
+    if n == 2 {
+        // Same as Hochberg for two p-values
+        return step_up(p_values, |i| (n - i + 1) as f64);
+    }
+    let order = sorted_order(p_values, false);
+    let p: Vec<f64> = order.iter().map(|index| p_values[*index]).collect();
+    let initial = (0..n)
+        .map(|i| n as f64 * p[i] / (i + 1) as f64)
+        .fold(f64::INFINITY, f64::min);
+    let mut q = vec![initial; n];
+    let mut pa = vec![initial; n];
+    for m in (2..n).rev() {
+        // The first n - m + 1 p-values and the rest, 0-based
+        let q1 = (n - m + 1..n)
+            .map(|i| m as f64 * p[i] / (i - (n - m) + 1) as f64)
+            .fold(f64::INFINITY, f64::min);
+        for i in 0..=n - m {
+            q[i] = (m as f64 * p[i]).min(q1);
+        }
+        for i in n - m + 1..n {
+            q[i] = q[n - m];
+        }
+        for i in 0..n {
+            pa[i] = pa[i].max(q[i]);
+        }
+    }
+    let mut adjusted = vec![0.0; n];
+    for (i, index) in order.iter().enumerate() {
+        adjusted[*index] = pa[i].max(p[i]);
+    }
+    adjusted
+}
+
+// Proportion of true null hypotheses (pi0) estimated as in pi0est() of the qvalue R package: pi0(lambda) = #(p >= lambda) / (n * (1 - lambda))
+// for lambda = 0.05, 0.10, ..., 0.95, smoothed with a cubic smoothing spline with 3 degrees of freedom and taken at lambda = 0.95. qvalue stops
+// with an error when the largest p-value is below 0.95 or the estimate is not positive, in these cases pi0 = 1 is used here (q-values are then
+// the same as Benjamini-Hochberg adjusted p-values). NaN p-values are ignored
+#[allow(dead_code)]
+pub fn estimate_pi0(p_values: &[f64]) -> f64 {
+    let p_values: Vec<f64> = p_values.iter().cloned().filter(|p| !p.is_nan()).collect();
+    let lambdas: Vec<f64> = (1..20).map(|i| i as f64 * 0.05).collect();
+    let max_p_value = p_values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    if p_values.is_empty() || max_p_value < lambdas[lambdas.len() - 1] {
+        return 1.0;
+    }
+    let n = p_values.len() as f64;
+    let pi0_lambdas: Vec<f64> = lambdas
+        .iter()
+        .map(|lambda| {
+            p_values.iter().filter(|p| *p >= lambda).count() as f64 / (n * (1.0 - lambda))
+        })
+        .collect();
+    let smoothed = smoothing_spline(&lambdas, &pi0_lambdas, 3.0);
+    let pi0 = smoothed[smoothed.len() - 1].min(1.0);
+    if pi0 <= 0.0 {
+        1.0
+    } else {
+        pi0
+    }
+}
+
+// Fitted values of a cubic smoothing spline with knots at every x (x increasing) and the given degrees of freedom (trace of the smoother
+// matrix), same as smooth.spline(x, y, df = df) in R. With the natural cubic spline penalty matrix K = Q R^-1 Q' (Green & Silverman 1994)
+// the fitted values are (I + alpha K)^-1 y, alpha is found by bisection so that the trace of (I + alpha K)^-1 is df
+fn smoothing_spline(x: &[f64], y: &[f64], df: f64) -> Vec<f64> {
+    let n = x.len();
+    let h: Vec<f64> = (0..n - 1).map(|i| x[i + 1] - x[i]).collect();
+    let mut q = vec![vec![0.0; n - 2]; n];
+    let mut r = vec![vec![0.0; n - 2]; n - 2];
+    for j in 1..n - 1 {
+        q[j - 1][j - 1] = 1.0 / h[j - 1];
+        q[j][j - 1] = -1.0 / h[j - 1] - 1.0 / h[j];
+        q[j + 1][j - 1] = 1.0 / h[j];
+        r[j - 1][j - 1] = (h[j - 1] + h[j]) / 3.0;
+        if j < n - 2 {
+            r[j - 1][j] = h[j] / 6.0;
+            r[j][j - 1] = h[j] / 6.0;
+        }
+    }
+    let r_inverse = invert_matrix(r);
+    let mut k = vec![vec![0.0; n]; n];
+    for a in 0..n {
+        for b in 0..n {
+            for i in 0..n - 2 {
+                for j in 0..n - 2 {
+                    k[a][b] += q[a][i] * r_inverse[i][j] * q[b][j];
+                }
+            }
+        }
+    }
+    let smoother = |log_alpha: f64| -> Vec<Vec<f64>> {
+        let alpha = log_alpha.exp();
+        let matrix: Vec<Vec<f64>> = (0..n)
+            .map(|a| {
+                (0..n)
+                    .map(|b| if a == b { 1.0 } else { 0.0 } + alpha * k[a][b])
+                    .collect()
+            })
+            .collect();
+        invert_matrix(matrix)
+    };
+    let trace = |matrix: &Vec<Vec<f64>>| (0..n).map(|i| matrix[i][i]).sum::<f64>();
+    let mut low: f64 = -40.0; // log(alpha), trace close to n
+    let mut high: f64 = 40.0; // trace close to 2
+    for _ in 0..200 {
+        let mid = (low + high) / 2.0;
+        if trace(&smoother(mid)) > df {
+            low = mid;
+        } else {
+            high = mid;
+        }
+        if high - low < 1e-12 {
+            break;
+        }
+    }
+    let s = smoother((low + high) / 2.0);
+    (0..n)
+        .map(|a| (0..n).map(|b| s[a][b] * y[b]).sum())
+        .collect()
+}
+
+// Inverse of a square matrix by Gauss-Jordan elimination with partial pivoting
+fn invert_matrix(mut matrix: Vec<Vec<f64>>) -> Vec<Vec<f64>> {
+    let n = matrix.len();
+    let mut inverse: Vec<Vec<f64>> = (0..n)
+        .map(|a| (0..n).map(|b| if a == b { 1.0 } else { 0.0 }).collect())
+        .collect();
+    for column in 0..n {
+        let pivot = (column..n)
+            .max_by(|a, b| {
+                matrix[*a][column]
+                    .abs()
+                    .partial_cmp(&matrix[*b][column].abs())
+                    .unwrap_or(Ordering::Equal)
+            })
+            .unwrap();
+        matrix.swap(column, pivot);
+        inverse.swap(column, pivot);
+        let pivot_value = matrix[column][column];
+        for j in 0..n {
+            matrix[column][j] /= pivot_value;
+            inverse[column][j] /= pivot_value;
+        }
+        for row in 0..n {
+            if row != column {
+                let factor = matrix[row][column];
+                if factor != 0.0 {
+                    for j in 0..n {
+                        matrix[row][j] -= factor * matrix[column][j];
+                        inverse[row][j] -= factor * inverse[column][j];
+                    }
+                }
+            }
+        }
+    }
+    inverse
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(observed: &Vec<f64>, expected: &Vec<f64>) {
+        assert_eq!(observed.len(), expected.len());
+        for (o, e) in observed.iter().zip(expected) {
+            assert!((o - e).abs() < 1e-12, "{:?} != {:?}", observed, expected);
+        }
+    }
+
+    #[test]
+    fn test_p_adjust() {
+        // Expected values are p.adjust(p, method) from R
+        let p_values = vec![0.01, 0.02, 0.03, 0.04, 0.05, 0.5, 0.001, 0.2, 0.8, 0.04];
+        let expected = vec![
+            (
+                "BH",
+                vec![
+                    0.05,
+                    0.0666666666666667,
+                    0.0666666666666667,
+                    0.0666666666666667,
+                    0.0714285714285714,
+                    0.555555555555556,
+                    0.01,
+                    0.25,
+                    0.8,
+                    0.0666666666666667,
+                ],
+            ),
+            (
+                "BY",
+                vec![
+                    0.146448412698413,
+                    0.19526455026455,
+                    0.19526455026455,
+                    0.19526455026455,
+                    0.20921201814059,
+                    1.0,
+                    0.0292896825396825,
+                    0.732242063492063,
+                    1.0,
+                    0.19526455026455,
+                ],
+            ),
+            (
+                "holm",
+                vec![0.09, 0.16, 0.21, 0.24, 0.24, 1.0, 0.01, 0.6, 1.0, 0.24],
+            ),
+            (
+                "hochberg",
+                vec![0.09, 0.16, 0.2, 0.2, 0.2, 0.8, 0.01, 0.6, 0.8, 0.2],
+            ),
+            (
+                "hommel",
+                vec![0.08, 0.1, 0.125, 0.16, 0.2, 0.8, 0.01, 0.6, 0.8, 0.16],
+            ),
+            (
+                "bonferroni",
+                vec![0.1, 0.2, 0.3, 0.4, 0.5, 1.0, 0.01, 1.0, 1.0, 0.4],
+            ),
+        ];
+        for (mtc, expected_values) in expected {
+            let method = Method::from_mtc(mtc).unwrap();
+            let adjusted: Vec<f64> = adjust_p_values(&p_values, method)
+                .iter()
+                .map(|x| format!("{:.15}", x).parse::<f64>().unwrap())
+                .collect();
+            assert_close(&adjusted, &expected_values);
+        }
+        // Hommel with two p-values is the same as Hochberg
+        assert_close(
+            &adjust_p_values(&[0.03, 0.02], Method::Hommel),
+            &vec![0.03, 0.03],
+        );
+        assert_eq!(Method::from_mtc("fdr"), Ok(Method::BenjaminiHochberg));
+        assert_eq!(Method::from_mtc("bon"), Ok(Method::Bonferroni));
+        assert!(Method::from_mtc("none").is_err());
+    }
+
+    #[test]
+    fn test_q_values() {
+        // Half of the p-values are true nulls spread uniformly over (0, 1), so pi0(lambda) is 0.5 for every lambda. The spline reproduces a constant
+        let mut p_values: Vec<f64> = (1..=100).map(|k| (k as f64 - 0.5) / 100.0).collect();
+        p_values.extend(vec![1e-4; 100]);
+        assert!((estimate_pi0(&p_values) - 0.5).abs() < 1e-9);
+        let q_values = adjust_p_values(&p_values, Method::StoreyQValue);
+        let bh = adjust_p_values(&p_values, Method::BenjaminiHochberg);
+        for (q, b) in q_values.iter().zip(&bh) {
+            assert!((q - 0.5 * b).abs() < 1e-9);
+        }
+
+        // qvalue stops when the largest p-value is below the lambda range, pi0 = 1 is used instead
+        assert_eq!(estimate_pi0(&[0.01, 0.2, 0.5]), 1.0);
+
+        // The smoothing spline has the requested degrees of freedom and reproduces a straight line
+        let x: Vec<f64> = (1..20).map(|i| i as f64 * 0.05).collect();
+        let y: Vec<f64> = x.iter().map(|x| 1.0 - 0.3 * x).collect();
+        assert_close(
+            &smoothing_spline(&x, &y, 3.0)
+                .iter()
+                .map(|v| (v * 1e10).round() / 1e10)
+                .collect(),
+            &y.iter().map(|v| (v * 1e10).round() / 1e10).collect(),
+        );
+    }
+
+    #[test]
+    fn test_q_values_smoothed_pi0() {
+        // 20 p-values from true alternatives and 20 spread over (0, 1), pi0(lambda) is not constant so pi0 comes from the smoothing spline.
+        // Expected values follow qvalue(p)$qvalues (pi0est() with smooth.df = 3), computed in 50-digit arithmetic with the truncated power
+        // basis of natural cubic splines (Hastie et al. 2009, eq. 5.4) instead of the penalty matrix used here. smooth.spline() in R only
+        // matches df up to its tolerance on spar, so qvalue() can differ from these values after the fifth significant digit
+        let p_values = vec![
+            0.0001, 0.0004, 0.0019, 0.0095, 0.0201, 0.0278, 0.0298, 0.0344, 0.0459, 0.0012, 0.0033,
+            0.0072, 0.0146, 0.0007, 0.0181, 0.0026, 0.0391, 0.0052, 0.0113, 0.0238, 0.0412, 0.1287,
+            0.1733, 0.2119, 0.2981, 0.3240, 0.3566, 0.4262, 0.4471, 0.5038, 0.5719, 0.6528, 0.6644,
+            0.7590, 0.8133, 0.8421, 0.9127, 0.9388, 0.9672, 0.9863,
+        ];
+        let expected = vec![
+            0.00361725220056507,
+            0.00723450440113014,
+            0.0137455583621473,
+            0.0343638959053682,
+            0.0519334065938271,
+            0.0628497569848181,
+            0.0634083032804936,
+            0.0691297087219102,
+            0.0790627980980651,
+            0.0108517566016952,
+            0.0170527603740925,
+            0.0289380176045206,
+            0.0440099017735417,
+            0.00844025513465183,
+            0.0503632806386367,
+            0.015674759535782,
+            0.0744392426537338,
+            0.023512139303673,
+            0.0371590453330775,
+            0.0573937349156324,
+            0.0745153953316404,
+            0.211609253733057,
+            0.272552089720838,
+            0.319373225541558,
+            0.431321152395379,
+            0.450765274224263,
+            0.477745235082038,
+            0.55059745995744,
+            0.557680503059532,
+            0.607457219548227,
+            0.667324688226827,
+            0.728273443047101,
+            0.728273443047101,
+            0.807498358890849,
+            0.840546061348449,
+            0.846135577248846,
+            0.891923961354332,
+            0.891923961354332,
+            0.891923961354332,
+            0.891923961354332,
+        ];
+        assert!((estimate_pi0(&p_values) - 0.904313050141267).abs() < 1e-9);
+        let q_values = adjust_p_values(&p_values, Method::StoreyQValue);
+        for (q, e) in q_values.iter().zip(&expected) {
+            assert!((q - e).abs() < 1e-9, "{} != {}", q, e);
+        }
+    }
+
+    #[test]
+    fn test_nan_p_values() {
+        // NaN p-values are not counted and stay NaN, as NA in p.adjust(c(0.01, NA, 0.04, 0.02), "BH")
+        let p_values = vec![0.01, f64::NAN, 0.04, 0.02];
+        for method in [
+            Method::BenjaminiHochberg,
+            Method::Holm,
+            Method::Hommel,
+            Method::StoreyQValue,
+        ] {
+            let adjusted = adjust_p_values(&p_values, method);
+            assert!(adjusted[1].is_nan());
+            let expected = adjust_p_values(&[0.01, 0.04, 0.02], method);
+            assert_eq!(vec![adjusted[0], adjusted[2], adjusted[3]], expected);
+        }
+        assert_close(
+            &adjust_p_values(&p_values, Method::BenjaminiHochberg)
+                .into_iter()
+                .filter(|q| !q.is_nan())
+                .collect(),
+            &vec![0.03, 0.04, 0.03],
+        );
+        assert_eq!(sorted_order(&[0.5, f64::NAN, 0.1], false), vec![2, 0, 1]);
+        assert_eq!(sorted_order(&[0.5, f64::NAN, 0.1], true), vec![0, 2, 1]);
+        assert_eq!(estimate_pi0(&[0.01, f64::NAN, 0.2, 0.5]), 1.0);
+    }
+}
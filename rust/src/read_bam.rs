@@ -0,0 +1,112 @@
+// Functions for reading alignments directly from an indexed BAM/CRAM file. This allows the indel binary to be run without the nodejs server pre-parsing the reads into the "reads" JSON array
+use rust_htslib::bam;
+use rust_htslib::bam::Read;
+use rust_htslib::faidx;
+
+#[derive(Debug, Clone)]
+pub struct BamRead {
+    pub qname: String,         // Read name
+    pub read_sequence: String, // Read sequence
+    pub start_position: i64, // 1-based start position, same as the POS field in SAM and the start_position field in the "reads" JSON array
+    pub cigar: String,       // Cigar sequence
+    pub flag: u16,           // SAM flag
+    pub quality: String, // Base pair quality scores (phred33, "*" when not available), same as the QUAL field in SAM
+    pub mapq: u8,        // Mapping quality (255 when not available)
+}
+
+// Fetches all reads overlapping chrom:start-stop. The BAM/CRAM index is expected to be next to the alignment file (e.g file.bam.bai or file.cram.crai)
+pub fn fetch_reads_from_bam(
+    bam_file: &String,           // Path to BAM/CRAM file
+    fasta_file: &Option<String>, // Path to FASTA file (indexed with samtools faidx), required for decoding CRAM files
+    chrom: &String,              // Chromosome name
+    start: i64,                  // Start position of region (0-based)
+    stop: i64,                   // Stop position of region (0-based, not inclusive)
+) -> Result<Vec<BamRead>, String> {
+    let mut reader = match bam::IndexedReader::from_path(bam_file) {
+        Ok(reader) => reader,
+        Err(error) => return Err(format!("Cannot open {}: {}", bam_file, error)),
+    };
+    if let Some(fasta) = fasta_file {
+        // Setting the reference is needed for CRAM files, it is harmless for BAM files
+        if let Err(error) = reader.set_reference(fasta) {
+            return Err(format!("Cannot set reference {}: {}", fasta, error));
+        }
+    }
+    if let Err(error) = reader.fetch((chrom.as_str(), start, stop)) {
+        return Err(format!(
+            "Cannot fetch {}:{}-{}: {}",
+            chrom, start, stop, error
+        ));
+    }
+
+    let mut reads = Vec::<BamRead>::new();
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(error) => return Err(format!("Cannot parse record: {}", error)),
+        };
+        if record.is_unmapped()
+            || record.is_secondary()
+            || record.is_supplementary()
+            || record.is_quality_check_failed()
+            || record.is_duplicate()
+        {
+            // Same reads that are filtered out by the server before they are passed to the indel binary
+            continue;
+        }
+        reads.push(BamRead {
+            qname: String::from_utf8_lossy(record.qname()).to_string(),
+            read_sequence: String::from_utf8_lossy(&record.seq().as_bytes()).to_string(),
+            start_position: record.pos() + 1, // rust-htslib positions are 0-based
+            cigar: record.cigar().to_string(),
+            flag: record.flags(),
+            quality: quality_to_phred33(record.qual()),
+            mapq: record.mapq(),
+        });
+    }
+    Ok(reads)
+}
+
+// Converts base pair quality scores from a BAM record into a phred33 string (QUAL field in SAM)
+fn quality_to_phred33(qual: &[u8]) -> String {
+    if qual.is_empty() || qual[0] == 255 {
+        // Quality scores are set to 0xff in the BAM record when not available
+        return "*".to_string();
+    }
+    qual.iter()
+        .map(|score| score.saturating_add(33) as char)
+        .collect()
+}
+
+// Retrieves the left and right flanking sequences of an allele from the FASTA file, using the same coordinates as the server (bam.kmer.indel.js) so that refseq = leftflankseq + refallele + rightflankseq
+pub fn fetch_flanking_sequences(
+    fasta_file: &String, // Path to FASTA file (indexed with samtools faidx)
+    chrom: &String,      // Chromosome name
+    variant_pos: i64, // ref_position of the allele (0-based position of the first nucleotide of refallele)
+    ref_length: usize, // Length of reference allele
+    flank_length: usize, // Number of flanking nucleotides on either side, the server uses the read length
+) -> Result<(String, String), String> {
+    let reader = match faidx::Reader::from_path(fasta_file) {
+        Ok(reader) => reader,
+        Err(error) => return Err(format!("Cannot open {}: {}", fasta_file, error)),
+    };
+    let mut left_start: i64 = variant_pos - flank_length as i64 - 1;
+    if left_start < 0 {
+        left_start = 0;
+    }
+    let leftflankseq = match reader.fetch_seq_string(
+        chrom,
+        left_start as usize,
+        (variant_pos - 1) as usize, // End coordinate is inclusive in faidx
+    ) {
+        Ok(seq) => seq.to_uppercase(),
+        Err(error) => return Err(format!("Cannot fetch left flanking sequence: {}", error)),
+    };
+    let right_start: usize = variant_pos as usize + ref_length;
+    let rightflankseq =
+        match reader.fetch_seq_string(chrom, right_start, right_start + flank_length) {
+            Ok(seq) => seq.to_uppercase(),
+            Err(error) => return Err(format!("Cannot fetch right flanking sequence: {}", error)),
+        };
+    Ok((leftflankseq, rightflankseq))
+}
@@ -1,187 +1,324 @@
 // Syntax: cd .. && cargo build --release
 use bio::alignment::pairwise::*;
 use bio::alignment::AlignmentOperation;
+use json::JsonValue;
+use serde::{Deserialize, Serialize};
 use std::cmp;
-use std::process::Command;
-use std::str;
-
-#[allow(unused_mut)] // Remove this line when further building this function
-#[allow(unused_variables)] // Remove this line when further building this function
-#[allow(dead_code)]
-fn check_base_pair_quality_scores(
-    // Function that checks for base pair quality score to ensure all reads being aligned have high quality reads near the variant position
-    quality_score_sequence: &String, // String containing read base pair sequence
-    alphabets: &Vec<char>,           // Alphabets from CIGAR sequence
-    numbers: &Vec<i64>,              // Numbers from CIGAR sequence
-    mut start_position: i64,         // Start position of read
-    variant_pos: i64,                // Start position of variant
-    flanking_region_length: i64, // Flanking region uptil where base-pair quality of nucleotides need to be checked
-    indel_length: i64,           // Indel length
+use std::collections::HashMap;
+
+pub fn check_base_pair_quality_scores(
+    // Function that checks for base pair quality score to ensure all reads being aligned have high quality reads near the variant position. Returns false if any nucleotide between left_flanking_region_limit and right_flanking_region_limit has a base quality lower than min_base_quality
+    quality_score_sequence: &str, // String containing read base pair quality scores (phred33, same as QUAL field in SAM)
+    alphabets: &[char],           // Alphabets from CIGAR sequence
+    numbers: &[i64],              // Numbers from CIGAR sequence
+    mut start_position: i64,      // Start position of read (0-based)
     left_flanking_region_limit: i64, // Left hand region upto where base-pair quality check needs to be carried out
     right_flanking_region_limit: i64, // Right hand region upto where base-pair quality check needs to be carried out
+    min_base_quality: u8,             // Minimum base pair quality (phred scale)
 ) -> bool {
-    let quality_check_pass_fail = true; // Flag to record whether base pair quality check passed or failed
-    let high_quality_score_characters: [&str; 11] =
-        ["?", "@", "A", "B", "C", "D", "E", "F", "G", "H", "I"]; // Array storing phred33 base pair quality scores >= 30
-    if &alphabets[0].to_string().as_str() == &"S" {
-        start_position -= numbers[0].to_string().parse::<i64>().unwrap();
+    let mut quality_check_pass_fail = true; // Flag to record whether base pair quality check passed or failed
+    if quality_score_sequence.is_empty() || quality_score_sequence == "*" {
+        // Base pair quality scores not available for this read
+        return quality_check_pass_fail;
+    }
+    let quality_scores: Vec<u8> = quality_score_sequence
+        .bytes()
+        .map(|score| score.saturating_sub(33)) // Converting phred33 characters to phred scores
+        .collect();
+    // Hard clips are not part of the read sequence, so a soft clip following leading hard clips (e.g 5H10S80M) is where the read sequence starts. No CIGAR operations ("*") leaves the start position unchanged
+    if let Some(first_idx) = alphabets.iter().position(|alphabet| *alphabet != 'H') {
+        if alphabets[first_idx] == 'S' {
+            start_position -= numbers[first_idx];
+        }
     }
 
     // Parsing part of read base-pair quality sequence which covers the variant and flanking region
     let mut current_pos = start_position; // Nucleotide iterator w.r.t genomic coordinates
-    let mut nclt_pos = 0; // Nucleotide iterator w.r.t read being analyzed
+    let mut nclt_pos: usize = 0; // Nucleotide iterator w.r.t read being analyzed
     for i in 0..alphabets.len() {
         if &alphabets[i].to_string().as_str() == &"H" {
             // Ignore hardclips
             continue;
         } else if &alphabets[i].to_string().as_str() == &"M"
             || &alphabets[i].to_string().as_str() == &"S"
+            || alphabets[i].to_string().as_str() == "="
+            || alphabets[i].to_string().as_str() == "X"
         {
             // Checking to see if within variant region or flanking region
             let fragment_length = numbers[i].to_string().parse::<i64>().unwrap(); // Length of CIGAR fragment
-            let mut current_nucl = current_pos; // Nucleotide iterator w.r.t genomic coordinates (used in for loop below)
-            let mut current_nucl_read = nclt_pos; // Nucleotide iterator w.r.t read being analyzed (used in for loop below)
             for j in 0..fragment_length {
+                let current_nucl = current_pos + j; // Nucleotide iterator w.r.t genomic coordinates
+                let current_nucl_read = nclt_pos + j as usize; // Nucleotide iterator w.r.t read being analyzed
                 if left_flanking_region_limit <= current_nucl
                     && current_nucl <= right_flanking_region_limit
-                {}
-
-                current_nucl += 1;
+                    && current_nucl_read < quality_scores.len()
+                    && quality_scores[current_nucl_read] < min_base_quality
+                {
+                    quality_check_pass_fail = false;
+                    break;
+                }
             }
 
             current_pos += fragment_length;
-            nclt_pos += fragment_length;
+            nclt_pos += fragment_length as usize;
         } else if &alphabets[i].to_string().as_str() == &"D"
             || &alphabets[i].to_string().as_str() == &"N"
         {
             current_pos += numbers[i].to_string().parse::<i64>().unwrap();
         } else if &alphabets[i].to_string().as_str() == &"I" {
-            nclt_pos += numbers[i].to_string().parse::<i64>().unwrap();
+            let fragment_length = numbers[i].to_string().parse::<i64>().unwrap(); // Length of CIGAR fragment
+            if left_flanking_region_limit <= current_pos
+                && current_pos <= right_flanking_region_limit
+            {
+                // Inserted nucleotides lie between current_pos - 1 and current_pos, all of them are checked if the insertion is within the variant/flanking region
+                for j in 0..fragment_length as usize {
+                    if nclt_pos + j < quality_scores.len()
+                        && quality_scores[nclt_pos + j] < min_base_quality
+                    {
+                        quality_check_pass_fail = false;
+                        break;
+                    }
+                }
+            }
+            nclt_pos += fragment_length as usize;
         } else {
             // Should not happen
             println!("CIGAR character not accounted for in check_base_pair_quality_scores()");
-            // Flanking region could start from within inserted nucleotides
+        }
+        if !quality_check_pass_fail {
+            break;
         }
     }
     quality_check_pass_fail
 }
 
-#[allow(unused_variables)] // Remove this line when further building this function
 #[allow(dead_code)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RealignmentResult {
+    pub read_indices: Vec<usize>, // Indices (in the input) of the reads used for the multiple alignment
+    pub aligned_sequences: Vec<String>, // Multiple alignment, the first entry is the reference sequence followed by each read. All entries have the same length, "-" is used for gaps and for positions not covered by a read
+    pub consensus_sequence: String, // Consensus of the aligned reads (gaps removed). The reference nucleotide is used where no read is present
+}
+
+#[allow(dead_code)] // Only used by the indel binary
 pub fn realign_reads(
-    sequences: &String, // Variable contains sequences separated by "-" character, the first two sequences contains the ref and alt sequences
-    start_positions: &String, // Variable contains start position of reads separated by "-" character
-    cigar_sequences: &String, // Variable contains cigar sequences separated by "-" character
-    quality_scores: &String, // Variable contains quality scores of reads separated by "-" character
-    clustalo_path: &String,  // Path to clustalo
-    ref_sequence: &String,   // Complete original reference sequence
-    alt_sequence: &String,   // Complete original alternate sequence
-    variant_pos: i64,        // Original variant position
-    indel_length: i64,       // Indel length
-) {
+    sequences: &[&str],       // Read sequences
+    start_positions: &[i64],  // Start position of each read (0-based)
+    cigar_sequences: &[&str], // Cigar sequence of each read
+    quality_scores: &[&str],  // Quality scores of each read (phred33, "*" when not available)
+    ref_sequence: &String,    // Complete original reference sequence
+    variant_pos: i64,         // Original variant position
+    indel_length: i64,        // Indel length
+) -> RealignmentResult {
     // NOTE: This function assumes there is only ONE alternate allele. In case of multi-allelelic variants in a region, may have to employ some machine-learning techniques like KMeans clustering to determine number of alternate alleles at a particular region.
 
-    // Select appropriate reads for clustalo alignment
+    // Select appropriate reads for multiple alignment
     let flanking_region_length = 10; // This constant describes the region flanking the variant region where each read will be checked for insertions/deletions
-    let max_num_reads_alignment = 10; // The maximum number of reads used for clustalo alignment
-    let mut num_reads_aligned = 0;
-    let sequences_list: Vec<&str> = sequences.split("-").collect(); // Vector containing sequences of reads
-    let start_positions_list: Vec<&str> = start_positions.split("-").collect(); // Vector containing start positions
-    let cigar_sequences_list: Vec<&str> = cigar_sequences.split("-").collect(); // Vector containing cigar sequences
-    let quality_scores_list: Vec<&str> = quality_scores.split("-").collect(); // Vector containing quality scores
+    let max_num_reads_alignment = 10; // The maximum number of reads used for multiple alignment
     let left_flanking_region_limit: i64 = variant_pos - flanking_region_length; // Left hand region upto where base-pair quality check needs to be carried out
     let right_flanking_region_limit: i64 = variant_pos + indel_length + flanking_region_length; // Right hand region upto where base-pair quality check needs to be carried out
 
-    // Parsing through every read to select appropriate reads for alignments with clustalo
-    let mut sequences_to_be_aligned = Vec::<String>::new(); // Vector containing sequences to be aligned using clustalo
-    for i in 0..cigar_sequences_list.len() {
-        if num_reads_aligned >= max_num_reads_alignment {
+    // Parsing through every read to select appropriate reads for multiple alignment
+    let mut read_indices = Vec::<usize>::new(); // Indices of reads to be aligned
+    for i in 0..cigar_sequences.len() {
+        if read_indices.len() >= max_num_reads_alignment {
             break;
         }
-        let cigar_seq = cigar_sequences_list[i].to_string();
-        let quality_score_seq = quality_scores_list[i].to_string();
-        let (alphabets, numbers) = parse_cigar(&cigar_seq); // Parsing out all the alphabets and numbers from the cigar sequence (using parse_cigar function)
-        if alphabets.len() == 1 && &alphabets[0].to_string().as_str() == &"M" {
+        if cigar_sequences[i] == "*" {
+            // Unmapped read or CIGAR not available, the read cannot be placed w.r.t the variant region
+            continue;
+        }
+        let (alphabets, numbers) = parse_cigar(&cigar_sequences[i].to_string()); // Parsing out all the alphabets and numbers from the cigar sequence (using parse_cigar function)
+        if alphabets.len() == 1 && alphabets[0] == 'M' {
             // If the read is representing the reference allele, they are not interesting and can be discarded
             continue;
-        } else if alphabets.len() > 1 {
-            // Check if read has insertions or deletions near variant region
-            let mut current_pos = start_positions_list[i].to_string().parse::<i64>().unwrap();
-            let no_ins_del = 0;
-            for j in 0..alphabets.len() {
-                if &alphabets[0].to_string().as_str() == &"S"
-                    || &alphabets[0].to_string().as_str() == &"H"
-                // Ignore hardclips at start of sequence
+        }
+        // Check if read has insertions or deletions near variant region
+        let mut current_pos = start_positions[i];
+        let mut ins_del_near_variant = false;
+        for j in 0..alphabets.len() {
+            if alphabets[j] == 'S' || alphabets[j] == 'H' {
+                // Softclips and hardclips do not consume reference nucleotides
+                continue;
+            } else if alphabets[j] == 'N' {
+                let ins_del_end = current_pos + numbers[j]; // Position of end-point of splicing
+                if left_flanking_region_limit <= ins_del_end
+                    && current_pos <= right_flanking_region_limit
                 {
-                    // When the first entry in the cigar sequence is a softclip the start position is generally starts from the next CIGAR entry
-                    continue;
-                } else if j == alphabets.len() - 1 && &alphabets[j].to_string().as_str() == &"H" {
-                    // Ignore hardclips at end of sequence
-                    continue;
-                } else if &alphabets[j].to_string().as_str() == &"N" {
-                    let ins_del_end = current_pos + numbers[j].to_string().parse::<i64>().unwrap(); // Position of end-point of insertion/deletion
-                    if (variant_pos - current_pos).abs() <= flanking_region_length
-                        || (current_pos + ins_del_end - variant_pos).abs() <= flanking_region_length
-                        || (variant_pos + indel_length - current_pos).abs()
-                            <= flanking_region_length
-                        || (current_pos + ins_del_end - variant_pos - indel_length).abs()
-                            <= flanking_region_length
                     // Checking if there is a splice site near the variant region. If yes, that read is not considered for realignment
-                    {
-                        break;
-                    }
-                    current_pos += numbers[j].to_string().parse::<i64>().unwrap();
-                } else if &alphabets[j].to_string().as_str() == &"I"
-                    || &alphabets[j].to_string().as_str() == &"D"
+                    ins_del_near_variant = false;
+                    break;
+                }
+                current_pos = ins_del_end;
+            } else if alphabets[j] == 'I' || alphabets[j] == 'D' {
+                let mut ins_del_end = current_pos; // Position of end-point of insertion/deletion, an insertion does not consume reference nucleotides
+                if alphabets[j] == 'D' {
+                    ins_del_end += numbers[j];
+                }
+                if left_flanking_region_limit <= ins_del_end
+                    && current_pos <= right_flanking_region_limit
                 {
-                    let ins_del_end = current_pos + numbers[j].to_string().parse::<i64>().unwrap(); // Position of end-point of insertion/deletion
-
-                    //println!("current_pos:{}", current_pos);
-                    //println!("ins_del_end:{}", ins_del_end);
-                    //println!("variant_pos:{}", variant_pos);
-                    //println!("variant_end:{}", variant_pos + indel_length);
-                    if (variant_pos < current_pos && variant_pos + indel_length >= current_pos)
-                        || (variant_pos < current_pos + ins_del_end
-                            && variant_pos + indel_length >= current_pos + ins_del_end)
-                        || (variant_pos >= current_pos + ins_del_end
-                            && variant_pos - flanking_region_length <= current_pos + ins_del_end)
-                        || (variant_pos + indel_length <= current_pos
-                            && variant_pos + indel_length + flanking_region_length >= current_pos)
+                    ins_del_near_variant = true;
+                }
+                current_pos = ins_del_end;
+            } else {
+                current_pos += numbers[j];
+            }
+        }
+        if ins_del_near_variant
+            && check_base_pair_quality_scores(
+                // Need to check if the bases near the variant region are of high quality
+                quality_scores[i],
+                &alphabets,
+                &numbers,
+                start_positions[i],
+                left_flanking_region_limit,
+                right_flanking_region_limit,
+                30, // Phred score >= 30
+            )
+        {
+            read_indices.push(i);
+        }
+    }
+
+    let selected_sequences: Vec<&str> = read_indices.iter().map(|i| sequences[*i]).collect();
+    let (aligned_sequences, consensus_sequence) =
+        multiple_alignment(ref_sequence, &selected_sequences);
+    RealignmentResult {
+        read_indices,
+        aligned_sequences,
+        consensus_sequence,
+    }
+}
+
+// Star (center) multiple alignment. Each read is aligned to the reference sequence (semiglobal alignment, so that the read is aligned end-to-end to a part of the reference) and the pairwise alignments are merged using the reference sequence as the center. Gaps inserted in the reference by any read are propagated to all other sequences.
+// Returns the aligned sequences (reference sequence first) and the consensus sequence of the reads
+fn multiple_alignment(ref_sequence: &String, sequences: &Vec<&str>) -> (Vec<String>, String) {
+    let ref_vector: Vec<char> = ref_sequence.chars().collect();
+    let ref_length = ref_vector.len();
+    let score = |a: u8, b: u8| if a == b { 1i32 } else { -1i32 };
+
+    // For each read, the nucleotide aligned to each reference position (None if the read does not cover that position, Some('-') in case of deletion) and the nucleotides inserted before each reference position
+    let mut aligned_nucleotides = Vec::<Vec<Option<char>>>::new();
+    let mut inserted_nucleotides = Vec::<Vec<Vec<char>>>::new();
+    let mut max_insertion_lengths = vec![0; ref_length + 1]; // Maximum number of nucleotides inserted before each reference position among all reads
+    for sequence in sequences {
+        let query_vector: Vec<char> = sequence.chars().collect();
+        let mut aligner = Aligner::with_capacity(
+            sequence.len(),
+            ref_sequence.len(),
+            -5, // gap open penalty
+            -1, // gap extension penalty
+            &score,
+        );
+        let alignment = aligner.semiglobal(sequence.as_bytes(), ref_sequence.as_bytes());
+        let mut read_nucleotides: Vec<Option<char>> = vec![None; ref_length];
+        let mut read_insertions: Vec<Vec<char>> = vec![Vec::<char>::new(); ref_length + 1];
+        let mut x = alignment.xstart; // Position in read
+        let mut y = alignment.ystart; // Position in reference sequence
+        for operation in alignment.operations {
+            match operation {
+                AlignmentOperation::Match | AlignmentOperation::Subst => {
+                    read_nucleotides[y] = Some(query_vector[x]);
+                    x += 1;
+                    y += 1;
+                }
+                AlignmentOperation::Del => {
+                    read_nucleotides[y] = Some('-');
+                    y += 1;
+                }
+                AlignmentOperation::Ins => {
+                    read_insertions[y].push(query_vector[x]);
+                    x += 1;
+                }
+                AlignmentOperation::Xclip(_) | AlignmentOperation::Yclip(_) => {} // Clipped parts are already accounted for by xstart and ystart
+            }
+        }
+        for ref_pos in 0..=ref_length {
+            max_insertion_lengths[ref_pos] = cmp::max(
+                max_insertion_lengths[ref_pos],
+                read_insertions[ref_pos].len(),
+            );
+        }
+        aligned_nucleotides.push(read_nucleotides);
+        inserted_nucleotides.push(read_insertions);
+    }
+
+    // Building the multiple alignment column by column. Each reference position is preceded by insertion columns (if any read has an insertion there)
+    let mut aligned_sequences: Vec<String> = vec![String::new(); sequences.len() + 1];
+    let mut consensus_sequence = String::new();
+    for ref_pos in 0..=ref_length {
+        for insertion_idx in 0..max_insertion_lengths[ref_pos] {
+            aligned_sequences[0].push('-');
+            let mut column = Vec::<char>::new(); // Nucleotides of reads covering this column
+            for read_idx in 0..sequences.len() {
+                let read_insertions = &inserted_nucleotides[read_idx][ref_pos];
+                if insertion_idx < read_insertions.len() {
+                    aligned_sequences[read_idx + 1].push(read_insertions[insertion_idx]);
+                    column.push(read_insertions[insertion_idx]);
+                } else {
+                    aligned_sequences[read_idx + 1].push('-');
+                    // An insertion column is covered by a read when the read covers the reference positions on both sides
+                    if ref_pos > 0
+                        && ref_pos < ref_length
+                        && aligned_nucleotides[read_idx][ref_pos - 1].is_some()
+                        && aligned_nucleotides[read_idx][ref_pos].is_some()
                     {
-                        // Need to check if the bases near the variant region are of high quality
-                        //println!("Success");
-                        //println!("i:{}", i);
-                        //println!("cigar_seq:{}", cigar_seq);
-                        check_base_pair_quality_scores(
-                            &quality_score_seq,
-                            &alphabets,
-                            &numbers,
-                            start_positions_list[i].to_string().parse::<i64>().unwrap(),
-                            variant_pos,
-                            flanking_region_length,
-                            indel_length,
-                            left_flanking_region_limit,
-                            right_flanking_region_limit,
-                        );
-                        sequences_to_be_aligned.push(sequences_list[i + 2].to_owned());
-                        // 2 added because first two sequences are reference and alternate sequence
-                        num_reads_aligned += 1;
+                        column.push('-');
                     }
-                    current_pos += numbers[j].to_string().parse::<i64>().unwrap();
-                } else {
-                    current_pos += numbers[j].to_string().parse::<i64>().unwrap();
                 }
             }
+            let consensus_nucleotide = majority_nucleotide(&column, '-');
+            if consensus_nucleotide != '-' {
+                consensus_sequence.push(consensus_nucleotide);
+            }
+        }
+        if ref_pos == ref_length {
+            break;
+        }
+        aligned_sequences[0].push(ref_vector[ref_pos]);
+        let mut column = Vec::<char>::new(); // Nucleotides of reads covering this column
+        for read_idx in 0..sequences.len() {
+            match aligned_nucleotides[read_idx][ref_pos] {
+                Some(nucleotide) => {
+                    aligned_sequences[read_idx + 1].push(nucleotide);
+                    column.push(nucleotide);
+                }
+                None => aligned_sequences[read_idx + 1].push('-'),
+            }
+        }
+        let consensus_nucleotide = majority_nucleotide(&column, ref_vector[ref_pos]);
+        if consensus_nucleotide != '-' {
+            consensus_sequence.push(consensus_nucleotide);
         }
     }
+    (aligned_sequences, consensus_sequence)
+}
 
-    let status = Command::new("sh") // Assuming this code is running on a Unix system
-        .arg("-c")
-        .arg("echo Hello world")
-        .output()
-        .expect("failed to execute process");
-
-    println!("output:{}", str::from_utf8(&status.stdout).unwrap()); // Converts slice of bytes into a string slice
+// Returns the most frequent character in the column. In case of a tie or an empty column, default_nucleotide is returned if it is among the most frequent characters
+fn majority_nucleotide(column: &Vec<char>, default_nucleotide: char) -> char {
+    if column.is_empty() {
+        return default_nucleotide;
+    }
+    let mut nucleotide_counts = Vec::<(char, usize)>::new();
+    for nucleotide in column {
+        match nucleotide_counts.iter_mut().find(|x| x.0 == *nucleotide) {
+            Some(nucleotide_count) => nucleotide_count.1 += 1,
+            None => nucleotide_counts.push((*nucleotide, 1)),
+        }
+    }
+    let max_count = nucleotide_counts.iter().map(|x| x.1).max().unwrap();
+    if nucleotide_counts
+        .iter()
+        .any(|x| x.0 == default_nucleotide && x.1 == max_count)
+    {
+        return default_nucleotide;
+    }
+    nucleotide_counts
+        .iter()
+        .find(|x| x.1 == max_count)
+        .unwrap()
+        .0
 }
 
 pub fn check_read_within_indel_region(
@@ -1340,38 +1477,140 @@ pub fn reverse_string(input: &str) -> String {
     result
 }
 
-pub fn align_single_reads(query_seq: &String, ref_seq: String) -> (String, String, String, f64) {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlignmentMode {
+    Global,     // Both the read and the reference sequence are aligned end-to-end
+    Semiglobal, // The read is aligned end-to-end to a part of the reference sequence
+    Local,      // Only the best matching parts of the read and the reference sequence are aligned
+    Banded, // Global alignment restricted to a band (of width band_width) around the expected diagonal determined from k-mer matches between the read and the reference sequence. Much faster than global mode at high read depth, gives an alignment with the same score as global mode as long as the optimal alignment lies within the band
+}
+
+#[derive(Debug, Clone)]
+pub struct AlignmentParameters {
+    pub mode: AlignmentMode, // Alignment mode
+    pub match_score: i32,    // Score for a match
+    pub mismatch_score: i32, // Score for a mismatch
+    pub gap_open: i32,       // Gap open penalty (must be <= 0)
+    pub gap_extend: i32,     // Gap extension penalty (must be <= 0)
+    pub kmer_length: usize, // Length of k-mers used to determine the expected diagonal (only for banded mode)
+    pub band_width: usize, // Number of diagonals on either side of the expected diagonal that are computed (only for banded mode)
+}
+
+// Scoring scheme used by the indel and align binaries when no parameters are given in the input JSON
+impl Default for AlignmentParameters {
+    fn default() -> AlignmentParameters {
+        AlignmentParameters {
+            mode: AlignmentMode::Global,
+            match_score: 1,
+            mismatch_score: -1,
+            gap_open: -5,
+            gap_extend: -1,
+            kmer_length: 8,
+            band_width: 20,
+        }
+    }
+}
+
+// Parses the (optional) alignment parameters from the input JSON of the indel and align binaries:
+//   alignment_mode: "global" (default), "semiglobal", "local" or "banded"
+//   match_score (default 1), mismatch_score (default -1), gap_open (default -5), gap_extend (default -1)
+//   kmer_length (default 8) and band_width (default 20), only used in banded mode
+pub fn parse_alignment_parameters(json_string: &JsonValue) -> Result<AlignmentParameters, String> {
+    let mut alignment_parameters = AlignmentParameters::default();
+    if !json_string["alignment_mode"].is_null() {
+        alignment_parameters.mode = match json_string["alignment_mode"].as_str() {
+            Some("global") => AlignmentMode::Global,
+            Some("semiglobal") => AlignmentMode::Semiglobal,
+            Some("local") => AlignmentMode::Local,
+            Some("banded") => AlignmentMode::Banded,
+            _ => {
+                return Err(format!(
+                    "Unknown alignment_mode {}, must be global, semiglobal, local or banded",
+                    json_string["alignment_mode"]
+                ))
+            }
+        };
+    }
+    for (key, score) in [
+        ("match_score", &mut alignment_parameters.match_score),
+        ("mismatch_score", &mut alignment_parameters.mismatch_score),
+        ("gap_open", &mut alignment_parameters.gap_open),
+        ("gap_extend", &mut alignment_parameters.gap_extend),
+    ] {
+        if !json_string[key].is_null() {
+            match json_string[key].as_i32() {
+                Some(value) => *score = value,
+                None => return Err(format!("{} must be an integer", key)),
+            }
+        }
+    }
+    for (key, length) in [
+        ("kmer_length", &mut alignment_parameters.kmer_length),
+        ("band_width", &mut alignment_parameters.band_width),
+    ] {
+        if !json_string[key].is_null() {
+            match json_string[key].as_usize() {
+                Some(value) if value > 0 => *length = value,
+                _ => return Err(format!("{} must be a positive integer", key)),
+            }
+        }
+    }
+    if alignment_parameters.gap_open > 0 || alignment_parameters.gap_extend > 0 {
+        return Err("gap_open and gap_extend must be <= 0".to_string());
+    }
+    if alignment_parameters.match_score <= alignment_parameters.mismatch_score {
+        return Err("match_score must be greater than mismatch_score".to_string());
+    }
+    Ok(alignment_parameters)
+}
+
+pub fn align_single_reads(
+    query_seq: &String,
+    ref_seq: String,
+    alignment_parameters: &AlignmentParameters, // Scoring scheme and alignment mode
+) -> (String, String, String, f64) {
     let query_vector: Vec<_> = query_seq.chars().collect();
     let ref_vector: Vec<_> = ref_seq.chars().collect();
 
-    let score = |a: u8, b: u8| if a == b { 1i32 } else { -1i32 };
-    // gap open score: -5, gap extension score: -1
-
-    let mut aligner = Aligner::with_capacity(
-        query_seq.as_bytes().len(),
-        ref_seq.as_bytes().len(),
-        -5, // gap open penalty
-        -1, // gap extension penalty
-        &score,
-    );
-
-    let alignment = aligner.global(&query_seq.as_bytes(), ref_seq.as_bytes());
-    //let alignment = aligner.semiglobal(query_seq.as_bytes(), ref_seq.as_bytes());
-    //let alignment = aligner.local(query_seq.as_bytes(), ref_seq.as_bytes());
-
-    //let scoring = Scoring::from_scores(-5, -1, 1, -1) // Gap open, extend, match, mismatch score
-    //    .xclip(MIN_SCORE) // Clipping penalty for x set to 'negative infinity', hence global in x
-    //    .yclip(MIN_SCORE); // Clipping penalty for y set to 'negative infinity', hence global in y
-    //
-    //let mut aligner = Aligner::with_scoring(scoring);
-    //let alignment = aligner.custom(query_seq.as_bytes(), ref_seq.as_bytes());
-
-    let alignment_seq = alignment.operations;
+    let match_score = alignment_parameters.match_score;
+    let mismatch_score = alignment_parameters.mismatch_score;
+    let score = |a: u8, b: u8| if a == b { match_score } else { mismatch_score };
+
+    let mut banded_alignment = None;
+    if alignment_parameters.mode == AlignmentMode::Banded {
+        banded_alignment = banded_global_alignment(
+            query_seq.as_bytes(),
+            ref_seq.as_bytes(),
+            alignment_parameters,
+        );
+    }
+    let (alignment_seq, xstart, ystart) = match banded_alignment {
+        Some(operations) => (operations, 0, 0),
+        None => {
+            // Full dynamic programming alignment. In banded mode this is only used when no k-mer is shared between the read and the reference sequence
+            let mut aligner = Aligner::with_capacity(
+                query_seq.len(),
+                ref_seq.len(),
+                alignment_parameters.gap_open,
+                alignment_parameters.gap_extend,
+                &score,
+            );
+            let alignment = match alignment_parameters.mode {
+                AlignmentMode::Semiglobal => {
+                    aligner.semiglobal(query_seq.as_bytes(), ref_seq.as_bytes())
+                }
+                AlignmentMode::Local => aligner.local(query_seq.as_bytes(), ref_seq.as_bytes()),
+                _ => aligner.global(query_seq.as_bytes(), ref_seq.as_bytes()),
+            };
+            (alignment.operations, alignment.xstart, alignment.ystart)
+        }
+    };
+
     let mut q_seq: String = String::new();
     let mut align: String = String::new();
     let mut r_seq: String = String::new();
-    let mut j: usize = 0;
-    let mut k: usize = 0;
+    let mut j: usize = xstart; // Position in query sequence, in local mode the start of the read may not be aligned
+    let mut k: usize = ystart; // Position in reference sequence, in semiglobal/local mode the part of the reference sequence before the read is not aligned
     for i in 0..alignment_seq.len() {
         if AlignmentOperation::Match == alignment_seq[i] {
             if j < query_vector.len() {
@@ -1416,6 +1655,9 @@ pub fn align_single_reads(query_seq: &String, ref_seq: String) -> (String, Strin
             }
             r_seq += &"-".to_string();
             align += &" ".to_string(); // Add empty space when there is an insertion
+        } else if let AlignmentOperation::Xclip(_) | AlignmentOperation::Yclip(_) = alignment_seq[i]
+        {
+            // Clipped parts are not part of the alignment, the start positions are already accounted for by xstart and ystart
         } else {
             // Should not happen, added to help debug if it ever happens
             println!("Alignment operation not found:{}{:?}", i, alignment_seq[i]);
@@ -1433,6 +1675,192 @@ pub fn align_single_reads(query_seq: &String, ref_seq: String) -> (String, Strin
     (q_seq_final, align_final, r_seq_final, num_matches)
 }
 
+// Determines the range of diagonals (reference position - query position) on which the query and reference sequence are expected to align, by counting k-mers shared between the two sequences on each diagonal. All diagonals supported by at least half as many k-mers as the best diagonal are included, so that both sides of an indel (which lie on different diagonals) are covered. Returns None if no k-mer is shared
+fn determine_expected_diagonals(
+    query: &[u8],       // Query sequence
+    reference: &[u8],   // Reference sequence
+    kmer_length: usize, // Length of k-mers
+) -> Option<(i64, i64)> {
+    if query.len() < kmer_length || reference.len() < kmer_length {
+        return None;
+    }
+    let mut reference_kmers = HashMap::<&[u8], Vec<usize>>::new(); // Positions of each k-mer in the reference sequence
+    for j in 0..=reference.len() - kmer_length {
+        reference_kmers
+            .entry(&reference[j..j + kmer_length])
+            .or_default()
+            .push(j);
+    }
+    let mut diagonal_counts = HashMap::<i64, usize>::new(); // Number of shared k-mers on each diagonal
+    for i in 0..=query.len() - kmer_length {
+        if let Some(positions) = reference_kmers.get(&query[i..i + kmer_length]) {
+            for j in positions {
+                *diagonal_counts.entry(*j as i64 - i as i64).or_insert(0) += 1;
+            }
+        }
+    }
+    let max_count = match diagonal_counts.values().max() {
+        Some(max_count) => *max_count,
+        None => return None,
+    };
+    let mut min_diagonal = i64::MAX;
+    let mut max_diagonal = i64::MIN;
+    for (diagonal, count) in &diagonal_counts {
+        if count * 2 >= max_count {
+            min_diagonal = cmp::min(min_diagonal, *diagonal);
+            max_diagonal = cmp::max(max_diagonal, *diagonal);
+        }
+    }
+    Some((min_diagonal, max_diagonal))
+}
+
+// Global alignment (same scoring as the global mode of the bio crate, a gap of length k costs gap_open + k * gap_extend) where only the cells within band_width diagonals of the expected diagonals are computed. The first row of the dynamic programming matrix always starts at the first column and the last row always extends to the last column, so that the part of the reference sequence before and after the read can be skipped with a single gap. This reduces the complexity from O(n*m) to O(n*band_width), which matters because align_single_reads() is called twice per read for every allele. Returns None if the expected diagonals cannot be determined, in which case the full alignment should be carried out
+fn banded_global_alignment(
+    query: &[u8],                               // Query sequence
+    reference: &[u8],                           // Reference sequence
+    alignment_parameters: &AlignmentParameters, // Scoring scheme, k-mer length and band width
+) -> Option<Vec<AlignmentOperation>> {
+    let (min_diagonal, max_diagonal) =
+        determine_expected_diagonals(query, reference, alignment_parameters.kmer_length)?;
+    let m = query.len();
+    let n = reference.len();
+    let band_width = alignment_parameters.band_width as i64;
+    let gap_open = alignment_parameters.gap_open;
+    let gap_extend = alignment_parameters.gap_extend;
+    let min_score = i32::MIN / 2; // Score of cells that cannot be reached, halved so that adding penalties does not overflow
+
+    // Traceback of each cell. Bits 0-1: origin of best score (0: diagonal, 1: insertion, 2: deletion), bit 2: insertion extended (otherwise opened), bit 3: deletion extended (otherwise opened)
+    let mut tracebacks = Vec::<Vec<u8>>::with_capacity(m + 1);
+    let mut band_starts = Vec::<usize>::with_capacity(m + 1); // First column computed in each row
+    let mut prev_scores = Vec::<i32>::new(); // Best scores in the previous row
+    let mut prev_insertion_scores = Vec::<i32>::new(); // Scores ending with an insertion in the previous row
+    let mut prev_start: usize = 0;
+    let mut final_score = min_score;
+    for i in 0..=m {
+        let band_start: usize = if i == 0 {
+            0
+        } else {
+            cmp::min(cmp::max(i as i64 + min_diagonal - band_width, 0), n as i64) as usize
+        };
+        let band_stop: usize = if i == m {
+            n
+        } else {
+            cmp::max(
+                cmp::min(cmp::max(i as i64 + max_diagonal + band_width, 0), n as i64) as usize,
+                band_start,
+            )
+        };
+        let mut scores = vec![min_score; band_stop - band_start + 1];
+        let mut insertion_scores = vec![min_score; band_stop - band_start + 1];
+        let mut traceback = vec![0u8; band_stop - band_start + 1];
+        let mut deletion_score = min_score; // Score ending with a deletion in the cell to the left
+        for j in band_start..=band_stop {
+            let idx = j - band_start;
+            if i == 0 && j == 0 {
+                scores[idx] = 0;
+                continue;
+            }
+            let mut cell_traceback: u8 = 0;
+            // Deletion (gap in query), coming from the cell to the left
+            if j > band_start {
+                let open = scores[idx - 1] + gap_open + gap_extend;
+                let extend = deletion_score + gap_extend;
+                if extend > open {
+                    deletion_score = extend;
+                    cell_traceback |= 8;
+                } else {
+                    deletion_score = open;
+                }
+            } else {
+                deletion_score = min_score;
+            }
+            // Insertion (gap in reference), coming from the cell above
+            let mut insertion_score = min_score;
+            if i > 0 && j >= prev_start && j - prev_start < prev_scores.len() {
+                let open = prev_scores[j - prev_start] + gap_open + gap_extend;
+                let extend = prev_insertion_scores[j - prev_start] + gap_extend;
+                if extend > open {
+                    insertion_score = extend;
+                    cell_traceback |= 4;
+                } else {
+                    insertion_score = open;
+                }
+            }
+            // Match/substitution, coming from the diagonal cell
+            let mut score = min_score;
+            if i > 0 && j > prev_start && j - 1 - prev_start < prev_scores.len() {
+                score = prev_scores[j - 1 - prev_start]
+                    + if query[i - 1] == reference[j - 1] {
+                        alignment_parameters.match_score
+                    } else {
+                        alignment_parameters.mismatch_score
+                    };
+            }
+            if insertion_score > score {
+                score = insertion_score;
+                cell_traceback |= 1;
+            }
+            if deletion_score > score {
+                score = deletion_score;
+                cell_traceback = (cell_traceback & !3) | 2;
+            }
+            scores[idx] = score;
+            insertion_scores[idx] = insertion_score;
+            traceback[idx] = cell_traceback;
+        }
+        if i == m {
+            final_score = scores[n - band_start];
+        }
+        tracebacks.push(traceback);
+        band_starts.push(band_start);
+        prev_scores = scores;
+        prev_insertion_scores = insertion_scores;
+        prev_start = band_start;
+    }
+    if final_score <= min_score / 2 {
+        // The end of the alignment could not be reached within the band
+        return None;
+    }
+
+    // Traceback from the bottom right cell
+    let mut operations = Vec::<AlignmentOperation>::with_capacity(m + n);
+    let mut i = m;
+    let mut j = n;
+    let mut state: u8 = 0; // 0: best score, 1: insertion, 2: deletion
+    while i > 0 || j > 0 {
+        let cell_traceback = tracebacks[i][j - band_starts[i]];
+        if state == 0 {
+            match cell_traceback & 3 {
+                0 => {
+                    if query[i - 1] == reference[j - 1] {
+                        operations.push(AlignmentOperation::Match);
+                    } else {
+                        operations.push(AlignmentOperation::Subst);
+                    }
+                    i -= 1;
+                    j -= 1;
+                }
+                1 => state = 1,
+                _ => state = 2,
+            }
+        } else if state == 1 {
+            operations.push(AlignmentOperation::Ins);
+            if cell_traceback & 4 == 0 {
+                state = 0;
+            }
+            i -= 1;
+        } else {
+            operations.push(AlignmentOperation::Del);
+            if cell_traceback & 8 == 0 {
+                state = 0;
+            }
+            j -= 1;
+        }
+    }
+    operations.reverse();
+    Some(operations)
+}
+
 // Experimental function which aims to trim reads that contain spliced sequence in flanking sequence which creates alignment artifacts
 #[allow(dead_code)]
 fn trim_misaligned_parts(
@@ -1814,3 +2242,200 @@ pub fn determine_start_stop_indel_region_in_read(
         red_region_stop_ref,
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_base_pair_quality_scores() {
+        // 10 soft clipped nucleotides (quality 40) followed by 10 matched nucleotides, the third of which has quality 2. The read starts at 100 (first matched nucleotide)
+        let quality_scores = "IIIIIIIIIIII#IIIIIII".to_string();
+        for cigar in ["10S10M", "5H10S10M"].iter() {
+            let (alphabets, numbers) = parse_cigar(&cigar.to_string());
+            assert!(!check_base_pair_quality_scores(
+                &quality_scores,
+                &alphabets,
+                &numbers,
+                100,
+                102,
+                102,
+                20
+            ));
+            assert!(check_base_pair_quality_scores(
+                &quality_scores,
+                &alphabets,
+                &numbers,
+                100,
+                92,
+                92,
+                20
+            ));
+        }
+        // CIGAR not available
+        let (alphabets, numbers) = parse_cigar(&"*".to_string());
+        assert!(check_base_pair_quality_scores(
+            &quality_scores,
+            &alphabets,
+            &numbers,
+            100,
+            102,
+            102,
+            20
+        ));
+    }
+
+    // Reference sequence shared by the alignment tests (no repeats around the indels so that the gap position is unambiguous)
+    const TEST_REFSEQ: &str = "GCTAAAGACAATTACATAACATACACGTCAGCACGAAACTTGTTGGCCCAGTGTGAATCG";
+
+    // Score of an alignment under the affine gap model of the bio crate (a gap of length k costs gap_open + k * gap_extend)
+    fn alignment_score(
+        operations: &[AlignmentOperation],
+        query: &[u8],
+        reference: &[u8],
+        alignment_parameters: &AlignmentParameters,
+    ) -> i32 {
+        let mut score = 0;
+        let mut i = 0;
+        let mut j = 0;
+        let mut previous_operation: Option<AlignmentOperation> = None;
+        for operation in operations {
+            match operation {
+                AlignmentOperation::Match | AlignmentOperation::Subst => {
+                    score += if query[i] == reference[j] {
+                        alignment_parameters.match_score
+                    } else {
+                        alignment_parameters.mismatch_score
+                    };
+                    i += 1;
+                    j += 1;
+                }
+                AlignmentOperation::Ins | AlignmentOperation::Del => {
+                    if previous_operation != Some(*operation) {
+                        score += alignment_parameters.gap_open;
+                    }
+                    score += alignment_parameters.gap_extend;
+                    if *operation == AlignmentOperation::Ins {
+                        i += 1;
+                    } else {
+                        j += 1;
+                    }
+                }
+                _ => panic!("Unexpected operation {:?} in global alignment", operation),
+            }
+            previous_operation = Some(*operation);
+        }
+        assert_eq!(i, query.len());
+        assert_eq!(j, reference.len());
+        score
+    }
+
+    #[test]
+    fn test_banded_global_alignment() {
+        let alignment_parameters = AlignmentParameters {
+            mode: AlignmentMode::Banded,
+            band_width: 4,
+            ..Default::default()
+        };
+        let score = |a: u8, b: u8| {
+            if a == b {
+                alignment_parameters.match_score
+            } else {
+                alignment_parameters.mismatch_score
+            }
+        };
+        let reference = &TEST_REFSEQ.as_bytes()[5..60];
+        let reads = [
+            TEST_REFSEQ[10..55].to_string(),                        // No indel
+            TEST_REFSEQ[10..34].to_string() + &TEST_REFSEQ[37..55], // Deletion of GAA
+            TEST_REFSEQ[10..30].to_string() + "TTT" + &TEST_REFSEQ[30..55], // Insertion of TTT
+            TEST_REFSEQ[8..26].to_string() + &TEST_REFSEQ[31..58], // Deletion longer than the band width
+            TEST_REFSEQ[12..30].to_string() + "GGTTAGGTTA" + &TEST_REFSEQ[30..50], // Insertion longer than the band width
+        ];
+        for read in reads.iter() {
+            let operations =
+                banded_global_alignment(read.as_bytes(), reference, &alignment_parameters).unwrap();
+            let mut aligner = Aligner::with_capacity(
+                read.len(),
+                reference.len(),
+                alignment_parameters.gap_open,
+                alignment_parameters.gap_extend,
+                &score,
+            );
+            let full_alignment = aligner.global(read.as_bytes(), reference);
+            assert_eq!(
+                alignment_score(
+                    &operations,
+                    read.as_bytes(),
+                    reference,
+                    &alignment_parameters
+                ),
+                full_alignment.score,
+                "read {}",
+                read
+            );
+        }
+        // No k-mer shared between the read and the reference sequence
+        assert!(banded_global_alignment(
+            "TTTTTTTTTTTTTTTTTTTT".as_bytes(),
+            reference,
+            &alignment_parameters
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn test_multiple_alignment() {
+        // Expected alignments worked out by hand. The flanking nucleotides of each indel differ from the deleted/inserted ones, so there is only one optimal placement of each gap
+        let ref_sequence = TEST_REFSEQ.to_string();
+        // Three reads with a deletion of GAA and one reference read
+        let deletion_reads = [
+            TEST_REFSEQ[2..34].to_string() + &TEST_REFSEQ[37..50],
+            TEST_REFSEQ[10..34].to_string() + &TEST_REFSEQ[37..60],
+            TEST_REFSEQ[15..34].to_string() + &TEST_REFSEQ[37..55],
+            TEST_REFSEQ[20..58].to_string(),
+        ];
+        let (aligned_sequences, consensus_sequence) = multiple_alignment(
+            &ref_sequence,
+            &deletion_reads.iter().map(|x| x.as_str()).collect(),
+        );
+        assert_eq!(
+            aligned_sequences,
+            vec![
+                TEST_REFSEQ,
+                "--TAAAGACAATTACATAACATACACGTCAGCAC---ACTTGTTGGCCCA----------",
+                "----------ATTACATAACATACACGTCAGCAC---ACTTGTTGGCCCAGTGTGAATCG",
+                "---------------ATAACATACACGTCAGCAC---ACTTGTTGGCCCAGTGTG-----",
+                "--------------------ATACACGTCAGCACGAAACTTGTTGGCCCAGTGTGAAT--",
+            ]
+        );
+        assert_eq!(
+            consensus_sequence,
+            "GCTAAAGACAATTACATAACATACACGTCAGCACACTTGTTGGCCCAGTGTGAATCG"
+        );
+
+        // Two reads with an insertion of TTT and one reference read
+        let insertion_reads = [
+            TEST_REFSEQ[5..30].to_string() + "TTT" + &TEST_REFSEQ[30..50],
+            TEST_REFSEQ[12..30].to_string() + "TTT" + &TEST_REFSEQ[30..58],
+            TEST_REFSEQ[0..45].to_string(),
+        ];
+        let (aligned_sequences, consensus_sequence) = multiple_alignment(
+            &ref_sequence,
+            &insertion_reads.iter().map(|x| x.as_str()).collect(),
+        );
+        assert_eq!(
+            aligned_sequences,
+            vec![
+                "GCTAAAGACAATTACATAACATACACGTCA---GCACGAAACTTGTTGGCCCAGTGTGAATCG",
+                "-----AGACAATTACATAACATACACGTCATTTGCACGAAACTTGTTGGCCCA----------",
+                "------------TACATAACATACACGTCATTTGCACGAAACTTGTTGGCCCAGTGTGAAT--",
+                "GCTAAAGACAATTACATAACATACACGTCA---GCACGAAACTTGTTG---------------",
+            ]
+        );
+        assert_eq!(
+            consensus_sequence,
+            "GCTAAAGACAATTACATAACATACACGTCATTTGCACGAAACTTGTTGGCCCAGTGTGAATCG"
+        );
+    }
+}
@@ -0,0 +1,419 @@
+// On-disk cache of byte ranges of remote files (e.g. bigWig files read through a URL), so that the header, index and data blocks are not fetched again over HTTP every time the same region is viewed.
+// The remote file is divided into fixed size blocks. Each block is fetched with an HTTP range request and stored as a separate file named after its byte range in a directory of the cache specific to the URL.
+// When a file is opened its ETag (or Last-Modified date when there is no ETag) and length are compared with the ones stored in the cache, and the cached blocks of the URL are discarded when the remote file has changed.
+// The total size of the cache is kept below max_bytes by removing the least recently used blocks (the modification time of a block file is updated each time it is read).
+use bigtools::utils::file::seekableread::Reopen;
+use std::fs;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+const BLOCK_SIZE: u64 = 65536; // Size of each cached byte range
+const EVICTION_INTERVAL: usize = 64; // Number of fetched blocks after which the size of the cache is checked again
+
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    pub dir: String,    // Cache directory, shared by all URLs
+    pub max_bytes: u64, // Maximum total size of the cached blocks
+}
+
+#[derive(Clone)]
+pub struct CachedRemoteFile {
+    url: String,
+    config: CacheConfig,
+    url_dir: PathBuf,      // Directory of the cached blocks of this URL
+    validator: String, // ETag or Last-Modified date of the remote file, sent in If-Range so that a change of the file during reading is detected
+    length: u64,       // Size of the remote file
+    position: u64,     // Current position of the reader
+    fetched_blocks: usize, // Number of blocks fetched over HTTP since the last eviction
+    client: reqwest::Client,
+    runtime: Arc<tokio::runtime::Runtime>,
+}
+
+impl CachedRemoteFile {
+    // Opens url through the cache. The remote file is validated with a 1-byte range request
+    pub fn open(url: &str, config: &CacheConfig) -> io::Result<CachedRemoteFile> {
+        let runtime = match tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+        {
+            Ok(runtime) => Arc::new(runtime),
+            Err(error) => return Err(error),
+        };
+        let client = reqwest::Client::new();
+        let response = runtime.block_on(async {
+            let response = client
+                .get(url)
+                .header("Range", "bytes=0-0")
+                .send()
+                .await
+                .map_err(http_error)?;
+            let status = response.status().as_u16();
+            let headers = response.headers().clone();
+            Ok::<_, io::Error>((status, headers))
+        })?;
+        let (status, headers) = response;
+        if status != 206 {
+            return Err(io::Error::other(format!(
+                "{} does not support range requests (HTTP status {})",
+                url, status
+            )));
+        }
+        // Content-Range: bytes 0-0/length
+        let length: u64 = match headers
+            .get("content-range")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.rsplit('/').next())
+            .and_then(|value| value.parse::<u64>().ok())
+        {
+            Some(length) => length,
+            None => {
+                return Err(io::Error::other(format!(
+                    "{} did not return the file size",
+                    url
+                )))
+            }
+        };
+        let validator: String = headers
+            .get("etag")
+            .or(headers.get("last-modified"))
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("")
+            .to_string();
+
+        let url_dir = PathBuf::from(&config.dir).join(format!("{:016x}", fnv1a_hash(url)));
+        let meta_file = url_dir.join("meta");
+        let meta = format!("{}\n{}\n{}\n", url, validator, length);
+        let cached_meta = fs::read_to_string(&meta_file).unwrap_or_default();
+        if cached_meta != meta || validator.is_empty() {
+            // The remote file has changed (or cannot be validated), so the cached blocks are discarded
+            if url_dir.exists() {
+                fs::remove_dir_all(&url_dir)?;
+            }
+            fs::create_dir_all(&url_dir)?;
+            if !validator.is_empty() {
+                fs::write(&meta_file, meta)?;
+            }
+        }
+        evict_least_recently_used(config)?;
+
+        Ok(CachedRemoteFile {
+            url: url.to_string(),
+            config: config.clone(),
+            url_dir,
+            validator,
+            length,
+            position: 0,
+            fetched_blocks: 0,
+            client,
+            runtime,
+        })
+    }
+
+    // Returns block block_idx from the cache or fetches it
+    fn block(&mut self, block_idx: u64) -> io::Result<Vec<u8>> {
+        let start = block_idx * BLOCK_SIZE;
+        let end = std::cmp::min(start + BLOCK_SIZE, self.length); // Not inclusive
+        let block_file = self.url_dir.join(format!("{}-{}", start, end));
+        if let Ok(block) = fs::read(&block_file) {
+            if block.len() as u64 == end - start {
+                // Marks the block as recently used
+                if let Ok(file) = fs::File::options().write(true).open(&block_file) {
+                    file.set_modified(SystemTime::now()).ok();
+                }
+                return Ok(block);
+            }
+        }
+
+        let block = self.runtime.block_on(async {
+            let mut request = self
+                .client
+                .get(&self.url)
+                .header("Range", format!("bytes={}-{}", start, end - 1));
+            if !self.validator.is_empty() {
+                request = request.header("If-Range", &self.validator);
+            }
+            let response = request.send().await.map_err(http_error)?;
+            if response.status().as_u16() != 206 {
+                // The server returns the whole file instead of the range when If-Range does not match
+                return Err(io::Error::other(format!(
+                    "{} has changed while being read (HTTP status {})",
+                    self.url,
+                    response.status().as_u16()
+                )));
+            }
+            let bytes = response.bytes().await.map_err(http_error)?;
+            Ok(bytes.to_vec())
+        })?;
+        if block.len() as u64 != end - start {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("Incomplete range {}-{} from {}", start, end, self.url),
+            ));
+        }
+
+        if !self.validator.is_empty() {
+            // Written to a temporary file first so that other processes never read a partial block
+            let temp_file =
+                self.url_dir
+                    .join(format!("{}-{}.tmp{}", start, end, std::process::id()));
+            if fs::write(&temp_file, &block).is_ok() {
+                fs::rename(&temp_file, &block_file).ok();
+            }
+            self.fetched_blocks += 1;
+            if self.fetched_blocks >= EVICTION_INTERVAL {
+                self.fetched_blocks = 0;
+                evict_least_recently_used(&self.config)?;
+            }
+        }
+        Ok(block)
+    }
+}
+
+impl Read for CachedRemoteFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.length || buf.is_empty() {
+            return Ok(0);
+        }
+        let block_idx = self.position / BLOCK_SIZE;
+        let block = self.block(block_idx)?;
+        let offset = (self.position - block_idx * BLOCK_SIZE) as usize;
+        let n = std::cmp::min(buf.len(), block.len() - offset);
+        buf[0..n].copy_from_slice(&block[offset..offset + n]);
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for CachedRemoteFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position: i64 = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::End(offset) => self.length as i64 + offset,
+            SeekFrom::Current(offset) => self.position as i64 + offset,
+        };
+        if position < 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Seek to a negative position",
+            ));
+        }
+        self.position = position as u64;
+        Ok(self.position)
+    }
+}
+
+impl Reopen<CachedRemoteFile> for CachedRemoteFile {
+    fn reopen(&self) -> io::Result<CachedRemoteFile> {
+        let mut file = self.clone();
+        file.position = 0;
+        Ok(file)
+    }
+}
+
+fn http_error(error: reqwest::Error) -> io::Error {
+    io::Error::other(error.to_string())
+}
+
+// 64-bit FNV-1a hash of the URL, used as the name of the directory of the URL in the cache. Unlike the std hasher it is stable across Rust versions
+fn fnv1a_hash(text: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in text.as_bytes() {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+// Removes the least recently used blocks of all URLs until the total size of the cache is at most max_bytes
+pub fn evict_least_recently_used(config: &CacheConfig) -> io::Result<()> {
+    let mut blocks = Vec::<(SystemTime, u64, PathBuf)>::new(); // Last use, size and path of each block
+    let mut total_bytes: u64 = 0;
+    let url_dirs = match fs::read_dir(&config.dir) {
+        Ok(url_dirs) => url_dirs,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(error),
+    };
+    for url_dir in url_dirs.flatten() {
+        if !url_dir.path().is_dir() {
+            continue;
+        }
+        for block_file in fs::read_dir(url_dir.path())?.flatten() {
+            if block_file.file_name() == "meta" {
+                continue;
+            }
+            if let Ok(metadata) = block_file.metadata() {
+                total_bytes += metadata.len();
+                blocks.push((
+                    metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                    metadata.len(),
+                    block_file.path(),
+                ));
+            }
+        }
+    }
+    if total_bytes <= config.max_bytes {
+        return Ok(());
+    }
+    blocks.sort_by_key(|a| a.0);
+    for (_last_use, size, path) in blocks {
+        if total_bytes <= config.max_bytes {
+            break;
+        }
+        if fs::remove_file(&path).is_ok() {
+            total_bytes -= size;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::net::TcpListener;
+    use std::sync::Mutex;
+
+    // Minimal HTTP server standing in for a remote file server. It serves content with range requests, supports If-Range and counts the requests
+    fn start_server(
+        content: Arc<Mutex<(Vec<u8>, String)>>, // File content and ETag
+        requests: Arc<Mutex<usize>>,
+    ) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/file.bw", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = Vec::<u8>::new();
+                let mut buffer = [0u8; 1024];
+                while !String::from_utf8_lossy(&request).contains("\r\n\r\n") {
+                    let n = stream.read(&mut buffer).unwrap();
+                    if n == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buffer[0..n]);
+                }
+                let request = String::from_utf8_lossy(&request).to_lowercase();
+                *requests.lock().unwrap() += 1;
+                let (data, etag) = content.lock().unwrap().clone();
+                let header = |name: &str| {
+                    request
+                        .lines()
+                        .find(|line| line.starts_with(name))
+                        .map(|line| line[name.len()..].trim().to_string())
+                };
+                let range = header("range: bytes=").map(|range| {
+                    let (start, end) = range.split_once('-').unwrap();
+                    (
+                        start.parse::<usize>().unwrap(),
+                        end.parse::<usize>().unwrap(),
+                    )
+                });
+                let if_range_matches = match header("if-range:") {
+                    Some(if_range) => if_range == etag.to_lowercase(),
+                    None => true,
+                };
+                let response = match range {
+                    Some((start, end)) if if_range_matches => {
+                        let end = std::cmp::min(end, data.len() - 1);
+                        let mut response = format!("HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\nContent-Length: {}\r\nETag: {}\r\nConnection: close\r\n\r\n", start, end, data.len(), end + 1 - start, etag).into_bytes();
+                        response.extend_from_slice(&data[start..end + 1]);
+                        response
+                    }
+                    _ => {
+                        let mut response = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nETag: {}\r\nConnection: close\r\n\r\n", data.len(), etag).into_bytes();
+                        response.extend_from_slice(&data);
+                        response
+                    }
+                };
+                stream.write_all(&response).ok();
+            }
+        });
+        url
+    }
+
+    fn read_range(file: &mut CachedRemoteFile, start: u64, length: usize) -> Vec<u8> {
+        let mut data = vec![0u8; length];
+        file.seek(SeekFrom::Start(start)).unwrap();
+        file.read_exact(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_remote_cache() {
+        let data: Vec<u8> = (0..200000u32).map(|i| (i % 251) as u8).collect();
+        let content = Arc::new(Mutex::new((data.clone(), "\"v1\"".to_string())));
+        let requests = Arc::new(Mutex::new(0usize));
+        let url = start_server(Arc::clone(&content), Arc::clone(&requests));
+        let cache_dir =
+            std::env::temp_dir().join(format!("remote_cache_test_{}", std::process::id()));
+        let config = CacheConfig {
+            dir: cache_dir.to_str().unwrap().to_string(),
+            max_bytes: 3 * BLOCK_SIZE,
+        };
+
+        // Range spanning two blocks is fetched with one validation and two range requests
+        let mut file = CachedRemoteFile::open(&url, &config).unwrap();
+        assert_eq!(
+            read_range(&mut file, 65000, 1000),
+            data[65000..66000].to_vec()
+        );
+        assert_eq!(*requests.lock().unwrap(), 3);
+
+        // Block modification times have a coarse resolution on some file systems, so the steps that use blocks are spaced out
+        let pause = || std::thread::sleep(std::time::Duration::from_millis(20));
+
+        // Reading the same range again after reopening only needs the validation request
+        pause();
+        let mut file = CachedRemoteFile::open(&url, &config).unwrap();
+        assert_eq!(
+            read_range(&mut file, 65500, 100),
+            data[65500..65600].to_vec()
+        );
+        assert_eq!(*requests.lock().unwrap(), 4);
+
+        // Last (partial) block of the file
+        pause();
+        assert_eq!(
+            read_range(&mut file, 199990, 10),
+            data[199990..200000].to_vec()
+        );
+        assert_eq!(*requests.lock().unwrap(), 5);
+
+        // The cache is limited to 3 blocks, the least recently used block (0) is evicted
+        evict_least_recently_used(&config).unwrap();
+        pause();
+        read_range(&mut file, 140000, 10);
+        evict_least_recently_used(&config).unwrap();
+        let mut file = CachedRemoteFile::open(&url, &config).unwrap();
+        let before = *requests.lock().unwrap();
+        read_range(&mut file, 65536, 10);
+        assert_eq!(*requests.lock().unwrap(), before);
+        read_range(&mut file, 0, 10);
+        assert_eq!(*requests.lock().unwrap(), before + 1);
+
+        // A new ETag discards the cached blocks
+        {
+            let mut content = content.lock().unwrap();
+            content.0 = data.iter().map(|x| x.wrapping_add(1)).collect();
+            content.1 = "\"v2\"".to_string();
+        }
+        let mut file = CachedRemoteFile::open(&url, &config).unwrap();
+        let before = *requests.lock().unwrap();
+        assert_eq!(
+            read_range(&mut file, 65536, 1),
+            vec![data[65536].wrapping_add(1)]
+        );
+        assert_eq!(*requests.lock().unwrap(), before + 1);
+
+        // A change of the file after opening is detected through If-Range
+        content.lock().unwrap().1 = "\"v3\"".to_string();
+        let mut buffer = [0u8; 10];
+        file.seek(SeekFrom::Start(180000)).unwrap();
+        assert!(file.read(&mut buffer).is_err());
+
+        fs::remove_dir_all(&cache_dir).ok();
+    }
+}
@@ -1,6 +1,7 @@
 use fishers_exact::fishers_exact;
 //use r_mathlib;
-use statrs::distribution::{ChiSquared, ContinuousCDF};
+use statrs::distribution::{Beta, ChiSquared, ContinuousCDF, Normal};
+use std::collections::HashMap;
 use std::panic;
 
 #[allow(dead_code)]
@@ -412,3 +413,904 @@ pub fn calculate_frac_rank(current_rank: f64, num_repeats: f64) -> f64 {
     }
     sum / num_repeats
 }
+
+// Wilcoxon signed rank test for paired samples, same as wilcox.test(group1, group2, paired = TRUE) in R. Pairs with zero difference are dropped, ties get average ranks. The exact distribution is used when there are fewer than threshold non-zero differences and no ties or zeros, otherwise the normal approximation (with correction for ties and optional continuity correction). Returns an error if the groups have different lengths or all differences are zero
+#[allow(dead_code)]
+pub fn wilcoxon_signed_rank_test(
+    group1: Vec<f64>,
+    group2: Vec<f64>,
+    threshold: usize,
+    alternative: char,
+    correct: bool,
+) -> Result<f64, String> {
+    if alternative != 't' && alternative != 'g' && alternative != 'l' {
+        return Err(format!("Unknown alternative option {}", alternative));
+    }
+    if group1.len() != group2.len() {
+        return Err(format!(
+            "Paired samples must have the same length ({} and {})",
+            group1.len(),
+            group2.len()
+        ));
+    }
+    let mut differences: Vec<f64> = group1
+        .iter()
+        .zip(group2.iter())
+        .map(|(x, y)| x - y)
+        .filter(|d| *d != 0.0)
+        .collect();
+    let zeros_present = differences.len() < group1.len();
+    let n = differences.len();
+    if n == 0 {
+        return Err("All paired differences are zero".to_string());
+    }
+    differences.sort_by(|a, b| a.abs().partial_cmp(&b.abs()).unwrap());
+
+    // Ranking absolute differences, the rank of tied values being the average of their positions
+    let mut weight_positive: f64 = 0.0; // Sum of ranks of positive differences (V statistic in R)
+    let mut tie_sizes: Vec<f64> = Vec::with_capacity(n);
+    let mut i = 0;
+    while i < n {
+        let mut j = i + 1;
+        while j < n && differences[j].abs() == differences[i].abs() {
+            j += 1;
+        }
+        let num_repeats = (j - i) as f64;
+        let frac_rank = calculate_frac_rank(i as f64 + 1.0, num_repeats);
+        weight_positive +=
+            frac_rank * differences[i..j].iter().filter(|d| **d > 0.0).count() as f64;
+        tie_sizes.push(num_repeats);
+        i = j;
+    }
+    let ties_present = tie_sizes.len() < n;
+    let n = n as f64;
+
+    let p_value = if (n as usize) < threshold && !ties_present && !zeros_present {
+        // Compute exact p-values
+        let mut p_value;
+        if alternative == 't' {
+            if weight_positive > n * (n + 1.0) / 4.0 {
+                p_value = 2.0 * r_mathlib::signrank_cdf(weight_positive - 1.0, n, false, false);
+            } else {
+                p_value = 2.0 * r_mathlib::signrank_cdf(weight_positive, n, true, false);
+            }
+            if p_value > 1.0 {
+                p_value = 1.0;
+            }
+        } else if alternative == 'g' {
+            p_value = r_mathlib::signrank_cdf(weight_positive - 1.0, n, false, false);
+        } else {
+            p_value = r_mathlib::signrank_cdf(weight_positive, n, true, false);
+        }
+        p_value
+    } else {
+        // Compute p-values from a normal distribution
+        let mut z = weight_positive - n * (n + 1.0) / 4.0;
+        let mut nties_sum: f64 = 0.0;
+        for t in &tie_sizes {
+            nties_sum += t * t * t - t;
+        }
+        let sigma = (n * (n + 1.0) * (2.0 * n + 1.0) / 24.0 - nties_sum / 48.0).sqrt();
+        let mut correction: f64 = 0.0;
+        if correct {
+            if alternative == 'g' {
+                correction = 0.5;
+            } else if alternative == 'l' {
+                correction = -0.5;
+            } else if z > 0.0 {
+                correction = 0.5;
+            } else if z < 0.0 {
+                correction = -0.5;
+            }
+        }
+        z = (z - correction) / sigma;
+        if alternative == 'g' {
+            r_mathlib::normal_cdf(z, 0.0, 1.0, false, false)
+        } else if alternative == 'l' {
+            r_mathlib::normal_cdf(z, 0.0, 1.0, true, false)
+        } else {
+            let p_g = r_mathlib::normal_cdf(z, 0.0, 1.0, false, false);
+            let p_l = r_mathlib::normal_cdf(z, 0.0, 1.0, true, false);
+            let mut p_value;
+            if p_g < p_l {
+                p_value = 2.0 * p_g;
+            } else {
+                p_value = 2.0 * p_l;
+            }
+            if p_value > 1.0 {
+                p_value = 1.0;
+            }
+            p_value
+        }
+    };
+    Ok(p_value)
+}
+
+// Exact (Clopper-Pearson) confidence interval of a binomial proportion using quantiles of the beta distribution. Same as binom.test(successes, trials, conf.level = confidence)$conf.int in R
+#[allow(dead_code)]
+pub fn clopper_pearson_interval(successes: u32, trials: u32, confidence: f64) -> (f64, f64) {
+    if trials == 0 {
+        return (0.0, 1.0);
+    }
+    let alpha: f64 = 1.0 - confidence;
+    let mut lower: f64 = 0.0;
+    let mut upper: f64 = 1.0;
+    if successes > 0 {
+        lower = beta_quantile(
+            alpha / 2.0,
+            successes as f64,
+            (trials - successes) as f64 + 1.0,
+        );
+    }
+    if successes < trials {
+        upper = beta_quantile(
+            1.0 - alpha / 2.0,
+            successes as f64 + 1.0,
+            (trials - successes) as f64,
+        );
+    }
+    (lower, upper)
+}
+
+// Quantile of the beta distribution. The inverse_cdf() in statrs only carries out a small number of bisections, so the bisection is done here until the interval is narrower than 1e-12
+#[allow(dead_code)]
+fn beta_quantile(p: f64, shape_a: f64, shape_b: f64) -> f64 {
+    let beta_distribution = Beta::new(shape_a, shape_b).unwrap();
+    let mut low: f64 = 0.0;
+    let mut high: f64 = 1.0;
+    while high - low > 1e-12 {
+        let mid = (low + high) / 2.0;
+        if beta_distribution.cdf(mid) < p {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    (low + high) / 2.0
+}
+
+// Diploid genotype likelihoods from the number of reads supporting each allele (first entry is ref, followed by each alt allele). A read supports the allele it was sequenced from with probability 1 - error_rate, otherwise it supports any one of the other alleles with equal probability. Returns phred scaled likelihoods (PL) in VCF order (genotype j/k with j <= k is at index k*(k+1)/2 + j) normalized so that the most likely genotype has PL = 0
+#[allow(dead_code)]
+pub fn genotype_likelihoods(allele_counts: &[u32], error_rate: f64) -> Vec<u32> {
+    let num_alleles = allele_counts.len();
+    let mut log_likelihoods = Vec::<f64>::new(); // log10 likelihood of each genotype
+    for k in 0..num_alleles {
+        for j in 0..=k {
+            let mut log_likelihood: f64 = 0.0;
+            for (allele, allele_count) in allele_counts.iter().enumerate() {
+                if *allele_count == 0 {
+                    continue;
+                }
+                // Probability of a read supporting this allele given that it comes from either chromosome with equal probability
+                let mut read_probability: f64 = 0.0;
+                for chromosome_allele in [j, k] {
+                    if chromosome_allele == allele {
+                        read_probability += 0.5 * (1.0 - error_rate);
+                    } else {
+                        read_probability += 0.5 * error_rate / (num_alleles - 1) as f64;
+                    }
+                }
+                log_likelihood += *allele_count as f64 * read_probability.log10();
+            }
+            log_likelihoods.push(log_likelihood);
+        }
+    }
+    let max_log_likelihood = log_likelihoods
+        .iter()
+        .cloned()
+        .fold(f64::NEG_INFINITY, f64::max);
+    log_likelihoods
+        .iter()
+        .map(|log_likelihood| (-10.0 * (log_likelihood - max_log_likelihood)).round() as u32)
+        .collect()
+}
+
+// Limits of the network algorithm, beyond which the exact R x C test is given up: number of column distributions enumerated and number of partial tables (distinct node and past probability pairs) kept at one column
+const NETWORK_MAX_EDGES: usize = 20000000;
+const NETWORK_MAX_PATHS: usize = 2000000;
+
+// Removes the rows and columns of a contingency table that only contain zeros
+#[allow(dead_code)]
+pub fn remove_empty_rows_columns(table: &[Vec<u32>]) -> Vec<Vec<u32>> {
+    let num_columns = table.iter().map(|row| row.len()).max().unwrap_or(0);
+    let non_empty_columns: Vec<usize> = (0..num_columns)
+        .filter(|j| table.iter().any(|row| row[*j] > 0))
+        .collect();
+    table
+        .iter()
+        .filter(|row| row.iter().any(|x| *x > 0))
+        .map(|row| non_empty_columns.iter().map(|j| row[*j]).collect())
+        .collect()
+}
+
+// log(n!) for n = 0..=max_n
+fn log_factorials(max_n: u32) -> Vec<f64> {
+    let mut log_factorials = vec![0.0; max_n as usize + 1];
+    for n in 1..=max_n as usize {
+        log_factorials[n] = log_factorials[n - 1] + (n as f64).ln();
+    }
+    log_factorials
+}
+
+// Enumerates the ways of distributing a column total over rows with remaining row sums node. Each edge is the log of the column term c!/prod(x_i!) and the (sorted) remaining row sums after the column
+struct ColumnFill<'a> {
+    node: &'a Vec<u32>,
+    capacity: Vec<u32>, // Sum of the remaining row sums of rows i.., used to skip distributions that cannot place the whole column total
+    column: Vec<u32>,
+    column_sum: u32,
+    log_factorials: &'a Vec<f64>,
+    edges: Vec<(f64, Vec<u32>)>,
+    max_edges: usize,
+}
+
+impl<'a> ColumnFill<'a> {
+    fn new(
+        node: &'a Vec<u32>,
+        column_sum: u32,
+        log_factorials: &'a Vec<f64>,
+        max_edges: usize,
+    ) -> ColumnFill<'a> {
+        let mut capacity = vec![0u32; node.len() + 1];
+        for i in (0..node.len()).rev() {
+            capacity[i] = capacity[i + 1] + node[i];
+        }
+        ColumnFill {
+            node,
+            capacity,
+            column: vec![0u32; node.len()],
+            column_sum,
+            log_factorials,
+            edges: Vec::new(),
+            max_edges,
+        }
+    }
+
+    fn fill(&mut self, row: usize, left: u32) {
+        if self.edges.len() > self.max_edges {
+            return;
+        }
+        if row == self.node.len() {
+            let term: f64 = self.log_factorials[self.column_sum as usize]
+                - self
+                    .column
+                    .iter()
+                    .map(|x| self.log_factorials[*x as usize])
+                    .sum::<f64>();
+            let mut next_node: Vec<u32> = self
+                .node
+                .iter()
+                .zip(&self.column)
+                .map(|(r, x)| r - x)
+                .collect();
+            next_node.sort();
+            self.edges.push((term, next_node));
+            return;
+        }
+        let min_x = left.saturating_sub(self.capacity[row + 1]);
+        let max_x = std::cmp::min(left, self.node[row]);
+        for x in min_x..=max_x {
+            self.column[row] = x;
+            self.fill(row + 1, left - x);
+        }
+    }
+}
+
+// Network of the R x C tables with the given margins. A node before column k is the sorted vector of row sums left after the first k columns
+struct Network {
+    column_sums: Vec<u32>,
+    log_factorials: Vec<f64>,
+    bounds: HashMap<(usize, Vec<u32>), (f64, f64)>, // Shortest and longest path (sum of log column terms) from a node to the end of the network
+    num_edges: usize, // Number of column distributions enumerated so far
+}
+
+impl Network {
+    // Edges from a node before column k, None once NETWORK_MAX_EDGES is exceeded
+    fn edges(&mut self, k: usize, node: &Vec<u32>) -> Option<Vec<(f64, Vec<u32>)>> {
+        let mut column_fill = ColumnFill::new(
+            node,
+            self.column_sums[k],
+            &self.log_factorials,
+            NETWORK_MAX_EDGES - self.num_edges,
+        );
+        column_fill.fill(0, self.column_sums[k]);
+        self.num_edges += column_fill.edges.len();
+        if self.num_edges > NETWORK_MAX_EDGES {
+            return None;
+        }
+        Some(column_fill.edges)
+    }
+
+    fn bounds(&mut self, k: usize, node: &Vec<u32>) -> Option<(f64, f64)> {
+        if k == self.column_sums.len() {
+            return Some((0.0, 0.0));
+        }
+        if let Some(node_bounds) = self.bounds.get(&(k, node.clone())) {
+            return Some(*node_bounds);
+        }
+        let mut shortest = f64::INFINITY;
+        let mut longest = f64::NEG_INFINITY;
+        for (term, next_node) in self.edges(k, node)? {
+            let (next_shortest, next_longest) = self.bounds(k + 1, &next_node)?;
+            shortest = shortest.min(term + next_shortest);
+            longest = longest.max(term + next_longest);
+        }
+        self.bounds.insert((k, node.clone()), (shortest, longest));
+        Some((shortest, longest))
+    }
+}
+
+// Fisher-Freeman-Halton exact test of an R x C contingency table using the network algorithm of Mehta & Patel. Tables are built one column at a time and each node carries the distinct probabilities of the partial tables that lead to it. When all completions of a partial table are at most as probable as the observed table their total probability is added in closed form (sum over completions of prod(c_j!/prod(x_ij!)) = M!/prod(r_i!)), when none of them is the partial table is dropped, otherwise it is extended by the next column. Same p-value as fisher.test() in R. Returns None when the network is too large (see NETWORK_MAX_EDGES and NETWORK_MAX_PATHS)
+#[allow(dead_code)]
+pub fn fisher_exact_rxc(table: &[Vec<u32>]) -> Option<f64> {
+    let mut table = remove_empty_rows_columns(table);
+    if table.len() < 2 || table[0].len() < 2 {
+        return Some(1.0);
+    }
+    if table.len() > table[0].len() {
+        // Rows are the smaller dimension so that the nodes are short
+        table = (0..table[0].len())
+            .map(|j| table.iter().map(|row| row[j]).collect())
+            .collect();
+    }
+    let row_sums: Vec<u32> = table.iter().map(|row| row.iter().sum()).collect();
+    let mut column_sums: Vec<u32> = (0..table[0].len())
+        .map(|j| table.iter().map(|row| row[j]).sum())
+        .collect();
+    let total: u32 = row_sums.iter().sum();
+    let log_factorials = log_factorials(total);
+
+    // The probability of a table is exp(constant + sum of the log column terms)
+    let constant: f64 = row_sums
+        .iter()
+        .map(|r| log_factorials[*r as usize])
+        .sum::<f64>()
+        - log_factorials[total as usize];
+    let observed: f64 = (0..column_sums.len())
+        .map(|j| {
+            log_factorials[column_sums[j] as usize]
+                - table
+                    .iter()
+                    .map(|row| log_factorials[row[j] as usize])
+                    .sum::<f64>()
+        })
+        .sum();
+    let tolerance: f64 = 1e-7; // Tables within this relative difference of the observed probability are counted as equally probable, as in R
+    column_sums.sort_by(|a, b| b.cmp(a));
+    let num_columns = column_sums.len();
+    let mut network = Network {
+        column_sums,
+        log_factorials,
+        bounds: HashMap::new(),
+        num_edges: 0,
+    };
+
+    let mut start_node = row_sums.clone();
+    start_node.sort();
+    let mut start_paths = HashMap::<i64, (f64, f64)>::new(); // Past log probability and number of partial tables, keyed by the rounded past log probability
+    start_paths.insert(0, (0.0, 1.0));
+    let mut nodes = HashMap::<Vec<u32>, HashMap<i64, (f64, f64)>>::new();
+    nodes.insert(start_node, start_paths);
+
+    let mut p_value: f64 = 0.0;
+    for k in 0..num_columns {
+        let mut next_nodes = HashMap::<Vec<u32>, HashMap<i64, (f64, f64)>>::new();
+        let mut num_paths: usize = 0;
+        for (node, paths) in nodes {
+            let (shortest, longest) = network.bounds(k, &node)?;
+            if network.bounds.len() > NETWORK_MAX_PATHS {
+                return None;
+            }
+            let remaining: u32 = node.iter().sum();
+            let log_completions: f64 = network.log_factorials[remaining as usize]
+                - node
+                    .iter()
+                    .map(|r| network.log_factorials[*r as usize])
+                    .sum::<f64>();
+            let mut edges = Option::<Vec<(f64, Vec<u32>)>>::None;
+            for (past, count) in paths.values() {
+                if past + longest <= observed + tolerance {
+                    p_value += count * (constant + past + log_completions).exp();
+                } else if past + shortest > observed + tolerance {
+                    continue;
+                } else {
+                    if edges.is_none() {
+                        edges = Some(network.edges(k, &node)?);
+                    }
+                    for (term, next_node) in edges.as_ref().unwrap() {
+                        let next_past = past + term;
+                        let next_paths = next_nodes.entry(next_node.clone()).or_default();
+                        let path = next_paths
+                            .entry((next_past * 1e9).round() as i64)
+                            .or_insert_with(|| {
+                                num_paths += 1;
+                                (next_past, 0.0)
+                            });
+                        path.1 += count;
+                    }
+                    if num_paths > NETWORK_MAX_PATHS {
+                        return None;
+                    }
+                }
+            }
+        }
+        nodes = next_nodes;
+    }
+    Some(p_value.min(1.0))
+}
+
+// Monte Carlo p-value of the Fisher-Freeman-Halton test, same as fisher.test(simulate.p.value = TRUE, B = replicates) in R. Random tables with the margins of the observed table are drawn by shuffling the row labels of all observations and dealing them out to the columns
+#[allow(dead_code)]
+pub fn fisher_montecarlo_rxc(table: &[Vec<u32>], replicates: usize, seed: u64) -> f64 {
+    let table = remove_empty_rows_columns(table);
+    if table.len() < 2 || table[0].len() < 2 {
+        return 1.0;
+    }
+    let column_sums: Vec<u32> = (0..table[0].len())
+        .map(|j| table.iter().map(|row| row[j]).sum())
+        .collect();
+    let mut labels = Vec::<usize>::new();
+    for (i, row) in table.iter().enumerate() {
+        for _ in 0..row.iter().sum::<u32>() {
+            labels.push(i);
+        }
+    }
+    let log_factorials = log_factorials(labels.len() as u32);
+    // Tables are ranked by -sum(log(x_ij!)), which orders them the same way as their probabilities
+    let observed: f64 = -table
+        .iter()
+        .flatten()
+        .map(|x| log_factorials[*x as usize])
+        .sum::<f64>();
+
+    // xorshift64* generator, seeded through splitmix64 so that nearby seeds give unrelated streams
+    let mut state: u64 = seed.wrapping_add(0x9e3779b97f4a7c15);
+    state = (state ^ (state >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    state = (state ^ (state >> 27)).wrapping_mul(0x94d049bb133111eb);
+    state = (state ^ (state >> 31)) | 1;
+    let mut random = move |n: usize| -> usize {
+        state ^= state >> 12;
+        state ^= state << 25;
+        state ^= state >> 27;
+        let x = state.wrapping_mul(0x2545f4914f6cdd1d);
+        ((x >> 11) as f64 / (1u64 << 53) as f64 * n as f64) as usize
+    };
+
+    let mut as_extreme: usize = 0;
+    let mut counts = vec![0u32; table.len()];
+    for _ in 0..replicates {
+        for i in (1..labels.len()).rev() {
+            labels.swap(i, random(i + 1));
+        }
+        let mut statistic: f64 = 0.0;
+        let mut position: usize = 0;
+        for column_sum in &column_sums {
+            counts.iter_mut().for_each(|x| *x = 0);
+            for label in &labels[position..position + *column_sum as usize] {
+                counts[*label] += 1;
+            }
+            position += *column_sum as usize;
+            statistic -= counts
+                .iter()
+                .map(|x| log_factorials[*x as usize])
+                .sum::<f64>();
+        }
+        if statistic <= observed + 1e-7 {
+            as_extreme += 1;
+        }
+    }
+    (1 + as_extreme) as f64 / (1 + replicates) as f64
+}
+
+// Pearson chi-square statistic of an R x C contingency table from its expected values
+#[allow(dead_code)]
+pub fn chi_square_statistic_rxc(table: &[Vec<u32>], expected_values: &[Vec<f64>]) -> f64 {
+    let mut chi_sq: f64 = 0.0;
+    for (row, expected_row) in table.iter().zip(expected_values) {
+        for (x, expected) in row.iter().zip(expected_row) {
+            chi_sq += (*x as f64 - expected) * (*x as f64 - expected) / expected;
+        }
+    }
+    chi_sq
+}
+
+// Pearson chi-square test of an R x C contingency table from its expected values, with (R - 1) * (C - 1) degrees of freedom
+#[allow(dead_code)]
+pub fn chi_square_test_rxc(table: &[Vec<u32>], expected_values: &[Vec<f64>]) -> f64 {
+    let chi_sq = chi_square_statistic_rxc(table, expected_values);
+    let degrees_of_freedom = ((table.len() - 1) * (table[0].len() - 1)) as f64;
+    let chi_sq_dist = ChiSquared::new(degrees_of_freedom).unwrap();
+    1.0 - chi_sq_dist.cdf(chi_sq)
+}
+
+// Distribution of the top left cell of a 2x2 table with fixed margins given the odds ratio (Fisher's noncentral hypergeometric distribution), as in fisher.test() in R
+struct NoncentralHypergeometric {
+    support: Vec<f64>,       // Possible values of the top left cell
+    log_densities: Vec<f64>, // Log densities of the (central) hypergeometric distribution over the support
+}
+
+impl NoncentralHypergeometric {
+    // Table [[n1, n2], [n3, n4]]
+    fn new(n1: u32, n2: u32, n3: u32, n4: u32) -> NoncentralHypergeometric {
+        let m = n1 + n3; // First column sum
+        let n = n2 + n4; // Second column sum
+        let k = n1 + n2; // First row sum
+        let log_factorials = log_factorials(m + n);
+        let log_choose = |a: u32, b: u32| {
+            log_factorials[a as usize]
+                - log_factorials[b as usize]
+                - log_factorials[(a - b) as usize]
+        };
+        let low = k.saturating_sub(n);
+        let high = std::cmp::min(k, m);
+        NoncentralHypergeometric {
+            support: (low..=high).map(|x| x as f64).collect(),
+            log_densities: (low..=high)
+                .map(|x| log_choose(m, x) + log_choose(n, k - x) - log_choose(m + n, k))
+                .collect(),
+        }
+    }
+
+    fn low(&self) -> f64 {
+        self.support[0]
+    }
+
+    fn high(&self) -> f64 {
+        self.support[self.support.len() - 1]
+    }
+
+    fn densities(&self, odds_ratio: f64) -> Vec<f64> {
+        if odds_ratio == 0.0 || odds_ratio.is_infinite() {
+            // All of the probability is at the lowest or highest value
+            let mut densities = vec![0.0; self.support.len()];
+            let index = if odds_ratio == 0.0 {
+                0
+            } else {
+                self.support.len() - 1
+            };
+            densities[index] = 1.0;
+            return densities;
+        }
+        let log_densities: Vec<f64> = self
+            .log_densities
+            .iter()
+            .zip(&self.support)
+            .map(|(log_density, x)| log_density + odds_ratio.ln() * x)
+            .collect();
+        let max_log_density = log_densities
+            .iter()
+            .cloned()
+            .fold(f64::NEG_INFINITY, f64::max);
+        let densities: Vec<f64> = log_densities
+            .iter()
+            .map(|log_density| (log_density - max_log_density).exp())
+            .collect();
+        let sum: f64 = densities.iter().sum();
+        densities.iter().map(|density| density / sum).collect()
+    }
+
+    fn mean(&self, odds_ratio: f64) -> f64 {
+        self.densities(odds_ratio)
+            .iter()
+            .zip(&self.support)
+            .map(|(density, x)| density * x)
+            .sum()
+    }
+
+    // P(X <= x), or P(X >= x) when upper_tail is true
+    fn cdf(&self, x: f64, odds_ratio: f64, upper_tail: bool) -> f64 {
+        self.densities(odds_ratio)
+            .iter()
+            .zip(&self.support)
+            .filter(|(_density, value)| {
+                if upper_tail {
+                    **value >= x
+                } else {
+                    **value <= x
+                }
+            })
+            .map(|(density, _value)| density)
+            .sum()
+    }
+}
+
+// Root of a monotone function over [low, high] by bisection, f(low) and f(high) having opposite signs
+fn bisect(f: &dyn Fn(f64) -> f64, mut low: f64, mut high: f64) -> f64 {
+    let f_low_negative = f(low) < 0.0;
+    for _ in 0..200 {
+        let mid = (low + high) / 2.0;
+        if (f(mid) < 0.0) == f_low_negative {
+            low = mid;
+        } else {
+            high = mid;
+        }
+        if high - low < 1e-14 {
+            break;
+        }
+    }
+    (low + high) / 2.0
+}
+
+// Root of f(odds_ratio) over (0, Inf), searched over [0, 1] when f(1) has the sign of f at infinity, otherwise over 1/t for t in (0, 1] (as fisher.test() in R)
+fn odds_ratio_root(f: &dyn Fn(f64) -> f64, below_one: bool) -> f64 {
+    if below_one {
+        bisect(f, 0.0, 1.0)
+    } else {
+        1.0 / bisect(&|t: f64| f(1.0 / t), f64::EPSILON, 1.0)
+    }
+}
+
+// Conditional maximum likelihood estimate of the odds ratio of the 2x2 table [[n1, n2], [n3, n4]] and its exact confidence interval, same as fisher.test()$estimate and $conf.int in R. R stops its root search at a tolerance of about 1e-4, so its values can differ from these in the fourth significant digit. Values can be infinite
+#[allow(dead_code)]
+pub fn conditional_odds_ratio(
+    n1: u32,
+    n2: u32,
+    n3: u32,
+    n4: u32,
+    confidence: f64,
+) -> (f64, f64, f64) {
+    let distribution = NoncentralHypergeometric::new(n1, n2, n3, n4);
+    let x = n1 as f64;
+    let low = distribution.low();
+    let high = distribution.high();
+    let mean = distribution.mean(1.0);
+    let estimate = if x == low {
+        0.0
+    } else if x == high {
+        f64::INFINITY
+    } else if mean == x {
+        1.0
+    } else {
+        odds_ratio_root(&|odds_ratio| distribution.mean(odds_ratio) - x, mean > x)
+    };
+
+    let alpha = (1.0 - confidence) / 2.0;
+    let lower = if x == low {
+        0.0
+    } else {
+        let p = distribution.cdf(x, 1.0, true);
+        if p == alpha {
+            1.0
+        } else {
+            odds_ratio_root(
+                &|odds_ratio| distribution.cdf(x, odds_ratio, true) - alpha,
+                p > alpha,
+            )
+        }
+    };
+    let upper = if x == high {
+        f64::INFINITY
+    } else {
+        let p = distribution.cdf(x, 1.0, false);
+        if p == alpha {
+            1.0
+        } else {
+            odds_ratio_root(
+                &|odds_ratio| distribution.cdf(x, odds_ratio, false) - alpha,
+                p < alpha,
+            )
+        }
+    };
+    (estimate, lower, upper)
+}
+
+// Wilson score interval of a binomial proportion
+fn wilson_interval(successes: u32, trials: u32, z: f64) -> (f64, f64) {
+    let n = trials as f64;
+    let p = successes as f64 / n;
+    let center = p + z * z / (2.0 * n);
+    let half_width = z * (p * (1.0 - p) / n + z * z / (4.0 * n * n)).sqrt();
+    let denominator = 1.0 + z * z / n;
+    (
+        (center - half_width) / denominator,
+        (center + half_width) / denominator,
+    )
+}
+
+// Relative risk of the 2x2 table [[n1, n2], [n3, n4]] (risk of the first column in the first row over the second row) with its Wald confidence interval on the log scale. None when a row is empty or a risk is zero
+#[allow(dead_code)]
+pub fn relative_risk(
+    n1: u32,
+    n2: u32,
+    n3: u32,
+    n4: u32,
+    confidence: f64,
+) -> Option<(f64, f64, f64)> {
+    if n1 == 0 || n3 == 0 {
+        return None;
+    }
+    let (a, b, c, d) = (n1 as f64, n2 as f64, n3 as f64, n4 as f64);
+    let estimate = (a / (a + b)) / (c / (c + d));
+    let z = Normal::new(0.0, 1.0)
+        .unwrap()
+        .inverse_cdf(1.0 - (1.0 - confidence) / 2.0);
+    let standard_error = (1.0 / a - 1.0 / (a + b) + 1.0 / c - 1.0 / (c + d)).sqrt();
+    Some((
+        estimate,
+        (estimate.ln() - z * standard_error).exp(),
+        (estimate.ln() + z * standard_error).exp(),
+    ))
+}
+
+// Confidence interval of the risk difference
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RiskDifferenceMethod {
+    Newcombe, // Newcombe hybrid score interval (method 10 of Newcombe 1998), which stays within [-1, 1] and is usable for risks of 0 or 1
+    Wald, // Wald interval p1 - p2 +/- z * sqrt(p1 * (1 - p1) / (n1 + n2) + p2 * (1 - p2) / (n3 + n4)), which may exceed [-1, 1] and has zero width when both risks are 0 or 1
+}
+
+impl RiskDifferenceMethod {
+    // Parses the value of the risk_difference_method option
+    #[allow(dead_code)]
+    pub fn from_option(method: &str) -> Result<RiskDifferenceMethod, String> {
+        match method {
+            "newcombe" => Ok(RiskDifferenceMethod::Newcombe),
+            "wald" => Ok(RiskDifferenceMethod::Wald),
+            _ => Err(format!(
+                "Unrecognized risk_difference_method option {}, it should be one of newcombe, wald",
+                method
+            )),
+        }
+    }
+
+    // Name of the method in the output
+    #[allow(dead_code)]
+    pub fn name(&self) -> &'static str {
+        match self {
+            RiskDifferenceMethod::Newcombe => "newcombe",
+            RiskDifferenceMethod::Wald => "wald",
+        }
+    }
+}
+
+// Risk difference of the 2x2 table [[n1, n2], [n3, n4]] (risk of the first column in the first row minus the second row) with its confidence interval computed by the given method. None when a row is empty
+#[allow(dead_code)]
+pub fn risk_difference(
+    n1: u32,
+    n2: u32,
+    n3: u32,
+    n4: u32,
+    confidence: f64,
+    method: RiskDifferenceMethod,
+) -> Option<(f64, f64, f64)> {
+    if n1 + n2 == 0 || n3 + n4 == 0 {
+        return None;
+    }
+    let p1 = n1 as f64 / (n1 + n2) as f64;
+    let p2 = n3 as f64 / (n3 + n4) as f64;
+    let z = Normal::new(0.0, 1.0)
+        .unwrap()
+        .inverse_cdf(1.0 - (1.0 - confidence) / 2.0);
+    let estimate = p1 - p2;
+    match method {
+        RiskDifferenceMethod::Newcombe => {
+            let (lower1, upper1) = wilson_interval(n1, n1 + n2, z);
+            let (lower2, upper2) = wilson_interval(n3, n3 + n4, z);
+            Some((
+                estimate,
+                estimate - ((p1 - lower1).powi(2) + (upper2 - p2).powi(2)).sqrt(),
+                estimate + ((upper1 - p1).powi(2) + (p2 - lower2).powi(2)).sqrt(),
+            ))
+        }
+        RiskDifferenceMethod::Wald => {
+            let standard_error =
+                (p1 * (1.0 - p1) / (n1 + n2) as f64 + p2 * (1.0 - p2) / (n3 + n4) as f64).sqrt();
+            Some((
+                estimate,
+                estimate - z * standard_error,
+                estimate + z * standard_error,
+            ))
+        }
+    }
+}
+
+// Cochran-Mantel-Haenszel test of 2x2 strata [n1, n2, n3, n4] (each the table [[n1, n2], [n3, n4]]) with continuity correction, the Mantel-Haenszel common odds ratio and its Robins-Breslow-Greenland confidence interval. Same as mantelhaen.test() in R, except that strata with less than 2 observations are skipped instead of being an error. Returns the p-value, odds ratio, lower and upper confidence limits, None when no stratum has a variance (e.g. every stratum has an empty row or column)
+#[allow(dead_code)]
+pub fn cochran_mantel_haenszel_test(
+    strata: &Vec<[u32; 4]>,
+    confidence: f64,
+) -> Option<(f64, f64, f64, f64)> {
+    let mut delta: f64 = 0.0; // Sum of observed - expected n1
+    let mut variance: f64 = 0.0;
+    let mut sum_diagonal: f64 = 0.0; // R
+    let mut sum_off_diagonal: f64 = 0.0; // S
+    let mut sum_pr: f64 = 0.0;
+    let mut sum_ps_qr: f64 = 0.0;
+    let mut sum_qs: f64 = 0.0;
+    for stratum in strata {
+        let [a, b, c, d] = stratum.map(|x| x as f64);
+        let t = a + b + c + d;
+        if t < 2.0 {
+            continue;
+        }
+        let row1 = a + b;
+        let column1 = a + c;
+        delta += a - row1 * column1 / t;
+        variance += row1 * column1 * (t - row1) * (t - column1) / (t * t * (t - 1.0));
+        let p = (a + d) / t;
+        let q = (b + c) / t;
+        let r = a * d / t;
+        let s = b * c / t;
+        sum_diagonal += r;
+        sum_off_diagonal += s;
+        sum_pr += p * r;
+        sum_ps_qr += p * s + q * r;
+        sum_qs += q * s;
+    }
+    if variance == 0.0 {
+        return None;
+    }
+    let yates = if delta.abs() >= 0.5 { 0.5 } else { 0.0 };
+    let statistic = (delta.abs() - yates).powi(2) / variance;
+    let p_value = 1.0 - ChiSquared::new(1.0).unwrap().cdf(statistic);
+
+    let odds_ratio = sum_diagonal / sum_off_diagonal;
+    let standard_error = (sum_pr / (2.0 * sum_diagonal * sum_diagonal)
+        + sum_ps_qr / (2.0 * sum_diagonal * sum_off_diagonal)
+        + sum_qs / (2.0 * sum_off_diagonal * sum_off_diagonal))
+        .sqrt();
+    let z = Normal::new(0.0, 1.0)
+        .unwrap()
+        .inverse_cdf(1.0 - (1.0 - confidence) / 2.0);
+    Some((
+        p_value,
+        odds_ratio,
+        odds_ratio * (-z * standard_error).exp(),
+        odds_ratio * (z * standard_error).exp(),
+    ))
+}
+
+// Breslow-Day test of homogeneity of the odds ratios of 2x2 strata [n1, n2, n3, n4] around a common odds ratio (usually the Mantel-Haenszel estimate), without Tarone's correction. Strata with less than 2 observations or an empty row or column are skipped. None when the common odds ratio is zero or infinite or there are less than 2 strata
+#[allow(dead_code)]
+pub fn breslow_day_test(strata: &Vec<[u32; 4]>, odds_ratio: f64) -> Option<f64> {
+    if !(odds_ratio > 0.0 && odds_ratio.is_finite()) {
+        return None;
+    }
+    let mut statistic: f64 = 0.0;
+    let mut num_strata: usize = 0;
+    for stratum in strata {
+        let [a, b, c, d] = stratum.map(|x| x as f64);
+        let t = a + b + c + d;
+        if t < 2.0 {
+            continue;
+        }
+        let row1 = a + b;
+        let column1 = a + c;
+        // Expected n1 given the margins and the common odds ratio, the root within the possible range of
+        // (1 - OR) x^2 + (t - row1 - column1 + OR * (row1 + column1)) x - OR * row1 * column1 = 0
+        let low = (row1 + column1 - t).max(0.0);
+        let high = row1.min(column1);
+        let quadratic_a = 1.0 - odds_ratio;
+        let quadratic_b = t - row1 - column1 + odds_ratio * (row1 + column1);
+        let quadratic_c = -odds_ratio * row1 * column1;
+        let expected = if quadratic_a.abs() < 1e-12 {
+            -quadratic_c / quadratic_b
+        } else {
+            let root = (quadratic_b * quadratic_b - 4.0 * quadratic_a * quadratic_c).sqrt();
+            let x1 = (-quadratic_b + root) / (2.0 * quadratic_a);
+            let x2 = (-quadratic_b - root) / (2.0 * quadratic_a);
+            if x1 >= low - 1e-9 && x1 <= high + 1e-9 {
+                x1
+            } else {
+                x2
+            }
+        };
+        let variance = 1.0
+            / (1.0 / expected
+                + 1.0 / (row1 - expected)
+                + 1.0 / (column1 - expected)
+                + 1.0 / (t - row1 - column1 + expected));
+        if variance > 0.0 && variance.is_finite() {
+            // Strata with an empty row or column have no variance and do not count
+            statistic += (a - expected).powi(2) / variance;
+            num_strata += 1;
+        }
+    }
+    if num_strata < 2 {
+        return None;
+    }
+    Some(
+        1.0 - ChiSquared::new((num_strata - 1) as f64)
+            .unwrap()
+            .cdf(statistic),
+    )
+}
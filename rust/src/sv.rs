@@ -1,11 +1,34 @@
 // Syntax: cd ~/proteinpaint/rust && cargo build --release && json='[{"refseq":"GATGAGAAAACGGAATCACGCCGGGCGTGGGTGGCTCATGTCTGTAATCCCAGCACTTTGGGAGGCCAACAGGAGAATGGATCACTTGAGGCCAGGAGTTCAAGACCAGCCTGGCCAACATGGTGAAACCTTGTCTCCACTGAAAAAAAAAAAAAAATTAGCTAGGTATGGTGGCATGCGCCTGTAATCCAAGCTACTCGGGAGGCTGAGGCAGGAGGTGGAGGTTGCAGTGAGCTGAGATTACGCCACTGCACTCCAGCCTGGGCGACAGAGCGAGACTCTGTTTCAAAAAAAAATAAAGAAAACGAAGTTTCCACACCAACCATGAGAGTGGTGATAGGAATGAAAAAGGCCACCCGACCTCCCTCTGCTGGCCTCCCCGGCAGCACGCACCTGTCTCTGCAGTTGCCTCCTCTTCTCCTCATTCTGCTCGTCCCGGGCTTGGAGATCCCTTTCGAACTGGCCCTTGAGCGCCTGCATGTTGACTTCCAGCCGCAGTTTGGCGTCCTCCGTGGCTTGCAGCTCGTCCTCCAGCTCTTCCAGCTGCGTCTTCATCTCCTCCATCTGGGTCTCCAGGGCCCGCTTGGACTTCTCCAGCTCATGGACCTGCCGGCAGAGCGGGCAGCCCCATTCTATGAGGCTCAACTTCATGAAGACGATTGAGAAACCCACCGTGAGCGGCACCTCAGGAGATCAGGGAGGTGGCTTTGGCCTCCCACAGGATGCATGGCCGGGACTCAAGATGACCCCTGAGAGTTCAGACCCCAGCCTTATCCTCGGACCCCCCAACTCAGACCCATCCTCGACTGCCATTCTCAGCCCCTCCCAGCCCCTGCACCAGTCCAAAAACCTCCTTCCATTTCCGATGATAGTTCGCTATGAAAAAGGCCAGGAGCTAGCCTCGCATGGACTGGTGAATAGCACAGAGGGTGGGCAGGCGAAACATGGACGAGAAAAACCACCCAGAGCCACTTACGTTCTTGCCCACGTCATCCTTGGAGCTGACCAGGTCTTCCATTTCGGCTTTGAGCATTTTGTTGGTCCGCTCGAGTTCCTCTTTGGCTTCCAAGGCCTCTTCAAGGGCCCGAGCCAGGGACAGGGCCTTGGTTTCCTTCTCCCTGGCTTCTGCCTCAGCTCTGTCCCTCTCATCCGCGTATTTGGAAGAGATGTTTTTCTCCTCGGCTAACAACTACAACACAAGACCCAGAGGTGACTTCTAGGCATATCCGGGGTCAGCGTCACTGAATTGTAAATACCGGGGGAAGCCCTGTGTCCTGCTGAATGTATTGAGGTGCAGGTGTAAGCAGTGTAGGTTAGCTATGGGAGTAATTTATATAATCATCTGGCGTTCTGACTTCTACATCAACCTTATGCAGAGCCAGAAATCAGCTGACTTGTTTTTTTTTGTTGGTTTGTGTGTTTGTTTGTTTTAGATGGAGTCTCACTTTGTCACCCAGGCTGGAGTGCAATGGTGCAGTCTTGGTTCACTGCAACCTCCGCCTCGTGGGTTCAAGCGATTCTCCTGCCTCAGCCTCCAGAGTAGTTGGGATTACAGGTGCCCACCACCACACCTGGCTAATTTTTGTATTTTAAGTAGAGATGTGGTTTTGCCATGTTGACCAGGCTGGTCTCGAACTCCTGACCCTCAAGTGAGCCTCCTGCCTCGGCCTCCCAAAGCACGGAGATTACAGGTGTGAGCCACCACACCTGGCCAAATCCTTGTGCTTCGAAACCACTAGACCTGCCCACGTTGCTTAGAGCACACTGAGGTCAAAGTGAGATTTAAAGCTGCCAGAAGCTAGTTATTAGTTTGTTT", "start": 235392047, "stop": 235393047,"chr":"chr16","pos":67116400,"entries":[{"sam_info":"A00567:64:HKTFJDSXX:4:2445:10818:14121\t163\t16\t15814153\t60\t151M\t=\t15814512\t510\tGTCTCATACTCGTGAAGCTGGGCGAGGAATAGAGATGTGTGCTGCCCCACTTGCCCCTGGGAGGTCCTTTGGCTCACCTAGGCAGCACATCACTGCACCCCTTCCCCAGCACAGCCCCCTTGTGAGGTGGGCATCTCATCCCCAGTTGCAG\tFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF:FFFFF:FFFFFFFFFFFFF:FFFFFFFFFFFFFFFFFFF:FFFFFFF:FF\tX0:i:1\tX1:i:0\tMD:Z:151\tPG:Z:MarkDuplicates\tRG:Z:r1626484\tXG:i:0\tAM:i:37\tNM:i:0\tSM:i:37\tXM:i:0\tXO:i:0\tXT:A:U","tempscore":"","ridx":0},{"sam_info":"A00647:38:HL3LGDSXX:1:2463:15076:9846\t99\t16\t15814153\t60\t151M\t=\t15814386\t384\tGTCTCATACTCGTGAAGCTGGGCGAGGAATAGAGATGTGTGCTGCCCCACTTGCCCCTGGGAGGTCCTTTGGCTCACCTAGGCAGCACATCACTGCACCCCTTCCCCAGCACAGCCCCCTTGTGAGGTGGGCATCTCATCCCCAGTTGCAG\tFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF:FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF:FFF,FFFFFFFFFFFF\tX0:i:1\tX1:i:0\tMD:Z:151\tPG:Z:MarkDuplicates\tRG:Z:r1626506\tXG:i:0\tAM:i:37\tNM:i:0\tSM:i:37\tXM:i:0\tXO:i:0\tXT:A:U","tempscore":"","ridx":0},{"sam_info":"A00647:38:HL3LGDSXX:2:1401:7166:23171\t99\t16\t15814157\t60\t151M\t=\t15814297\t291\tCATACTCGTGAAGCTGGGCGAGGAATAGAGATGTGTGCTGCCCCACTTGCCCCTGGGAGGTCCTTTGGCTCACCTAGGCAGCACATCACTGCACCCCTTCCCCAGCACAGCCCCCTTGTGAGGTGGGCATCTCATCCCCAGTTGCAGATGA\tFFFFF:FFFFFFFFFFFFFFFFFFFFFFF::FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF,FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF:FFFFFFFFFFF\tX0:i:1\tX1:i:0\tMD:Z:151\tPG:Z:MarkDuplicates\tRG:Z:r1626508\tXG:i:0\tAM:i:37\tNM:i:0\tSM:i:37\tXM:i:0\tXO:i:0\tXT:A:U","tempscore":"","ridx":0}]}, {"refseq":"GATGAGAAAACGGAATCACGCCGGGCGTGGGTGGCTCATGTCTGTAATCCCAGCACTTTGGGAGGCCAACAGGAGAATGGATCACTTGAGGCCAGGAGTTCAAGACCAGCCTGGCCAACATGGTGAAACCTTGTCTCCACTGAAAAAAAAAAAAAAATTAGCTAGGTATGGTGGCATGCGCCTGTAATCCAAGCTACTCGGGAGGCTGAGGCAGGAGGTGGAGGTTGCAGTGAGCTGAGATTACGCCACTGCACTCCAGCCTGGGCGACAGAGCGAGACTCTGTTTCAAAAAAAAATAAAGAAAACGAAGTTTCCACACCAACCATGAGAGTGGTGATAGGAATGAAAAAGGCCACCCGACCTCCCTCTGCTGGCCTCCCCGGCAGCACGCACCTGTCTCTGCAGTTGCCTCCTCTTCTCCTCATTCTGCTCGTCCCGGGCTTGGAGATCCCTTTCGAACTGGCCCTTGAGCGCCTGCATGTTGACTTCCAGCCGCAGTTTGGCGTCCTCCGTGGCTTGCAGCTCGTCCTCCAGCTCTTCCAGCTGCGTCTTCATCTCCTCCATCTGGGTCTCCAGGGCCCGCTTGGACTTCTCCAGCTCATGGACCTGCCGGCAGAGCGGGCAGCCCCATTCTATGAGGCTCAACTTCATGAAGACGATTGAGAAACCCACCGTGAGCGGCACCTCAGGAGATCAGGGAGGTGGCTTTGGCCTCCCACAGGATGCATGGCCGGGACTCAAGATGACCCCTGAGAGTTCAGACCCCAGCCTTATCCTCGGACCCCCCAACTCAGACCCATCCTCGACTGCCATTCTCAGCCCCTCCCAGCCCCTGCACCAGTCCAAAAACCTCCTTCCATTTCCGATGATAGTTCGCTATGAAAAAGGCCAGGAGCTAGCCTCGCATGGACTGGTGAATAGCACAGAGGGTGGGCAGGCGAAACATGGACGAGAAAAACCACCCAGAGCCACTTACGTTCTTGCCCACGTCATCCTTGGAGCTGACCAGGTCTTCCATTTCGGCTTTGAGCATTTTGTTGGTCCGCTCGAGTTCCTCTTTGGCTTCCAAGGCCTCTTCAAGGGCCCGAGCCAGGGACAGGGCCTTGGTTTCCTTCTCCCTGGCTTCTGCCTCAGCTCTGTCCCTCTCATCCGCGTATTTGGAAGAGATGTTTTTCTCCTCGGCTAACAACTACAACACAAGACCCAGAGGTGACTTCTAGGCATATCCGGGGTCAGCGTCACTGAATTGTAAATACCGGGGGAAGCCCTGTGTCCTGCTGAATGTATTGAGGTGCAGGTGTAAGCAGTGTAGGTTAGCTATGGGAGTAATTTATATAATCATCTGGCGTTCTGACTTCTACATCAACCTTATGCAGAGCCAGAAATCAGCTGACTTGTTTTTTTTTGTTGGTTTGTGTGTTTGTTTGTTTTAGATGGAGTCTCACTTTGTCACCCAGGCTGGAGTGCAATGGTGCAGTCTTGGTTCACTGCAACCTCCGCCTCGTGGGTTCAAGCGATTCTCCTGCCTCAGCCTCCAGAGTAGTTGGGATTACAGGTGCCCACCACCACACCTGGCTAATTTTTGTATTTTAAGTAGAGATGTGGTTTTGCCATGTTGACCAGGCTGGTCTCGAACTCCTGACCCTCAAGTGAGCCTCCTGCCTCGGCCTCCCAAAGCACGGAGATTACAGGTGTGAGCCACCACACCTGGCCAAATCCTTGTGCTTCGAAACCACTAGACCTGCCCACGTTGCTTAGAGCACACTGAGGTCAAAGTGAGATTTAAAGCTGCCAGAAGCTAGTTATTAGTTTGTTT","start": 235657639,"stop":235658639,"chr":"chr16","pos":15814303,"entries":[{"sam_info":"A00567:64:HKTFJDSXX:4:2445:10818:14121\t163\t16\t15814153\t60\t151M\t=\t15814512\t510\tGTCTCATACTCGTGAAGCTGGGCGAGGAATAGAGATGTGTGCTGCCCCACTTGCCCCTGGGAGGTCCTTTGGCTCACCTAGGCAGCACATCACTGCACCCCTTCCCCAGCACAGCCCCCTTGTGAGGTGGGCATCTCATCCCCAGTTGCAG\tFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF:FFFFF:FFFFFFFFFFFFF:FFFFFFFFFFFFFFFFFFF:FFFFFFF:FF\tX0:i:1\tX1:i:0\tMD:Z:151\tPG:Z:MarkDuplicates\tRG:Z:r1626484\tXG:i:0\tAM:i:37\tNM:i:0\tSM:i:37\tXM:i:0\tXO:i:0\tXT:A:U","tempscore":"","ridx":0},{"sam_info":"A00647:38:HL3LGDSXX:1:2463:15076:9846\t99\t16\t15814153\t60\t151M\t=\t15814386\t384\tGTCTCATACTCGTGAAGCTGGGCGAGGAATAGAGATGTGTGCTGCCCCACTTGCCCCTGGGAGGTCCTTTGGCTCACCTAGGCAGCACATCACTGCACCCCTTCCCCAGCACAGCCCCCTTGTGAGGTGGGCATCTCATCCCCAGTTGCAG\tFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF:FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF:FFF,FFFFFFFFFFFF\tX0:i:1\tX1:i:0\tMD:Z:151\tPG:Z:MarkDuplicates\tRG:Z:r1626506\tXG:i:0\tAM:i:37\tNM:i:0\tSM:i:37\tXM:i:0\tXO:i:0\tXT:A:U","tempscore":"","ridx":0},{"sam_info":"A00647:38:HL3LGDSXX:2:1401:7166:23171\t99\t16\t15814157\t60\t151M\t=\t15814297\t291\tCATACTCGTGAAGCTGGGCGAGGAATAGAGATGTGTGCTGCCCCACTTGCCCCTGGGAGGTCCTTTGGCTCACCTAGGCAGCACATCACTGCACCCCTTCCCCAGCACAGCCCCCTTGTGAGGTGGGCATCTCATCCCCAGTTGCAGATGA\tFFFFF:FFFFFFFFFFFFFFFFFFFFFFF::FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF,FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF:FFFFFFFFFFF\tX0:i:1\tX1:i:0\tMD:Z:151\tPG:Z:MarkDuplicates\tRG:Z:r1626508\tXG:i:0\tAM:i:37\tNM:i:0\tSM:i:37\tXM:i:0\tXO:i:0\tXT:A:U","tempscore":"","ridx":0}]}]' && time echo "$json" | target/release/sv
 
+// Input JSON:
+//   [{ refseq, chr, start, stop, pos, entries: [{ sam_info }] }] One entry per break point region (pos is the break point, sam_info is the SAM line of each read in the region)
+//   Alternatively { regions, junctions } where regions is the array above and junctions is an array of pairs of region indices joined by the SV/fusion e.g [[0,1],[1,2]] for a three-way fusion. By default each region is joined to the next region
+//
+// Output:
+//   single_region_templates: [{ sam_info, ridx }] Reads containing a break point whose template does not support any junction
+//   multi_region_templates: [{ sam_info, ridx, junction }] Reads of templates supporting a junction (reads in both regions of the junction, or discordant mates)
+//   fragment_counts: { supporting_sv, supporting_ref } Number of templates in multi_region_templates and single_region_templates
+//   breakpoints: [{ region, pos, original_pos, side, clipped_reads, consensus }] Break point of each region refined using soft-clipped reads within breakpoint_window (optional, default 10) of pos, and the consensus of the soft-clipped sequences
+//   junctions: [{ regions, split_reads, spanning_pairs, microhomology, inserted_sequence }] Number of split reads and spanning read pairs supporting each junction, and the microhomology/inserted sequence at the junction (when determined from the soft-clipped consensus)
+//   fusion_junctions: [{ junction, regions, exon_pairs: [{ exons, positions, junction_reads }], junction_reads, spanning_fragments }] Only for RNA fusions, when exons are given for both regions of a junction (see below)
+//   sv_events: [{ junction, breakends: [{ region, chr, pos, orientation, ref_reads, alt_reads, vaf, ci_lower, ci_upper, consistent_reads, inconsistent_reads }], split_reads, discordant_pairs, orientation_consistent }] Genotyping summary of each junction (see below)
+//
+// SV events: for each break point (breakend) of a junction, ref_reads is the number of templates containing the break point that do not support any junction and are not soft-clipped at the break point, alt_reads is the number of templates supporting the junction (split reads and discordant pairs) plus templates soft-clipped at the break point. vaf = alt_reads / (alt_reads + ref_reads) with a 95% Clopper-Pearson confidence interval. orientation is "+" when the junction lies to the right of the retained sequence of the region and "-" when it lies to the left (from the side of the soft clips at the refined break point, otherwise from the majority of supporting reads). Discordant mates are consistent when they point towards the junction (forward strand for "+"), split reads are consistent when they are clipped on the junction side. orientation_consistent is true when at least 90% of the supporting reads at both breakends are consistent
+// When vcf_file is given (sample_name is optional), both breakends of each junction are also written as VCF BND records with SR/PE in INFO and AD/DP in FORMAT
+//
+// RNA fusion mode: each region may have exons: [{ start, stop }] (1-based, inclusive, in transcript order) of the partner gene in that region. Spliced reads (N in the CIGAR), soft-clipped reads and split reads whose aligned blocks end at an exon boundary of one partner gene and continue at an exon boundary of the other partner gene are counted as junction reads for that exon pair (exon numbers are the 1-based index in exons). Templates whose mates lie in exons of both partner genes without any read crossing the junction are counted as spanning fragments. exon_boundary_window (optional, default 3) is the maximum distance between a read block boundary and an exon boundary
+
 use json::JsonValue;
 use serde::{Deserialize, Serialize};
 use serde_json;
-//use std::cmp::Ordering;
+use std::cmp;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::collections::HashSet;
 pub mod realign;
 use std::io; // Import functions from realign.rs
+mod stats_functions; // Imports functions from stats_functions.rs
+mod vcf; // Imports functions from vcf.rs
 
 #[derive(Debug, Serialize, Deserialize)]
 struct ReadInfo {
@@ -16,6 +39,14 @@ struct ReadInfo {
     flag: i64,
     ridx: usize,
     sam_info: String,
+    mate_chr: String, // Chromosome of the mate (RNEXT, "=" replaced by the chromosome of the read)
+    mate_position: i64, // Position of the mate (PNEXT)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FragmentCounts {
+    supporting_sv: usize, // Number of templates supporting the SV/fusion (split across both regions or discordant mates)
+    supporting_ref: usize, // Number of templates containing a break point but not supporting the SV/fusion
 }
 
 // A separate struct has been created from ReadInfo so as to minimize amount of data piped out of the Rust script
@@ -23,6 +54,80 @@ struct ReadInfo {
 struct OutputReadInfo {
     sam_info: String,
     ridx: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    junction: Option<usize>, // Index of the junction supported by the template (only for multi_region_templates)
+}
+
+// Break point region given by the server
+struct Region {
+    refseq: String, // Reference sequence of the region, the first nucleotide is at position start
+    chr: String,    // Chromosome ("" when not given)
+    start: i64,     // Start of region
+    stop: i64,      // Stop of region
+    pos: i64,       // Break point
+    exons: Vec<(i64, i64)>, // Exons (start, stop) of the fusion partner gene in this region, empty when not given
+    reads: Vec<ReadInfo>,   // All reads in the region
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Breakpoint {
+    region: usize,        // Index of the region
+    pos: i64, // Refined break point (1-based position of the first nucleotide to the right of the junction)
+    original_pos: i64, // Break point given in the input
+    side: Option<String>, // Side of the break point on which the soft-clipped sequence lies ("left" or "right"), None when no read is soft-clipped near the break point
+    clipped_reads: usize, // Number of reads soft-clipped at the refined break point
+    consensus: String,    // Consensus of the soft-clipped sequences
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JunctionSupport {
+    regions: [usize; 2],   // Indices of the two regions joined at this junction
+    split_reads: usize, // Number of templates with a read split across both regions (supplementary alignment)
+    spanning_pairs: usize, // Number of templates whose mates map to both regions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    microhomology: Option<String>, // Nucleotides present on both sides of the junction (on the strand reading from the first into the second region)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    inserted_sequence: Option<String>, // Non-templated nucleotides inserted at the junction
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Breakend {
+    region: usize,             // Index of the region
+    chr: String,               // Chromosome of the region
+    pos: i64,                  // Refined break point
+    orientation: String, // "+" when the junction lies to the right of the retained sequence of the region, "-" when it lies to the left
+    ref_reads: usize,    // Number of templates supporting the reference at this break point
+    alt_reads: usize,    // Number of templates supporting the junction at this break point
+    vaf: f64,            // alt_reads / (alt_reads + ref_reads)
+    ci_lower: f64,       // Lower limit of 95% confidence interval (Clopper-Pearson) of vaf
+    ci_upper: f64,       // Upper limit of 95% confidence interval (Clopper-Pearson) of vaf
+    consistent_reads: usize, // Number of supporting reads in the region whose orientation agrees with the breakend orientation
+    inconsistent_reads: usize, // Number of supporting reads in the region whose orientation disagrees with the breakend orientation
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SvEvent {
+    junction: usize,              // Index of the junction
+    breakends: Vec<Breakend>,     // Both break points of the junction
+    split_reads: usize,           // Number of templates with a read split across both regions
+    discordant_pairs: usize,      // Number of templates whose mates map to both regions
+    orientation_consistent: bool, // Whether at least 90% of the supporting reads at both breakends have the expected orientation
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ExonPairSupport {
+    exons: [usize; 2], // Exon numbers (1-based) of the two partner genes joined in the fusion transcript
+    positions: [i64; 2], // Exon boundaries joined in the fusion transcript
+    junction_reads: usize, // Number of templates with a read crossing this exon-exon junction
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FusionSupport {
+    junction: usize,                  // Index of the junction
+    regions: [usize; 2],              // Indices of the two regions joined at this junction
+    exon_pairs: Vec<ExonPairSupport>, // Exon-exon junctions supported by reads, most supported first
+    junction_reads: usize, // Number of templates with a read crossing an exon-exon junction of the two partner genes
+    spanning_fragments: usize, // Number of templates with mates in exons of both partner genes but no read crossing the junction
 }
 
 fn main() {
@@ -43,129 +148,276 @@ fn main() {
         Ok(json_string) => {
             //println!("Input json:{}", json_string);
 
-            // Assuming there are only two regions for now
-            let region1 = &json_string[0];
-            let region2 = &json_string[1];
-            let _refseq_region1: String = json_string[0]["refseq"].as_str().unwrap().to_string();
-            //println!("region1:{}", region1);
-            let _refseq_region2: String = json_string[1]["refseq"].as_str().unwrap().to_string();
-            //println!("region2:{}", region2);
-            let _chr_region1: String = json_string[0]["chr"].as_str().unwrap().to_string();
-            let _chr_region2: String = json_string[1]["chr"].as_str().unwrap().to_string();
-            let start_region1: i64 = json_string[0]["start"].as_i64().unwrap();
-            let stop_region1: i64 = json_string[0]["stop"].as_i64().unwrap();
-            let _start_region2: i64 = json_string[1]["start"].as_i64().unwrap();
-            let _stop_region2: i64 = json_string[1]["stop"].as_i64().unwrap();
-            let mut pos_region1: i64 = json_string[0]["pos"].as_i64().unwrap();
-            let mut pos_region2: i64 = json_string[1]["pos"].as_i64().unwrap();
-            //println!("pos_region1:{}", pos_region1);
-            //println!("pos_region2:{}", pos_region2);
-
-            // Check if pos_region1 lies between start_region1 and stop_region1. If not exchange values between pos_region1 and pos_region1
-            if start_region1 <= pos_region1 && pos_region1 <= stop_region1 {
+            // The input is either an array of regions, or an object { regions, junctions } where junctions is an array of pairs of region indices e.g [[0,1],[1,2]]. When junctions are not given, each region is assumed to be joined to the next region (for two regions this is the single junction between them)
+            let regions_list: &JsonValue = if json_string.is_array() {
+                &json_string
             } else {
-                let temp_pos: i64 = pos_region1;
-                pos_region1 = pos_region2;
-                pos_region2 = temp_pos;
+                &json_string["regions"]
+            };
+            let mut regions = Vec::<Region>::new();
+            for ridx in 0..regions_list.len() {
+                let region_json = &regions_list[ridx];
+                let mut reads = Vec::<ReadInfo>::new();
+                let reads_region: &JsonValue = &region_json["entries"]; // Parsing each region separately
+                for i in 0..reads_region.len() {
+                    let read_temp: String =
+                        reads_region[i]["sam_info"].as_str().unwrap().to_string();
+                    //println!("read_temp:{:?}", read_temp);
+                    let args: Vec<&str> = read_temp.split('\t').collect();
+                    reads.push(ReadInfo {
+                        qname: args[0].parse::<String>().unwrap(),
+                        seq: args[9].parse::<String>().unwrap(),
+                        cigar: args[5].parse::<String>().unwrap(),
+                        start_position: args[3].parse::<i64>().unwrap(),
+                        flag: args[1].parse::<i64>().unwrap(),
+                        ridx,
+                        mate_chr: mate_chromosome(&args),
+                        mate_position: args[7].parse::<i64>().unwrap_or(0),
+                        sam_info: read_temp,
+                    })
+                }
+                regions.push(Region {
+                    refseq: region_json["refseq"].as_str().unwrap_or("").to_string(),
+                    chr: region_json["chr"].as_str().unwrap_or("").to_string(), // Chromosome is only needed for detecting discordant mates
+                    start: region_json["start"].as_i64().unwrap(),
+                    stop: region_json["stop"].as_i64().unwrap(),
+                    pos: region_json["pos"].as_i64().unwrap(),
+                    exons: region_json["exons"]
+                        .members()
+                        .map(|exon| {
+                            (
+                                exon["start"].as_i64().unwrap_or(0),
+                                exon["stop"].as_i64().unwrap_or(0),
+                            )
+                        })
+                        .collect(),
+                    reads,
+                });
             }
 
-            let reads_region1: &JsonValue = &region1["entries"]; // Parsing each region separately
-            let reads_region2: &JsonValue = &region2["entries"]; // Parsing each region separately
+            // Check if the break point of each region lies between start and stop of that region. If not exchange break points with the region containing it (the server does not know which break point belongs to which region)
+            for i in 0..regions.len() {
+                if regions[i].start <= regions[i].pos && regions[i].pos <= regions[i].stop {
+                    continue;
+                }
+                for j in i + 1..regions.len() {
+                    if regions[i].start <= regions[j].pos && regions[j].pos <= regions[i].stop {
+                        let temp_pos: i64 = regions[i].pos;
+                        regions[i].pos = regions[j].pos;
+                        regions[j].pos = temp_pos;
+                        break;
+                    }
+                }
+            }
 
-            // Select only those reads that contain the break-point of the SV/fusion
+            let mut junctions = Vec::<(usize, usize)>::new(); // Pairs of regions joined by the SV/fusion
+            if json_string["junctions"].is_array() {
+                for junction in json_string["junctions"].members() {
+                    let region_a = junction[0].as_usize().unwrap_or(regions.len());
+                    let region_b = junction[1].as_usize().unwrap_or(regions.len());
+                    if region_a >= regions.len()
+                        || region_b >= regions.len()
+                        || region_a == region_b
+                    {
+                        println!("Invalid junction: {}", junction);
+                        return;
+                    }
+                    junctions.push((region_a, region_b));
+                }
+            } else {
+                for ridx in 1..regions.len() {
+                    junctions.push((ridx - 1, ridx));
+                }
+            }
 
-            let (reads1_overlap, reads1_output) =
-                check_read_containing_break_point(reads_region1, pos_region1, 0);
-            let (reads2_overlap, reads2_output) =
-                check_read_containing_break_point(reads_region2, pos_region2, 1);
-
-            //println!("reads1_overlap length:{}", reads1_overlap.len());
-            //println!("reads2_overlap length:{}", reads2_overlap.len());
-
-            let mut reads1 = Vec::<ReadInfo>::new();
-            for i in 0..reads_region1.len() {
-                let read_temp: String = reads_region1[i]["sam_info"].as_str().unwrap().to_string();
-                //println!("read_temp:{:?}", read_temp);
-                let args: Vec<&str> = read_temp.split('\t').collect();
-                reads1.push(ReadInfo {
-                    qname: args[0].parse::<String>().unwrap(),
-                    seq: args[9].parse::<String>().unwrap(),
-                    cigar: args[5].parse::<String>().unwrap(),
-                    start_position: args[3].parse::<i64>().unwrap(),
-                    flag: args[1].parse::<i64>().unwrap(),
-                    ridx: 0,
-                    sam_info: read_temp,
-                })
+            // Break points from upstream callers are often off by a few nucleotides, refining them using the soft-clipped reads before classifying reads
+            let breakpoint_window: i64 = json_string["breakpoint_window"].as_i64().unwrap_or(10); // Maximum distance of soft clips from the given break point
+            let mut breakpoints = Vec::<Breakpoint>::new();
+            for (ridx, region) in regions.iter_mut().enumerate() {
+                let breakpoint = refine_breakpoint(region, ridx, breakpoint_window);
+                region.pos = breakpoint.pos;
+                breakpoints.push(breakpoint);
             }
 
-            let mut reads2 = Vec::<ReadInfo>::new();
-            for i in 0..reads_region2.len() {
-                let read_temp: String = reads_region2[i]["sam_info"].as_str().unwrap().to_string();
-                //println!("read_temp:{:?}", read_temp);
-                let args: Vec<&str> = read_temp.split('\t').collect();
-                reads2.push(ReadInfo {
-                    qname: args[0].parse::<String>().unwrap(),
-                    seq: args[9].parse::<String>().unwrap(),
-                    cigar: args[5].parse::<String>().unwrap(),
-                    start_position: args[3].parse::<i64>().unwrap(),
-                    flag: args[1].parse::<i64>().unwrap(),
-                    ridx: 1,
-                    sam_info: read_temp,
-                })
+            // Select only those reads that contain the break-point of the SV/fusion
+            let mut reads_overlap = Vec::<Vec<ReadInfo>>::new();
+            let mut reads_output = Vec::<Vec<OutputReadInfo>>::new();
+            for ridx in 0..regions.len() {
+                let (reads_region_overlap, reads_region_output) = check_read_containing_break_point(
+                    &regions_list[ridx]["entries"],
+                    regions[ridx].pos,
+                    ridx,
+                );
+                reads_overlap.push(reads_region_overlap);
+                reads_output.push(reads_region_output);
             }
-            //println!("reads1:{}", reads1.len());
-            //println!("reads2:{}", reads2.len());
 
+            // Classifying templates supporting each junction
             let mut multi_region_templates: String = "[".to_string();
             let mut single_region_templates: String = "[".to_string();
-            let mut multi_region_templates_list = Vec::<String>::new();
-            for i in 0..reads1.len() {
-                for j in 0..reads2.len() {
-                    if reads1[i].qname == reads2[j].qname {
-                        multi_region_templates += &serde_json::to_string(&OutputReadInfo {
-                            sam_info: reads1[i].sam_info.clone(),
-                            ridx: 0,
-                        })
-                        .unwrap();
-                        multi_region_templates += &",".to_string();
-                        multi_region_templates += &serde_json::to_string(&OutputReadInfo {
-                            sam_info: reads2[j].sam_info.clone(),
-                            ridx: 1,
-                        })
-                        .unwrap();
-                        multi_region_templates += &",".to_string();
-                        multi_region_templates_list.push(reads1[i].qname.clone());
-                        break;
+            let mut multi_region_templates_list = HashSet::<String>::new(); // Templates supporting any of the junctions
+            let mut junction_supports = Vec::<JunctionSupport>::new();
+            let exon_boundary_window: i64 =
+                json_string["exon_boundary_window"].as_i64().unwrap_or(3); // Maximum distance of a read block boundary from an exon boundary (RNA fusion mode)
+            let mut fusion_supports = Vec::<FusionSupport>::new();
+            let mut junction_templates = Vec::<(HashSet<String>, HashSet<String>)>::new(); // Supporting templates and split-read templates of each junction
+            for (junction_idx, &(region_a, region_b)) in junctions.iter().enumerate() {
+                let reads1 = &regions[region_a].reads;
+                let reads2 = &regions[region_b].reads;
+                let mut junction_templates_list = HashSet::<String>::new(); // Templates supporting this junction
+                let mut split_reads: usize = 0;
+                let mut spanning_pairs: usize = 0;
+                let mut split_templates_list = HashSet::<String>::new(); // Templates supporting this junction with a split read
+                for read1 in reads1.iter() {
+                    if junction_templates_list.contains(&read1.qname) {
+                        // Other mate of a template already added, each template is added only once
+                        continue;
+                    }
+                    for read2 in reads2.iter() {
+                        if read1.qname == read2.qname {
+                            // A template with reads in both regions is either a split read (one part of the read is a supplementary alignment in the other region) or a pair of mates spanning the junction
+                            if is_split_read(read1) || is_split_read(read2) {
+                                split_reads += 1;
+                                split_templates_list.insert(read1.qname.clone());
+                            } else {
+                                spanning_pairs += 1;
+                            }
+                            junction_templates_list.insert(read1.qname.clone());
+                            if !multi_region_templates_list.contains(&read1.qname) {
+                                multi_region_templates += &serde_json::to_string(&OutputReadInfo {
+                                    sam_info: read1.sam_info.clone(),
+                                    ridx: region_a,
+                                    junction: Some(junction_idx),
+                                })
+                                .unwrap();
+                                multi_region_templates += ",";
+                                multi_region_templates += &serde_json::to_string(&OutputReadInfo {
+                                    sam_info: read2.sam_info.clone(),
+                                    ridx: region_b,
+                                    junction: Some(junction_idx),
+                                })
+                                .unwrap();
+                                multi_region_templates += ",";
+                                multi_region_templates_list.insert(read1.qname.clone());
+                            }
+                            break;
+                        }
                     }
                 }
-            }
 
-            // Putting all reads(templates) that contain SV/fusion break points but are not in multiple regions into single_region_templates
-            for i in 0..reads1_overlap.len() {
-                let mut hit = 0;
-                for j in 0..multi_region_templates_list.len() {
-                    if reads1_overlap[i].qname == multi_region_templates_list[j] {
-                        hit = 1;
-                        break;
+                // Discordant mates: the mate is not among the reads of the other region (e.g filtered out by the server) but its mapped position (RNEXT, PNEXT) lies in the other region and the aligner did not pair the mates properly. Such templates also support the SV/fusion
+                for (ridx, other_ridx) in [(region_a, region_b), (region_b, region_a)] {
+                    let other_region = &regions[other_ridx];
+                    for read in regions[ridx].reads.iter() {
+                        if read.flag & 1 == 1 // Read is paired
+                            && read.flag & 8 == 0 // Mate is mapped
+                            && is_discordant(read)
+                            && !junction_templates_list.contains(&read.qname)
+                            && same_chromosome(&read.mate_chr, &other_region.chr)
+                            && other_region.start <= read.mate_position
+                            && read.mate_position <= other_region.stop
+                        {
+                            spanning_pairs += 1;
+                            junction_templates_list.insert(read.qname.clone());
+                            if !multi_region_templates_list.contains(&read.qname) {
+                                multi_region_templates += &serde_json::to_string(&OutputReadInfo {
+                                    sam_info: read.sam_info.clone(),
+                                    ridx,
+                                    junction: Some(junction_idx),
+                                })
+                                .unwrap();
+                                multi_region_templates += ",";
+                                multi_region_templates_list.insert(read.qname.clone());
+                            }
+                        }
                     }
                 }
-                if hit == 0 {
-                    single_region_templates += &serde_json::to_string(&reads1_output[i]).unwrap();
-                    //single_region_templates += &reads1_overlap[i].qname;
-                    single_region_templates += &",".to_string();
+                let junction = junction_sequence_both_breakends(
+                    &breakpoints[region_a],
+                    &regions[region_a],
+                    &breakpoints[region_b],
+                    &regions[region_b],
+                    breakpoint_window,
+                );
+                if !regions[region_a].exons.is_empty() && !regions[region_b].exons.is_empty() {
+                    fusion_supports.push(fusion_support(
+                        junction_idx,
+                        region_a,
+                        &regions[region_a],
+                        region_b,
+                        &regions[region_b],
+                        &junction_templates_list,
+                        exon_boundary_window,
+                    ));
                 }
+                junction_supports.push(JunctionSupport {
+                    regions: [region_a, region_b],
+                    split_reads,
+                    spanning_pairs,
+                    microhomology: junction.as_ref().map(|x| x.0.to_string()),
+                    inserted_sequence: junction.as_ref().map(|x| x.1.to_string()),
+                });
+                junction_templates.push((junction_templates_list, split_templates_list));
             }
-            for i in 0..reads2_overlap.len() {
-                let mut hit = 0;
-                for j in 0..multi_region_templates_list.len() {
-                    if reads2_overlap[i].qname == multi_region_templates_list[j] {
-                        hit = 1;
-                        break;
-                    }
+
+            // Genotyping summary of each junction
+            let mut sv_events = Vec::<SvEvent>::new();
+            for junction_idx in 0..junctions.len() {
+                let (region_a, region_b) = junctions[junction_idx];
+                let (junction_templates_list, split_templates_list) =
+                    &junction_templates[junction_idx];
+                let mut breakends = Vec::<Breakend>::new();
+                for ridx in [region_a, region_b] {
+                    breakends.push(breakend_summary(
+                        ridx,
+                        &regions[ridx],
+                        &breakpoints[ridx],
+                        &reads_overlap[ridx],
+                        &multi_region_templates_list,
+                        junction_templates_list,
+                        split_templates_list,
+                    ));
+                }
+                let orientation_consistent = breakends
+                    .iter()
+                    .all(|breakend| breakend.inconsistent_reads * 9 <= breakend.consistent_reads);
+                sv_events.push(SvEvent {
+                    junction: junction_idx,
+                    breakends,
+                    split_reads: junction_supports[junction_idx].split_reads,
+                    discordant_pairs: junction_supports[junction_idx].spanning_pairs,
+                    orientation_consistent,
+                });
+            }
+
+            if let Some(vcf_file) = json_string["vcf_file"].as_str() {
+                let (vcf_records, header_lines) =
+                    create_bnd_records(&sv_events, &regions, &junction_supports);
+                if let Err(error) = vcf::write_vcf(
+                    vcf_file,
+                    "proteinpaint_sv",
+                    ".", // Contigs of all regions are added in header_lines
+                    json_string["sample_name"].as_str().unwrap_or("SAMPLE"),
+                    &vcf_records,
+                    &header_lines,
+                ) {
+                    println!("{}", error);
                 }
-                if hit == 0 {
-                    single_region_templates += &serde_json::to_string(&reads2_output[i]).unwrap();
-                    //single_region_templates += &reads2_overlap[i].qname;
+            }
+
+            // Putting all reads(templates) that contain SV/fusion break points but are not in multiple regions into single_region_templates. Both mates are output when they contain the break point, but the template is counted only once in fragment_counts
+            let mut single_region_templates_list = Vec::<String>::new();
+            for ridx in 0..regions.len() {
+                for i in 0..reads_overlap[ridx].len() {
+                    if multi_region_templates_list.contains(&reads_overlap[ridx][i].qname) {
+                        continue;
+                    }
+                    if !single_region_templates_list.contains(&reads_overlap[ridx][i].qname) {
+                        single_region_templates_list.push(reads_overlap[ridx][i].qname.clone());
+                    }
+                    single_region_templates +=
+                        &serde_json::to_string(&reads_output[ridx][i]).unwrap();
+                    //single_region_templates += &reads_overlap[ridx][i].qname;
                     single_region_templates += &",".to_string();
                 }
             }
@@ -180,11 +432,770 @@ fn main() {
 
             println!("single_region_templates:{}", single_region_templates);
             println!("multi_region_templates:{}", multi_region_templates);
+            println!(
+                "fragment_counts:{}",
+                serde_json::to_string(&FragmentCounts {
+                    supporting_sv: multi_region_templates_list.len(),
+                    supporting_ref: single_region_templates_list.len(),
+                })
+                .unwrap()
+            );
+            println!(
+                "breakpoints:{}",
+                serde_json::to_string(&breakpoints).unwrap()
+            );
+            println!(
+                "junctions:{}",
+                serde_json::to_string(&junction_supports).unwrap()
+            );
+            println!("sv_events:{}", serde_json::to_string(&sv_events).unwrap());
+            if regions.iter().any(|region| !region.exons.is_empty()) {
+                println!(
+                    "fusion_junctions:{}",
+                    serde_json::to_string(&fusion_supports).unwrap()
+                );
+            }
         }
         Err(error) => println!("Incorrect json: {}", error),
     }
 }
 
+// Soft-clipped parts of a read. Returns (position, side, clipped sequence) for each soft clip. The position is the 1-based position of the first nucleotide to the right of the junction, side is "left" when the clipped sequence lies to the left of the position (leading soft clip) and "right" when it lies to the right (trailing soft clip)
+fn soft_clips(read: &ReadInfo) -> Vec<(i64, String, String)> {
+    let mut clips = Vec::<(i64, String, String)>::new();
+    if read.cigar == "*" || read.cigar == "=" {
+        return clips;
+    }
+    let (alphabets, numbers) = realign::parse_cigar(&read.cigar);
+    let sequence_vector: Vec<char> = read.seq.chars().collect();
+    let mut ops: Vec<(char, i64)> = Vec::new(); // CIGAR operations without hard clips, since hard-clipped nucleotides are not present in the read sequence
+    for i in 0..alphabets.len() {
+        if alphabets[i] != 'H' {
+            ops.push((alphabets[i], numbers[i]));
+        }
+    }
+    if ops.len() < 2 {
+        return clips;
+    }
+    if ops[0].0 == 'S' && (ops[0].1 as usize) <= sequence_vector.len() {
+        clips.push((
+            read.start_position,
+            "left".to_string(),
+            sequence_vector[..ops[0].1 as usize].iter().collect(),
+        ));
+    }
+    let last = ops[ops.len() - 1];
+    if last.0 == 'S' && (last.1 as usize) <= sequence_vector.len() {
+        let mut ref_consumed: i64 = 0; // Number of reference nucleotides covered by the aligned part of the read
+        for (alphabet, number) in &ops {
+            if *alphabet == 'M'
+                || *alphabet == 'D'
+                || *alphabet == 'N'
+                || *alphabet == '='
+                || *alphabet == 'X'
+            {
+                ref_consumed += number;
+            }
+        }
+        clips.push((
+            read.start_position + ref_consumed,
+            "right".to_string(),
+            sequence_vector[sequence_vector.len() - last.1 as usize..]
+                .iter()
+                .collect(),
+        ));
+    }
+    clips
+}
+
+// Consensus of the soft-clipped sequences at a break point. The sequences are anchored at the junction (right-justified for left soft clips, left-justified for right soft clips) and the most frequent nucleotide in each column is used. The consensus is extended as long as at least two sequences (or the single sequence) cover the column
+fn clipped_sequence_consensus(clipped_sequences: &[String], side: &str) -> String {
+    let min_coverage: usize = if clipped_sequences.len() > 1 { 2 } else { 1 };
+    let oriented_sequences: Vec<Vec<char>> = clipped_sequences
+        .iter()
+        .map(|sequence| {
+            if side == "left" {
+                sequence.chars().rev().collect() // Nucleotide next to the junction first
+            } else {
+                sequence.chars().collect()
+            }
+        })
+        .collect();
+    let mut consensus = String::new();
+    let mut column: usize = 0;
+    loop {
+        let mut nucleotide_counts = HashMap::<char, usize>::new();
+        for sequence in &oriented_sequences {
+            if column < sequence.len() {
+                *nucleotide_counts.entry(sequence[column]).or_insert(0) += 1;
+            }
+        }
+        if nucleotide_counts.values().sum::<usize>() < min_coverage {
+            break;
+        }
+        let mut best_nucleotide = 'N';
+        let mut best_count: usize = 0;
+        for nucleotide in ['A', 'C', 'G', 'T', 'N'] {
+            let count = *nucleotide_counts.get(&nucleotide).unwrap_or(&0);
+            if count > best_count {
+                best_nucleotide = nucleotide;
+                best_count = count;
+            }
+        }
+        consensus.push(best_nucleotide);
+        column += 1;
+    }
+    if side == "left" {
+        consensus = realign::reverse_string(&consensus);
+    }
+    consensus
+}
+
+// Refines the break point of a region to base-pair precision. All soft clips of reads in the region within breakpoint_window of the given break point are piled up, and the position (and side) supported by the largest number of soft-clipped reads is chosen. When no read is soft-clipped near the break point, the given break point is kept
+fn refine_breakpoint(region: &Region, ridx: usize, breakpoint_window: i64) -> Breakpoint {
+    let mut clip_pileup = HashMap::<(i64, String), Vec<String>>::new(); // Clipped sequences for each (position, side)
+    for read in &region.reads {
+        for (position, side, clipped_sequence) in soft_clips(read) {
+            if (position - region.pos).abs() <= breakpoint_window {
+                clip_pileup
+                    .entry((position, side))
+                    .or_default()
+                    .push(clipped_sequence);
+            }
+        }
+    }
+    let mut best: Option<(&(i64, String), &Vec<String>)> = None;
+    for (key, clipped_sequences) in &clip_pileup {
+        best = match best {
+            None => Some((key, clipped_sequences)),
+            Some((best_key, best_sequences)) => {
+                // Most soft-clipped reads first, then the position closest to the given break point
+                let order = clipped_sequences
+                    .len()
+                    .cmp(&best_sequences.len())
+                    .then(
+                        (best_key.0 - region.pos)
+                            .abs()
+                            .cmp(&(key.0 - region.pos).abs()),
+                    )
+                    .then(best_key.cmp(key));
+                if order == Ordering::Greater {
+                    Some((key, clipped_sequences))
+                } else {
+                    Some((best_key, best_sequences))
+                }
+            }
+        };
+    }
+    match best {
+        Some(((position, side), clipped_sequences)) => Breakpoint {
+            region: ridx,
+            pos: *position,
+            original_pos: region.pos,
+            side: Some(side.to_string()),
+            clipped_reads: clipped_sequences.len(),
+            consensus: clipped_sequence_consensus(clipped_sequences, side),
+        },
+        None => Breakpoint {
+            region: ridx,
+            pos: region.pos,
+            original_pos: region.pos,
+            side: None,
+            clipped_reads: 0,
+            consensus: "".to_string(),
+        },
+    }
+}
+
+// Determines the microhomology and the inserted sequence at a junction from the soft-clipped consensus of both break points. The consensus of each break point is matched against the other region, and the match with fewer mismatches is kept (the match of the first region on ties). Both sequences are reported on the strand reading from region_a into region_b. Returns None if neither consensus matches the other region
+fn junction_sequence_both_breakends(
+    breakpoint_a: &Breakpoint, // Break point of the first region of the junction
+    region_a: &Region,         // First region of the junction
+    breakpoint_b: &Breakpoint, // Break point of the second region of the junction
+    region_b: &Region,         // Second region of the junction
+    search_window: i64, // Maximum distance from the other break point where each consensus is searched
+) -> Option<(String, String)> {
+    let match_a = junction_sequence(
+        breakpoint_a,
+        region_a,
+        breakpoint_b,
+        region_b,
+        search_window,
+    );
+    let match_b = junction_sequence(
+        breakpoint_b,
+        region_b,
+        breakpoint_a,
+        region_a,
+        search_window,
+    )
+    .map(|(mismatches, microhomology, inserted_sequence)| {
+        // Reported on the strand reading from the first into the second region
+        (
+            mismatches,
+            reverse_complement(&microhomology),
+            reverse_complement(&inserted_sequence),
+        )
+    });
+    let best_match = match (match_a, match_b) {
+        (Some(match_a), Some(match_b)) => {
+            if match_b.0 < match_a.0 {
+                Some(match_b)
+            } else {
+                Some(match_a)
+            }
+        }
+        (match_a, match_b) => match_a.or(match_b),
+    };
+    best_match
+        .map(|(_mismatches, microhomology, inserted_sequence)| (microhomology, inserted_sequence))
+}
+
+// Determines the microhomology and the inserted sequence at a junction from the soft-clipped consensus of the first break point, which is searched in the reference sequence of the second region near its break point (the break points of both regions are refined independently, so in case of microhomology they are shifted with respect to each other). Returns (mismatches, microhomology, inserted sequence), where mismatches is the number of mismatches between the consensus and the second region, or None if the first break point has no soft-clipped consensus or the consensus does not match the second region
+fn junction_sequence(
+    breakpoint_a: &Breakpoint, // Break point with soft-clipped consensus
+    region_a: &Region,         // Region of breakpoint_a
+    breakpoint_b: &Breakpoint, // Break point at the other side of the junction
+    region_b: &Region,         // Region of breakpoint_b
+    search_window: i64,        // Maximum distance from breakpoint_b where the consensus is searched
+) -> Option<(usize, String, String)> {
+    let (side_a, side_b) = match (&breakpoint_a.side, &breakpoint_b.side) {
+        (Some(side_a), Some(side_b)) => (side_a, side_b),
+        _ => return None,
+    };
+    let idx_a = breakpoint_a.pos - region_a.start; // Index of the break point in refseq of region_a
+    let idx_b = breakpoint_b.pos - region_b.start;
+    if breakpoint_a.consensus.is_empty()
+        || idx_a < 0
+        || idx_a as usize > region_a.refseq.len()
+        || idx_b < 0
+        || idx_b as usize > region_b.refseq.len()
+    {
+        return None;
+    }
+    let idx_a = idx_a as usize;
+    // All sequences below are on the strand of the junction read, reading from the first region into the second region
+    let out_a: Vec<char> = if side_a == "right" {
+        breakpoint_a.consensus.chars().collect()
+    } else {
+        reverse_complement(&breakpoint_a.consensus)
+            .chars()
+            .collect()
+    }; // Soft-clipped sequence, starting at the junction
+    let before_a: Vec<char> = if side_a == "right" {
+        region_a.refseq[..idx_a].chars().collect()
+    } else {
+        reverse_complement(&region_a.refseq[idx_a..])
+            .chars()
+            .collect()
+    }; // Aligned part of the first region, ending at the junction
+    let (oriented_b, expected_idx_b): (Vec<char>, i64) = if side_b == "left" {
+        (region_b.refseq.chars().collect(), idx_b)
+    } else {
+        (
+            reverse_complement(&region_b.refseq).chars().collect(),
+            region_b.refseq.len() as i64 - idx_b,
+        )
+    }; // Reference sequence of the second region, the aligned part of the second region starts at expected_idx_b
+
+    // The inserted sequence is the prefix of the soft-clipped consensus after which the consensus matches the second region (allowing 10% mismatches). The match with fewest mismatches is chosen, ties are broken by the shortest insertion and then by the distance from the break point of the second region
+    let min_match_length: usize = 5;
+    let max_match_length: usize = 20;
+    let mut best_match: Option<(usize, usize, i64)> = None; // (mismatches, insert_length, junction_idx_b)
+    for insert_length in 0..out_a.len() {
+        if out_a.len() - insert_length < min_match_length {
+            break;
+        }
+        for junction_idx_b in cmp::max(expected_idx_b - search_window, 0)
+            ..=cmp::min(expected_idx_b + search_window, oriented_b.len() as i64)
+        {
+            let match_length = cmp::min(
+                max_match_length,
+                cmp::min(
+                    out_a.len() - insert_length,
+                    oriented_b.len() - junction_idx_b as usize,
+                ),
+            );
+            if match_length < min_match_length {
+                continue;
+            }
+            let mismatches = (0..match_length)
+                .filter(|i| out_a[insert_length + i] != oriented_b[junction_idx_b as usize + i])
+                .count();
+            if mismatches * 10 > match_length {
+                continue;
+            }
+            let is_better = match best_match {
+                Some((best_mismatches, best_insert_length, best_idx_b)) => {
+                    (
+                        mismatches,
+                        insert_length,
+                        (junction_idx_b - expected_idx_b).abs(),
+                    ) < (
+                        best_mismatches,
+                        best_insert_length,
+                        (best_idx_b - expected_idx_b).abs(),
+                    )
+                }
+                None => true,
+            };
+            if is_better {
+                best_match = Some((mismatches, insert_length, junction_idx_b));
+            }
+        }
+    }
+    let (mismatches, insert_length, junction_idx_b) = best_match?;
+    let junction_idx_b = junction_idx_b as usize;
+    let inserted_sequence: String = out_a[..insert_length].iter().collect();
+    let mut microhomology = String::new();
+    if insert_length == 0 {
+        // Nucleotides at the end of the first region that are also present before the junction in the second region, the junction could lie anywhere within these nucleotides
+        let mut k: usize = 0;
+        while k < before_a.len()
+            && k < junction_idx_b
+            && before_a[before_a.len() - 1 - k] == oriented_b[junction_idx_b - 1 - k]
+        {
+            k += 1;
+        }
+        microhomology = before_a[before_a.len() - k..].iter().collect();
+    }
+    Some((mismatches, microhomology, inserted_sequence))
+}
+
+// Reference and alternate read counts, allele fraction and orientation check at one break point of a junction
+fn breakend_summary(
+    ridx: usize,                                   // Index of the region
+    region: &Region,                               // Region containing the break point
+    breakpoint: &Breakpoint,                       // Refined break point of the region
+    reads_overlap: &[ReadInfo],                    // Reads of the region containing the break point
+    multi_region_templates_list: &HashSet<String>, // Templates supporting any junction
+    junction_templates_list: &HashSet<String>,     // Templates supporting this junction
+    split_templates_list: &HashSet<String>, // Templates supporting this junction with a split read
+) -> Breakend {
+    let confidence: f64 = 0.95; // Confidence level of allele fraction confidence interval
+    let mut alt_templates: HashSet<String> = junction_templates_list.clone();
+    let mut ref_templates = Vec::<String>::new();
+    for read in reads_overlap {
+        let clipped_at_breakpoint = soft_clips(read)
+            .iter()
+            .any(|(position, _side, _clipped)| *position == breakpoint.pos);
+        if clipped_at_breakpoint {
+            alt_templates.insert(read.qname.clone());
+        } else if !multi_region_templates_list.contains(&read.qname)
+            && !ref_templates.contains(&read.qname)
+        {
+            ref_templates.push(read.qname.clone());
+        }
+    }
+    ref_templates.retain(|qname| !alt_templates.contains(qname)); // A template with one mate clipped at the break point is not a reference template
+    let alt_reads = alt_templates.len();
+    let ref_reads = ref_templates.len();
+    let mut vaf: f64 = 0.0;
+    if alt_reads + ref_reads > 0 {
+        vaf = alt_reads as f64 / (alt_reads + ref_reads) as f64;
+    }
+    let (ci_lower, ci_upper) = stats_functions::clopper_pearson_interval(
+        alt_reads as u32,
+        (alt_reads + ref_reads) as u32,
+        confidence,
+    );
+
+    // Orientation of each supporting read in this region: "+" when it points towards a junction on its right
+    let mut read_orientations = Vec::<bool>::new(); // true for "+"
+    for read in &region.reads {
+        if !junction_templates_list.contains(&read.qname) {
+            continue;
+        }
+        if split_templates_list.contains(&read.qname) {
+            let (leading_clip, trailing_clip) = clipped_ends(read);
+            if leading_clip != trailing_clip {
+                read_orientations.push(trailing_clip);
+            }
+        } else {
+            read_orientations.push(read.flag & 16 == 0);
+        }
+    }
+    let forward_reads = read_orientations.iter().filter(|x| **x).count();
+    let reverse_reads = read_orientations.len() - forward_reads;
+    let orientation_forward = match &breakpoint.side {
+        Some(side) => side == "right", // Soft-clipped sequence to the right of the break point, so the retained sequence lies to the left
+        None => forward_reads >= reverse_reads,
+    };
+    let (consistent_reads, inconsistent_reads) = if orientation_forward {
+        (forward_reads, reverse_reads)
+    } else {
+        (reverse_reads, forward_reads)
+    };
+    Breakend {
+        region: ridx,
+        chr: region.chr.to_string(),
+        pos: breakpoint.pos,
+        orientation: if orientation_forward { "+" } else { "-" }.to_string(),
+        ref_reads,
+        alt_reads,
+        vaf,
+        ci_lower,
+        ci_upper,
+        consistent_reads,
+        inconsistent_reads,
+    }
+}
+
+// Whether the read is clipped (soft or hard) at its start and at its end
+fn clipped_ends(read: &ReadInfo) -> (bool, bool) {
+    if read.cigar == "*" || read.cigar == "=" {
+        return (false, false);
+    }
+    let (alphabets, _numbers) = realign::parse_cigar(&read.cigar);
+    let first = alphabets[0];
+    let last = alphabets[alphabets.len() - 1];
+    (
+        first == 'S' || first == 'H',
+        alphabets.len() > 1 && (last == 'S' || last == 'H'),
+    )
+}
+
+// Creates two VCF BND records (one for each breakend) for each junction, along with the header lines defining their INFO fields and contigs. Records are sorted by contig (in the order of the regions) and position
+fn create_bnd_records(
+    sv_events: &[SvEvent],                 // Genotyping summary of each junction
+    regions: &[Region],                    // Break point regions
+    junction_supports: &[JunctionSupport], // Junction sequence of each junction
+) -> (Vec<vcf::VcfRecord>, Vec<String>) {
+    let mut contigs = Vec::<String>::new();
+    for region in regions {
+        if !region.chr.is_empty() && !contigs.contains(&region.chr) {
+            contigs.push(region.chr.to_string());
+        }
+    }
+    let mut header_lines: Vec<String> = contigs
+        .iter()
+        .map(|contig| "##contig=<ID=".to_string() + contig + ">")
+        .collect();
+    header_lines.push(
+        "##INFO=<ID=SVTYPE,Number=1,Type=String,Description=\"Type of structural variant\">"
+            .to_string(),
+    );
+    header_lines.push(
+        "##INFO=<ID=MATEID,Number=.,Type=String,Description=\"ID of mate breakend\">".to_string(),
+    );
+    header_lines.push("##INFO=<ID=SR,Number=1,Type=Integer,Description=\"Number of split reads supporting the junction\">".to_string());
+    header_lines.push("##INFO=<ID=PE,Number=1,Type=Integer,Description=\"Number of discordant read pairs supporting the junction\">".to_string());
+    header_lines.push("##INFO=<ID=HOMSEQ,Number=.,Type=String,Description=\"Sequence of microhomology at the junction\">".to_string());
+
+    let mut vcf_records = Vec::<vcf::VcfRecord>::new();
+    for event in sv_events {
+        let junction = &junction_supports[event.junction];
+        let inserted_sequence = junction
+            .inserted_sequence
+            .as_ref()
+            .map(|x| x.to_string())
+            .unwrap_or("".to_string()); // On the strand reading from the first into the second region
+        for (idx, other_idx) in [(0, 1), (1, 0)] {
+            let breakend = &event.breakends[idx];
+            let mate = &event.breakends[other_idx];
+            let forward = breakend.orientation == "+";
+            // The record is placed on the last retained nucleotide, which is left of the break point for "+" orientation
+            let pos = if forward {
+                breakend.pos - 1
+            } else {
+                breakend.pos
+            };
+            let mate_pos = if mate.orientation == "+" {
+                mate.pos - 1
+            } else {
+                mate.pos
+            };
+            let region = &regions[breakend.region];
+            let ref_base = region
+                .refseq
+                .chars()
+                .nth((pos - region.start).max(0) as usize)
+                .unwrap_or('N')
+                .to_string();
+            // Inserted sequence reading from this breakend into its mate, on the forward strand of this breakend
+            let reading_sequence = if idx == 0 {
+                inserted_sequence.to_string()
+            } else {
+                reverse_complement(&inserted_sequence)
+            };
+            let genomic_insertion = if forward {
+                reading_sequence
+            } else {
+                reverse_complement(&reading_sequence)
+            };
+            // The mate piece extends to the right of mate_pos when the mate orientation is "-", and to the left when it is "+"
+            let mate_piece = if mate.orientation == "-" {
+                format!("[{}:{}[", mate.chr, mate_pos)
+            } else {
+                format!("]{}:{}]", mate.chr, mate_pos)
+            };
+            let alt_allele = if forward {
+                ref_base.to_string() + &genomic_insertion + &mate_piece
+            } else {
+                mate_piece + &genomic_insertion + &ref_base
+            };
+            let mut info = vec![
+                "SVTYPE=BND".to_string(),
+                format!("MATEID=bnd{}_{}", event.junction, other_idx + 1),
+                format!("SR={}", event.split_reads),
+                format!("PE={}", event.discordant_pairs),
+            ];
+            match &junction.microhomology {
+                Some(microhomology) if !microhomology.is_empty() => {
+                    info.push("HOMSEQ=".to_string() + microhomology)
+                }
+                _ => {}
+            }
+            vcf_records.push(vcf::VcfRecord {
+                chrom: breakend.chr.to_string(),
+                pos,
+                id: format!("bnd{}_{}", event.junction, idx + 1),
+                ref_allele: ref_base,
+                alt_allele,
+                info,
+                format: vec!["AD".to_string(), "DP".to_string()],
+                sample: vec![
+                    format!("{},{}", breakend.ref_reads, breakend.alt_reads),
+                    (breakend.ref_reads + breakend.alt_reads).to_string(),
+                ],
+            });
+        }
+    }
+    vcf_records.sort_by_key(|record| {
+        (
+            contigs
+                .iter()
+                .position(|contig| contig == &record.chrom)
+                .unwrap_or(contigs.len()),
+            record.pos,
+        )
+    });
+    (vcf_records, header_lines)
+}
+
+// Counts the reads supporting a fusion transcript at a junction between the partner genes of region_a and region_b. For each template the exon-exon junction is determined from its reads: a spliced read whose intron (N) joins an exon boundary of one gene to an exon boundary of the other gene (both genes on the same chromosome), or clipped reads whose aligned part ends at an exon boundary in both genes (split reads, or soft-clipped reads in both regions)
+fn fusion_support(
+    junction_idx: usize,                       // Index of the junction
+    ridx_a: usize,                             // Index of region_a
+    region_a: &Region,                         // Region of the first partner gene
+    ridx_b: usize,                             // Index of region_b
+    region_b: &Region,                         // Region of the second partner gene
+    junction_templates_list: &HashSet<String>, // Templates with reads (or mates) in both regions
+    exon_boundary_window: i64, // Maximum distance of a read block boundary from an exon boundary
+) -> FusionSupport {
+    let mut templates = Vec::<String>::new(); // Templates in the order they are encountered
+    let mut template_boundaries = HashMap::<String, [Vec<(usize, i64)>; 2]>::new(); // Exon boundaries (exon index, position) at the clipped ends of the reads of each template in region_a and region_b
+    let mut template_exon_pairs = HashMap::<String, ((usize, i64), (usize, i64))>::new(); // Exon pair of templates with a spliced read crossing the junction
+    let mut template_in_exons = HashMap::<String, bool>::new(); // Whether all reads of the template overlap an exon of their partner gene
+    for (side, region, other_region) in [(0, region_a, region_b), (1, region_b, region_a)] {
+        for read in &region.reads {
+            let blocks = aligned_blocks(read);
+            if blocks.is_empty() {
+                continue;
+            }
+            if !template_boundaries.contains_key(&read.qname) {
+                templates.push(read.qname.clone());
+                template_boundaries.insert(read.qname.clone(), [Vec::new(), Vec::new()]);
+            }
+            let in_exons = blocks.iter().any(|(block_start, block_stop)| {
+                region.exons.iter().any(|(exon_start, exon_stop)| {
+                    block_start <= exon_stop && exon_start <= block_stop
+                })
+            });
+            let all_in_exons = template_in_exons.entry(read.qname.clone()).or_insert(true);
+            *all_in_exons = *all_in_exons && in_exons;
+
+            // Clipped ends of the read: a leading clip joins the first aligned nucleotide to the other gene, a trailing clip joins the last aligned nucleotide to the other gene
+            let (alphabets, _numbers) = realign::parse_cigar(&read.cigar);
+            let boundaries = template_boundaries.get_mut(&read.qname).unwrap();
+            if alphabets[0] == 'S' || alphabets[0] == 'H' {
+                if let Some(exon_idx) =
+                    matching_exon_boundary(&region.exons, blocks[0].0, false, exon_boundary_window)
+                {
+                    boundaries[side].push((exon_idx, region.exons[exon_idx].0));
+                }
+            }
+            let last_alphabet = alphabets[alphabets.len() - 1];
+            if alphabets.len() > 1 && (last_alphabet == 'S' || last_alphabet == 'H') {
+                if let Some(exon_idx) = matching_exon_boundary(
+                    &region.exons,
+                    blocks[blocks.len() - 1].1,
+                    true,
+                    exon_boundary_window,
+                ) {
+                    boundaries[side].push((exon_idx, region.exons[exon_idx].1));
+                }
+            }
+
+            // Introns of the read: a fusion between genes on the same chromosome (e.g caused by a deletion) is reported as splicing by RNA-seq aligners
+            if template_exon_pairs.contains_key(&read.qname)
+                || !same_chromosome(&region.chr, &other_region.chr)
+            {
+                continue;
+            }
+            for k in 1..blocks.len() {
+                let donor = blocks[k - 1].1; // Last nucleotide before the intron
+                let acceptor = blocks[k].0; // First nucleotide after the intron
+                let exon_pair = match (
+                    matching_exon_boundary(&region_a.exons, donor, true, exon_boundary_window),
+                    matching_exon_boundary(&region_b.exons, acceptor, false, exon_boundary_window),
+                    matching_exon_boundary(&region_b.exons, donor, true, exon_boundary_window),
+                    matching_exon_boundary(&region_a.exons, acceptor, false, exon_boundary_window),
+                ) {
+                    (Some(exon_a), Some(exon_b), _, _) => Some((
+                        (exon_a, region_a.exons[exon_a].1),
+                        (exon_b, region_b.exons[exon_b].0),
+                    )),
+                    (_, _, Some(exon_b), Some(exon_a)) => Some((
+                        (exon_a, region_a.exons[exon_a].0),
+                        (exon_b, region_b.exons[exon_b].1),
+                    )),
+                    _ => None,
+                };
+                if let Some(exon_pair) = exon_pair {
+                    template_exon_pairs.insert(read.qname.clone(), exon_pair);
+                    break;
+                }
+            }
+        }
+    }
+
+    let mut exon_pairs = Vec::<ExonPairSupport>::new();
+    let mut junction_reads: usize = 0;
+    let mut spanning_fragments: usize = 0;
+    for qname in &templates {
+        let boundaries = &template_boundaries[qname];
+        let exon_pair = match template_exon_pairs.get(qname) {
+            Some(exon_pair) => Some(*exon_pair),
+            None if !boundaries[0].is_empty() && !boundaries[1].is_empty() => {
+                Some((boundaries[0][0], boundaries[1][0]))
+            }
+            None => None,
+        };
+        match exon_pair {
+            Some(((exon_a, position_a), (exon_b, position_b))) => {
+                junction_reads += 1;
+                match exon_pairs
+                    .iter_mut()
+                    .find(|pair| pair.exons == [exon_a + 1, exon_b + 1])
+                {
+                    Some(pair) => pair.junction_reads += 1,
+                    None => exon_pairs.push(ExonPairSupport {
+                        exons: [exon_a + 1, exon_b + 1],
+                        positions: [position_a, position_b],
+                        junction_reads: 1,
+                    }),
+                }
+            }
+            None => {
+                if junction_templates_list.contains(qname) && template_in_exons[qname] {
+                    spanning_fragments += 1;
+                }
+            }
+        }
+    }
+    exon_pairs.sort_by_key(|exon_pair| std::cmp::Reverse(exon_pair.junction_reads)); // Stable sort, ties remain in the order they were encountered
+    FusionSupport {
+        junction: junction_idx,
+        regions: [ridx_a, ridx_b],
+        exon_pairs,
+        junction_reads,
+        spanning_fragments,
+    }
+}
+
+// Aligned blocks (start, stop) of a read in reference coordinates (1-based, inclusive). The read is split into blocks at introns (N), deletions are included in the blocks
+fn aligned_blocks(read: &ReadInfo) -> Vec<(i64, i64)> {
+    let mut blocks = Vec::<(i64, i64)>::new();
+    if read.cigar == "*" || read.cigar == "=" {
+        return blocks;
+    }
+    let (alphabets, numbers) = realign::parse_cigar(&read.cigar);
+    let mut block_start = read.start_position;
+    let mut position = read.start_position; // Reference position of the next aligned nucleotide
+    for i in 0..alphabets.len() {
+        if alphabets[i] == 'M' || alphabets[i] == 'D' || alphabets[i] == '=' || alphabets[i] == 'X'
+        {
+            position += numbers[i];
+        } else if alphabets[i] == 'N' {
+            if position > block_start {
+                blocks.push((block_start, position - 1));
+            }
+            position += numbers[i];
+            block_start = position;
+        }
+    }
+    if position > block_start {
+        blocks.push((block_start, position - 1));
+    }
+    blocks
+}
+
+// Index of the exon whose start (is_end = false) or stop (is_end = true) lies within exon_boundary_window of position, closest exon boundary first
+fn matching_exon_boundary(
+    exons: &[(i64, i64)],      // Exons (start, stop)
+    position: i64,             // Read block boundary
+    is_end: bool,              // Whether position is the last nucleotide of a block
+    exon_boundary_window: i64, // Maximum distance between position and the exon boundary
+) -> Option<usize> {
+    (0..exons.len())
+        .map(|exon_idx| {
+            let boundary = if is_end {
+                exons[exon_idx].1
+            } else {
+                exons[exon_idx].0
+            };
+            (exon_idx, (boundary - position).abs())
+        })
+        .filter(|(_exon_idx, distance)| *distance <= exon_boundary_window)
+        .min_by_key(|(_exon_idx, distance)| *distance)
+        .map(|(exon_idx, _distance)| exon_idx)
+}
+
+// Reverse complement of a nucleotide sequence
+fn reverse_complement(sequence: &str) -> String {
+    sequence
+        .chars()
+        .rev()
+        .map(|nucleotide| match nucleotide {
+            'A' => 'T',
+            'T' => 'A',
+            'C' => 'G',
+            'G' => 'C',
+            other => other,
+        })
+        .collect()
+}
+
+// Checks if a read is part of a split alignment, i.e it is a supplementary alignment or its primary alignment has supplementary alignments (SA tag)
+fn is_split_read(read: &ReadInfo) -> bool {
+    read.flag & 2048 == 2048
+        || read
+            .sam_info
+            .split('\t')
+            .skip(11)
+            .any(|tag| tag.starts_with("SA:Z:"))
+}
+
+// Checks if the mates of a paired read are discordant, i.e the aligner did not flag them as a proper pair or both mates are aligned to the same strand
+fn is_discordant(read: &ReadInfo) -> bool {
+    read.flag & 2 == 0 || (read.flag & 16 == 0) == (read.flag & 32 == 0)
+}
+
+// Chromosome of the mate from the RNEXT field of a SAM line, "=" means the mate is on the same chromosome as the read (RNAME)
+fn mate_chromosome(args: &[&str]) -> String {
+    if args[6] == "=" {
+        args[2].to_string()
+    } else {
+        args[6].to_string()
+    }
+}
+
+// Compares chromosome names ignoring the "chr" prefix, since the BAM file and the genome may use different naming conventions (e.g 16 and chr16)
+fn same_chromosome(chr1: &str, chr2: &str) -> bool {
+    chr1.trim_start_matches("chr") == chr2.trim_start_matches("chr")
+}
+
 fn check_read_containing_break_point(
     reads_region: &JsonValue,
     pos_region: i64,
@@ -251,11 +1262,14 @@ fn check_read_containing_break_point(
                         start_position: args[3].parse::<i64>().unwrap(),
                         flag: args[1].parse::<i64>().unwrap(),
                         ridx: ridx,
+                        mate_chr: mate_chromosome(&args),
+                        mate_position: args[7].parse::<i64>().unwrap_or(0),
                         sam_info: read_temp.clone(),
                     });
                     reads_output.push(OutputReadInfo {
                         sam_info: read_temp,
                         ridx: ridx,
+                        junction: None,
                     })
                 }
             //else {
@@ -369,11 +1383,14 @@ fn check_read_containing_break_point(
                         start_position: args[3].parse::<i64>().unwrap(),
                         flag: args[1].parse::<i64>().unwrap(),
                         ridx: ridx,
+                        mate_chr: mate_chromosome(&args),
+                        mate_position: args[7].parse::<i64>().unwrap_or(0),
                         sam_info: read_temp.clone(),
                     });
                     reads_output.push(OutputReadInfo {
                         sam_info: read_temp,
                         ridx: ridx,
+                        junction: None,
                     })
                 }
             }
@@ -382,3 +1399,420 @@ fn check_read_containing_break_point(
     //println!("reads:{:?}", reads);
     (reads, reads_output)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reference sequences of the two regions of the test junction. The junction joins chr1:1029 to chr2:5030 (both on the forward strand), the two nucleotides before the junction ("CA") are present in both regions
+    const REFSEQ_A: &str = "GCTAAAGACAATTACATAACATACACGTCAGCACGAAACTTGTTGGCCCAGTGTGAATCG"; // chr1:1000-1059
+    const REFSEQ_B: &str = "CTTAAGGGTTAAGTAAGTGTGATGCATACACCTTTACTTGCTGTGTCCACCCCATCGGAC"; // chr2:5000-5059
+
+    fn test_read(qname: &str, flag: i64, start_position: i64, cigar: &str, seq: &str) -> ReadInfo {
+        ReadInfo {
+            qname: qname.to_string(),
+            seq: seq.to_string(),
+            cigar: cigar.to_string(),
+            start_position,
+            flag,
+            ridx: 0,
+            sam_info: format!(
+                "{}\t{}\tchr1\t{}\t60\t{}\t*\t0\t0\t{}\t*",
+                qname, flag, start_position, cigar, seq
+            ),
+            mate_chr: "*".to_string(),
+            mate_position: 0,
+        }
+    }
+
+    fn test_region(refseq: &str, chr: &str, start: i64, pos: i64, reads: Vec<ReadInfo>) -> Region {
+        Region {
+            refseq: refseq.to_string(),
+            chr: chr.to_string(),
+            start,
+            stop: start + refseq.len() as i64 - 1,
+            pos,
+            exons: Vec::new(),
+            reads,
+        }
+    }
+
+    // Regions whose reads are split at the junction with the given inserted sequence. In the second region the reads are aligned as far to the left as possible, so that its break point lies before the microhomology
+    fn junction_regions(inserted_sequence: &str) -> (Region, Region) {
+        let junction_read =
+            "ATTACATAACATACACGTCA".to_string() + inserted_sequence + "CCTTTACTTGCTGTGTCCAC";
+        let insert_length = inserted_sequence.len();
+        let (cigar_b, start_b) = if insert_length == 0 {
+            ("18S22M".to_string(), 5028)
+        } else {
+            (format!("{}S20M", 20 + insert_length), 5030)
+        };
+        let reads_a = vec![
+            test_read(
+                "t1",
+                97,
+                1010,
+                &format!("20M{}S", 20 + insert_length),
+                &junction_read,
+            ),
+            test_read(
+                "t2",
+                97,
+                1010,
+                &format!("20M{}S", 20 + insert_length),
+                &junction_read,
+            ),
+            test_read("t3", 99, 1005, "40M", &REFSEQ_A[5..45]),
+        ];
+        let reads_b = vec![
+            test_read("t1", 2145, start_b, &cigar_b, &junction_read),
+            test_read("t2", 2145, start_b, &cigar_b, &junction_read),
+        ];
+        (
+            test_region(REFSEQ_A, "chr1", 1000, 1027, reads_a),
+            test_region(REFSEQ_B, "chr2", 5000, 5032, reads_b),
+        )
+    }
+
+    #[test]
+    fn test_clipped_sequence_consensus() {
+        let right_clips: Vec<String> =
+            vec!["ACGTA".to_string(), "ACGAA".to_string(), "ACG".to_string()];
+        assert_eq!(clipped_sequence_consensus(&right_clips, "right"), "ACGAA");
+        // Left soft clips are anchored at their last nucleotide
+        let left_clips: Vec<String> =
+            vec!["TTGCA".to_string(), "GCA".to_string(), "AGCA".to_string()];
+        assert_eq!(clipped_sequence_consensus(&left_clips, "left"), "AGCA");
+        // A single soft clip is its own consensus
+        assert_eq!(
+            clipped_sequence_consensus(&["ACG".to_string()], "left"),
+            "ACG"
+        );
+    }
+
+    #[test]
+    fn test_refine_breakpoint() {
+        let (region_a, region_b) = junction_regions("");
+        let breakpoint_a = refine_breakpoint(&region_a, 0, 10);
+        assert_eq!(breakpoint_a.pos, 1030);
+        assert_eq!(breakpoint_a.original_pos, 1027);
+        assert_eq!(breakpoint_a.side, Some("right".to_string()));
+        assert_eq!(breakpoint_a.clipped_reads, 2);
+        assert_eq!(breakpoint_a.consensus, "CCTTTACTTGCTGTGTCCAC");
+        let breakpoint_b = refine_breakpoint(&region_b, 1, 10);
+        assert_eq!(breakpoint_b.pos, 5028);
+        assert_eq!(breakpoint_b.side, Some("left".to_string()));
+        assert_eq!(breakpoint_b.consensus, "ATTACATAACATACACGT");
+
+        // Soft clips farther than breakpoint_window from the given break point are ignored
+        let breakpoint_far = refine_breakpoint(&region_a, 0, 2);
+        assert_eq!(breakpoint_far.pos, 1027);
+        assert_eq!(breakpoint_far.side, None);
+        assert_eq!(breakpoint_far.clipped_reads, 0);
+        assert_eq!(breakpoint_far.consensus, "");
+    }
+
+    #[test]
+    fn test_junction_sequence() {
+        // Microhomology: the junction could lie on either side of "CA"
+        let (region_a, region_b) = junction_regions("");
+        let breakpoint_a = refine_breakpoint(&region_a, 0, 10);
+        let breakpoint_b = refine_breakpoint(&region_b, 1, 10);
+        assert_eq!(
+            junction_sequence(&breakpoint_a, &region_a, &breakpoint_b, &region_b, 10),
+            Some((0, "CA".to_string(), "".to_string()))
+        );
+        // From the second region the consensus is matched on the opposite strand
+        assert_eq!(
+            junction_sequence(&breakpoint_b, &region_b, &breakpoint_a, &region_a, 10),
+            Some((0, "TG".to_string(), "".to_string()))
+        );
+
+        // Inserted sequence
+        let (region_a, region_b) = junction_regions("TTT");
+        let breakpoint_a = refine_breakpoint(&region_a, 0, 10);
+        let breakpoint_b = refine_breakpoint(&region_b, 1, 10);
+        assert_eq!(breakpoint_a.pos, 1030);
+        assert_eq!(breakpoint_b.pos, 5030);
+        assert_eq!(
+            junction_sequence(&breakpoint_a, &region_a, &breakpoint_b, &region_b, 10),
+            Some((0, "".to_string(), "TTT".to_string()))
+        );
+        assert_eq!(
+            junction_sequence(&breakpoint_b, &region_b, &breakpoint_a, &region_a, 10),
+            Some((0, "".to_string(), "AAA".to_string()))
+        );
+
+        // No soft-clipped reads in the first region
+        let region_c = test_region(REFSEQ_A, "chr1", 1000, 1030, Vec::new());
+        let breakpoint_c = refine_breakpoint(&region_c, 0, 10);
+        assert_eq!(
+            junction_sequence(&breakpoint_c, &region_c, &breakpoint_b, &region_b, 10),
+            None
+        );
+    }
+
+    #[test]
+    fn test_matching_exon_boundary() {
+        let exons = vec![(100, 200), (300, 400), (500, 600)];
+        assert_eq!(matching_exon_boundary(&exons, 302, false, 3), Some(1));
+        assert_eq!(matching_exon_boundary(&exons, 302, true, 3), None);
+        assert_eq!(matching_exon_boundary(&exons, 398, true, 3), Some(1));
+        assert_eq!(matching_exon_boundary(&exons, 305, false, 3), None);
+        // Closest exon boundary first
+        let close_exons = vec![(100, 200), (203, 300), (204, 400)];
+        assert_eq!(matching_exon_boundary(&close_exons, 204, false, 3), Some(2));
+        assert_eq!(matching_exon_boundary(&close_exons, 202, false, 3), Some(1));
+    }
+
+    #[test]
+    fn test_fusion_support() {
+        // Partner genes on the same chromosome, each with two exons
+        let reads_a = vec![
+            test_read("spliced1", 99, 1081, "20M3899N20M", &"A".repeat(40)), // Exon 1 of gene A spliced to exon 1 of gene B
+            test_read("spliced2", 99, 1083, "18M3899N22M", &"A".repeat(40)),
+            test_read("split", 99, 1281, "20M20S", &"A".repeat(40)), // Exon 2 of gene A clipped at its end
+            test_read("spanning", 99, 1010, "50M", &"A".repeat(50)),
+            test_read("intronic", 99, 1150, "30M", &"A".repeat(30)),
+            test_read("single", 99, 1020, "50M", &"A".repeat(50)),
+        ];
+        let reads_b = vec![
+            test_read("split", 2145, 5200, "20S20M", &"A".repeat(40)), // Exon 2 of gene B clipped at its start
+            test_read("spanning", 147, 5010, "50M", &"A".repeat(50)),
+            test_read("intronic", 147, 5020, "50M", &"A".repeat(50)),
+        ];
+        let mut region_a = test_region("", "chr1", 1000, 1100, reads_a);
+        region_a.stop = 1400;
+        region_a.exons = vec![(1000, 1100), (1200, 1300)];
+        let mut region_b = test_region("", "chr1", 4900, 5000, reads_b);
+        region_b.stop = 5400;
+        region_b.exons = vec![(5000, 5100), (5200, 5300)];
+        let junction_templates_list: HashSet<String> = ["split", "spanning", "intronic"]
+            .iter()
+            .map(|qname| qname.to_string())
+            .collect();
+
+        let support = fusion_support(0, 0, &region_a, 1, &region_b, &junction_templates_list, 3);
+        assert_eq!(support.regions, [0, 1]);
+        assert_eq!(support.junction_reads, 3);
+        assert_eq!(support.spanning_fragments, 1); // The intronic template does not lie within exons of gene A
+        assert_eq!(support.exon_pairs.len(), 2);
+        assert_eq!(support.exon_pairs[0].exons, [1, 1]);
+        assert_eq!(support.exon_pairs[0].positions, [1100, 5000]);
+        assert_eq!(support.exon_pairs[0].junction_reads, 2);
+        assert_eq!(support.exon_pairs[1].exons, [2, 2]);
+        assert_eq!(support.exon_pairs[1].positions, [1300, 5200]);
+        assert_eq!(support.exon_pairs[1].junction_reads, 1);
+    }
+
+    #[test]
+    fn test_junction_sequence_both_breakends() {
+        let (region_a, region_b) = junction_regions("");
+        let breakpoint_a = refine_breakpoint(&region_a, 0, 10);
+        let breakpoint_b = refine_breakpoint(&region_b, 1, 10);
+        assert_eq!(
+            junction_sequence_both_breakends(
+                &breakpoint_a,
+                &region_a,
+                &breakpoint_b,
+                &region_b,
+                10
+            ),
+            Some(("CA".to_string(), "".to_string()))
+        );
+        let (region_a, region_b) = junction_regions("TTT");
+        let breakpoint_a = refine_breakpoint(&region_a, 0, 10);
+        let breakpoint_b = refine_breakpoint(&region_b, 1, 10);
+        assert_eq!(
+            junction_sequence_both_breakends(
+                &breakpoint_a,
+                &region_a,
+                &breakpoint_b,
+                &region_b,
+                10
+            ),
+            Some(("".to_string(), "TTT".to_string()))
+        );
+
+        // The soft-clipped consensus of the first region is too short to be matched, the junction is determined from the second region
+        let (_region_a, region_b) = junction_regions("");
+        let region_a = test_region(
+            REFSEQ_A,
+            "chr1",
+            1000,
+            1030,
+            vec![test_read("t1", 97, 1013, "17M3S", "ACATAACATACACGTCACCT")],
+        );
+        let breakpoint_a = refine_breakpoint(&region_a, 0, 10);
+        let breakpoint_b = refine_breakpoint(&region_b, 1, 10);
+        assert_eq!(breakpoint_a.consensus, "CCT");
+        assert_eq!(
+            junction_sequence(&breakpoint_a, &region_a, &breakpoint_b, &region_b, 10),
+            None
+        );
+        assert_eq!(
+            junction_sequence_both_breakends(
+                &breakpoint_a,
+                &region_a,
+                &breakpoint_b,
+                &region_b,
+                10
+            ),
+            Some(("CA".to_string(), "".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_breakend_summary() {
+        let reads = || {
+            vec![
+                test_read(
+                    "t1",
+                    97,
+                    1010,
+                    "20M20S",
+                    "ATTACATAACATACACGTCACCTTTACTTGCTGTGTCCAC",
+                ), // Split read, clipped on the junction side
+                test_read("t4", 99, 1005, "40M", &REFSEQ_A[5..45]),
+                test_read("t5", 163, 1005, "40M", &REFSEQ_A[5..45]),
+                test_read("t6", 113, 1040, "20M", &REFSEQ_A[40..60]), // Discordant mate pointing away from the junction
+                test_read("t7", 99, 1005, "40M", &REFSEQ_A[5..45]), // Template supporting another junction
+            ]
+        };
+        let region = test_region(REFSEQ_A, "chr1", 1000, 1030, reads());
+        let breakpoint = refine_breakpoint(&region, 0, 10);
+        let reads_overlap: Vec<ReadInfo> = reads()
+            .into_iter()
+            .filter(|read| read.qname != "t6")
+            .collect(); // t6 does not contain the break point
+        let to_set = |qnames: &[&str]| -> HashSet<String> {
+            qnames.iter().map(|qname| qname.to_string()).collect()
+        };
+        let breakend = breakend_summary(
+            0,
+            &region,
+            &breakpoint,
+            &reads_overlap,
+            &to_set(&["t1", "t6", "t7"]),
+            &to_set(&["t1", "t6"]),
+            &to_set(&["t1"]),
+        );
+        assert_eq!(breakend.chr, "chr1");
+        assert_eq!(breakend.pos, 1030);
+        assert_eq!(breakend.orientation, "+");
+        assert_eq!(breakend.ref_reads, 2);
+        assert_eq!(breakend.alt_reads, 2);
+        assert_eq!(breakend.vaf, 0.5);
+        // binom.test(2, 4)$conf.int in R
+        assert!((breakend.ci_lower - 0.06758599).abs() < 1e-8);
+        assert!((breakend.ci_upper - 0.93241401).abs() < 1e-8);
+        assert_eq!(breakend.consistent_reads, 1);
+        assert_eq!(breakend.inconsistent_reads, 1);
+    }
+
+    fn test_breakend(region: usize, chr: &str, pos: i64, orientation: &str) -> Breakend {
+        Breakend {
+            region,
+            chr: chr.to_string(),
+            pos,
+            orientation: orientation.to_string(),
+            ref_reads: 3,
+            alt_reads: 2,
+            vaf: 0.4,
+            ci_lower: 0.0,
+            ci_upper: 1.0,
+            consistent_reads: 2,
+            inconsistent_reads: 0,
+        }
+    }
+
+    // BND records of a junction between chr1:1029/1030 and chr2:5029/5030 with the given orientations, inserted sequence and microhomology
+    fn bnd_records(
+        orientation_a: &str,
+        orientation_b: &str,
+        inserted_sequence: &str,
+        microhomology: &str,
+    ) -> Vec<vcf::VcfRecord> {
+        let regions = vec![
+            test_region(REFSEQ_A, "chr1", 1000, 1030, Vec::new()),
+            test_region(REFSEQ_B, "chr2", 5000, 5030, Vec::new()),
+        ];
+        let sv_events = vec![SvEvent {
+            junction: 0,
+            breakends: vec![
+                test_breakend(0, "chr1", 1030, orientation_a),
+                test_breakend(1, "chr2", 5030, orientation_b),
+            ],
+            split_reads: 4,
+            discordant_pairs: 1,
+            orientation_consistent: true,
+        }];
+        let junction_supports = vec![JunctionSupport {
+            regions: [0, 1],
+            split_reads: 4,
+            spanning_pairs: 1,
+            microhomology: Some(microhomology.to_string()),
+            inserted_sequence: Some(inserted_sequence.to_string()),
+        }];
+        let (vcf_records, _header_lines) =
+            create_bnd_records(&sv_events, &regions, &junction_supports);
+        vcf_records
+    }
+
+    #[test]
+    fn test_create_bnd_records() {
+        // Retained sequence left of chr1:1030 joined to the retained sequence right of chr2:5030
+        let records = bnd_records("+", "-", "TT", "");
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            (
+                records[0].chrom.as_str(),
+                records[0].pos,
+                records[0].ref_allele.as_str()
+            ),
+            ("chr1", 1029, "A")
+        );
+        assert_eq!(records[0].alt_allele, "ATT[chr2:5030[");
+        assert_eq!(records[0].id, "bnd0_1");
+        assert_eq!(
+            records[0].info,
+            vec!["SVTYPE=BND", "MATEID=bnd0_2", "SR=4", "PE=1"]
+        );
+        assert_eq!(records[0].format, vec!["AD", "DP"]);
+        assert_eq!(records[0].sample, vec!["3,2", "5"]);
+        assert_eq!(
+            (
+                records[1].chrom.as_str(),
+                records[1].pos,
+                records[1].ref_allele.as_str()
+            ),
+            ("chr2", 5030, "C")
+        );
+        assert_eq!(records[1].alt_allele, "]chr1:1029]TTC");
+        assert_eq!(records[1].id, "bnd0_2");
+
+        // Both retained sequences left of the break points (inversion)
+        let records = bnd_records("+", "+", "TT", "");
+        assert_eq!((records[0].pos, records[1].pos), (1029, 5029));
+        assert_eq!(records[0].alt_allele, "ATT]chr2:5029]");
+        assert_eq!(records[1].alt_allele, "AAA]chr1:1029]");
+
+        // Both retained sequences right of the break points (inversion)
+        let records = bnd_records("-", "-", "TT", "");
+        assert_eq!((records[0].pos, records[1].pos), (1030, 5030));
+        assert_eq!(records[0].alt_allele, "[chr2:5030[AAG");
+        assert_eq!(records[1].alt_allele, "[chr1:1030[TTC");
+
+        // Retained sequence right of chr1:1030 joined to the retained sequence left of chr2:5029
+        let records = bnd_records("-", "+", "TT", "");
+        assert_eq!((records[0].pos, records[1].pos), (1030, 5029));
+        assert_eq!(records[0].alt_allele, "]chr2:5029]AAG");
+        assert_eq!(records[1].alt_allele, "AAA[chr1:1030[");
+
+        // Microhomology is reported in INFO
+        let records = bnd_records("+", "-", "", "CA");
+        assert_eq!(records[0].alt_allele, "A[chr2:5030[");
+        assert_eq!(records[1].alt_allele, "]chr1:1029]C");
+        assert_eq!(records[0].info[4], "HOMSEQ=CA");
+    }
+}
@@ -3,7 +3,7 @@ mod tests {
 
     #[test]
     fn test_wilcoxon_rank_sum() {
-        use crate::wilcoxon_rank_sum_test;
+        use crate::stats_functions::wilcoxon_rank_sum_test;
         assert_eq!(
             wilcoxon_rank_sum_test(
                 vec![
@@ -639,4 +639,249 @@ mod tests {
             1.3480170302526593e-25
         );
     }
+
+    #[test]
+    #[allow(clippy::approx_constant)] // 3.14 below is a measurement, not pi
+    fn test_wilcoxon_signed_rank() {
+        use crate::stats_functions::wilcoxon_signed_rank_test;
+        // Values from wilcox.test(x, y, paired = TRUE) in R
+        let x = vec![1.83, 0.50, 1.62, 2.48, 1.68, 1.88, 1.55, 3.06, 1.30];
+        let y = vec![0.878, 0.647, 0.598, 2.05, 1.06, 1.29, 1.06, 3.14, 1.29];
+        // Exact distribution
+        assert!(
+            (wilcoxon_signed_rank_test(x.clone(), y.clone(), 50, 't', true).unwrap() - 0.0390625)
+                .abs()
+                < 1e-10
+        );
+        assert!(
+            (wilcoxon_signed_rank_test(x.clone(), y.clone(), 50, 'g', true).unwrap() - 0.01953125)
+                .abs()
+                < 1e-10
+        );
+        assert!(
+            (wilcoxon_signed_rank_test(x.clone(), y.clone(), 50, 'l', true).unwrap() - 0.986328125)
+                .abs()
+                < 1e-10
+        );
+        // Normal approximation, same as exact = FALSE
+        assert!(
+            (wilcoxon_signed_rank_test(x, y, 5, 't', true).unwrap() - 0.04401098401295146).abs()
+                < 1e-10
+        );
+
+        // Ties and zero differences force the normal approximation
+        let x = vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0];
+        let y = vec![1.0, 1.0, 1.0, 2.0, 2.0, 9.0, 3.0, 3.0];
+        assert!(
+            (wilcoxon_signed_rank_test(x.clone(), y.clone(), 50, 't', true).unwrap()
+                - 0.12682565837709112)
+                .abs()
+                < 1e-10
+        );
+        assert!(
+            (wilcoxon_signed_rank_test(x.clone(), y.clone(), 50, 'g', true).unwrap()
+                - 0.06341282918854556)
+                .abs()
+                < 1e-10
+        );
+        assert!(
+            (wilcoxon_signed_rank_test(x.clone(), y.clone(), 50, 'l', true).unwrap()
+                - 0.9550928280208523)
+                .abs()
+                < 1e-10
+        );
+        // correct = FALSE
+        assert!(
+            (wilcoxon_signed_rank_test(x, y, 50, 't', false).unwrap() - 0.10705794596591933).abs()
+                < 1e-10
+        );
+
+        // 55 pairs, above the threshold for the exact distribution
+        let x = vec![
+            8.41, 9.09, 1.41, -7.57, -9.59, -2.79, 6.57, 9.89, 4.12, -5.44, -10.0, -5.37, 4.2,
+            9.91, 6.5, -2.88, -9.61, -7.51, 1.5, 9.13, 8.37, -0.09, -8.46, -9.06, -1.32, 7.63,
+            9.56, 2.71, -6.64, -9.88, -4.04, 5.51, 10.0, 5.29, -4.28, -9.92, -6.44, 2.96, 9.64,
+            7.45, -1.59, -9.17, -8.32, 0.18, 8.51, 9.02, 1.24, -7.68, -9.54, -2.62, 6.7, 9.87,
+            3.96, -5.59, -10.0,
+        ];
+        let y = vec![
+            1.6, -5.14, -4.36, 2.81, 5.86, 0.32, -5.69, -3.37, 3.89, 5.44, -0.97, -5.97, -2.22,
+            4.78, 4.77, -2.22, -5.96, -0.97, 5.45, 3.88, -3.37, -5.68, 0.33, 5.86, 2.81, -4.36,
+            -5.14, 1.61, 6.0, 1.6, -5.14, -4.35, 2.82, 5.86, 0.32, -5.69, -3.36, 3.89, 5.44, -0.98,
+            -5.97, -2.21, 4.78, 4.77, -2.23, -5.96, -0.96, 5.45, 3.88, -3.37, -5.68, 0.33, 5.86,
+            2.8, -4.36,
+        ];
+        assert!(
+            (wilcoxon_signed_rank_test(x.clone(), y.clone(), 50, 't', true).unwrap()
+                - 0.8900459653488421)
+                .abs()
+                < 1e-10
+        );
+        assert!(
+            (wilcoxon_signed_rank_test(x.clone(), y.clone(), 50, 'g', true).unwrap()
+                - 0.44502298267442103)
+                .abs()
+                < 1e-10
+        );
+        assert!(
+            (wilcoxon_signed_rank_test(x, y, 50, 'l', true).unwrap() - 0.558285829768735).abs()
+                < 1e-10
+        );
+
+        // All differences are zero, groups of different lengths
+        assert!(wilcoxon_signed_rank_test(vec![1.0, 2.0], vec![1.0, 2.0], 50, 't', true).is_err());
+        assert!(wilcoxon_signed_rank_test(vec![1.0, 2.0], vec![1.0], 50, 't', true).is_err());
+    }
+
+    #[test]
+    fn test_clopper_pearson_interval() {
+        use crate::stats_functions::clopper_pearson_interval;
+        // Values from binom.test() in R
+        let (lower, upper) = clopper_pearson_interval(3, 10, 0.95);
+        assert!((lower - 0.06673951).abs() < 1e-8);
+        assert!((upper - 0.65245285).abs() < 1e-8);
+
+        let (lower, upper) = clopper_pearson_interval(0, 20, 0.95);
+        assert_eq!(lower, 0.0);
+        assert!((upper - 0.1684335).abs() < 1e-7);
+
+        let (lower, upper) = clopper_pearson_interval(20, 20, 0.95);
+        assert!((lower - 0.8315665).abs() < 1e-7);
+        assert_eq!(upper, 1.0);
+    }
+
+    #[test]
+    fn test_genotype_likelihoods() {
+        use crate::stats_functions::genotype_likelihoods;
+        // Genotypes in order 0/0, 0/1, 1/1
+        assert_eq!(genotype_likelihoods(&[10, 10], 0.01), vec![140, 0, 140]);
+        assert_eq!(genotype_likelihoods(&[20, 0], 0.01), vec![0, 59, 399]);
+        assert_eq!(genotype_likelihoods(&[0, 0], 0.01), vec![0, 0, 0]);
+        // Multi-allelic site, genotypes in order 0/0, 0/1, 1/1, 0/2, 1/2, 2/2
+        assert_eq!(
+            genotype_likelihoods(&[0, 10, 10], 0.01),
+            vec![400, 200, 170, 200, 0, 170]
+        );
+    }
+
+    #[test]
+    fn test_fisher_exact_rxc() {
+        use crate::stats_functions::fisher_exact_rxc;
+        // fisher.test() in R (same as the p-value obtained by enumerating all tables with the margins of the observed table)
+        let p_value =
+            fisher_exact_rxc(&[vec![12, 30, 41], vec![21, 19, 33], vec![4, 9, 2]]).unwrap();
+        assert!((p_value - 0.01176235).abs() < 1e-8);
+        let p_value = fisher_exact_rxc(&[vec![2, 0, 3], vec![1, 4, 0], vec![0, 2, 5]]).unwrap();
+        assert!((p_value - 0.01665981).abs() < 1e-8);
+        // Empty rows and columns are removed, leaving a 2x2 table
+        let p_value = fisher_exact_rxc(&[vec![0, 5, 0], vec![0, 0, 0], vec![10, 10, 0]]).unwrap();
+        assert!((p_value - 0.06126482).abs() < 1e-8);
+    }
+
+    #[test]
+    fn test_fisher_montecarlo_rxc() {
+        use crate::stats_functions::fisher_montecarlo_rxc;
+        let table = vec![vec![12, 30, 41], vec![21, 19, 33], vec![4, 9, 2]];
+        let p_value = fisher_montecarlo_rxc(&table, 20000, 1);
+        // Within Monte Carlo error (standard error 0.0008) of the exact p-value 0.01176235
+        assert!((p_value - 0.01176235).abs() < 0.004);
+        // The random tables only depend on the seed
+        assert_eq!(p_value, fisher_montecarlo_rxc(&table, 20000, 1));
+        assert_eq!(
+            fisher_montecarlo_rxc(&[vec![3, 0], vec![0, 0]], 100, 1),
+            1.0
+        );
+    }
+
+    #[test]
+    fn test_conditional_odds_ratio() {
+        use crate::stats_functions::conditional_odds_ratio;
+        // Exact roots of the conditional likelihood equations of fisher.test(matrix(c(3, 1, 1, 3), 2)), R stops its root search at a tolerance of about 1e-4 so its values differ from these in the fourth significant digit
+        let (estimate, lower, upper) = conditional_odds_ratio(3, 1, 1, 3, 0.95);
+        assert!((estimate - 6.408320).abs() < 1e-5);
+        assert!((lower - 0.2117356).abs() < 1e-6);
+        assert!((upper - 626.2435).abs() < 1e-3);
+        // fisher.test(matrix(c(2, 15, 10, 3), 2))
+        let (estimate, lower, upper) = conditional_odds_ratio(2, 10, 15, 3, 0.95);
+        assert!((estimate - 0.04693664).abs() < 1e-7);
+        assert!((lower - 0.003317164).abs() < 1e-8);
+        assert!((upper - 0.3631896).abs() < 1e-6);
+        // Zero cell: fisher.test(matrix(c(0, 10, 5, 10), 2))
+        let (estimate, lower, upper) = conditional_odds_ratio(0, 5, 10, 10, 0.95);
+        assert_eq!(estimate, 0.0);
+        assert_eq!(lower, 0.0);
+        assert!((upper - 1.451644).abs() < 1e-6);
+        let (estimate, _lower, upper) = conditional_odds_ratio(10, 10, 0, 5, 0.95);
+        assert_eq!(estimate, f64::INFINITY);
+        assert_eq!(upper, f64::INFINITY);
+    }
+
+    #[test]
+    fn test_relative_risk() {
+        use crate::stats_functions::relative_risk;
+        let (estimate, lower, upper) = relative_risk(56, 14, 48, 32, 0.95).unwrap();
+        assert!((estimate - 4.0 / 3.0).abs() < 1e-12);
+        assert!((lower - 1.076626).abs() < 1e-6);
+        assert!((upper - 1.651249).abs() < 1e-6);
+        // Zero risk
+        assert!(relative_risk(0, 5, 10, 10, 0.95).is_none());
+    }
+
+    #[test]
+    fn test_risk_difference() {
+        use crate::stats_functions::{risk_difference, RiskDifferenceMethod};
+        // Examples of Newcombe (1998), method 10 (hybrid score) and method 1 (Wald)
+        let (estimate, lower, upper) =
+            risk_difference(56, 14, 48, 32, 0.95, RiskDifferenceMethod::Newcombe).unwrap();
+        assert!((estimate - 0.2).abs() < 1e-12);
+        assert!((lower - 0.0524).abs() < 5e-5);
+        assert!((upper - 0.3339).abs() < 5e-5);
+        let (_estimate, lower, upper) =
+            risk_difference(9, 1, 3, 7, 0.95, RiskDifferenceMethod::Newcombe).unwrap();
+        assert!((lower - 0.1705).abs() < 5e-5);
+        assert!((upper - 0.8090).abs() < 5e-5);
+        let (_estimate, lower, upper) =
+            risk_difference(56, 14, 48, 32, 0.95, RiskDifferenceMethod::Wald).unwrap();
+        assert!((lower - 0.0575).abs() < 5e-5);
+        assert!((upper - 0.3425).abs() < 5e-5);
+        // Zero cells: the Newcombe interval is still usable, the Wald interval has no width
+        let (estimate, lower, upper) =
+            risk_difference(0, 10, 0, 20, 0.95, RiskDifferenceMethod::Newcombe).unwrap();
+        assert_eq!(estimate, 0.0);
+        assert!((lower + 0.1611).abs() < 5e-5);
+        assert!((upper - 0.2775).abs() < 5e-5);
+        assert_eq!(
+            risk_difference(0, 10, 0, 20, 0.95, RiskDifferenceMethod::Wald),
+            Some((0.0, 0.0, 0.0))
+        );
+        // Empty row
+        assert!(risk_difference(0, 0, 3, 4, 0.95, RiskDifferenceMethod::Newcombe).is_none());
+    }
+
+    #[test]
+    fn test_cochran_mantel_haenszel_test() {
+        use crate::stats_functions::{breslow_day_test, cochran_mantel_haenszel_test};
+        // Rabbits data of mantelhaen.test() in R: X-squared = 3.9286, p-value = 0.04747, common odds ratio 7 (1.026713, 47.725133)
+        let rabbits = vec![
+            [0, 6, 0, 5],
+            [3, 3, 0, 6],
+            [6, 0, 2, 4],
+            [5, 1, 6, 0],
+            [2, 0, 5, 0],
+        ];
+        let (p_value, odds_ratio, lower, upper) =
+            cochran_mantel_haenszel_test(&rabbits, 0.95).unwrap();
+        assert!((p_value - 0.04747226).abs() < 1e-8);
+        assert!((odds_ratio - 7.0).abs() < 1e-12);
+        assert!((lower - 1.026713).abs() < 1e-6);
+        assert!((upper - 47.725133).abs() < 1e-6);
+        // Strata with an empty row or column are skipped (3 strata left, 2 degrees of freedom)
+        let p_value = breslow_day_test(&rabbits, odds_ratio).unwrap();
+        assert!((p_value - 0.01338439).abs() < 1e-8);
+        assert!(breslow_day_test(&rabbits, 0.0).is_none());
+        assert!(breslow_day_test(&vec![[3, 3, 0, 6]], 2.0).is_none());
+
+        // No stratum has a variance
+        assert!(cochran_mantel_haenszel_test(&vec![[0, 6, 0, 5], [2, 0, 5, 0]], 0.95).is_none());
+    }
 }
@@ -0,0 +1,166 @@
+// Functions for writing results in VCF 4.3 format, so that the output of the indel, align and sv binaries can be consumed by bcftools and other downstream tools
+use std::fs;
+
+pub struct VcfRecord {
+    pub chrom: String,       // Chromosome name
+    pub pos: i64,            // 1-based position of the first nucleotide of ref_allele
+    pub id: String, // Record identifier ("." when not needed, breakend records refer to each other by ID)
+    pub ref_allele: String, // Reference allele
+    pub alt_allele: String, // Alternate allele
+    pub info: Vec<String>, // INFO fields in KEY=VALUE format
+    pub format: Vec<String>, // FORMAT keys e.g GT, AD
+    pub sample: Vec<String>, // Value of each FORMAT key for the sample
+}
+
+// Header lines of the VCF file. All INFO and FORMAT fields that can be written by the indel and align binaries are defined here, other binaries add their own definitions through extra_header_lines
+fn vcf_header(
+    source: &str,
+    chrom: &str,
+    sample_name: &str,
+    extra_header_lines: &[String],
+) -> String {
+    let mut header = "##fileformat=VCFv4.3\n".to_string();
+    header += &format!("##source={}\n", source);
+    if chrom != "." {
+        header += &format!("##contig=<ID={}>\n", chrom);
+    }
+    header += "##INFO=<ID=FS,Number=1,Type=Float,Description=\"Phred-scaled p-value using Fisher's exact test (chi-square test for large read counts) to detect strand bias\">\n";
+    header += "##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">\n";
+    header += "##FORMAT=<ID=GQ,Number=1,Type=Integer,Description=\"Genotype quality\">\n";
+    header += "##FORMAT=<ID=AD,Number=R,Type=Integer,Description=\"Number of reads supporting each allele (ref, alt)\">\n";
+    header += "##FORMAT=<ID=DP,Number=1,Type=Integer,Description=\"Number of reads supporting the ref or an alt allele (sum of AD), reads containing the variant region but not assigned to any allele are not counted\">\n";
+    header += "##FORMAT=<ID=PL,Number=G,Type=Integer,Description=\"Phred-scaled genotype likelihoods\">\n";
+    header += "##FORMAT=<ID=SB,Number=4,Type=Integer,Description=\"Number of reads supporting each allele split by strand (ref forward, ref reverse, alt forward, alt reverse)\">\n";
+    for line in extra_header_lines {
+        header += line;
+        header += "\n";
+    }
+    header += "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\t";
+    header += sample_name;
+    header
+}
+
+// VCF requires the ref and alt alleles of an indel to start with the nucleotide preceding the event (anchor base). Returns the 1-based position, ref and alt allele of the record: alleles of equal length (SNV/MNV) and indel alleles sharing their first nucleotide are kept as they are, otherwise preceding_nucleotide is prepended to both alleles. An error is returned when the anchor base is needed but not available
+#[allow(dead_code)] // Not used by the sv binary, breakend records carry their own anchor base
+pub fn anchor_alleles(
+    variant_pos: i64, // 0-based position of the first nucleotide of ref_allele
+    ref_allele: &str, // Reference allele
+    alt_allele: &str, // Alternate allele
+    preceding_nucleotide: Option<char>, // Reference nucleotide at variant_pos - 1 (if known)
+) -> Result<(i64, String, String), String> {
+    if ref_allele == alt_allele {
+        return Err(format!(
+            "Ref and alt allele are identical ({}) at position {}",
+            ref_allele,
+            variant_pos + 1
+        ));
+    }
+    let shares_first_nucleotide = match (ref_allele.chars().next(), alt_allele.chars().next()) {
+        (Some(ref_first), Some(alt_first)) => ref_first.eq_ignore_ascii_case(&alt_first),
+        _ => false,
+    };
+    if ref_allele.len() == alt_allele.len() || shares_first_nucleotide {
+        return Ok((
+            variant_pos + 1,
+            ref_allele.to_string(),
+            alt_allele.to_string(),
+        ));
+    }
+    match preceding_nucleotide {
+        Some(nucleotide) if variant_pos > 0 => Ok((
+            variant_pos, // 1-based position of the preceding nucleotide
+            nucleotide.to_string() + ref_allele,
+            nucleotide.to_string() + alt_allele,
+        )),
+        _ => Err(format!(
+            "Indel {}>{} at position {} has no anchor base and the preceding nucleotide is not available",
+            ref_allele,
+            alt_allele,
+            variant_pos + 1
+        )),
+    }
+}
+
+// Formats a single VCF data line (QUAL and FILTER are not determined and are set to ".")
+fn vcf_record(record: &VcfRecord) -> String {
+    let mut info = record.info.join(";");
+    if info.is_empty() {
+        info = ".".to_string();
+    }
+    let fields: Vec<String> = vec![
+        record.chrom.to_string(),
+        record.pos.to_string(),
+        record.id.to_string(),
+        record.ref_allele.to_string(),
+        record.alt_allele.to_string(),
+        ".".to_string(),
+        ".".to_string(),
+        info,
+        record.format.join(":"),
+        record.sample.join(":"),
+    ];
+    fields.join("\t")
+}
+
+// Writes the VCF header followed by all records to vcf_file. The VCF is written to a file rather than stdout because stdout is parsed by nodejs and also contains diagnostic messages
+pub fn write_vcf(
+    vcf_file: &str,                // Path to output VCF file
+    source: &str,                  // Name of the binary writing the VCF
+    chrom: &str,                   // Chromosome name
+    sample_name: &str,             // Sample name
+    records: &[VcfRecord],         // VCF records
+    extra_header_lines: &[String], // Additional header lines (e.g INFO definitions and contigs), written before the #CHROM line
+) -> Result<(), String> {
+    let mut vcf_string = vcf_header(source, chrom, sample_name, extra_header_lines) + "\n";
+    for record in records {
+        vcf_string += &vcf_record(record);
+        vcf_string += "\n";
+    }
+    match fs::write(vcf_file, vcf_string) {
+        Ok(_) => Ok(()),
+        Err(error) => Err(format!("Cannot write VCF file {}: {}", vcf_file, error)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_anchor_alleles() {
+        // SNV, MNV and anchored indels are kept as they are
+        assert_eq!(
+            anchor_alleles(99, "A", "G", None),
+            Ok((100, "A".to_string(), "G".to_string()))
+        );
+        assert_eq!(
+            anchor_alleles(99, "AC", "GT", None),
+            Ok((100, "AC".to_string(), "GT".to_string()))
+        );
+        assert_eq!(
+            anchor_alleles(99, "A", "ATT", Some('C')),
+            Ok((100, "A".to_string(), "ATT".to_string()))
+        );
+        assert_eq!(
+            anchor_alleles(99, "CTT", "C", None),
+            Ok((100, "CTT".to_string(), "C".to_string()))
+        );
+        // Indels without anchor base get the preceding nucleotide prepended
+        assert_eq!(
+            anchor_alleles(99, "", "TT", Some('C')),
+            Ok((99, "C".to_string(), "CTT".to_string()))
+        );
+        assert_eq!(
+            anchor_alleles(99, "TT", "", Some('C')),
+            Ok((99, "CTT".to_string(), "C".to_string()))
+        );
+        assert_eq!(
+            anchor_alleles(99, "AT", "G", Some('C')),
+            Ok((99, "CAT".to_string(), "CG".to_string()))
+        );
+        // Anchor base needed but not available
+        assert!(anchor_alleles(99, "", "TT", None).is_err());
+        assert!(anchor_alleles(0, "", "TT", Some('C')).is_err());
+        assert!(anchor_alleles(99, "A", "A", Some('C')).is_err());
+    }
+}
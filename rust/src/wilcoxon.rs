@@ -3,11 +3,13 @@
 # Wilcoxon rank sum test #
 ##########################
 
+Also runs the Wilcoxon signed rank test for paired samples (e.g. tumor/normal, pre/post-treatment) when paired is set to true
+
 ##########################
 # Documentation
 ##########################
 
-This wilcoxon test implementation aims to copy the methodology used in R's wilcox_test() function
+This wilcoxon test implementation aims to copy the methodology used in R's wilcox_test() function. Paired entries are tested like wilcox.test(group1_values, group2_values, paired = TRUE): pairs with zero difference are dropped, and the normal approximation (with continuity correction) is used instead of the exact distribution when there are ties, zeros or more than 50 pairs
 
 #########
 # Usage #
@@ -15,6 +17,8 @@ This wilcoxon test implementation aims to copy the methodology used in R's wilco
 
 # Usage: cd .. && cargo build --release && time echo '[{"group1_id":"European Ancestry","group1_values":[3.7,2.5,5.9,13.1,1,10.6,3.2,3,6.5,15.5,2.6,16.5,2.6,4,8.6,8.3,1.9,7.9,7.9,6.1,17.6,3.1,3,1.5,8.1,18.2,-1.8,3.6,6,1.9,8.9,3.2,0.3,-1,11.2,6.2,16.2,7.5,9,9.4,18.9,0.1,11.5,10.1,12.5,14.6,1.5,17.3,15.4,7.6,2.4,13.5,3.8,17],"group2_id":"African Ancestry","group2_values":[11.5,5.1,21.1,4.4,-0.04]},{"group1_id":"European Ancestry","group1_values":[3.7,2.5,5.9,13.1,1,10.6,3.2,3,6.5,15.5,2.6,16.5,2.6,4,8.6,8.3,1.9,7.9,7.9,6.1,17.6,3.1,3,1.5,8.1,18.2,-1.8,3.6,6,1.9,8.9,3.2,0.3,-1,11.2,6.2,16.2,7.5,9,9.4,18.9,0.1,11.5,10.1,12.5,14.6,1.5,17.3,15.4,7.6,2.4,13.5,3.8,17],"group2_id":"Asian Ancestry","group2_values":[1.7]},{"group1_id":"African Ancestry","group1_values":[11.5,5.1,21.1,4.4,-0.04],"group2_id":"Asian Ancestry","group2_values":[]}]' | target/release/wilcoxon
 
+# Paired example: cd .. && cargo build --release && time echo '[{"group1_id":"pre","group1_values":[1.83,0.50,1.62,2.48,1.68,1.88,1.55,3.06,1.30],"group2_id":"post","group2_values":[0.878,0.647,0.598,2.05,1.06,1.29,1.06,3.14,1.29],"paired":true}]' | target/release/wilcoxon
+
 # Several examples are present in test_examples.rs. This can be tested using the command: cd .. && cargo build --release && time cargo test
 
 # Input data is in JSON format and is read in from <in.json> file.
@@ -26,6 +30,7 @@ This wilcoxon test implementation aims to copy the methodology used in R's wilco
 #   group1_values: [] group1 data values,
 #   group2_id: group2 id,
 #   group2_values: [] group2 data values
+#   paired: true/false (optional, default false). If true, group1_values[i] and group2_values[i] are measurements of the same sample and the signed rank test is used. Both arrays must then have the same length, otherwise this entry gets a null pvalue and an error
 # }]
 #
 # Output JSON specifications:
@@ -34,7 +39,8 @@ This wilcoxon test implementation aims to copy the methodology used in R's wilco
 #   group1_values: [] group1 data values,
 #   group2_id: group2 id,
 #   group2_values: [] group2 data values,
-#   pvalue: p-value of test
+#   pvalue: p-value of test, null if either group is empty or the paired test could not be run
+#   error: reason the paired test could not be run (only present in that case), e.g. groups of different lengths or all paired differences being zero
 # }]
 
 
@@ -58,6 +64,8 @@ struct OutputJson {
     group1_values: Vec<f64>,
     group2_values: Vec<f64>,
     pvalue: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
 }
 
 //#[derive(Debug)]
@@ -97,6 +105,7 @@ fn main() {
                         }
                         //println!("vec1:{:?}", vec1);
                         //println!("vec2:{:?}", vec2);
+                        let paired: bool = json_string[i]["paired"].as_bool().unwrap_or(false);
 
                         if vec1.len() == 0 || vec2.len() == 0 {
                             // If one of the vectors has a length of zero, wilcoxon test is not performed and a pvalue of NULL is given.
@@ -112,17 +121,40 @@ fn main() {
                                 group1_values: vec1,
                                 group2_values: vec2,
                                 pvalue: None,
+                                error: None,
                             })
                             .unwrap();
                             output_string += &",".to_string();
                         } else {
-                            let pvalue: f64 = stats_functions::wilcoxon_rank_sum_test(
-                                vec1.clone(),
-                                vec2.clone(),
-                                THRESHOLD,
-                                't', // two-sided test
-                                true,
-                            );
+                            let result: Result<f64, String> = if paired && vec1.len() != vec2.len()
+                            {
+                                // Only this entry is skipped, the remaining entries are still tested
+                                Err(format!(
+                                    "Paired samples must have the same length ({} and {})",
+                                    vec1.len(),
+                                    vec2.len()
+                                ))
+                            } else if paired {
+                                stats_functions::wilcoxon_signed_rank_test(
+                                    vec1.clone(),
+                                    vec2.clone(),
+                                    THRESHOLD,
+                                    't', // two-sided test
+                                    true,
+                                )
+                            } else {
+                                Ok(stats_functions::wilcoxon_rank_sum_test(
+                                    vec1.clone(),
+                                    vec2.clone(),
+                                    THRESHOLD,
+                                    't', // two-sided test
+                                    true,
+                                ))
+                            };
+                            let (pvalue, error) = match result {
+                                Ok(pvalue) => (Some(pvalue), None),
+                                Err(error) => (None, Some(error)),
+                            };
 
                             //if pvalue > 0.01 {
                             //    pvalue = format!("{:.4}", pvalue).parse().unwrap();
@@ -139,7 +171,8 @@ fn main() {
                                     .to_string(),
                                 group1_values: vec1,
                                 group2_values: vec2,
-                                pvalue: Some(pvalue),
+                                pvalue,
+                                error,
                             })
                             .unwrap();
                             output_string += &",".to_string();
@@ -925,6 +925,7 @@ async function do_query(q) {
 			result.reference_forward_count = out.reference_forward_count // Number of reads classified as reference forward
 			result.alternate_reverse_count = out.alternate_reverse_count // Number of reads classified as alternate reverse
 			result.reference_reverse_count = out.reference_reverse_count // Number of reads classified as reference reverse
+			result.genotype = out.genotype // Genotype call, PL and allele fraction (with confidence interval) of each alt allele
 
 			if (out.strand_significance == true) {
 				// Tells whether the FS score is significant/insignificant
@@ -1703,8 +1704,11 @@ async function match_sv(templates, q, region_widths) {
 
 	let multi_region_templates
 	let single_region_templates
+	let fragment_counts // Number of templates (mates merged) supporting SV/fusion and reference
 	for (let item of rust_output_list) {
-		if (item.includes('multi_region_templates:')) {
+		if (item.includes('fragment_counts:')) {
+			fragment_counts = JSON.parse(item.replace('fragment_counts:', ''))
+		} else if (item.includes('multi_region_templates:')) {
 			multi_region_templates = JSON.parse(item.replace('multi_region_templates:', ''))
 		} else if (item.includes('single_region_templates:')) {
 			single_region_templates = JSON.parse(item.replace('single_region_templates:', ''))
@@ -1730,7 +1734,15 @@ async function match_sv(templates, q, region_widths) {
 		const g = type2group[k]
 		if (g.templates.length == 0) continue // empty group, do not include
 		if (k == bamcommon.type_supportsv) {
-			if (g.templates.length == 1) {
+			if (fragment_counts) {
+				g.messages.push({
+					isheader: true,
+					t:
+						fragment_counts.supporting_sv +
+						(fragment_counts.supporting_sv == 1 ? ' fragment' : ' fragments') +
+						' supporting SV/fusion'
+				})
+			} else if (g.templates.length == 1) {
 				g.messages.push({
 					isheader: true,
 					t: g.templates.length + ' read supporting SV/fusion'
@@ -1742,10 +1754,20 @@ async function match_sv(templates, q, region_widths) {
 				})
 			}
 		} else if (k == bamcommon.type_supportref) {
-			g.messages.push({
-				isheader: true,
-				t: g.templates.length + ' reads supporting reference allele'
-			})
+			if (fragment_counts) {
+				g.messages.push({
+					isheader: true,
+					t:
+						fragment_counts.supporting_ref +
+						(fragment_counts.supporting_ref == 1 ? ' fragment' : ' fragments') +
+						' supporting reference allele'
+				})
+			} else {
+				g.messages.push({
+					isheader: true,
+					t: g.templates.length + ' reads supporting reference allele'
+				})
+			}
 		}
 		g.widths = region_widths
 		groups.push(g)
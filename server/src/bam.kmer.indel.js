@@ -187,7 +187,15 @@ export async function match_complexvariant_rust(q, templates_info, region_widths
 	const reads = []
 	for (let i = 0; i < templates_info.length; i++) {
 		const item = templates_info[i].sam_info.split('\t')
-		reads.push({ read_sequence: item[9], start_position: Number(item[3]), cigar: item[5], flag: Number(item[1]) })
+		reads.push({
+			read_sequence: item[9],
+			start_position: Number(item[3]),
+			cigar: item[5],
+			flag: Number(item[1]),
+			quality: item[10],
+			mapq: Number(item[4]),
+			qname: item[0] // Used to merge mates of the same template
+		})
 	}
 
 	const alleles = []
@@ -209,6 +217,8 @@ export async function match_complexvariant_rust(q, templates_info, region_widths
 	}
 
 	const input_data = { reads: reads, alleles: alleles, strictness: Number(q.strictness) }
+	if (Number.isFinite(Number(q.min_base_quality))) input_data.min_base_quality = Number(q.min_base_quality)
+	if (Number.isFinite(Number(q.min_mapping_quality))) input_data.min_mapping_quality = Number(q.min_mapping_quality)
 
 	//if (is_realignment_reads == 1) {
 	//	// When realignment of reads is neccessary, quality scores and path to clustalo is passed for determining correct indel sequence (not functional, currently in development)
@@ -237,6 +247,7 @@ export async function match_complexvariant_rust(q, templates_info, region_widths
 	const rust_output_list = rust_output.split('\n')
 	let final_output
 	let fisher_strand_output
+	let genotype // Genotype call and allele fraction of each alt allele
 	let group_ids = []
 	let categories = []
 	let diff_scores = []
@@ -252,6 +263,8 @@ export async function match_complexvariant_rust(q, templates_info, region_widths
 			reference_forward_count = fisher_strand_output.reference_forward_count
 			reference_reverse_count = fisher_strand_output.reference_reverse_count
 			strand_probability = fisher_strand_output.p_value
+		} else if (item.includes('genotype:')) {
+			genotype = JSON.parse(item.replace('genotype:', ''))
 		} else {
 			console.log(item)
 		}
@@ -433,6 +446,7 @@ export async function match_complexvariant_rust(q, templates_info, region_widths
 		min_diff_score,
 		strand_probability,
 		strand_significance,
+		genotype,
 		refseqs,
 		altseqs,
 		leftflankseqs,
@@ -7,6 +7,7 @@ import * as termdb from './termdb'
 import readline from 'readline'
 import serverconfig from './serverconfig'
 import run_R from './run_R.js'
+import { run_rust } from '@sjcrh/proteinpaint-rust'
 
 /*
 ********************** EXPORTED
@@ -65,14 +66,20 @@ export async function trigger(q, res, ds) {
 
 	///////// fisher
 	{
-		const lines = []
-		for (let i = 0; i < tests.length; i++) {
-			lines.push(i + '\t' + tests[i].table.join('\t'))
-		}
-		const plines = await lines2R(path.join(serverconfig.binpath, 'utils/fisher.2x3.R'), lines)
-		let i = 0
-		for (const line of plines) {
-			tests[i++].pvalue = Number(line.split('\t')[7])
+		// test.table is [href1, href2, het1, het2, halt1, halt2], tested as a 2x3 table of group x genotype
+		const input = tests.map((test, index) => {
+			const t = test.table
+			return {
+				index,
+				table: [
+					[t[0], t[2], t[4]],
+					[t[1], t[3], t[5]]
+				]
+			}
+		})
+		const out = JSON.parse(await run_rust('fisher', JSON.stringify({ input })))
+		for (const test of out) {
+			tests[test.index].pvalue = test.p_value
 		}
 	}
 